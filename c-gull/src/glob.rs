@@ -0,0 +1,509 @@
+//! `glob` and `globfree`.
+//!
+//! Patterns are expanded one `/`-separated component at a time, matching
+//! components with wildcards against the directory entries with `fnmatch`.
+
+use core::ffi::CStr;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr::{copy_nonoverlapping, null_mut};
+use errno::errno;
+use libc::{c_char, c_int, c_void, size_t};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStringExt;
+
+/// glibc's `glob_t`, including the callbacks for `GLOB_ALTDIRFUNC`, which
+/// the libc crate leaves private.
+#[repr(C)]
+struct Glob {
+    gl_pathc: size_t,
+    gl_pathv: *mut *mut c_char,
+    gl_offs: size_t,
+    gl_flags: c_int,
+    gl_closedir: Option<unsafe extern "C" fn(*mut c_void)>,
+    gl_readdir: Option<unsafe extern "C" fn(*mut c_void) -> *mut libc::dirent>,
+    gl_opendir: Option<unsafe extern "C" fn(*const c_char) -> *mut c_void>,
+    gl_lstat: Option<unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int>,
+    gl_stat: Option<unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int>,
+}
+
+/// The flag `glob` sets in `gl_flags` if the pattern had wildcards.
+const GLOB_MAGCHAR: c_int = 1 << 8;
+
+type ErrFunc = Option<extern "C" fn(epath: *const c_char, errno: c_int) -> c_int>;
+
+#[no_mangle]
+unsafe extern "C" fn glob(
+    pattern: *const c_char,
+    flags: c_int,
+    errfunc: ErrFunc,
+    pglob: *mut libc::glob_t,
+) -> c_int {
+    libc!(libc::glob(pattern, flags, errfunc, pglob));
+
+    let pglob: *mut Glob = checked_cast!(pglob);
+    let pglob = &mut *pglob;
+    if flags & libc::GLOB_APPEND == 0 {
+        pglob.gl_pathc = 0;
+        pglob.gl_pathv = null_mut();
+        if flags & libc::GLOB_DOOFFS == 0 {
+            pglob.gl_offs = 0;
+        }
+    }
+    pglob.gl_flags = flags & !GLOB_MAGCHAR;
+
+    let pattern = CStr::from_ptr(pattern).to_bytes();
+    let globber = Globber {
+        flags,
+        errfunc,
+        dir: if flags & libc::GLOB_ALTDIRFUNC == libc::GLOB_ALTDIRFUNC {
+            DirFuncs {
+                opendir: pglob.gl_opendir.unwrap(),
+                readdir: pglob.gl_readdir.unwrap(),
+                closedir: pglob.gl_closedir.unwrap(),
+                lstat: pglob.gl_lstat.unwrap(),
+                stat: pglob.gl_stat.unwrap(),
+            }
+        } else {
+            DirFuncs {
+                opendir: default_opendir,
+                readdir: default_readdir,
+                closedir: default_closedir,
+                lstat: libc::lstat,
+                stat: libc::stat,
+            }
+        },
+    };
+
+    let patterns = if flags & libc::GLOB_BRACE == libc::GLOB_BRACE && pattern != b"{}" {
+        globber.braces(pattern)
+    } else {
+        vec![pattern.to_vec()]
+    };
+
+    // Expand each alternative, sorting their matches separately.
+    let mut paths = Vec::new();
+    let mut magic = false;
+    for pattern in &patterns {
+        let mut found = match globber.expand(pattern, &mut magic) {
+            Ok(found) => found,
+            Err(code) => return code,
+        };
+        if flags & libc::GLOB_NOSORT == 0 {
+            found.sort();
+        }
+        paths.extend(found);
+    }
+    if paths.is_empty() {
+        let nomagic = flags & libc::GLOB_NOMAGIC == libc::GLOB_NOMAGIC && !magic;
+        if flags & libc::GLOB_NOCHECK == 0 && !nomagic {
+            return libc::GLOB_NOMATCH;
+        }
+        paths.push(pattern.to_vec());
+    }
+    if magic {
+        pglob.gl_flags |= GLOB_MAGCHAR;
+    }
+
+    append(pglob, flags, &paths)
+}
+
+#[no_mangle]
+unsafe extern "C" fn globfree(pglob: *mut libc::glob_t) {
+    libc!(libc::globfree(pglob));
+
+    let pglob: *mut Glob = checked_cast!(pglob);
+    let pglob = &mut *pglob;
+    if pglob.gl_pathv.is_null() {
+        return;
+    }
+    for i in 0..pglob.gl_pathc {
+        libc::free((*pglob.gl_pathv.add(pglob.gl_offs + i)).cast());
+    }
+    libc::free(pglob.gl_pathv.cast());
+    pglob.gl_pathv = null_mut();
+    pglob.gl_pathc = 0;
+}
+
+/// Add `paths` to the end of `pglob.gl_pathv`, after `gl_offs` null entries
+/// if `GLOB_DOOFFS` is set.
+unsafe fn append(pglob: &mut Glob, flags: c_int, paths: &[Vec<u8>]) -> c_int {
+    let offs = if flags & libc::GLOB_DOOFFS == libc::GLOB_DOOFFS {
+        pglob.gl_offs
+    } else {
+        0
+    };
+    let old = if pglob.gl_pathv.is_null() {
+        0
+    } else {
+        pglob.gl_pathc
+    };
+    let len = offs + old + paths.len() + 1;
+    let pathv =
+        libc::realloc(pglob.gl_pathv.cast(), len * size_of::<*mut c_char>()).cast::<*mut c_char>();
+    if pathv.is_null() {
+        return libc::GLOB_NOSPACE;
+    }
+    if pglob.gl_pathv.is_null() {
+        for i in 0..offs {
+            pathv.add(i).write(null_mut());
+        }
+    }
+    pglob.gl_pathv = pathv;
+
+    for (i, path) in paths.iter().enumerate() {
+        let copy = libc::malloc(path.len() + 1).cast::<c_char>();
+        if copy.is_null() {
+            pathv.add(offs + old + i).write(null_mut());
+            pglob.gl_pathc = old + i;
+            return libc::GLOB_NOSPACE;
+        }
+        copy_nonoverlapping(path.as_ptr().cast(), copy, path.len());
+        copy.add(path.len()).write(0);
+        pathv.add(offs + old + i).write(copy);
+    }
+    pathv.add(offs + old + paths.len()).write(null_mut());
+    pglob.gl_pathc = old + paths.len();
+    0
+}
+
+/// The functions `glob` uses to read directories, which are either the
+/// usual ones or the `GLOB_ALTDIRFUNC` callbacks.
+struct DirFuncs {
+    opendir: unsafe extern "C" fn(*const c_char) -> *mut c_void,
+    readdir: unsafe extern "C" fn(*mut c_void) -> *mut libc::dirent,
+    closedir: unsafe extern "C" fn(*mut c_void),
+    lstat: unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int,
+    stat: unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int,
+}
+
+unsafe extern "C" fn default_opendir(path: *const c_char) -> *mut c_void {
+    libc::opendir(path).cast()
+}
+
+unsafe extern "C" fn default_readdir(dir: *mut c_void) -> *mut libc::dirent {
+    libc::readdir(dir.cast())
+}
+
+unsafe extern "C" fn default_closedir(dir: *mut c_void) {
+    libc::closedir(dir.cast());
+}
+
+struct Globber {
+    flags: c_int,
+    errfunc: ErrFunc,
+    dir: DirFuncs,
+}
+
+impl Globber {
+    fn flag(&self, flag: c_int) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// Return the paths matching `pattern`, which has no braces left to
+    /// expand. Set `magic` if it has wildcards or escapes.
+    unsafe fn expand(&self, pattern: &[u8], magic: &mut bool) -> Result<Vec<Vec<u8>>, c_int> {
+        let mut pattern = pattern.to_vec();
+        if (self.flag(libc::GLOB_TILDE) || self.flag(libc::GLOB_TILDE_CHECK))
+            && pattern.first() == Some(&b'~')
+        {
+            let end = pattern
+                .iter()
+                .position(|b| *b == b'/')
+                .unwrap_or(pattern.len());
+            match home(&pattern[1..end]) {
+                Some(mut home) => {
+                    home.extend_from_slice(&pattern[end..]);
+                    pattern = home;
+                }
+                None if self.flag(libc::GLOB_TILDE_CHECK) => return Err(libc::GLOB_NOMATCH),
+                // As in glibc, a lone unknown `~user` is returned as is.
+                // Otherwise, the `~user` is left as is, and the pattern is
+                // matched like any other, so that literal paths only match if
+                // they exist.
+                None if pattern[end..].iter().all(|b| *b == b'/') => {
+                    pattern.truncate(end);
+                    return Ok(vec![pattern]);
+                }
+                None => {}
+            }
+        }
+        // As in glibc, escapes count as wildcards for `GLOB_MAGCHAR` and
+        // `GLOB_NOMAGIC`.
+        if self.has_magic(&pattern) || (!self.flag(libc::GLOB_NOESCAPE) && pattern.contains(&b'\\'))
+        {
+            *magic = true;
+        }
+        if pattern.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // A trailing `/` means that only directories match, and they keep
+        // the `/`.
+        let trailing_slash = pattern.len() > 1 && pattern.ends_with(b"/");
+        let components: Vec<&[u8]> = pattern
+            .split(|b| *b == b'/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        let mut paths = vec![if pattern[0] == b'/' {
+            b"/".to_vec()
+        } else {
+            Vec::new()
+        }];
+        let mut checked = false;
+
+        for (index, component) in components.iter().enumerate() {
+            let last = index + 1 == components.len();
+            if !self.has_magic(component) {
+                let name = self.unescape(component);
+                for path in &mut paths {
+                    join(path, &name);
+                }
+                checked = false;
+                continue;
+            }
+
+            let only_dirs = !last || trailing_slash || self.flag(libc::GLOB_ONLYDIR);
+            let mut matches = Vec::new();
+            for path in &paths {
+                self.read_dir(path, component, only_dirs, &mut matches)?;
+            }
+            paths = matches;
+            checked = true;
+        }
+
+        // If the last component didn't have wildcards, we haven't looked for
+        // it yet.
+        if !checked {
+            paths.retain(|path| {
+                let mut stat = MaybeUninit::uninit();
+                let Ok(path) = CString::new(path.as_slice()) else {
+                    return false;
+                };
+                if trailing_slash {
+                    (self.dir.stat)(path.as_ptr(), stat.as_mut_ptr()) == 0
+                        && is_dir(&stat.assume_init())
+                } else {
+                    (self.dir.lstat)(path.as_ptr(), stat.as_mut_ptr()) == 0
+                }
+            });
+        }
+
+        for path in &mut paths {
+            if (trailing_slash || (self.flag(libc::GLOB_MARK) && self.is_dir_path(path)))
+                && !path.ends_with(b"/")
+            {
+                path.push(b'/');
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Add the entries in the directory `path` that match `component` to
+    /// `matches`.
+    unsafe fn read_dir(
+        &self,
+        path: &[u8],
+        component: &[u8],
+        only_dirs: bool,
+        matches: &mut Vec<Vec<u8>>,
+    ) -> Result<(), c_int> {
+        let dir_path = if path.is_empty() {
+            CString::new(".").unwrap()
+        } else {
+            CString::new(path).unwrap()
+        };
+        let dir = (self.dir.opendir)(dir_path.as_ptr());
+        if dir.is_null() {
+            let errno = errno().0;
+            if errno != libc::ENOTDIR
+                && (self
+                    .errfunc
+                    .is_some_and(|errfunc| errfunc(dir_path.as_ptr(), errno) != 0)
+                    || self.flag(libc::GLOB_ERR))
+            {
+                return Err(libc::GLOB_ABORTED);
+            }
+            return Ok(());
+        }
+
+        let mut fnmatch_flags = 0;
+        if !self.flag(libc::GLOB_PERIOD) {
+            fnmatch_flags |= libc::FNM_PERIOD;
+        }
+        if self.flag(libc::GLOB_NOESCAPE) {
+            fnmatch_flags |= libc::FNM_NOESCAPE;
+        }
+        let component = CString::new(component).unwrap();
+
+        loop {
+            let entry = (self.dir.readdir)(dir);
+            if entry.is_null() {
+                break;
+            }
+            let name = CStr::from_ptr((*entry).d_name.as_ptr());
+            if libc::fnmatch(component.as_ptr(), name.as_ptr(), fnmatch_flags) != 0 {
+                continue;
+            }
+            let mut found = path.to_vec();
+            join(&mut found, name.to_bytes());
+            if only_dirs {
+                match (*entry).d_type {
+                    libc::DT_DIR => {}
+                    libc::DT_LNK | libc::DT_UNKNOWN if self.is_dir_path(&found) => {}
+                    _ => continue,
+                }
+            }
+            matches.push(found);
+        }
+        (self.dir.closedir)(dir);
+        Ok(())
+    }
+
+    /// Test whether `path` is a directory, or a symlink to one.
+    unsafe fn is_dir_path(&self, path: &[u8]) -> bool {
+        let Ok(path) = CString::new(path) else {
+            return false;
+        };
+        let mut stat = MaybeUninit::uninit();
+        (self.dir.stat)(path.as_ptr(), stat.as_mut_ptr()) == 0 && is_dir(&stat.assume_init())
+    }
+
+    /// Test whether `component` has unescaped wildcards.
+    fn has_magic(&self, component: &[u8]) -> bool {
+        let mut bytes = component.iter();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'\\' if !self.flag(libc::GLOB_NOESCAPE) => {
+                    bytes.next();
+                }
+                b'*' | b'?' | b'[' => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Remove the backslashes from a component without wildcards.
+    fn unescape(&self, component: &[u8]) -> Vec<u8> {
+        if self.flag(libc::GLOB_NOESCAPE) {
+            return component.to_vec();
+        }
+        let mut name = Vec::with_capacity(component.len());
+        let mut bytes = component.iter();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'\\' => name.push(*bytes.next().unwrap_or(&b'\\')),
+                _ => name.push(*b),
+            }
+        }
+        name
+    }
+
+    /// Expand the braces in `pattern`, producing the patterns in order.
+    fn braces(&self, pattern: &[u8]) -> Vec<Vec<u8>> {
+        let noescape = self.flag(libc::GLOB_NOESCAPE);
+        let mut i = 0;
+        while i < pattern.len() {
+            match pattern[i] {
+                b'\\' if !noescape => i += 1,
+                b'{' => {
+                    if let Some((alternatives, end)) = brace_group(&pattern[i..], noescape) {
+                        let mut patterns = Vec::new();
+                        for alternative in alternatives {
+                            let mut expanded = pattern[..i].to_vec();
+                            expanded.extend_from_slice(alternative);
+                            expanded.extend_from_slice(&pattern[i + end..]);
+                            patterns.extend(self.braces(&expanded));
+                        }
+                        return patterns;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        vec![pattern.to_vec()]
+    }
+}
+
+/// Split the brace group at the start of `pattern` into its top-level
+/// `,`-separated alternatives, and return them with the length of the group,
+/// or `None` if it isn't terminated.
+fn brace_group(pattern: &[u8], noescape: bool) -> Option<(Vec<&[u8]>, usize)> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 1;
+    let mut i = 1;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'\\' if !noescape => i += 1,
+            b'{' => depth += 1,
+            b'}' if depth == 0 => {
+                alternatives.push(&pattern[start..i]);
+                return Some((alternatives, i + 1));
+            }
+            b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Append `name` to `path` as a new path component.
+fn join(path: &mut Vec<u8>, name: &[u8]) {
+    if !path.is_empty() && !path.ends_with(b"/") {
+        path.push(b'/');
+    }
+    path.extend_from_slice(name);
+}
+
+fn is_dir(stat: &libc::stat) -> bool {
+    stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+}
+
+/// Return the home directory of `user`, or of the current user if it's
+/// empty.
+pub(crate) unsafe fn home(user: &[u8]) -> Option<Vec<u8>> {
+    if user.is_empty() {
+        if let Some(home) = std::env::var_os("HOME") {
+            return Some(home.into_vec());
+        }
+    }
+
+    let mut buf = vec![0_u8; 1024];
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    loop {
+        let mut result = null_mut();
+        let r = if user.is_empty() {
+            libc::getpwuid_r(
+                libc::getuid(),
+                passwd.as_mut_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut result,
+            )
+        } else {
+            let name = CString::new(user).ok()?;
+            libc::getpwnam_r(
+                name.as_ptr(),
+                passwd.as_mut_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        if r == libc::ERANGE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if r != 0 || result.is_null() {
+            return None;
+        }
+        return Some(CStr::from_ptr((*result).pw_dir).to_bytes().to_vec());
+    }
+}
@@ -18,6 +18,8 @@ pub use c_scape::*;
 #[macro_use]
 mod use_libc;
 
+#[cfg(feature = "std")]
+mod glob;
 #[cfg(feature = "std")]
 mod nss;
 #[cfg(feature = "std")]
@@ -33,6 +35,8 @@ mod time;
 #[cfg(feature = "std")]
 #[cfg(not(target_env = "musl"))]
 mod utmp;
+#[cfg(feature = "std")]
+mod wordexp;
 
 #[cfg(feature = "std")]
 #[cold]
@@ -599,12 +599,11 @@ unsafe extern "C" fn getgrouplist(
     let ngroups_in = ngroups.read();
     let mut ngroups_out = 0;
 
-    if ngroups_out == ngroups_in {
-        return -1;
+    if ngroups_out < ngroups_in {
+        groups.write(group);
+        groups = groups.add(1);
     }
     ngroups_out += 1;
-    groups.write(group);
-    groups = groups.add(1);
 
     for part in parts {
         let gid: u32 = match part.parse() {
@@ -614,18 +613,43 @@ unsafe extern "C" fn getgrouplist(
         if gid == group {
             continue;
         }
-        if ngroups_out == ngroups_in {
-            return -1;
+        if ngroups_out < ngroups_in {
+            groups.write(gid);
+            groups = groups.add(1);
         }
         ngroups_out += 1;
-        groups.write(gid);
-        groups = groups.add(1);
     }
 
+    // If there wasn't enough room, report how much room is needed.
     ngroups.write(ngroups_out);
+    if ngroups_out > ngroups_in {
+        return -1;
+    }
     ngroups_out
 }
 
+#[no_mangle]
+unsafe extern "C" fn initgroups(user: *const c_char, group: gid_t) -> c_int {
+    //libc!(libc::initgroups(user, group));
+
+    let mut groups = Vec::new();
+    let mut ngroups: c_int = 32;
+    loop {
+        groups.resize(ngroups as usize, 0);
+        let capacity = ngroups;
+        if getgrouplist(user, group, groups.as_mut_ptr(), &mut ngroups) >= 0 {
+            break;
+        }
+        // `getgrouplist` only grows `ngroups` if it ran out of room.
+        if ngroups <= capacity {
+            set_errno(Errno(libc::ENOENT));
+            return -1;
+        }
+    }
+
+    libc::setgroups(ngroups as usize, groups.as_ptr())
+}
+
 #[no_mangle]
 unsafe extern "C" fn getservbyport_r(
     port: c_int,
@@ -12,6 +12,7 @@ macro_rules! checked_cast {
         let target_ptr = src_ptr.cast();
 
         #[allow(unnecessary_transmutes)]
+        #[allow(clippy::missing_transmute_annotations)]
         if false {
             let target = crate::use_libc::Pad::new(core::ptr::read(target_ptr));
 
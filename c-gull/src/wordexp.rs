@@ -0,0 +1,740 @@
+//! `wordexp` and `wordfree`.
+//!
+//! This does the shell's tilde, parameter, command, and arithmetic
+//! expansions, field splitting, pathname expansion, and quote removal.
+//! Command substitutions are run with `/bin/sh`.
+
+use crate::glob::home;
+use core::ffi::CStr;
+use core::mem::{size_of, zeroed};
+use core::ptr::{copy_nonoverlapping, null_mut};
+use libc::{c_char, c_int, size_t};
+use std::ffi::{CString, OsStr};
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process::{Command, Stdio};
+
+#[repr(C)]
+struct WordExp {
+    we_wordc: size_t,
+    we_wordv: *mut *mut c_char,
+    we_offs: size_t,
+}
+
+const WRDE_DOOFFS: c_int = 1 << 0;
+const WRDE_APPEND: c_int = 1 << 1;
+const WRDE_NOCMD: c_int = 1 << 2;
+const WRDE_REUSE: c_int = 1 << 3;
+const WRDE_SHOWERR: c_int = 1 << 4;
+const WRDE_UNDEF: c_int = 1 << 5;
+
+const WRDE_NOSPACE: c_int = 1;
+const WRDE_BADCHAR: c_int = 2;
+const WRDE_BADVAL: c_int = 3;
+const WRDE_CMDSUB: c_int = 4;
+const WRDE_SYNTAX: c_int = 5;
+
+#[no_mangle]
+unsafe extern "C" fn wordexp(words: *const c_char, pwordexp: *mut WordExp, flags: c_int) -> c_int {
+    //libc!(libc::wordexp(words, pwordexp, flags));
+
+    let we = &mut *pwordexp;
+    if flags & WRDE_REUSE == WRDE_REUSE {
+        wordfree(we);
+    }
+    if flags & WRDE_APPEND == 0 {
+        we.we_wordc = 0;
+        we.we_wordv = null_mut();
+        if flags & WRDE_DOOFFS == 0 {
+            we.we_offs = 0;
+        }
+    }
+
+    let ifs = match std::env::var_os("IFS") {
+        Some(ifs) => ifs.into_vec(),
+        None => b" \t\n".to_vec(),
+    };
+    let mut parser = Parser {
+        words: CStr::from_ptr(words).to_bytes(),
+        i: 0,
+        flags,
+        ifs,
+        split: true,
+        fields: Fields::default(),
+    };
+    if let Err(code) = parser.parse() {
+        return code;
+    }
+    parser.fields.finish();
+
+    let mut words = Vec::new();
+    for field in parser.fields.fields {
+        if field.glob {
+            words.extend(pathname_expansion(field));
+        } else {
+            words.push(field.text);
+        }
+    }
+
+    append(we, flags, &words)
+}
+
+#[no_mangle]
+unsafe extern "C" fn wordfree(pwordexp: *mut WordExp) {
+    //libc!(libc::wordfree(pwordexp));
+
+    let we = &mut *pwordexp;
+    if we.we_wordv.is_null() {
+        return;
+    }
+    for i in 0..we.we_wordc {
+        libc::free((*we.we_wordv.add(we.we_offs + i)).cast());
+    }
+    libc::free(we.we_wordv.cast());
+    we.we_wordv = null_mut();
+    we.we_wordc = 0;
+}
+
+/// Add `words` to the end of `we.we_wordv`, after `we_offs` null entries if
+/// `WRDE_DOOFFS` is set.
+unsafe fn append(we: &mut WordExp, flags: c_int, words: &[Vec<u8>]) -> c_int {
+    let offs = if flags & WRDE_DOOFFS == WRDE_DOOFFS {
+        we.we_offs
+    } else {
+        0
+    };
+    let old = if we.we_wordv.is_null() {
+        0
+    } else {
+        we.we_wordc
+    };
+    let len = offs + old + words.len() + 1;
+    let wordv =
+        libc::realloc(we.we_wordv.cast(), len * size_of::<*mut c_char>()).cast::<*mut c_char>();
+    if wordv.is_null() {
+        return WRDE_NOSPACE;
+    }
+    if we.we_wordv.is_null() {
+        for i in 0..offs {
+            wordv.add(i).write(null_mut());
+        }
+    }
+    we.we_wordv = wordv;
+
+    for (i, word) in words.iter().enumerate() {
+        let copy = libc::malloc(word.len() + 1).cast::<c_char>();
+        if copy.is_null() {
+            wordv.add(offs + old + i).write(null_mut());
+            we.we_wordc = old + i;
+            return WRDE_NOSPACE;
+        }
+        copy_nonoverlapping(word.as_ptr().cast(), copy, word.len());
+        copy.add(word.len()).write(0);
+        wordv.add(offs + old + i).write(copy);
+    }
+    wordv.add(offs + old + words.len()).write(null_mut());
+    we.we_wordc = old + words.len();
+    0
+}
+
+/// Expand the wildcards in `field`, or return its text if nothing matches.
+unsafe fn pathname_expansion(field: Field) -> Vec<Vec<u8>> {
+    let Ok(pattern) = CString::new(field.pattern) else {
+        return vec![field.text];
+    };
+    let mut glob: libc::glob_t = zeroed();
+    if libc::glob(pattern.as_ptr(), 0, None, &mut glob) != 0 {
+        return vec![field.text];
+    }
+    let paths = (0..glob.gl_pathc)
+        .map(|i| CStr::from_ptr(*glob.gl_pathv.add(i)).to_bytes().to_vec())
+        .collect();
+    libc::globfree(&mut glob);
+    paths
+}
+
+/// A field being built.
+#[derive(Default)]
+struct Field {
+    /// The field's text, after quote removal.
+    text: Vec<u8>,
+    /// The field as a `glob` pattern, with quoted wildcards escaped.
+    pattern: Vec<u8>,
+    /// Whether the field has unquoted wildcards.
+    glob: bool,
+    /// Whether the field exists, which it does once it has any text or
+    /// quotes.
+    started: bool,
+}
+
+#[derive(Default)]
+struct Fields {
+    fields: Vec<Field>,
+    current: Field,
+    /// Whether the last thing was IFS whitespace that ended a field, which a
+    /// following IFS non-whitespace delimiter is part of.
+    after_whitespace: bool,
+}
+
+impl Fields {
+    /// Add `bytes` to the current field. If they're quoted, wildcards in them
+    /// match literally.
+    fn push(&mut self, bytes: &[u8], quoted: bool) {
+        for &b in bytes {
+            self.current.text.push(b);
+            match b {
+                b'*' | b'?' | b'[' if !quoted => self.current.glob = true,
+                b'*' | b'?' | b'[' | b'\\' => self.current.pattern.push(b'\\'),
+                _ => {}
+            }
+            self.current.pattern.push(b);
+        }
+        self.current.started = true;
+        self.after_whitespace = false;
+    }
+
+    /// Add the result of an unquoted expansion, splitting it on the
+    /// characters in `ifs`.
+    fn push_split(&mut self, bytes: &[u8], ifs: &[u8]) {
+        for &b in bytes {
+            if !ifs.contains(&b) {
+                self.push(&[b], false);
+            } else if b == b' ' || b == b'\t' || b == b'\n' {
+                if self.current.started {
+                    self.finish();
+                    self.after_whitespace = true;
+                }
+            } else if self.after_whitespace {
+                self.after_whitespace = false;
+            } else {
+                self.current.started = true;
+                self.finish();
+            }
+        }
+    }
+
+    /// End the current field, if there is one.
+    fn finish(&mut self) {
+        let field = core::mem::take(&mut self.current);
+        if field.started {
+            self.fields.push(field);
+        }
+        self.after_whitespace = false;
+    }
+}
+
+struct Parser<'a> {
+    words: &'a [u8],
+    i: usize,
+    flags: c_int,
+    ifs: Vec<u8>,
+    /// Whether we're splitting fields, as opposed to expanding the word in
+    /// a `${name:-word}` or `$((expression))`.
+    split: bool,
+    fields: Fields,
+}
+
+impl Parser<'_> {
+    fn flag(&self, flag: c_int) -> bool {
+        self.flags & flag == flag
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.words.get(self.i).copied()
+    }
+
+    fn parse(&mut self) -> Result<(), c_int> {
+        while let Some(c) = self.peek() {
+            self.i += 1;
+            match c {
+                b' ' | b'\t' if self.split => self.fields.finish(),
+                b'\n' | b'|' | b'&' | b';' | b'<' | b'>' | b'(' | b')' | b'{' | b'}'
+                    if self.split =>
+                {
+                    return Err(WRDE_BADCHAR)
+                }
+                b'\\' => {
+                    let Some(c) = self.peek() else {
+                        return Err(WRDE_SYNTAX);
+                    };
+                    self.i += 1;
+                    self.fields.push(&[c], true);
+                }
+                b'\'' => {
+                    let len = self.words[self.i..]
+                        .iter()
+                        .position(|b| *b == b'\'')
+                        .ok_or(WRDE_SYNTAX)?;
+                    let quoted = &self.words[self.i..self.i + len];
+                    self.fields.push(quoted, true);
+                    self.i += len + 1;
+                }
+                b'"' => self.double_quoted()?,
+                b'$' => {
+                    let value = self.dollar()?;
+                    self.push_expansion(&value, false);
+                }
+                b'`' => {
+                    let value = self.backquote()?;
+                    self.push_expansion(&value, false);
+                }
+                b'~' if !self.fields.current.started => {
+                    let len = self.words[self.i..]
+                        .iter()
+                        .position(|b| matches!(b, b'/' | b' ' | b'\t' | b':'))
+                        .unwrap_or(self.words.len() - self.i);
+                    let user = &self.words[self.i..self.i + len];
+                    let literal = user
+                        .iter()
+                        .any(|b| matches!(b, b'\\' | b'\'' | b'"' | b'$'));
+                    match (literal, unsafe { home(user) }) {
+                        (false, Some(home)) => {
+                            self.fields.push(&home, true);
+                            self.i += len;
+                        }
+                        _ => self.fields.push(b"~", false),
+                    }
+                }
+                _ => self.fields.push(&[c], false),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the result of an expansion, which is split into fields unless it's
+    /// quoted.
+    fn push_expansion(&mut self, value: &[u8], quoted: bool) {
+        if quoted || !self.split {
+            self.fields.push(value, quoted);
+        } else {
+            self.fields.push_split(value, &self.ifs);
+        }
+    }
+
+    /// Parse the rest of a `"`-quoted string.
+    fn double_quoted(&mut self) -> Result<(), c_int> {
+        self.fields.push(b"", true);
+        loop {
+            let c = self.peek().ok_or(WRDE_SYNTAX)?;
+            self.i += 1;
+            match c {
+                b'"' => return Ok(()),
+                b'\\' => {
+                    let c = self.peek().ok_or(WRDE_SYNTAX)?;
+                    if matches!(c, b'$' | b'`' | b'"' | b'\\' | b'\n') {
+                        self.i += 1;
+                        if c != b'\n' {
+                            self.fields.push(&[c], true);
+                        }
+                    } else {
+                        self.fields.push(b"\\", true);
+                    }
+                }
+                b'$' => {
+                    let value = self.dollar()?;
+                    self.push_expansion(&value, true);
+                }
+                b'`' => {
+                    let value = self.backquote()?;
+                    self.push_expansion(&value, true);
+                }
+                _ => self.fields.push(&[c], true),
+            }
+        }
+    }
+
+    /// Expand the `$` expression after a `$`.
+    fn dollar(&mut self) -> Result<Vec<u8>, c_int> {
+        let rest = &self.words[self.i..];
+        if rest.starts_with(b"((") {
+            // `$((...))` needs the inner parenthesized expression to end
+            // right before the outer `)`. Otherwise, it's a subshell in a
+            // command substitution.
+            let close = 1 + matching(&rest[1..], b'(', b')').ok_or(WRDE_SYNTAX)?;
+            if rest.get(close + 1) != Some(&b')') {
+                return self.command_substitution();
+            }
+            let expression = &rest[2..close];
+            self.i += close + 2;
+            let expression = self.expand_word(expression)?;
+            let value = arithmetic(&expression).ok_or(WRDE_SYNTAX)?;
+            return Ok(value.to_string().into_bytes());
+        }
+        match rest.first() {
+            Some(b'(') => self.command_substitution(),
+            Some(b'{') => self.braced_parameter(),
+            Some(b'$') => {
+                self.i += 1;
+                Ok(std::process::id().to_string().into_bytes())
+            }
+            Some(c) if c.is_ascii_digit() => {
+                self.i += 1;
+                self.unset(&rest[..1])
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+                let len = name_len(rest);
+                self.i += len;
+                match getenv(&rest[..len]) {
+                    Some(value) => Ok(value),
+                    None => self.unset(&rest[..len]),
+                }
+            }
+            _ => Ok(b"$".to_vec()),
+        }
+    }
+
+    /// Return the value of the unset variable `name`, which is empty unless
+    /// `WRDE_UNDEF` makes it an error.
+    fn unset(&self, name: &[u8]) -> Result<Vec<u8>, c_int> {
+        if self.flag(WRDE_UNDEF) {
+            if self.flag(WRDE_SHOWERR) {
+                let mut message = name.to_vec();
+                message.extend_from_slice(b": parameter not set\n");
+                std::io::stderr().write_all(&message).ok();
+            }
+            return Err(WRDE_BADVAL);
+        }
+        Ok(Vec::new())
+    }
+
+    /// Expand a `${...}` parameter expansion.
+    fn braced_parameter(&mut self) -> Result<Vec<u8>, c_int> {
+        let rest = &self.words[self.i..];
+        let len = matching(rest, b'{', b'}').ok_or(WRDE_SYNTAX)?;
+        let inner = &rest[1..len];
+        self.i += len + 1;
+
+        if let Some(name) = inner.strip_prefix(b"#") {
+            if name.is_empty() || name_len(name) != name.len() {
+                return Err(WRDE_SYNTAX);
+            }
+            let value = match getenv(name) {
+                Some(value) => value,
+                None => self.unset(name)?,
+            };
+            return Ok(value.len().to_string().into_bytes());
+        }
+
+        let len = name_len(inner);
+        if len == 0 {
+            return Err(WRDE_SYNTAX);
+        }
+        let name = &inner[..len];
+        let operator = &inner[len..];
+        let value = getenv(name);
+        let (colon, operator) = match operator.strip_prefix(b":") {
+            Some(operator) => (true, operator),
+            None => (false, operator),
+        };
+        // With a `:`, an empty value counts as unset.
+        let set = value
+            .as_ref()
+            .is_some_and(|value| !colon || !value.is_empty());
+
+        let Some((&op, word)) = operator.split_first() else {
+            if colon {
+                return Err(WRDE_SYNTAX);
+            }
+            return match value {
+                Some(value) => Ok(value),
+                None => self.unset(name),
+            };
+        };
+        match op {
+            b'-' if set => Ok(value.unwrap()),
+            b'-' => self.expand_word(word),
+            b'=' if set => Ok(value.unwrap()),
+            b'=' => {
+                let word = self.expand_word(word)?;
+                std::env::set_var(OsStr::from_bytes(name), OsStr::from_bytes(&word));
+                Ok(word)
+            }
+            b'?' if set => Ok(value.unwrap()),
+            b'?' => {
+                // As in glibc, this reports the error without failing, and
+                // the expansion is empty.
+                let mut message = name.to_vec();
+                message.extend_from_slice(b": ");
+                match self.expand_word(word)? {
+                    word if word.is_empty() => {
+                        message.extend_from_slice(b"parameter null or not set")
+                    }
+                    word => message.extend_from_slice(&word),
+                }
+                message.push(b'\n');
+                std::io::stderr().write_all(&message).ok();
+                Ok(Vec::new())
+            }
+            b'+' if set => self.expand_word(word),
+            b'+' => Ok(Vec::new()),
+            b'#' | b'%' if !colon => {
+                let value = match value {
+                    Some(value) => value,
+                    None => self.unset(name)?,
+                };
+                let (longest, word) = match word.strip_prefix(&[op]) {
+                    Some(word) => (true, word),
+                    None => (false, word),
+                };
+                let pattern = self.expand_word(word)?;
+                Ok(remove(&value, &pattern, op == b'#', longest).to_vec())
+            }
+            _ => Err(WRDE_SYNTAX),
+        }
+    }
+
+    /// Expand `word` without splitting it into fields.
+    fn expand_word(&self, word: &[u8]) -> Result<Vec<u8>, c_int> {
+        let mut parser = Parser {
+            words: word,
+            i: 0,
+            flags: self.flags,
+            ifs: Vec::new(),
+            split: false,
+            fields: Fields::default(),
+        };
+        parser.parse()?;
+        Ok(parser.fields.current.text)
+    }
+
+    /// Run the command in a `$(...)`.
+    fn command_substitution(&mut self) -> Result<Vec<u8>, c_int> {
+        let rest = &self.words[self.i..];
+        let len = matching(rest, b'(', b')').ok_or(WRDE_SYNTAX)?;
+        let command = &rest[1..len];
+        self.i += len + 1;
+        self.run(command)
+    }
+
+    /// Run the command in a `` `...` ``.
+    fn backquote(&mut self) -> Result<Vec<u8>, c_int> {
+        let mut command = Vec::new();
+        loop {
+            let c = self.peek().ok_or(WRDE_SYNTAX)?;
+            self.i += 1;
+            match c {
+                b'`' => break,
+                b'\\' if matches!(self.peek(), Some(b'$' | b'`' | b'\\')) => {
+                    command.push(self.peek().unwrap());
+                    self.i += 1;
+                }
+                _ => command.push(c),
+            }
+        }
+        self.run(&command)
+    }
+
+    /// Run `command` with `/bin/sh`, and return its output without trailing
+    /// newlines.
+    fn run(&self, command: &[u8]) -> Result<Vec<u8>, c_int> {
+        if self.flag(WRDE_NOCMD) {
+            return Err(WRDE_CMDSUB);
+        }
+        let mut sh = Command::new("/bin/sh");
+        sh.arg("-c").arg(OsStr::from_bytes(command));
+        sh.stdin(Stdio::null());
+        if !self.flag(WRDE_SHOWERR) {
+            sh.stderr(Stdio::null());
+        }
+        let mut output = match sh.output() {
+            Ok(output) => output.stdout,
+            Err(_) => return Err(WRDE_NOSPACE),
+        };
+        while output.last() == Some(&b'\n') {
+            output.pop();
+        }
+        Ok(output)
+    }
+}
+
+/// Return the length of the name at the start of `bytes`.
+fn name_len(bytes: &[u8]) -> usize {
+    if !bytes
+        .first()
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_')
+    {
+        return 0;
+    }
+    bytes
+        .iter()
+        .position(|c| !c.is_ascii_alphanumeric() && *c != b'_')
+        .unwrap_or(bytes.len())
+}
+
+fn getenv(name: &[u8]) -> Option<Vec<u8>> {
+    std::env::var_os(OsStr::from_bytes(name)).map(|value| value.into_vec())
+}
+
+/// Return the index of the `close` that matches the `open` at the start of
+/// `bytes`, skipping over quoted text.
+fn matching(bytes: &[u8], open: u8, close: u8) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' => i += 1 + bytes[i + 1..].iter().position(|b| *b == b'\'')?,
+            b'"' => {
+                i += 1;
+                while *bytes.get(i)? != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Remove the shortest or longest prefix or suffix of `value` matching
+/// `pattern`, for `${name#pattern}` and friends.
+fn remove<'a>(value: &'a [u8], pattern: &[u8], prefix: bool, longest: bool) -> &'a [u8] {
+    let Ok(pattern) = CString::new(pattern) else {
+        return value;
+    };
+    let matches = |part: &[u8]| {
+        let Ok(part) = CString::new(part) else {
+            return false;
+        };
+        unsafe { libc::fnmatch(pattern.as_ptr(), part.as_ptr(), 0) == 0 }
+    };
+    let lens: Vec<usize> = if longest {
+        (0..=value.len()).rev().collect()
+    } else {
+        (0..=value.len()).collect()
+    };
+    for len in lens {
+        if prefix && matches(&value[..len]) {
+            return &value[len..];
+        }
+        if !prefix && matches(&value[value.len() - len..]) {
+            return &value[..value.len() - len];
+        }
+    }
+    value
+}
+
+/// Evaluate the `$((...))` arithmetic `expression`, after parameter
+/// expansion.
+fn arithmetic(expression: &[u8]) -> Option<i64> {
+    let mut arithmetic = Arithmetic {
+        bytes: expression,
+        i: 0,
+    };
+    let value = arithmetic.sum()?;
+    arithmetic.skip_spaces();
+    if arithmetic.i != expression.len() {
+        return None;
+    }
+    Some(value)
+}
+
+/// A recursive-descent parser for `+`, `-`, `*`, `/`, `%`, unary `+` and
+/// `-`, and parentheses.
+struct Arithmetic<'a> {
+    bytes: &'a [u8],
+    i: usize,
+}
+
+impl Arithmetic<'_> {
+    fn skip_spaces(&mut self) {
+        while self
+            .bytes
+            .get(self.i)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.i += 1;
+        }
+    }
+
+    fn next_op(&mut self, ops: &[u8]) -> Option<u8> {
+        self.skip_spaces();
+        let op = *self.bytes.get(self.i)?;
+        if ops.contains(&op) {
+            self.i += 1;
+            Some(op)
+        } else {
+            None
+        }
+    }
+
+    fn sum(&mut self) -> Option<i64> {
+        let mut value = self.product()?;
+        while let Some(op) = self.next_op(b"+-") {
+            let rhs = self.product()?;
+            value = match op {
+                b'+' => value.wrapping_add(rhs),
+                _ => value.wrapping_sub(rhs),
+            };
+        }
+        Some(value)
+    }
+
+    fn product(&mut self) -> Option<i64> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.next_op(b"*/%") {
+            let rhs = self.unary()?;
+            value = match op {
+                b'*' => value.wrapping_mul(rhs),
+                b'/' => value.checked_div(rhs)?,
+                _ => value.checked_rem(rhs)?,
+            };
+        }
+        Some(value)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        match self.next_op(b"+-(") {
+            Some(b'+') => self.unary(),
+            Some(b'-') => self.unary().map(i64::wrapping_neg),
+            Some(_) => {
+                let value = self.sum()?;
+                self.next_op(b")")?;
+                Some(value)
+            }
+            None => self.number(),
+        }
+    }
+
+    /// Parse a decimal, octal, or hexadecimal number, or a variable name,
+    /// whose value is a number, or 0 if it's unset.
+    fn number(&mut self) -> Option<i64> {
+        let rest = &self.bytes[self.i..];
+        let len = name_len(rest);
+        if len != 0 {
+            self.i += len;
+            let Some(value) = getenv(&rest[..len]) else {
+                return Some(0);
+            };
+            return arithmetic(&value);
+        }
+
+        let len = rest
+            .iter()
+            .position(|b| !b.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let digits = core::str::from_utf8(&rest[..len]).ok()?;
+        self.i += len;
+        if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            i64::from_str_radix(hex, 16).ok()
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            i64::from_str_radix(&digits[1..], 8).ok()
+        } else {
+            digits.parse().ok()
+        }
+    }
+}
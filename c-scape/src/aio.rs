@@ -0,0 +1,673 @@
+//! POSIX asynchronous I/O.
+//!
+//! Requests are performed by a pool of worker threads, created with
+//! `pthread_create`. Requests on the same file descriptor are performed one
+//! at a time, in the order they were submitted, so that an `aio_fsync`, for
+//! example, covers all the writes submitted before it.
+
+use crate::process::kill::queue_signal;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::mem::{zeroed, MaybeUninit};
+use core::num::NonZeroU32;
+use core::ptr::{addr_of, null, null_mut};
+use core::slice;
+use core::sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering};
+use core::time::Duration;
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int, c_void, off_t, sigval, ssize_t};
+use rustix::fd::BorrowedFd;
+use rustix::thread::futex;
+use rustix::time::{ClockId, Nsecs, Timespec};
+use rustix_futex_sync::{Condvar, Mutex};
+
+extern "C" {
+    // This isn't in the `libc` crate.
+    fn pthread_attr_getdetachstate(attr: *const libc::pthread_attr_t, state: *mut c_int) -> c_int;
+}
+
+/// The most worker threads we run at once.
+const MAX_THREADS: usize = 20;
+
+/// How long an idle worker thread waits for more work before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The largest `aio_reqprio` we accept. We don't otherwise use it.
+const AIO_PRIO_DELTA_MAX: c_int = 20;
+
+/// glibc's `struct aiocb`, including the private fields which hold a
+/// request's status.
+#[repr(C)]
+struct Aiocb {
+    aio_fildes: c_int,
+    aio_lio_opcode: c_int,
+    aio_reqprio: c_int,
+    aio_buf: *mut c_void,
+    aio_nbytes: usize,
+    aio_sigevent: libc::sigevent,
+    __next_prio: *mut Aiocb,
+    __abs_prio: c_int,
+    __policy: c_int,
+    __error_code: AtomicI32,
+    __return_value: ssize_t,
+    aio_offset: off_t,
+    #[cfg(all(not(target_arch = "x86_64"), target_pointer_width = "32"))]
+    __unused1: [c_char; 4],
+    __glibc_reserved: [c_char; 32],
+}
+
+/// The layout of `struct sigevent` when `sigev_notify` is `SIGEV_THREAD`.
+#[repr(C)]
+struct SigeventThread {
+    sigev_value: sigval,
+    sigev_signo: c_int,
+    sigev_notify: c_int,
+    sigev_notify_function: Option<unsafe extern "C" fn(sigval)>,
+    sigev_notify_attributes: *mut libc::pthread_attr_t,
+}
+
+/// How to notify the application that a request, or a `lio_listio` list of
+/// requests, has completed.
+#[derive(Clone, Copy)]
+enum Notify {
+    None,
+    Signal(c_int, sigval),
+    Thread(
+        unsafe extern "C" fn(sigval),
+        sigval,
+        *mut libc::pthread_attr_t,
+    ),
+}
+
+// SAFETY: The pointers in a `Notify` are only passed back to the
+// application, from whichever thread completes the request.
+unsafe impl Send for Notify {}
+unsafe impl Sync for Notify {}
+
+impl Notify {
+    unsafe fn new(sigevent: *const libc::sigevent) -> Result<Self, c_int> {
+        let sigevent = &*sigevent.cast::<SigeventThread>();
+        match sigevent.sigev_notify {
+            libc::SIGEV_NONE => Ok(Self::None),
+            libc::SIGEV_SIGNAL => Ok(Self::Signal(sigevent.sigev_signo, sigevent.sigev_value)),
+            libc::SIGEV_THREAD => match sigevent.sigev_notify_function {
+                Some(function) => Ok(Self::Thread(
+                    function,
+                    sigevent.sigev_value,
+                    sigevent.sigev_notify_attributes,
+                )),
+                None => Err(libc::EINVAL),
+            },
+            _ => Err(libc::EINVAL),
+        }
+    }
+
+    unsafe fn send(self) {
+        match self {
+            Self::None => {}
+            Self::Signal(signo, value) => {
+                let pid = rustix::process::getpid().as_raw_nonzero().get();
+                queue_signal(pid, signo, libc::SI_ASYNCIO, value);
+            }
+            Self::Thread(function, value, attr) => {
+                extern "C" fn start(arg: *mut c_void) -> *mut c_void {
+                    unsafe {
+                        let (function, value) =
+                            *Box::from_raw(arg.cast::<(unsafe extern "C" fn(sigval), sigval)>());
+
+                        // We're started from a worker thread, which has all
+                        // signals blocked; the application's function runs
+                        // with none blocked, as in glibc.
+                        let mut set = zeroed();
+                        libc::sigemptyset(&mut set);
+                        libc::pthread_sigmask(libc::SIG_SETMASK, &set, null_mut());
+
+                        function(value);
+                    }
+                    null_mut()
+                }
+
+                let arg = Box::into_raw(Box::new((function, value)));
+                let mut thread = zeroed();
+                if libc::pthread_create(&mut thread, attr, start, arg.cast()) != 0 {
+                    drop(Box::from_raw(arg));
+                    return;
+                }
+
+                // Nothing else knows the thread's id, so detach it unless
+                // the application's attributes already did.
+                let mut detach_state = libc::PTHREAD_CREATE_JOINABLE;
+                if !attr.is_null() {
+                    pthread_attr_getdetachstate(attr, &mut detach_state);
+                }
+                if detach_state == libc::PTHREAD_CREATE_JOINABLE {
+                    libc::pthread_detach(thread);
+                }
+            }
+        }
+    }
+}
+
+/// A `lio_listio` call's requests, for sending its notification once they've
+/// all completed.
+struct List {
+    remaining: AtomicUsize,
+    notify: Notify,
+}
+
+impl List {
+    unsafe fn release(&self) {
+        if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.notify.send();
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Read,
+    Write,
+    Fsync,
+    Fdatasync,
+}
+
+impl Op {
+    unsafe fn perform(self, aiocb: *mut Aiocb) -> Result<usize, c_int> {
+        let fd = (*aiocb).aio_fildes;
+        if fd == -1 {
+            return Err(libc::EBADF);
+        }
+        let fd = BorrowedFd::borrow_raw(fd);
+        let buf = (*aiocb).aio_buf;
+        let len = (*aiocb).aio_nbytes;
+        let offset = (*aiocb).aio_offset as u64;
+
+        // Descriptors which can't seek, such as pipes and sockets, are read
+        // and written at their current position.
+        let result = match self {
+            Self::Read => {
+                let buf = slice::from_raw_parts_mut(buf.cast::<MaybeUninit<u8>>(), len);
+                match rustix::io::pread(fd, &mut *buf, offset).map(|(init, _)| init.len()) {
+                    Err(rustix::io::Errno::SPIPE) => {
+                        rustix::io::read(fd, buf).map(|(init, _)| init.len())
+                    }
+                    result => result,
+                }
+            }
+            Self::Write => {
+                let buf = slice::from_raw_parts(buf.cast::<u8>(), len);
+                match rustix::io::pwrite(fd, buf, offset) {
+                    Err(rustix::io::Errno::SPIPE) => rustix::io::write(fd, buf),
+                    result => result,
+                }
+            }
+            Self::Fsync => rustix::fs::fsync(fd).map(|()| 0),
+            Self::Fdatasync => rustix::fs::fdatasync(fd).map(|()| 0),
+        };
+        result.map_err(|err| err.raw_os_error())
+    }
+}
+
+struct Request {
+    aiocb: *mut Aiocb,
+    op: Op,
+    notify: Notify,
+    list: Option<Arc<List>>,
+}
+
+// SAFETY: The application keeps the `aiocb` alive and untouched until the
+// request completes.
+unsafe impl Send for Request {}
+
+impl Request {
+    /// Wake any threads waiting for a completion, and send the request's
+    /// notifications. The `aiocb` may no longer be used.
+    unsafe fn notify(self) {
+        GENERATION.fetch_add(1, Ordering::Release);
+        futex::wake(&GENERATION, futex::Flags::PRIVATE, i32::MAX as u32).ok();
+
+        self.notify.send();
+        if let Some(list) = self.list {
+            list.release();
+        }
+    }
+}
+
+unsafe fn set_status(aiocb: *mut Aiocb, result: Result<usize, c_int>) {
+    let (value, error) = match result {
+        Ok(n) => (n as ssize_t, 0),
+        Err(err) => (-1, err),
+    };
+    (*aiocb).__return_value = value;
+    (*aiocb).__error_code.store(error, Ordering::Release);
+}
+
+/// The requests for one file descriptor.
+struct Queue {
+    /// The request a worker is currently performing, if any.
+    running: *mut Aiocb,
+    pending: VecDeque<Request>,
+}
+
+// SAFETY: See `Request`.
+unsafe impl Send for Queue {}
+
+struct State {
+    queues: BTreeMap<c_int, Queue>,
+    /// File descriptors with pending requests and no running request, in
+    /// the order they became ready.
+    ready: VecDeque<c_int>,
+    threads: usize,
+    idle: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    queues: BTreeMap::new(),
+    ready: VecDeque::new(),
+    threads: 0,
+    idle: 0,
+});
+
+/// Signaled when a file descriptor becomes ready.
+static WORK: Condvar = Condvar::new();
+
+/// Incremented, and woken, whenever a request completes.
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn worker(_arg: *mut c_void) -> *mut c_void {
+    let mut state = STATE.lock();
+    loop {
+        let Some(fd) = state.ready.pop_front() else {
+            state.idle += 1;
+            let (guard, result) = WORK.wait_timeout(state, IDLE_TIMEOUT);
+            state = guard;
+            state.idle -= 1;
+            if result.timed_out() && state.ready.is_empty() {
+                state.threads -= 1;
+                return null_mut();
+            }
+            continue;
+        };
+
+        let queue = state.queues.get_mut(&fd).unwrap();
+        let request = queue.pending.pop_front().unwrap();
+        queue.running = request.aiocb;
+        drop(state);
+
+        let result = unsafe { request.op.perform(request.aiocb) };
+
+        // Record the result with the state locked, so that `aio_cancel`
+        // never sees a request which is neither queued nor complete.
+        state = STATE.lock();
+        unsafe { set_status(request.aiocb, result) };
+        let queue = state.queues.get_mut(&fd).unwrap();
+        queue.running = null_mut();
+        if queue.pending.is_empty() {
+            state.queues.remove(&fd);
+        } else {
+            state.ready.push_back(fd);
+        }
+        drop(state);
+
+        unsafe { request.notify() };
+        state = STATE.lock();
+    }
+}
+
+/// Start a worker thread. It blocks all signals, so that they're delivered
+/// to the application's threads instead.
+unsafe fn spawn_worker() -> bool {
+    let mut all = zeroed();
+    let mut old = zeroed();
+    libc::sigfillset(&mut all);
+    libc::pthread_sigmask(libc::SIG_SETMASK, &all, &mut old);
+
+    let mut thread = zeroed();
+    let ok = libc::pthread_create(&mut thread, null(), worker, null_mut()) == 0;
+    if ok {
+        libc::pthread_detach(thread);
+    }
+
+    libc::pthread_sigmask(libc::SIG_SETMASK, &old, null_mut());
+    ok
+}
+
+/// Queue a request, and start a worker for it if needed. On failure, the
+/// error is recorded in the `aiocb` too.
+unsafe fn enqueue(aiocb: *mut Aiocb, op: Op, list: Option<Arc<List>>) -> Result<(), c_int> {
+    let result = try_enqueue(aiocb, op, list);
+    if let Err(err) = result {
+        set_status(aiocb, Err(err));
+    }
+    result
+}
+
+unsafe fn try_enqueue(aiocb: *mut Aiocb, op: Op, list: Option<Arc<List>>) -> Result<(), c_int> {
+    if !(0..=AIO_PRIO_DELTA_MAX).contains(&(*aiocb).aio_reqprio) {
+        return Err(libc::EINVAL);
+    }
+    let notify = Notify::new(addr_of!((*aiocb).aio_sigevent))?;
+
+    (*aiocb).__return_value = 0;
+    (*aiocb)
+        .__error_code
+        .store(libc::EINPROGRESS, Ordering::Relaxed);
+
+    let fd = (*aiocb).aio_fildes;
+    let mut state = STATE.lock();
+    let queue = state.queues.entry(fd).or_insert_with(|| Queue {
+        running: null_mut(),
+        pending: VecDeque::new(),
+    });
+    let ready = queue.running.is_null() && queue.pending.is_empty();
+    queue.pending.push_back(Request {
+        aiocb,
+        op,
+        notify,
+        list,
+    });
+    if !ready {
+        // The worker performing the current request will pick this up.
+        return Ok(());
+    }
+    state.ready.push_back(fd);
+
+    if state.ready.len() > state.idle && state.threads < MAX_THREADS {
+        if spawn_worker() {
+            state.threads += 1;
+        } else if state.threads == 0 {
+            // Nothing would ever perform the request, so withdraw it.
+            state.ready.pop_back();
+            state.queues.remove(&fd);
+            return Err(libc::EAGAIN);
+        }
+    }
+    WORK.notify_one();
+    Ok(())
+}
+
+/// Wait for a request to complete, if none has since `GENERATION` was
+/// `generation`.
+fn wait(generation: u32, deadline: Option<&Timespec>) -> Result<(), c_int> {
+    let result = match deadline {
+        Some(deadline) => futex::wait_bitset(
+            &GENERATION,
+            futex::Flags::PRIVATE,
+            generation,
+            Some(deadline),
+            NonZeroU32::MAX,
+        ),
+        None => futex::wait(&GENERATION, futex::Flags::PRIVATE, generation, None),
+    };
+    match result {
+        Err(rustix::io::Errno::TIMEDOUT) => Err(libc::EAGAIN),
+        Err(rustix::io::Errno::INTR) => Err(libc::EINTR),
+        _ => Ok(()),
+    }
+}
+
+unsafe fn in_progress(aiocb: *const Aiocb) -> bool {
+    (*aiocb).__error_code.load(Ordering::Acquire) == libc::EINPROGRESS
+}
+
+unsafe fn check_fd(fd: c_int) -> Result<(), c_int> {
+    if libc::fcntl(fd, libc::F_GETFL) == -1 {
+        return Err(libc::EBADF);
+    }
+    Ok(())
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_read(aiocbp: *mut libc::aiocb) -> c_int {
+    libc!(libc::aio_read(aiocbp));
+
+    match enqueue(aiocbp.cast(), Op::Read, None) {
+        Ok(()) => 0,
+        Err(err) => {
+            set_errno(Errno(err));
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_write(aiocbp: *mut libc::aiocb) -> c_int {
+    libc!(libc::aio_write(aiocbp));
+
+    match enqueue(aiocbp.cast(), Op::Write, None) {
+        Ok(()) => 0,
+        Err(err) => {
+            set_errno(Errno(err));
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_fsync(op: c_int, aiocbp: *mut libc::aiocb) -> c_int {
+    libc!(libc::aio_fsync(op, aiocbp));
+
+    let aiocb = aiocbp.cast::<Aiocb>();
+    let op = match op {
+        libc::O_SYNC => Op::Fsync,
+        libc::O_DSYNC => Op::Fdatasync,
+        _ => {
+            set_errno(Errno(libc::EINVAL));
+            return -1;
+        }
+    };
+
+    match check_fd((*aiocb).aio_fildes).and_then(|()| enqueue(aiocb, op, None)) {
+        Ok(()) => 0,
+        Err(err) => {
+            set_errno(Errno(err));
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_error(aiocbp: *const libc::aiocb) -> c_int {
+    libc!(libc::aio_error(aiocbp));
+
+    let aiocb = aiocbp.cast::<Aiocb>();
+    (*aiocb).__error_code.load(Ordering::Acquire)
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_return(aiocbp: *mut libc::aiocb) -> ssize_t {
+    libc!(libc::aio_return(aiocbp));
+
+    let aiocb = aiocbp.cast::<Aiocb>();
+    (*aiocb).__return_value
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_suspend(
+    aiocb_list: *const *const libc::aiocb,
+    nitems: c_int,
+    timeout: *const libc::timespec,
+) -> c_int {
+    libc!(libc::aio_suspend(aiocb_list, nitems, timeout));
+
+    let Ok(nitems) = usize::try_from(nitems) else {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    };
+    let list = slice::from_raw_parts(aiocb_list.cast::<*const Aiocb>(), nitems);
+
+    // The timeout is relative; convert it to an absolute monotonic time so
+    // that it isn't extended by spurious wakeups.
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let timeout = &*timeout;
+        if timeout.tv_sec < 0 || !(0..1_000_000_000).contains(&timeout.tv_nsec) {
+            set_errno(Errno(libc::EINVAL));
+            return -1;
+        }
+        let now = rustix::time::clock_gettime(ClockId::Monotonic);
+        let mut deadline = Timespec {
+            tv_sec: now.tv_sec.saturating_add(timeout.tv_sec as _),
+            tv_nsec: now.tv_nsec + timeout.tv_nsec as Nsecs,
+        };
+        if deadline.tv_nsec >= 1_000_000_000 {
+            deadline.tv_sec = deadline.tv_sec.saturating_add(1);
+            deadline.tv_nsec -= 1_000_000_000;
+        }
+        Some(deadline)
+    };
+
+    loop {
+        let generation = GENERATION.load(Ordering::Acquire);
+        if list
+            .iter()
+            .any(|aiocb| !aiocb.is_null() && !in_progress(*aiocb))
+        {
+            return 0;
+        }
+        if let Err(err) = wait(generation, deadline.as_ref()) {
+            set_errno(Errno(err));
+            return -1;
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn aio_cancel(fd: c_int, aiocbp: *mut libc::aiocb) -> c_int {
+    libc!(libc::aio_cancel(fd, aiocbp));
+
+    let aiocb = aiocbp.cast::<Aiocb>();
+    if let Err(err) = check_fd(fd) {
+        set_errno(Errno(err));
+        return -1;
+    }
+    if !aiocb.is_null() && (*aiocb).aio_fildes != fd {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+
+    let mut state = STATE.lock();
+    let Some(queue) = state.queues.get_mut(&fd) else {
+        return libc::AIO_ALLDONE;
+    };
+
+    // A request that a worker has started can't be canceled.
+    let canceled: VecDeque<Request> = if aiocb.is_null() {
+        queue.pending.drain(..).collect()
+    } else {
+        match queue
+            .pending
+            .iter()
+            .position(|request| request.aiocb == aiocb)
+        {
+            Some(index) => queue.pending.remove(index).into_iter().collect(),
+            None => VecDeque::new(),
+        }
+    };
+    let running = !queue.running.is_null() && (aiocb.is_null() || queue.running == aiocb);
+    if queue.pending.is_empty() {
+        if queue.running.is_null() {
+            state.queues.remove(&fd);
+        }
+        state.ready.retain(|ready| *ready != fd);
+    }
+
+    for request in &canceled {
+        set_status(request.aiocb, Err(libc::ECANCELED));
+    }
+    drop(state);
+
+    let result = if running {
+        libc::AIO_NOTCANCELED
+    } else if !canceled.is_empty() {
+        libc::AIO_CANCELED
+    } else {
+        libc::AIO_ALLDONE
+    };
+    for request in canceled {
+        request.notify();
+    }
+    result
+}
+
+#[no_mangle]
+unsafe extern "C" fn lio_listio(
+    mode: c_int,
+    aiocb_list: *const *mut libc::aiocb,
+    nitems: c_int,
+    sevp: *mut libc::sigevent,
+) -> c_int {
+    libc!(libc::lio_listio(mode, aiocb_list, nitems, sevp));
+
+    if mode != libc::LIO_WAIT && mode != libc::LIO_NOWAIT {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+    let Ok(nitems) = usize::try_from(nitems) else {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    };
+    let notify = if mode == libc::LIO_NOWAIT && !sevp.is_null() {
+        match Notify::new(sevp) {
+            Ok(notify) => notify,
+            Err(err) => {
+                set_errno(Errno(err));
+                return -1;
+            }
+        }
+    } else {
+        Notify::None
+    };
+    let aiocbs = slice::from_raw_parts(aiocb_list.cast::<*mut Aiocb>(), nitems);
+
+    // Hold a reference to the list while submitting, so that its
+    // notification isn't sent until every request is submitted. If no
+    // requests are submitted, it's sent immediately.
+    let list = Arc::new(List {
+        remaining: AtomicUsize::new(1),
+        notify,
+    });
+    let mut failed = false;
+    for aiocb in aiocbs.iter().copied().filter(|aiocb| !aiocb.is_null()) {
+        let op = match (*aiocb).aio_lio_opcode {
+            libc::LIO_READ => Op::Read,
+            libc::LIO_WRITE => Op::Write,
+            libc::LIO_NOP => continue,
+            _ => {
+                set_status(aiocb, Err(libc::EINVAL));
+                failed = true;
+                continue;
+            }
+        };
+        list.remaining.fetch_add(1, Ordering::Relaxed);
+        if enqueue(aiocb, op, Some(list.clone())).is_err() {
+            list.remaining.fetch_sub(1, Ordering::Relaxed);
+            failed = true;
+        }
+    }
+    list.release();
+
+    if mode == libc::LIO_WAIT {
+        for aiocb in aiocbs.iter().copied().filter(|aiocb| !aiocb.is_null()) {
+            if (*aiocb).aio_lio_opcode == libc::LIO_NOP {
+                continue;
+            }
+            loop {
+                let generation = GENERATION.load(Ordering::Acquire);
+                if !in_progress(aiocb) {
+                    break;
+                }
+                wait(generation, None).ok();
+            }
+            failed |= (*aiocb).__error_code.load(Ordering::Relaxed) != 0;
+        }
+    }
+
+    if failed {
+        set_errno(Errno(libc::EIO));
+        return -1;
+    }
+    0
+}
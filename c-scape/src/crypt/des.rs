@@ -0,0 +1,181 @@
+//! Traditional DES-based `crypt`, for verifying legacy hashes.
+//!
+//! Bits are numbered from one at the most significant end, as in FIPS 46.
+//! This is a straightforward bit-at-a-time implementation, which is slow,
+//! but this hash is only here for compatibility.
+
+use super::ITOA64;
+use alloc::vec::Vec;
+
+const IP: [u8; 64] = [
+    58, 50, 42, 34, 26, 18, 10, 2, 60, 52, 44, 36, 28, 20, 12, 4, 62, 54, 46, 38, 30, 22, 14, 6,
+    64, 56, 48, 40, 32, 24, 16, 8, 57, 49, 41, 33, 25, 17, 9, 1, 59, 51, 43, 35, 27, 19, 11, 3, 61,
+    53, 45, 37, 29, 21, 13, 5, 63, 55, 47, 39, 31, 23, 15, 7,
+];
+
+const FP: [u8; 64] = [
+    40, 8, 48, 16, 56, 24, 64, 32, 39, 7, 47, 15, 55, 23, 63, 31, 38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29, 36, 4, 44, 12, 52, 20, 60, 28, 35, 3, 43, 11, 51, 19, 59, 27,
+    34, 2, 42, 10, 50, 18, 58, 26, 33, 1, 41, 9, 49, 17, 57, 25,
+];
+
+const E: [u8; 48] = [
+    32, 1, 2, 3, 4, 5, 4, 5, 6, 7, 8, 9, 8, 9, 10, 11, 12, 13, 12, 13, 14, 15, 16, 17, 16, 17, 18,
+    19, 20, 21, 20, 21, 22, 23, 24, 25, 24, 25, 26, 27, 28, 29, 28, 29, 30, 31, 32, 1,
+];
+
+const P: [u8; 32] = [
+    16, 7, 20, 21, 29, 12, 28, 17, 1, 15, 23, 26, 5, 18, 31, 10, 2, 8, 24, 14, 32, 27, 3, 9, 19,
+    13, 30, 6, 22, 11, 4, 25,
+];
+
+const PC1: [u8; 56] = [
+    57, 49, 41, 33, 25, 17, 9, 1, 58, 50, 42, 34, 26, 18, 10, 2, 59, 51, 43, 35, 27, 19, 11, 3, 60,
+    52, 44, 36, 63, 55, 47, 39, 31, 23, 15, 7, 62, 54, 46, 38, 30, 22, 14, 6, 61, 53, 45, 37, 29,
+    21, 13, 5, 28, 20, 12, 4,
+];
+
+const PC2: [u8; 48] = [
+    14, 17, 11, 24, 1, 5, 3, 28, 15, 6, 21, 10, 23, 19, 12, 4, 26, 8, 16, 7, 27, 20, 13, 2, 41, 52,
+    31, 37, 47, 55, 30, 40, 51, 45, 33, 48, 44, 49, 39, 56, 34, 53, 46, 42, 50, 36, 29, 32,
+];
+
+const SHIFTS: [u32; 16] = [1, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1];
+
+const S: [[u8; 64]; 8] = [
+    [
+        14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7, 0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12,
+        11, 9, 5, 3, 8, 4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0, 15, 12, 8, 2, 4, 9,
+        1, 7, 5, 11, 3, 14, 10, 0, 6, 13,
+    ],
+    [
+        15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10, 3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1,
+        10, 6, 9, 11, 5, 0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15, 13, 8, 10, 1, 3, 15,
+        4, 2, 11, 6, 7, 12, 0, 5, 14, 9,
+    ],
+    [
+        10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8, 13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5,
+        14, 12, 11, 15, 1, 13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7, 1, 10, 13, 0, 6,
+        9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12,
+    ],
+    [
+        7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15, 13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2,
+        12, 1, 10, 14, 9, 10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4, 3, 15, 0, 6, 10, 1,
+        13, 8, 9, 4, 5, 11, 12, 7, 2, 14,
+    ],
+    [
+        2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9, 14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15,
+        10, 3, 9, 8, 6, 4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14, 11, 8, 12, 7, 1, 14,
+        2, 13, 6, 15, 0, 9, 10, 4, 5, 3,
+    ],
+    [
+        12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11, 10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13,
+        14, 0, 11, 3, 8, 9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6, 4, 3, 2, 12, 9, 5,
+        15, 10, 11, 14, 1, 7, 6, 0, 8, 13,
+    ],
+    [
+        4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1, 13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5,
+        12, 2, 15, 8, 6, 1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2, 6, 11, 13, 8, 1, 4,
+        10, 7, 9, 5, 0, 15, 14, 2, 3, 12,
+    ],
+    [
+        13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7, 1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6,
+        11, 0, 14, 9, 2, 7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8, 2, 1, 14, 7, 4, 10,
+        8, 13, 15, 12, 9, 0, 3, 5, 6, 11,
+    ],
+];
+
+/// Select bits of the `width`-bit `input` according to `table`.
+fn permute(input: u64, width: u32, table: &[u8]) -> u64 {
+    table.iter().fold(0, |out, bit| {
+        (out << 1) | ((input >> (width - u32::from(*bit))) & 1)
+    })
+}
+
+/// Compute the 16 round subkeys for `key`.
+fn key_schedule(key: u64) -> [u64; 16] {
+    let pc1 = permute(key, 64, &PC1);
+    let mut c = (pc1 >> 28) as u32;
+    let mut d = pc1 as u32 & 0x0fff_ffff;
+    let mut subkeys = [0; 16];
+    for (subkey, shift) in subkeys.iter_mut().zip(SHIFTS) {
+        c = ((c << shift) | (c >> (28 - shift))) & 0x0fff_ffff;
+        d = ((d << shift) | (d >> (28 - shift))) & 0x0fff_ffff;
+        *subkey = permute((u64::from(c) << 28) | u64::from(d), 56, &PC2);
+    }
+    subkeys
+}
+
+/// The DES round function, where each set bit of the 24-bit `saltbits`
+/// swaps a bit in the left half of the expansion with the corresponding
+/// bit in the right half.
+fn feistel(r: u32, subkey: u64, saltbits: u64) -> u32 {
+    let mut e = permute(r.into(), 32, &E);
+    let swap = ((e >> 24) ^ e) & saltbits;
+    e ^= swap | (swap << 24);
+    e ^= subkey;
+
+    let mut out = 0_u32;
+    for (i, sbox) in S.iter().enumerate() {
+        let six = (e >> (42 - 6 * i)) as usize & 0x3f;
+        let row = ((six & 0x20) >> 4) | (six & 1);
+        let col = (six >> 1) & 0xf;
+        out = (out << 4) | u32::from(sbox[row * 16 + col]);
+    }
+    permute(out.into(), 32, &P) as u32
+}
+
+fn ascii_to_bin(c: u8) -> Option<u64> {
+    ITOA64.iter().position(|x| *x == c).map(|i| i as u64)
+}
+
+/// Compute a traditional DES hash. The first two bytes of `setting` are the
+/// salt, and the rest is ignored.
+pub(super) fn crypt(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    let salt = ascii_to_bin(*setting.first()?)? | (ascii_to_bin(*setting.get(1)?)? << 6);
+
+    // Salt bit 0 selects the first bit of the expansion's left half.
+    let mut saltbits = 0;
+    for i in 0..12 {
+        if salt & (1 << i) != 0 {
+            saltbits |= 0x80_0000 >> i;
+        }
+    }
+
+    // Only the low seven bits of the first eight characters are used.
+    let mut key = 0_u64;
+    for i in 0..8 {
+        let c = phrase.get(i).copied().unwrap_or(0);
+        key = (key << 8) | u64::from(c << 1);
+    }
+    let subkeys = key_schedule(key);
+
+    let mut block = 0_u64;
+    for _ in 0..25 {
+        let ip = permute(block, 64, &IP);
+        let mut l = (ip >> 32) as u32;
+        let mut r = ip as u32;
+        for subkey in subkeys {
+            let next = l ^ feistel(r, subkey, saltbits);
+            l = r;
+            r = next;
+        }
+        block = permute((u64::from(r) << 32) | u64::from(l), 64, &FP);
+    }
+
+    // Encode the 64 bits six at a time from the top, padded with zeros.
+    let bits = u128::from(block) << 2;
+    let mut out = setting[..2].to_vec();
+    for i in 0..11 {
+        out.push(ITOA64[(bits >> (60 - 6 * i)) as usize & 0x3f]);
+    }
+    Some(out)
+}
+
+/// Compute a two-character DES salt from random bytes.
+pub(super) fn gensalt(rbytes: &[u8]) -> Option<Vec<u8>> {
+    let [a, b, ..] = *rbytes else {
+        return None;
+    };
+    Some([ITOA64[usize::from(a & 0x3f)], ITOA64[usize::from(b & 0x3f)]].to_vec())
+}
@@ -0,0 +1,175 @@
+//! MD5, and the FreeBSD-derived `$1$` crypt built on it.
+
+use super::{push_b64, push_salt};
+use alloc::vec::Vec;
+use libc::c_ulong;
+
+const T: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+const SHIFTS: [[u32; 4]; 4] = [
+    [7, 12, 17, 22],
+    [5, 9, 14, 20],
+    [4, 11, 16, 23],
+    [6, 10, 15, 21],
+];
+
+struct Md5 {
+    state: [u32; 4],
+    buf: [u8; 64],
+    len: u64,
+}
+
+impl Md5 {
+    fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        let used = (self.len % 64) as usize;
+        self.len += data.len() as u64;
+        if used != 0 {
+            let n = data.len().min(64 - used);
+            self.buf[used..used + n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+            if used + n < 64 {
+                return;
+            }
+            let block = self.buf;
+            self.compress(&block);
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bits = self.len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.len % 64 != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_le_bytes());
+        let mut out = [0; 16];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut m = [0_u32; 16];
+        for (m, chunk) in m.iter_mut().zip(block.chunks_exact(4)) {
+            *m = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let t = a.wrapping_add(f).wrapping_add(T[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(t.rotate_left(SHIFTS[i / 16][i % 4]));
+        }
+        for (state, x) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(x);
+        }
+    }
+}
+
+/// Compute a `$1$` hash. `setting` starts with the `$1$` prefix.
+pub(super) fn crypt(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    let rest = &setting[3..];
+    let salt = &rest[..rest.iter().take(8).take_while(|b| **b != b'$').count()];
+
+    let mut ctx = Md5::new();
+    ctx.update(phrase);
+    ctx.update(salt);
+    ctx.update(phrase);
+    let alternate = ctx.finish();
+
+    let mut ctx = Md5::new();
+    ctx.update(phrase);
+    ctx.update(b"$1$");
+    ctx.update(salt);
+    for chunk in phrase.chunks(16) {
+        ctx.update(&alternate[..chunk.len()]);
+    }
+    // This mixes in a zero byte or the first byte of the phrase for each
+    // bit of the length, which is what the original implementation ended
+    // up doing.
+    let mut i = phrase.len();
+    while i != 0 {
+        if i & 1 != 0 {
+            ctx.update(&[0]);
+        } else {
+            ctx.update(&phrase[..1]);
+        }
+        i >>= 1;
+    }
+    let mut digest = ctx.finish();
+
+    for i in 0..1000 {
+        let mut ctx = Md5::new();
+        if i & 1 != 0 {
+            ctx.update(phrase);
+        } else {
+            ctx.update(&digest);
+        }
+        if i % 3 != 0 {
+            ctx.update(salt);
+        }
+        if i % 7 != 0 {
+            ctx.update(phrase);
+        }
+        if i & 1 != 0 {
+            ctx.update(&digest);
+        } else {
+            ctx.update(phrase);
+        }
+        digest = ctx.finish();
+    }
+
+    let mut out = Vec::with_capacity(3 + salt.len() + 1 + 22);
+    out.extend_from_slice(b"$1$");
+    out.extend_from_slice(salt);
+    out.push(b'$');
+    for [a, b, c] in [[0, 6, 12], [1, 7, 13], [2, 8, 14], [3, 9, 15], [4, 10, 5]] {
+        let value = u32::from(digest[a]) << 16 | u32::from(digest[b]) << 8 | u32::from(digest[c]);
+        push_b64(&mut out, value, 4);
+    }
+    push_b64(&mut out, digest[11].into(), 2);
+    Some(out)
+}
+
+/// Compute a `$1$` setting from random bytes.
+pub(super) fn gensalt(count: c_ulong, rbytes: &[u8]) -> Option<Vec<u8>> {
+    if count != 0 || rbytes.len() < 3 {
+        return None;
+    }
+
+    let mut out = b"$1$".to_vec();
+    push_salt(&mut out, rbytes, 8);
+    Some(out)
+}
@@ -0,0 +1,377 @@
+//! `crypt` and friends.
+//!
+//! The supported hashes, settings, and failure behavior follow libxcrypt.
+
+mod bcrypt;
+mod des;
+mod md5;
+mod sha2;
+mod sha_crypt;
+mod yescrypt;
+
+use alloc::vec::Vec;
+use core::cell::SyncUnsafeCell;
+use core::ffi::CStr;
+use core::ptr::null_mut;
+use core::slice;
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int, c_ulong};
+
+/// The alphabet used by most hashes.
+const ITOA64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const CRYPT_OUTPUT_SIZE: usize = 384;
+const CRYPT_MAX_PASSPHRASE_SIZE: usize = 512;
+const CRYPT_GENSALT_OUTPUT_SIZE: usize = 192;
+
+/// libxcrypt's `struct crypt_data`.
+#[repr(C)]
+struct CryptData {
+    output: [c_char; CRYPT_OUTPUT_SIZE],
+    setting: [c_char; CRYPT_OUTPUT_SIZE],
+    input: [c_char; CRYPT_MAX_PASSPHRASE_SIZE],
+    reserved: [c_char; 767],
+    initialized: c_char,
+    internal: [c_char; 30720],
+}
+
+#[derive(Clone, Copy)]
+enum Hash {
+    Yescrypt,
+    Bcrypt,
+    Sha512,
+    Sha256,
+    Md5,
+    Des,
+}
+
+impl Hash {
+    /// Identify the hash selected by a setting or a `crypt_gensalt` prefix.
+    fn for_prefix(prefix: &[u8]) -> Option<Self> {
+        let is_salt_char = |c: Option<&u8>| c.is_some_and(|c| ITOA64.contains(c));
+
+        if prefix.starts_with(b"$y$") {
+            Some(Self::Yescrypt)
+        } else if prefix.starts_with(b"$2")
+            && matches!(prefix.get(2), Some(b'a' | b'b' | b'x' | b'y'))
+            && prefix.get(3) == Some(&b'$')
+        {
+            Some(Self::Bcrypt)
+        } else if prefix.starts_with(b"$6$") {
+            Some(Self::Sha512)
+        } else if prefix.starts_with(b"$5$") {
+            Some(Self::Sha256)
+        } else if prefix.starts_with(b"$1$") {
+            Some(Self::Md5)
+        } else if prefix.is_empty() || (is_salt_char(prefix.first()) && is_salt_char(prefix.get(1)))
+        {
+            Some(Self::Des)
+        } else {
+            None
+        }
+    }
+
+    /// How many random bytes `crypt_gensalt` uses when it picks its own.
+    fn random_bytes(self) -> usize {
+        match self {
+            Self::Des => 2,
+            _ => 16,
+        }
+    }
+
+    fn crypt(self, phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::Yescrypt => yescrypt::crypt(phrase, setting),
+            Self::Bcrypt => bcrypt::crypt(phrase, setting),
+            Self::Sha512 => sha_crypt::crypt_sha512(phrase, setting),
+            Self::Sha256 => sha_crypt::crypt_sha256(phrase, setting),
+            Self::Md5 => md5::crypt(phrase, setting),
+            Self::Des => des::crypt(phrase, setting),
+        }
+    }
+
+    fn gensalt(self, prefix: &[u8], count: c_ulong, rbytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::Yescrypt => yescrypt::gensalt(count, rbytes),
+            Self::Bcrypt => bcrypt::gensalt(prefix, count, rbytes),
+            Self::Sha512 => sha_crypt::gensalt(b"$6$", count, rbytes),
+            Self::Sha256 => sha_crypt::gensalt(b"$5$", count, rbytes),
+            Self::Md5 => md5::gensalt(count, rbytes),
+            Self::Des => des::gensalt(rbytes),
+        }
+    }
+}
+
+/// Append `n` characters encoding `value`, six bits at a time from the
+/// least significant end.
+fn push_b64(out: &mut Vec<u8>, mut value: u32, n: usize) {
+    for _ in 0..n {
+        out.push(ITOA64[value as usize & 0x3f]);
+        value >>= 6;
+    }
+}
+
+/// Append up to `max` salt characters derived from `rbytes`, three bytes at
+/// a time, leaving the last byte unused as libxcrypt does.
+fn push_salt(out: &mut Vec<u8>, rbytes: &[u8], max: usize) {
+    let usable = &rbytes[..rbytes.len().saturating_sub(1)];
+    for group in usable.chunks_exact(3).take(max / 4) {
+        let value = u32::from(group[0]) | u32::from(group[1]) << 8 | u32::from(group[2]) << 16;
+        push_b64(out, value, 4);
+    }
+}
+
+fn hash(phrase: &[u8], setting: &[u8]) -> Result<Vec<u8>, c_int> {
+    if phrase.len() >= CRYPT_MAX_PASSPHRASE_SIZE {
+        return Err(libc::ERANGE);
+    }
+
+    // Reject characters which could be confused with field separators in
+    // files like /etc/shadow, even where a hash would otherwise ignore them.
+    if setting
+        .iter()
+        .any(|c| *c <= b' ' || *c >= 0x7f || b"!*:;\\".contains(c))
+    {
+        return Err(libc::EINVAL);
+    }
+
+    Hash::for_prefix(setting)
+        .and_then(|hash| hash.crypt(phrase, setting))
+        .filter(|hash| hash.len() < CRYPT_OUTPUT_SIZE)
+        .ok_or(libc::EINVAL)
+}
+
+#[no_mangle]
+unsafe extern "C" fn crypt(phrase: *const c_char, setting: *const c_char) -> *mut c_char {
+    //libc!(libc::crypt(phrase, setting));
+
+    static DATA: SyncUnsafeCell<CryptData> = SyncUnsafeCell::new(CryptData {
+        output: [0; CRYPT_OUTPUT_SIZE],
+        setting: [0; CRYPT_OUTPUT_SIZE],
+        input: [0; CRYPT_MAX_PASSPHRASE_SIZE],
+        reserved: [0; 767],
+        initialized: 0,
+        internal: [0; 30720],
+    });
+
+    crypt_r(phrase, setting, DATA.get())
+}
+
+#[no_mangle]
+unsafe extern "C" fn crypt_r(
+    phrase: *const c_char,
+    setting: *const c_char,
+    data: *mut CryptData,
+) -> *mut c_char {
+    //libc!(libc::crypt_r(phrase, setting, data));
+
+    let phrase = CStr::from_ptr(phrase).to_bytes();
+    let setting = CStr::from_ptr(setting).to_bytes();
+    let output =
+        slice::from_raw_parts_mut((*data).output.as_mut_ptr().cast::<u8>(), CRYPT_OUTPUT_SIZE);
+
+    match hash(phrase, setting) {
+        Ok(hash) => {
+            output[..hash.len()].copy_from_slice(&hash);
+            output[hash.len()] = 0;
+        }
+        Err(err) => {
+            // Like libxcrypt, return a string which can never match a
+            // hash, and which differs from the setting.
+            let token = if setting.starts_with(b"*0") {
+                b"*1\0"
+            } else {
+                b"*0\0"
+            };
+            output[..3].copy_from_slice(token);
+            set_errno(Errno(err));
+        }
+    }
+
+    output.as_mut_ptr().cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn crypt_gensalt(
+    prefix: *const c_char,
+    count: c_ulong,
+    rbytes: *const c_char,
+    nrbytes: c_int,
+) -> *mut c_char {
+    //libc!(libc::crypt_gensalt(prefix, count, rbytes, nrbytes));
+
+    static OUTPUT: SyncUnsafeCell<[u8; CRYPT_GENSALT_OUTPUT_SIZE]> =
+        SyncUnsafeCell::new([0; CRYPT_GENSALT_OUTPUT_SIZE]);
+
+    // A null prefix selects the default hash.
+    let prefix = if prefix.is_null() {
+        b"$y$"
+    } else {
+        CStr::from_ptr(prefix).to_bytes()
+    };
+    let Some(hash) = Hash::for_prefix(prefix) else {
+        set_errno(Errno(libc::EINVAL));
+        return null_mut();
+    };
+
+    let mut random = [0_u8; 16];
+    let rbytes = if rbytes.is_null() {
+        let random = &mut random[..hash.random_bytes()];
+        if libc::getentropy(random.as_mut_ptr().cast(), random.len()) != 0 {
+            return null_mut();
+        }
+        random
+    } else {
+        let Ok(nrbytes) = usize::try_from(nrbytes) else {
+            set_errno(Errno(libc::EINVAL));
+            return null_mut();
+        };
+        slice::from_raw_parts(rbytes.cast::<u8>(), nrbytes)
+    };
+
+    let output = &mut *OUTPUT.get();
+    match hash.gensalt(prefix, count, rbytes) {
+        Some(setting) if setting.len() < output.len() => {
+            output[..setting.len()].copy_from_slice(&setting);
+            output[setting.len()] = 0;
+            output.as_mut_ptr().cast()
+        }
+        _ => {
+            set_errno(Errno(libc::EINVAL));
+            null_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::ffi::CString;
+    use alloc::string::String;
+    use core::mem::zeroed;
+    use core::ptr::null;
+    use errno::errno;
+
+    /// Hash `phrase` with `setting` using `crypt_r`.
+    fn crypt(phrase: &str, setting: &str) -> String {
+        let phrase = CString::new(phrase).unwrap();
+        let setting = CString::new(setting).unwrap();
+        unsafe {
+            let mut data = Box::new(zeroed::<CryptData>());
+            let hash = crypt_r(phrase.as_ptr(), setting.as_ptr(), &mut *data);
+            CStr::from_ptr(hash).to_str().unwrap().into()
+        }
+    }
+
+    /// Generate a setting from a fixed set of random bytes.
+    unsafe fn gensalt(prefix: *const c_char, count: c_ulong) -> Option<&'static str> {
+        let rbytes = b"0123456789abcdef";
+        let setting = crypt_gensalt(prefix, count, rbytes.as_ptr().cast(), 16);
+        (!setting.is_null()).then(|| CStr::from_ptr(setting).to_str().unwrap())
+    }
+
+    // The expected hashes are from libxcrypt.
+
+    #[test]
+    fn test_crypt_md5() {
+        assert_eq!(
+            crypt("password", "$1$saltstri"),
+            "$1$saltstri$qQY4WxjABChYG1ccLpfkz/"
+        );
+        assert_eq!(crypt("password", "$1$"), "$1$$I2o9Z7NcvQAKp7wyCTlia0");
+    }
+
+    #[test]
+    fn test_crypt_sha256() {
+        assert_eq!(
+            crypt("password", "$5$saltstring"),
+            "$5$saltstring$OH4IDuTlsuTYPdED1gsuiRMyTAwNlRWyA6Xr3I4/dQ5"
+        );
+        // Salts are truncated to 16 characters.
+        assert_eq!(
+            crypt("password", "$5$rounds=10000$saltstringsaltstring"),
+            "$5$rounds=10000$saltstringsaltst$DnR6.aMZDwOMqtynA.o2eobA3dYZepULoyGw/CZ4ID0"
+        );
+    }
+
+    #[test]
+    fn test_crypt_sha512() {
+        assert_eq!(
+            crypt("password", "$6$saltstring"),
+            "$6$saltstring$adDbXsJjcDlq2662QPgd.tkSOVmnG9Tt3oXl4HR60SusC3AGjirnDenVZp3DGwLwqy6iYKCzannhaX9DR72nN1"
+        );
+        assert_eq!(
+            crypt("password", "$6$rounds=1000$roundsalt"),
+            "$6$rounds=1000$roundsalt$.5mYe7AXZNNji.rZibqlffO.3YoDcHSMpZ5Cq8cnumEpfdJgb2OF7BpRYEbbKR2anPgTQaFLkwuLmK7AYQU0/0"
+        );
+    }
+
+    #[test]
+    fn test_crypt_bcrypt() {
+        assert_eq!(
+            crypt("password", "$2b$05$LhayLxezLhK1LhWvKxCyLO"),
+            "$2b$05$LhayLxezLhK1LhWvKxCyLO4XRFevcDuwbjkfQgCC06agHN7E/gQhm"
+        );
+        assert_eq!(
+            crypt("password", "$2y$04$abcdefghijklmnopqrstuu"),
+            "$2y$04$abcdefghijklmnopqrstuughE8Ev8uGFaUgY2cNEySvxngrb/Jzdm"
+        );
+        assert_eq!(
+            crypt("U*U", "$2b$05$CCCCCCCCCCCCCCCCCCCCC."),
+            "$2b$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW"
+        );
+    }
+
+    #[test]
+    fn test_crypt_yescrypt() {
+        assert_eq!(
+            crypt("password", "$y$j75$LdJMENpBABJJ3hIHjB1Bi."),
+            "$y$j75$LdJMENpBABJJ3hIHjB1Bi.$AwSWBvo9otG8BLH4EfD1adasacj5dqew9dxGW5j5f24"
+        );
+    }
+
+    #[test]
+    fn test_crypt_des() {
+        assert_eq!(crypt("password", "ab"), "abJnggxhB/yWI");
+        assert_eq!(crypt("password", "xx"), "xxj31ZMTZzkVA");
+        assert_eq!(crypt("", ".."), "..X8NBuQ4l6uQ");
+    }
+
+    #[test]
+    fn test_crypt_invalid() {
+        assert_eq!(crypt("x", "$9$"), "*0");
+        assert_eq!(errno().0, libc::EINVAL);
+        assert_eq!(crypt("x", "*0"), "*1");
+    }
+
+    #[test]
+    fn test_crypt_gensalt() {
+        unsafe {
+            assert_eq!(gensalt(c"$1$".as_ptr(), 0), Some("$1$k2XAnEHB"));
+            assert_eq!(gensalt(c"$5$".as_ptr(), 0), Some("$5$k2XAnEHBqQ1Ct2aM"));
+            assert_eq!(
+                gensalt(c"$5$".as_ptr(), 10000),
+                Some("$5$rounds=10000$k2XAnEHBqQ1Ct2aM")
+            );
+            assert_eq!(gensalt(c"$6$".as_ptr(), 0), Some("$6$k2XAnEHBqQ1Ct2aM"));
+            assert_eq!(
+                gensalt(c"$2b$".as_ptr(), 5),
+                Some("$2b$05$KBCwKxOzLha2MUDgW0PjXe")
+            );
+            assert_eq!(
+                gensalt(c"$y$".as_ptr(), 0),
+                Some("$y$j9T$k2XAnEHBqQ1Ct2aMXFKNa/")
+            );
+            assert_eq!(
+                gensalt(c"$y$".as_ptr(), 3),
+                Some("$y$j7T$k2XAnEHBqQ1Ct2aMXFKNa/")
+            );
+            assert_eq!(gensalt(c"".as_ptr(), 0), Some("kl"));
+            // A null prefix selects yescrypt.
+            assert_eq!(gensalt(null(), 0), Some("$y$j9T$k2XAnEHBqQ1Ct2aMXFKNa/"));
+            assert_eq!(gensalt(c"$9$".as_ptr(), 0), None);
+            assert_eq!(errno().0, libc::EINVAL);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! The `$5$` and `$6$` crypt algorithms, as specified by Ulrich Drepper.
+
+use super::sha2::{Sha256, Sha512};
+use super::{push_b64, push_salt};
+use alloc::format;
+use alloc::vec::Vec;
+use libc::c_ulong;
+
+const ROUNDS_DEFAULT: u64 = 5000;
+const ROUNDS_MIN: u64 = 1000;
+const ROUNDS_MAX: u64 = 999_999_999;
+const SALT_MAX: usize = 16;
+
+trait Digest: Clone {
+    type Output: AsRef<[u8]>;
+
+    /// The setting prefix.
+    const PREFIX: &'static [u8];
+
+    /// The order in which digest bytes are encoded, three at a time.
+    const ORDER: &'static [usize];
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finish(self) -> Self::Output;
+}
+
+impl Digest for Sha256 {
+    type Output = [u8; 32];
+
+    const PREFIX: &'static [u8] = b"$5$";
+    const ORDER: &'static [usize] = &[
+        0, 10, 20, 21, 1, 11, 12, 22, 2, 3, 13, 23, 24, 4, 14, 15, 25, 5, 6, 16, 26, 27, 7, 17, 18,
+        28, 8, 9, 19, 29, 31, 30,
+    ];
+
+    fn new() -> Self {
+        Sha256::new()
+    }
+    fn update(&mut self, data: &[u8]) {
+        Sha256::update(self, data)
+    }
+    fn finish(self) -> Self::Output {
+        Sha256::finish(self)
+    }
+}
+
+impl Digest for Sha512 {
+    type Output = [u8; 64];
+
+    const PREFIX: &'static [u8] = b"$6$";
+    const ORDER: &'static [usize] = &[
+        0, 21, 42, 22, 43, 1, 44, 2, 23, 3, 24, 45, 25, 46, 4, 47, 5, 26, 6, 27, 48, 28, 49, 7, 50,
+        8, 29, 9, 30, 51, 31, 52, 10, 53, 11, 32, 12, 33, 54, 34, 55, 13, 56, 14, 35, 15, 36, 57,
+        37, 58, 16, 59, 17, 38, 18, 39, 60, 40, 61, 19, 62, 20, 41, 63,
+    ];
+
+    fn new() -> Self {
+        Sha512::new()
+    }
+    fn update(&mut self, data: &[u8]) {
+        Sha512::update(self, data)
+    }
+    fn finish(self) -> Self::Output {
+        Sha512::finish(self)
+    }
+}
+
+/// Compute a `$5$` hash. `setting` starts with the `$5$` prefix.
+pub(super) fn crypt_sha256(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    crypt::<Sha256>(phrase, setting)
+}
+
+/// Compute a `$6$` hash. `setting` starts with the `$6$` prefix.
+pub(super) fn crypt_sha512(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    crypt::<Sha512>(phrase, setting)
+}
+
+fn crypt<D: Digest>(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    let mut rest = &setting[D::PREFIX.len()..];
+
+    // An explicit round count is echoed in the output, even if it's the
+    // default.
+    let mut rounds = None;
+    if let Some(after) = rest.strip_prefix(b"rounds=") {
+        let digits = after.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 || digits > 9 || after[0] == b'0' || after.get(digits) != Some(&b'$') {
+            return None;
+        }
+        let n = after[..digits]
+            .iter()
+            .fold(0, |n, b| n * 10 + u64::from(b - b'0'));
+        if !(ROUNDS_MIN..=ROUNDS_MAX).contains(&n) {
+            return None;
+        }
+        rounds = Some(n);
+        rest = &after[digits + 1..];
+    }
+
+    let salt = &rest[..rest
+        .iter()
+        .take(SALT_MAX)
+        .take_while(|b| **b != b'$')
+        .count()];
+
+    let mut ctx = D::new();
+    ctx.update(phrase);
+    ctx.update(salt);
+    ctx.update(phrase);
+    let alternate = ctx.finish();
+    let alternate = alternate.as_ref();
+
+    let mut ctx = D::new();
+    ctx.update(phrase);
+    ctx.update(salt);
+    for chunk in phrase.chunks(alternate.len()) {
+        ctx.update(&alternate[..chunk.len()]);
+    }
+    let mut n = phrase.len();
+    while n != 0 {
+        if n & 1 != 0 {
+            ctx.update(alternate);
+        } else {
+            ctx.update(phrase);
+        }
+        n >>= 1;
+    }
+    let mut digest = ctx.finish();
+
+    let mut ctx = D::new();
+    for _ in 0..phrase.len() {
+        ctx.update(phrase);
+    }
+    let p_bytes: Vec<u8> = ctx
+        .finish()
+        .as_ref()
+        .iter()
+        .copied()
+        .cycle()
+        .take(phrase.len())
+        .collect();
+
+    let mut ctx = D::new();
+    for _ in 0..16 + usize::from(digest.as_ref()[0]) {
+        ctx.update(salt);
+    }
+    let s_bytes: Vec<u8> = ctx
+        .finish()
+        .as_ref()
+        .iter()
+        .copied()
+        .cycle()
+        .take(salt.len())
+        .collect();
+
+    for round in 0..rounds.unwrap_or(ROUNDS_DEFAULT) {
+        let mut ctx = D::new();
+        if round & 1 != 0 {
+            ctx.update(&p_bytes);
+        } else {
+            ctx.update(digest.as_ref());
+        }
+        if round % 3 != 0 {
+            ctx.update(&s_bytes);
+        }
+        if round % 7 != 0 {
+            ctx.update(&p_bytes);
+        }
+        if round & 1 != 0 {
+            ctx.update(digest.as_ref());
+        } else {
+            ctx.update(&p_bytes);
+        }
+        digest = ctx.finish();
+    }
+
+    let mut out = D::PREFIX.to_vec();
+    if let Some(rounds) = rounds {
+        out.extend_from_slice(format!("rounds={rounds}$").as_bytes());
+    }
+    out.extend_from_slice(salt);
+    out.push(b'$');
+    let digest = digest.as_ref();
+    for group in D::ORDER.chunks(3) {
+        let value = group
+            .iter()
+            .fold(0, |value, i| value << 8 | u32::from(digest[*i]));
+        push_b64(&mut out, value, group.len() + 1);
+    }
+    Some(out)
+}
+
+/// Compute a `$5$` or `$6$` setting from random bytes.
+pub(super) fn gensalt(prefix: &[u8], count: c_ulong, rbytes: &[u8]) -> Option<Vec<u8>> {
+    if rbytes.len() < 3 {
+        return None;
+    }
+
+    let mut out = prefix.to_vec();
+    if count != 0 && count != ROUNDS_DEFAULT as c_ulong {
+        let rounds = count.clamp(ROUNDS_MIN as c_ulong, ROUNDS_MAX as c_ulong);
+        out.extend_from_slice(format!("rounds={rounds}$").as_bytes());
+    }
+    push_salt(&mut out, rbytes, SALT_MAX);
+    Some(out)
+}
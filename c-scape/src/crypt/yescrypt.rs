@@ -0,0 +1,595 @@
+//! yescrypt, the `$y$` crypt algorithm, following the reference
+//! implementation.
+//!
+//! Besides the default read-write mode, settings can select classic scrypt
+//! or yescrypt's write-once mode. ROM-based hashes and hash upgrades aren't
+//! supported, as in libxcrypt.
+
+use super::sha2::{pbkdf2_sha256, HmacSha256, Sha256};
+use super::{push_b64, ITOA64};
+use alloc::vec::Vec;
+use libc::c_ulong;
+
+const YESCRYPT_WORM: u32 = 1;
+const YESCRYPT_RW: u32 = 2;
+const YESCRYPT_RW_FLAVOR_MASK: u32 = 0x3fc;
+/// The default, and only supported, read-write flavor: 6 rounds of
+/// pwxform with 4-way gathers of 2-way simple operations on 12 KiB S-boxes.
+const YESCRYPT_DEFAULTS: u32 = 0xb6;
+const YESCRYPT_PREHASH: u32 = 0x1000_0000;
+
+const PWX_SIMPLE: usize = 2;
+const PWX_GATHER: usize = 4;
+const PWX_ROUNDS: usize = 6;
+const PWX_WORDS: usize = PWX_GATHER * PWX_SIMPLE * 2;
+const S_WIDTH: u32 = 8;
+const S_WORDS: usize = 3 * (1 << S_WIDTH) * PWX_SIMPLE * 2;
+const S_MASK: u32 = ((1 << S_WIDTH) - 1) * PWX_SIMPLE as u32 * 8;
+
+/// The largest decoded salt we accept.
+const SALT_MAX: usize = 64;
+
+const COUNT_DEFAULT: c_ulong = 5;
+
+struct Params {
+    flags: u32,
+    n: u64,
+    r: u32,
+    p: u32,
+    t: u32,
+}
+
+/// The pwxform state: an S-box divided into three parts whose roles rotate,
+/// and the position of the next write into the S2 part.
+struct Pwxform {
+    s: Vec<u32>,
+    s0: usize,
+    s1: usize,
+    s2: usize,
+    w: usize,
+}
+
+fn atoi64(c: u8) -> Option<u32> {
+    ITOA64.iter().position(|x| *x == c).map(|i| i as u32)
+}
+
+/// Decode one of yescrypt's variable-length parameter encodings.
+fn decode64_uint32(src: &[u8], min: u32) -> Option<(u32, &[u8])> {
+    let (first, mut src) = src.split_first()?;
+    let c = atoi64(*first)?;
+
+    let mut start = 0;
+    let mut end = 47;
+    let mut chars = 1;
+    let mut bits = 0;
+    let mut value = min;
+    while c > end {
+        value = value.wrapping_add((end + 1 - start) << bits);
+        start = end + 1;
+        end = start + (62 - end) / 2;
+        chars += 1;
+        bits += 6;
+    }
+    value = value.wrapping_add((c - start) << bits);
+
+    for _ in 1..chars {
+        let (c, rest) = src.split_first()?;
+        src = rest;
+        bits -= 6;
+        value = value.wrapping_add(atoi64(*c)? << bits);
+    }
+    Some((value, src))
+}
+
+fn encode64_uint32(out: &mut Vec<u8>, value: u32, min: u32) {
+    let mut value = value - min;
+    let mut start = 0;
+    let mut end = 47;
+    let mut chars = 1;
+    let mut bits = 0;
+    loop {
+        let count = (end + 1 - start) << bits;
+        if value < count {
+            break;
+        }
+        start = end + 1;
+        end = start + (62 - end) / 2;
+        value -= count;
+        chars += 1;
+        bits += 6;
+    }
+
+    out.push(ITOA64[(start + (value >> bits)) as usize]);
+    for _ in 1..chars {
+        bits -= 6;
+        out.push(ITOA64[(value >> bits) as usize & 0x3f]);
+    }
+}
+
+/// Decode bytes encoded little-endian, six bits per character.
+fn decode64(src: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    for group in src.chunks(4) {
+        let mut value = 0;
+        let mut bits = 0;
+        for c in group {
+            value |= atoi64(*c)? << bits;
+            bits += 6;
+        }
+        // A group must hold at least one whole byte, and no leftover bits.
+        if bits < 12 {
+            return None;
+        }
+        while bits >= 8 {
+            out.push(value as u8);
+            value >>= 8;
+            bits -= 8;
+        }
+        if value != 0 {
+            return None;
+        }
+    }
+    (out.len() <= SALT_MAX).then_some(out)
+}
+
+fn encode64(out: &mut Vec<u8>, bytes: &[u8]) {
+    for group in bytes.chunks(3) {
+        let value = group
+            .iter()
+            .rev()
+            .fold(0, |value, b| (value << 8) | u32::from(*b));
+        push_b64(out, value, group.len() + 1);
+    }
+}
+
+/// Allocate a zeroed buffer, or fail if there isn't enough memory.
+fn try_alloc(len: usize) -> Option<Vec<u32>> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(len).ok()?;
+    v.resize(len, 0);
+    Some(v)
+}
+
+/// Salsa20 with `rounds` rounds, on a block stored in the SIMD-friendly
+/// order the reference implementation uses, where word `i` of the block
+/// is stored at index `i * 13 % 16`.
+fn salsa20(b: &mut [u32], rounds: usize) {
+    fn quarter(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+
+    let mut x = [0_u32; 16];
+    for i in 0..16 {
+        x[i * 5 % 16] = b[i];
+    }
+    for _ in 0..rounds / 2 {
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for i in 0..16 {
+        b[i] = b[i].wrapping_add(x[i * 5 % 16]);
+    }
+}
+
+fn xor(dst: &mut [u32], src: &[u32]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+/// scrypt's BlockMix with Salsa20/8, using `y` as scratch space.
+fn blockmix_salsa8(b: &mut [u32], y: &mut [u32], r: usize) {
+    let mut x: [u32; 16] = b[(2 * r - 1) * 16..][..16].try_into().unwrap();
+    for i in 0..2 * r {
+        xor(&mut x, &b[i * 16..][..16]);
+        salsa20(&mut x, 8);
+        y[i * 16..][..16].copy_from_slice(&x);
+    }
+    for i in 0..r {
+        b[i * 16..][..16].copy_from_slice(&y[i * 32..][..16]);
+        b[(i + r) * 16..][..16].copy_from_slice(&y[i * 32 + 16..][..16]);
+    }
+}
+
+fn pwxform(b: &mut [u32; PWX_WORDS], ctx: &mut Pwxform) {
+    let s = &mut ctx.s;
+    let mut w = ctx.w;
+    for i in 0..PWX_ROUNDS {
+        for j in 0..PWX_GATHER {
+            let lane = j * PWX_SIMPLE * 2;
+            let p0 = ctx.s0 + (b[lane] & S_MASK) as usize / 4;
+            let p1 = ctx.s1 + (b[lane + 1] & S_MASK) as usize / 4;
+            for k in 0..PWX_SIMPLE {
+                let s0 = (u64::from(s[p0 + 2 * k + 1]) << 32) | u64::from(s[p0 + 2 * k]);
+                let s1 = (u64::from(s[p1 + 2 * k + 1]) << 32) | u64::from(s[p1 + 2 * k]);
+                let xl = b[lane + 2 * k];
+                let xh = b[lane + 2 * k + 1];
+                let x = (u64::from(xh) * u64::from(xl)).wrapping_add(s0) ^ s1;
+                b[lane + 2 * k] = x as u32;
+                b[lane + 2 * k + 1] = (x >> 32) as u32;
+                if i != 0 && i != PWX_ROUNDS - 1 {
+                    s[ctx.s2 + 2 * w] = x as u32;
+                    s[ctx.s2 + 2 * w + 1] = (x >> 32) as u32;
+                    w += 1;
+                }
+            }
+        }
+    }
+    (ctx.s0, ctx.s1, ctx.s2) = (ctx.s2, ctx.s0, ctx.s1);
+    ctx.w = w & ((1 << S_WIDTH) * PWX_SIMPLE - 1);
+}
+
+/// yescrypt's BlockMix with pwxform.
+fn blockmix_pwxform(b: &mut [u32], ctx: &mut Pwxform, r: usize) {
+    let r1 = 2 * r;
+    let mut x: [u32; PWX_WORDS] = b[(r1 - 1) * PWX_WORDS..][..PWX_WORDS].try_into().unwrap();
+    for i in 0..r1 {
+        if r1 > 1 {
+            xor(&mut x, &b[i * PWX_WORDS..][..PWX_WORDS]);
+        }
+        pwxform(&mut x, ctx);
+        b[i * PWX_WORDS..][..PWX_WORDS].copy_from_slice(&x);
+    }
+    salsa20(&mut b[(r1 - 1) * 16..][..16], 2);
+}
+
+fn blockmix(x: &mut [u32], y: &mut [u32], r: usize, ctx: Option<&mut Pwxform>) {
+    match ctx {
+        Some(ctx) => blockmix_pwxform(x, ctx, r),
+        None => blockmix_salsa8(x, y, r),
+    }
+}
+
+fn integerify(x: &[u32], r: usize) -> u64 {
+    let last = &x[(2 * r - 1) * 16..];
+    (u64::from(last[13]) << 32) + u64::from(last[0])
+}
+
+/// The largest power of two no greater than `x`.
+fn p2floor(x: u64) -> u64 {
+    1 << x.ilog2()
+}
+
+fn wrap(x: u64, i: u64) -> u64 {
+    let n = p2floor(i);
+    (x & (n - 1)) + (i - n)
+}
+
+fn shuffle(x: &mut [u32], b: &[u32]) {
+    for (x, b) in x.chunks_exact_mut(16).zip(b.chunks_exact(16)) {
+        for i in 0..16 {
+            x[i] = b[i * 5 % 16];
+        }
+    }
+}
+
+fn unshuffle(b: &mut [u32], x: &[u32]) {
+    for (b, x) in b.chunks_exact_mut(16).zip(x.chunks_exact(16)) {
+        for i in 0..16 {
+            b[i * 5 % 16] = x[i];
+        }
+    }
+}
+
+/// The first loop of SMix, which fills `v` with `n` blocks.
+fn smix1(b: &mut [u32], n: u64, v: &mut [u32], xy: &mut [u32], mut ctx: Option<&mut Pwxform>) {
+    let s = b.len();
+    let r = s / 32;
+    let (x, y) = xy.split_at_mut(s);
+    shuffle(x, b);
+    for i in 0..n {
+        v[i as usize * s..][..s].copy_from_slice(x);
+        if ctx.is_some() && i > 1 {
+            let j = wrap(integerify(x, r), i) as usize;
+            xor(x, &v[j * s..][..s]);
+        }
+        blockmix(x, y, r, ctx.as_deref_mut());
+    }
+    unshuffle(b, x);
+}
+
+/// The second loop of SMix, which reads, and in read-write mode updates,
+/// pseudorandom blocks of `v`.
+fn smix2(
+    b: &mut [u32],
+    n: u64,
+    nloop: u64,
+    v: &mut [u32],
+    write: bool,
+    xy: &mut [u32],
+    mut ctx: Option<&mut Pwxform>,
+) {
+    let s = b.len();
+    let r = s / 32;
+    let (x, y) = xy.split_at_mut(s);
+    shuffle(x, b);
+    for _ in 0..nloop {
+        let j = (integerify(x, r) & (n - 1)) as usize;
+        let vj = &mut v[j * s..][..s];
+        xor(x, vj);
+        if write {
+            vj.copy_from_slice(x);
+        }
+        blockmix(x, y, r, ctx.as_deref_mut());
+    }
+    unshuffle(b, x);
+}
+
+fn smix(
+    b: &mut [u32],
+    params: &Params,
+    v: &mut [u32],
+    xy: &mut [u32],
+    ctx: &mut [Pwxform],
+    passwd: &mut [u8; 32],
+) {
+    let Params { flags, n, r, p, t } = *params;
+    let s = 32 * r as usize;
+    let rw = flags & YESCRYPT_RW != 0;
+
+    let mut nchunk = n / u64::from(p);
+    let mut nloop_all = nchunk;
+    if rw {
+        if t <= 1 {
+            if t != 0 {
+                nloop_all *= 2;
+            }
+            nloop_all = nloop_all.div_ceil(3);
+        } else {
+            nloop_all *= u64::from(t - 1);
+        }
+    } else if t != 0 {
+        if t == 1 {
+            nloop_all += nloop_all.div_ceil(2);
+        }
+        nloop_all *= u64::from(t);
+    }
+    let mut nloop_rw = if rw { nloop_all / u64::from(p) } else { 0 };
+
+    nchunk &= !1;
+    nloop_all = (nloop_all + 1) & !1;
+    nloop_rw = (nloop_rw + 1) & !1;
+
+    let mut vchunk = 0;
+    for i in 0..p as usize {
+        let np = if i < p as usize - 1 {
+            nchunk
+        } else {
+            n - vchunk
+        };
+        let bp = &mut b[s * i..][..s];
+        let vp = &mut v[s * vchunk as usize..];
+        let mut ctx_i = None;
+        if rw {
+            let c = &mut ctx[i];
+            smix1(&mut bp[..32], (S_WORDS / 32) as u64, &mut c.s, xy, None);
+            c.s2 = 0;
+            c.s1 = c.s2 + (1 << S_WIDTH) * PWX_SIMPLE * 2;
+            c.s0 = c.s1 + (1 << S_WIDTH) * PWX_SIMPLE * 2;
+            c.w = 0;
+            if i == 0 {
+                let mut key = [0_u8; 64];
+                for (chunk, word) in key.chunks_exact_mut(4).zip(&bp[s - 16..]) {
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                }
+                *passwd = HmacSha256::mac(&key, passwd);
+            }
+            ctx_i = Some(c);
+        }
+        smix1(bp, np, vp, xy, ctx_i.as_deref_mut());
+        smix2(bp, p2floor(np), nloop_rw, vp, rw, xy, ctx_i);
+        vchunk += nchunk;
+    }
+
+    for i in 0..p as usize {
+        let bp = &mut b[s * i..][..s];
+        let ctx_i = if rw { Some(&mut ctx[i]) } else { None };
+        smix2(bp, n, nloop_all - nloop_rw, v, false, xy, ctx_i);
+    }
+}
+
+fn kdf_body(passwd: &[u8], salt: &[u8], params: &Params, flags: u32) -> Option<[u8; 32]> {
+    let Params { n, r, p, t, .. } = *params;
+
+    match flags & (YESCRYPT_WORM | YESCRYPT_RW) {
+        0 if flags == 0 && t == 0 => {}
+        YESCRYPT_WORM if flags == YESCRYPT_WORM => {}
+        YESCRYPT_RW if flags & !YESCRYPT_PREHASH == YESCRYPT_DEFAULTS => {}
+        _ => return None,
+    }
+    if u64::from(r) * u64::from(p) >= 1 << 30 || !n.is_power_of_two() || n <= 1 || r < 1 || p < 1 {
+        return None;
+    }
+    if n > u64::MAX / (u64::from(t) + 1) {
+        return None;
+    }
+    let rw = flags & YESCRYPT_RW != 0;
+    if rw && n / u64::from(p) <= 1 {
+        return None;
+    }
+
+    let r = r as usize;
+    let s = 32 * r;
+    let mut v = try_alloc(s.checked_mul(usize::try_from(n).ok()?)?)?;
+    let mut b = try_alloc(s.checked_mul(p as usize)?)?;
+    let mut xy = try_alloc(2 * s)?;
+    let mut ctx = Vec::new();
+    if rw {
+        for _ in 0..p {
+            ctx.push(Pwxform {
+                s: try_alloc(S_WORDS)?,
+                s0: 0,
+                s1: 0,
+                s2: 0,
+                w: 0,
+            });
+        }
+    }
+
+    // Except for classic scrypt, the password is first hashed, and the hash
+    // is then replaced by the start of the initial blocks, and then updated
+    // again during SMix.
+    let mut prehashed = [0_u8; 32];
+    if flags != 0 {
+        let key: &[u8] = if flags & YESCRYPT_PREHASH != 0 {
+            b"yescrypt-prehash"
+        } else {
+            b"yescrypt"
+        };
+        prehashed = HmacSha256::mac(key, passwd);
+    }
+
+    let mut bytes = alloc::vec![0_u8; b.len() * 4];
+    pbkdf2_sha256(
+        if flags != 0 { &prehashed } else { passwd },
+        salt,
+        &mut bytes,
+    );
+    if flags != 0 {
+        prehashed.copy_from_slice(&bytes[..32]);
+    }
+    for (word, chunk) in b.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    if rw {
+        smix(&mut b, params, &mut v, &mut xy, &mut ctx, &mut prehashed);
+    } else {
+        let params = Params { p: 1, ..*params };
+        for bp in b.chunks_exact_mut(s) {
+            smix(bp, &params, &mut v, &mut xy, &mut [], &mut prehashed);
+        }
+    }
+
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(&b) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    let mut dk = [0_u8; 32];
+    pbkdf2_sha256(
+        if flags != 0 { &prehashed } else { passwd },
+        &bytes,
+        &mut dk,
+    );
+
+    // Finish like SCRAM does, so that everything above could be done by a
+    // client.
+    if flags != 0 && flags & YESCRYPT_PREHASH == 0 {
+        dk = Sha256::digest(&HmacSha256::mac(&dk, b"Client Key"));
+    }
+    Some(dk)
+}
+
+fn kdf(passwd: &[u8], salt: &[u8], params: &Params) -> Option<[u8; 32]> {
+    let Params { flags, n, r, p, .. } = *params;
+
+    // With large memory costs, first hash the password with a smaller one,
+    // so that an attacker can't cheaply reject candidate passwords.
+    if flags & YESCRYPT_RW != 0
+        && p >= 1
+        && n / u64::from(p) >= 0x100
+        && n / u64::from(p) * u64::from(r) >= 0x20000
+    {
+        let prehash = Params {
+            n: n >> 6,
+            t: 0,
+            ..*params
+        };
+        let dk = kdf_body(passwd, salt, &prehash, flags | YESCRYPT_PREHASH)?;
+        return kdf_body(&dk, salt, params, flags);
+    }
+    kdf_body(passwd, salt, params, flags)
+}
+
+/// Parse the parameters of a `$y$` setting, returning them and the rest of
+/// the setting.
+fn decode_params(setting: &[u8]) -> Option<(Params, &[u8])> {
+    let (flavor, src) = decode64_uint32(setting, 0)?;
+    let flags = if flavor < YESCRYPT_RW {
+        flavor
+    } else if flavor <= YESCRYPT_RW + (YESCRYPT_RW_FLAVOR_MASK >> 2) {
+        YESCRYPT_RW + ((flavor - YESCRYPT_RW) << 2)
+    } else {
+        return None;
+    };
+
+    let (n_log2, src) = decode64_uint32(src, 1)?;
+    if n_log2 > 63 {
+        return None;
+    }
+    let (r, mut src) = decode64_uint32(src, 1)?;
+
+    let mut params = Params {
+        flags,
+        n: 1 << n_log2,
+        r,
+        p: 1,
+        t: 0,
+    };
+    if src.first() != Some(&b'$') {
+        let (have, rest) = decode64_uint32(src, 1)?;
+        src = rest;
+        if have & 1 != 0 {
+            (params.p, src) = decode64_uint32(src, 2)?;
+        }
+        if have & 2 != 0 {
+            (params.t, src) = decode64_uint32(src, 1)?;
+        }
+        // Hash upgrades and ROMs aren't supported.
+        if have & 4 != 0 || have & 8 != 0 {
+            return None;
+        }
+    }
+
+    Some((params, src.strip_prefix(b"$")?))
+}
+
+/// Compute a `$y$` hash. `setting` starts with the `$y$` prefix.
+pub(super) fn crypt(phrase: &[u8], setting: &[u8]) -> Option<Vec<u8>> {
+    let (params, rest) = decode_params(&setting[3..])?;
+
+    // The salt runs up to the last `$`, if there is one, so that a complete
+    // hash can be passed as the setting.
+    let salt_chars = match rest.iter().rposition(|b| *b == b'$') {
+        Some(end) => &rest[..end],
+        None => rest,
+    };
+    let salt = decode64(salt_chars)?;
+
+    let hash = kdf(phrase, &salt, &params)?;
+
+    let prefix_len = setting.len() - rest.len() + salt_chars.len();
+    let mut out = setting[..prefix_len].to_vec();
+    out.push(b'$');
+    encode64(&mut out, &hash);
+    Some(out)
+}
+
+/// Compute a `$y$` setting from random bytes.
+pub(super) fn gensalt(count: c_ulong, rbytes: &[u8]) -> Option<Vec<u8>> {
+    let count = if count == 0 { COUNT_DEFAULT } else { count };
+    if !(1..=11).contains(&count) || rbytes.len() < 16 {
+        return None;
+    }
+    let (n, r) = if count <= 2 {
+        (512 << count, 8)
+    } else {
+        (128 << count, 32)
+    };
+
+    let mut out = b"$y$".to_vec();
+    encode64_uint32(&mut out, YESCRYPT_RW + (YESCRYPT_DEFAULTS >> 2), 0);
+    encode64_uint32(&mut out, (n as u64).ilog2(), 1);
+    encode64_uint32(&mut out, r, 1);
+    out.push(b'$');
+    encode64(&mut out, rbytes);
+    Some(out)
+}
@@ -0,0 +1,368 @@
+//! ELF definitions, and symbol lookup in loaded objects.
+
+use alloc::vec::Vec;
+use core::ffi::{c_char, CStr};
+use core::slice;
+
+#[cfg(target_pointer_width = "32")]
+pub(super) use libc::{
+    Elf32_Ehdr as Ehdr, Elf32_Phdr as Phdr, Elf32_Shdr as Shdr, Elf32_Sym as Sym,
+};
+#[cfg(target_pointer_width = "64")]
+pub(super) use libc::{
+    Elf64_Ehdr as Ehdr, Elf64_Phdr as Phdr, Elf64_Shdr as Shdr, Elf64_Sym as Sym,
+};
+
+/// An entry in a dynamic section.
+#[repr(C)]
+pub(super) struct Dyn {
+    pub(super) d_tag: isize,
+    pub(super) d_val: usize,
+}
+
+pub(super) const DT_NULL: isize = 0;
+pub(super) const DT_NEEDED: isize = 1;
+pub(super) const DT_PLTRELSZ: isize = 2;
+pub(super) const DT_HASH: isize = 4;
+pub(super) const DT_STRTAB: isize = 5;
+pub(super) const DT_SYMTAB: isize = 6;
+pub(super) const DT_RELA: isize = 7;
+pub(super) const DT_RELASZ: isize = 8;
+pub(super) const DT_STRSZ: isize = 10;
+pub(super) const DT_INIT: isize = 12;
+pub(super) const DT_FINI: isize = 13;
+pub(super) const DT_SONAME: isize = 14;
+pub(super) const DT_RPATH: isize = 15;
+pub(super) const DT_REL: isize = 17;
+pub(super) const DT_PLTREL: isize = 20;
+pub(super) const DT_TEXTREL: isize = 22;
+pub(super) const DT_JMPREL: isize = 23;
+pub(super) const DT_INIT_ARRAY: isize = 25;
+pub(super) const DT_FINI_ARRAY: isize = 26;
+pub(super) const DT_INIT_ARRAYSZ: isize = 27;
+pub(super) const DT_FINI_ARRAYSZ: isize = 28;
+pub(super) const DT_RUNPATH: isize = 29;
+pub(super) const DT_FLAGS: isize = 30;
+pub(super) const DT_RELRSZ: isize = 35;
+pub(super) const DT_RELR: isize = 36;
+pub(super) const DT_GNU_HASH: isize = 0x6fff_fef5;
+pub(super) const DT_VERSYM: isize = 0x6fff_fff0;
+pub(super) const DT_FLAGS_1: isize = 0x6fff_fffb;
+
+pub(super) const DF_TEXTREL: usize = 0x4;
+pub(super) const DF_1_NODELETE: usize = 0x8;
+pub(super) const DF_1_PIE: usize = 0x0800_0000;
+
+pub(super) const SHT_SYMTAB: u32 = 2;
+
+pub(super) const SHN_UNDEF: u16 = 0;
+pub(super) const SHN_ABS: u16 = 0xfff1;
+
+pub(super) const STB_LOCAL: u8 = 0;
+pub(super) const STB_GLOBAL: u8 = 1;
+pub(super) const STB_WEAK: u8 = 2;
+pub(super) const STB_GNU_UNIQUE: u8 = 10;
+
+pub(super) const STT_TLS: u8 = 6;
+pub(super) const STT_GNU_IFUNC: u8 = 10;
+
+/// The bias that `DTPOFF` relocations subtract from offsets in TLS blocks,
+/// and that `__tls_get_addr` adds back.
+#[cfg(target_arch = "riscv64")]
+pub(super) const DTV_OFFSET: usize = 0x800;
+#[cfg(not(target_arch = "riscv64"))]
+pub(super) const DTV_OFFSET: usize = 0;
+
+/// A hidden version, which unversioned lookups don't see.
+const VERSYM_HIDDEN: u16 = 0x8000;
+
+/// The hash table of a dynamic symbol table.
+#[derive(Clone, Copy)]
+pub(super) enum Hash {
+    Gnu(*const u32),
+    Sysv(*const u32),
+}
+
+/// A relocation with an explicit addend.
+#[repr(C)]
+pub(super) struct Rela {
+    pub(super) r_offset: usize,
+    pub(super) r_info: usize,
+    pub(super) r_addend: isize,
+}
+
+/// The entries we use from a dynamic section. Pointers are converted to
+/// run-time addresses, and string table offsets are left as they are.
+#[derive(Default)]
+pub(super) struct Dynamic {
+    pub(super) symtab: usize,
+    pub(super) strtab: usize,
+    pub(super) strsz: usize,
+    pub(super) hash: Option<Hash>,
+    pub(super) versym: usize,
+    pub(super) needed: Vec<usize>,
+    pub(super) soname: Option<usize>,
+    pub(super) rpath: Option<usize>,
+    pub(super) runpath: Option<usize>,
+    pub(super) rela: usize,
+    pub(super) relasz: usize,
+    pub(super) rel: bool,
+    pub(super) jmprel: usize,
+    pub(super) pltrelsz: usize,
+    pub(super) pltrel: isize,
+    pub(super) relr: usize,
+    pub(super) relrsz: usize,
+    pub(super) init: usize,
+    pub(super) fini: usize,
+    pub(super) init_array: usize,
+    pub(super) init_arraysz: usize,
+    pub(super) fini_array: usize,
+    pub(super) fini_arraysz: usize,
+    pub(super) flags: usize,
+    pub(super) flags_1: usize,
+}
+
+impl Dynamic {
+    /// Read the dynamic section at `dynamic`. `addr` converts a `d_ptr`
+    /// value to a run-time address.
+    pub(super) unsafe fn read(mut dynamic: *const Dyn, addr: impl Fn(usize) -> usize) -> Self {
+        let mut result = Self::default();
+        loop {
+            let Dyn { d_tag, d_val } = *dynamic;
+            match d_tag {
+                DT_NULL => break,
+                DT_NEEDED => result.needed.push(d_val),
+                DT_PLTRELSZ => result.pltrelsz = d_val,
+                DT_HASH if result.hash.is_none() => {
+                    result.hash = Some(Hash::Sysv(addr(d_val) as *const u32))
+                }
+                DT_STRTAB => result.strtab = addr(d_val),
+                DT_SYMTAB => result.symtab = addr(d_val),
+                DT_RELA => result.rela = addr(d_val),
+                DT_RELASZ => result.relasz = d_val,
+                DT_STRSZ => result.strsz = d_val,
+                DT_INIT => result.init = addr(d_val),
+                DT_FINI => result.fini = addr(d_val),
+                DT_SONAME => result.soname = Some(d_val),
+                DT_RPATH => result.rpath = Some(d_val),
+                DT_REL => result.rel = true,
+                DT_PLTREL => result.pltrel = d_val as isize,
+                DT_TEXTREL => result.flags |= DF_TEXTREL,
+                DT_JMPREL => result.jmprel = addr(d_val),
+                DT_INIT_ARRAY => result.init_array = addr(d_val),
+                DT_FINI_ARRAY => result.fini_array = addr(d_val),
+                DT_INIT_ARRAYSZ => result.init_arraysz = d_val,
+                DT_FINI_ARRAYSZ => result.fini_arraysz = d_val,
+                DT_RUNPATH => result.runpath = Some(d_val),
+                DT_FLAGS => result.flags |= d_val,
+                DT_RELRSZ => result.relrsz = d_val,
+                DT_RELR => result.relr = addr(d_val),
+                DT_GNU_HASH => result.hash = Some(Hash::Gnu(addr(d_val) as *const u32)),
+                DT_VERSYM => result.versym = addr(d_val),
+                DT_FLAGS_1 => result.flags_1 = d_val,
+                _ => {}
+            }
+            dynamic = dynamic.add(1);
+        }
+        result
+    }
+
+    /// Return the string at `offset` in the string table.
+    pub(super) unsafe fn string(&self, offset: usize) -> &'static CStr {
+        CStr::from_ptr((self.strtab as *const c_char).add(offset))
+    }
+}
+
+/// A symbol table and its string table.
+#[derive(Clone, Copy)]
+pub(super) struct Symbols {
+    syms: *const Sym,
+    count: usize,
+    strtab: *const c_char,
+    /// The size of `strtab`, if known.
+    strsz: usize,
+    hash: Option<Hash>,
+    versym: *const u16,
+}
+
+// SAFETY: Symbol tables are never modified or unmapped.
+unsafe impl Send for Symbols {}
+unsafe impl Sync for Symbols {}
+
+impl Symbols {
+    /// Describe the dynamic symbol table of an object.
+    pub(super) unsafe fn from_dynamic(dynamic: &Dynamic) -> Option<Self> {
+        if dynamic.symtab == 0 || dynamic.strtab == 0 {
+            return None;
+        }
+
+        let count = match dynamic.hash {
+            Some(Hash::Sysv(table)) => *table.add(1) as usize,
+            Some(Hash::Gnu(table)) => gnu_hash_count(table),
+            // Without a hash table, there's no way to tell where the table
+            // ends, so don't use it.
+            None => 0,
+        };
+        Some(Self {
+            syms: dynamic.symtab as *const Sym,
+            count,
+            strtab: dynamic.strtab as *const c_char,
+            strsz: if dynamic.strsz == 0 {
+                usize::MAX
+            } else {
+                dynamic.strsz
+            },
+            hash: dynamic.hash,
+            versym: dynamic.versym as *const u16,
+        })
+    }
+
+    /// Describe a symbol table from section headers.
+    pub(super) fn from_sections(syms: &[Sym], strtab: &[u8]) -> Self {
+        Self {
+            syms: syms.as_ptr(),
+            count: syms.len(),
+            strtab: strtab.as_ptr().cast(),
+            strsz: strtab.len(),
+            hash: None,
+            versym: core::ptr::null(),
+        }
+    }
+
+    pub(super) fn all(&self) -> &[Sym] {
+        unsafe { slice::from_raw_parts(self.syms, self.count) }
+    }
+
+    /// Return the symbol at `index`, which a relocation refers to.
+    pub(super) unsafe fn get(&self, index: usize) -> &Sym {
+        &*self.syms.add(index)
+    }
+
+    /// The name of `sym`, which must be in this table.
+    pub(super) fn name(&self, sym: &Sym) -> &CStr {
+        let offset = sym.st_name as usize;
+        if offset >= self.strsz {
+            return c"";
+        }
+        unsafe { CStr::from_ptr(self.strtab.add(offset)) }
+    }
+
+    /// Test whether `sym` is a definition that other objects can see.
+    pub(super) fn is_exported(&self, sym: &Sym) -> bool {
+        let bind = sym.st_info >> 4;
+        if sym.st_shndx == SHN_UNDEF || !matches!(bind, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE) {
+            return false;
+        }
+        if !self.versym.is_null() {
+            let index = unsafe { (sym as *const Sym).offset_from(self.syms) } as usize;
+            if unsafe { *self.versym.add(index) } & VERSYM_HIDDEN != 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Find the exported definition of `name`.
+    pub(super) fn lookup(&self, name: &CStr) -> Option<&Sym> {
+        let matches = |sym: &Sym| self.is_exported(sym) && self.name(sym) == name;
+        match self.hash {
+            Some(Hash::Gnu(table)) => unsafe { self.gnu_lookup(table, name, matches) },
+            Some(Hash::Sysv(table)) => unsafe { self.sysv_lookup(table, name, matches) },
+            None => self.all().iter().find(|sym| matches(sym)),
+        }
+    }
+
+    unsafe fn gnu_lookup(
+        &self,
+        table: *const u32,
+        name: &CStr,
+        matches: impl Fn(&Sym) -> bool,
+    ) -> Option<&Sym> {
+        let nbuckets = *table as usize;
+        let symoffset = *table.add(1) as usize;
+        let bloom_size = *table.add(2) as usize;
+        let buckets = table.add(4).cast::<usize>().add(bloom_size).cast::<u32>();
+        let chains = buckets.add(nbuckets);
+        if nbuckets == 0 {
+            return None;
+        }
+
+        let hash = gnu_hash(name.to_bytes());
+        let mut index = *buckets.add(hash as usize % nbuckets) as usize;
+        if index < symoffset {
+            return None;
+        }
+        loop {
+            let chain = *chains.add(index - symoffset);
+            if chain | 1 == hash | 1 {
+                let sym = &*self.syms.add(index);
+                if matches(sym) {
+                    return Some(sym);
+                }
+            }
+            if chain & 1 != 0 {
+                return None;
+            }
+            index += 1;
+        }
+    }
+
+    unsafe fn sysv_lookup(
+        &self,
+        table: *const u32,
+        name: &CStr,
+        matches: impl Fn(&Sym) -> bool,
+    ) -> Option<&Sym> {
+        let nbuckets = *table as usize;
+        let nchains = *table.add(1) as usize;
+        let buckets = table.add(2);
+        let chains = buckets.add(nbuckets);
+        if nbuckets == 0 {
+            return None;
+        }
+
+        let mut index = *buckets.add(sysv_hash(name.to_bytes()) as usize % nbuckets) as usize;
+        while index != 0 && index < nchains {
+            let sym = &*self.syms.add(index);
+            if matches(sym) {
+                return Some(sym);
+            }
+            index = *chains.add(index) as usize;
+        }
+        None
+    }
+}
+
+/// Count the symbols in a table with a GNU hash table, which doesn't record
+/// the count, by finding the end of the last chain.
+unsafe fn gnu_hash_count(table: *const u32) -> usize {
+    let nbuckets = *table as usize;
+    let symoffset = *table.add(1) as usize;
+    let bloom_size = *table.add(2) as usize;
+    let buckets = table.add(4).cast::<usize>().add(bloom_size).cast::<u32>();
+    let chains = buckets.add(nbuckets);
+
+    let last = (0..nbuckets).map(|i| *buckets.add(i) as usize).max();
+    match last {
+        Some(mut index) if index >= symoffset => {
+            while *chains.add(index - symoffset) & 1 == 0 {
+                index += 1;
+            }
+            index + 1
+        }
+        _ => symoffset,
+    }
+}
+
+fn gnu_hash(name: &[u8]) -> u32 {
+    name.iter().fold(5381_u32, |h, c| {
+        h.wrapping_mul(33).wrapping_add(u32::from(*c))
+    })
+}
+
+fn sysv_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0_u32, |h, c| {
+        let h = (h << 4).wrapping_add(u32::from(*c));
+        (h ^ ((h & 0xf000_0000) >> 24)) & 0x0fff_ffff
+    })
+}
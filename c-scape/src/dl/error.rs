@@ -0,0 +1,48 @@
+//! `dlerror`.
+
+use core::cell::SyncUnsafeCell;
+use core::ptr::null_mut;
+use libc::c_char;
+
+/// The most recent error from a `dl*` function on this thread.
+struct Error {
+    message: [u8; 256],
+    /// Whether `message` hasn't been returned by `dlerror` yet.
+    pending: bool,
+}
+
+#[cfg_attr(any(feature = "thread", not(feature = "take-charge")), thread_local)]
+static ERROR: SyncUnsafeCell<Error> = SyncUnsafeCell::new(Error {
+    message: [0; 256],
+    pending: false,
+});
+
+/// Record an error message for `dlerror`, made of `parts` concatenated,
+/// truncated if need be.
+pub(super) fn set_error(parts: &[&[u8]]) {
+    // SAFETY: `ERROR` is thread-local, and we don't hold any other
+    // references to it.
+    let error = unsafe { &mut *ERROR.get() };
+    let capacity = error.message.len() - 1;
+    let mut len = 0;
+    for part in parts {
+        let n = part.len().min(capacity - len);
+        error.message[len..len + n].copy_from_slice(&part[..n]);
+        len += n;
+    }
+    error.message[len] = b'\0';
+    error.pending = true;
+}
+
+#[no_mangle]
+unsafe extern "C" fn dlerror() -> *mut c_char {
+    libc!(libc::dlerror());
+
+    // The message stays valid until the next `dl*` failure on this thread.
+    let error = &mut *ERROR.get();
+    if !error.pending {
+        return null_mut();
+    }
+    error.pending = false;
+    error.message.as_mut_ptr().cast()
+}
@@ -0,0 +1,703 @@
+//! `dlopen` and `dlclose`, and mapping shared objects into memory.
+//!
+//! `dlopen` maps an object and everything in its `DT_NEEDED` that isn't
+//! already loaded, relocates them, and then runs their initialization
+//! functions, dependencies first. Libraries that c-scape itself implements,
+//! such as `libc.so.6` and `libm.so.6`, are never loaded; their symbols are
+//! found in the executable. The linker discards functions the executable
+//! doesn't call, so programs which load objects should link with
+//! `-Wl,--export-dynamic` (for example, with
+//! `cargo:rustc-link-arg=-Wl,--export-dynamic` in a build script), which
+//! keeps all of c-scape's functions; see the dlopen example crate.
+//!
+//! When `dlclose` releases the last reference to an object, it runs the
+//! object's finalization functions and removes it from symbol lookups, but
+//! leaves it mapped, because functions it registered with `atexit` or
+//! `__cxa_atexit` may still be pending. Objects that are still open when the
+//! program exits are finalized then.
+
+use super::elf::{Dynamic, Ehdr, Phdr, Symbols, DF_1_NODELETE, DF_1_PIE};
+use super::error::set_error;
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+use super::tls;
+use super::{executable, find_symbol, handle, reloc, LinkMap, Loaded, Object, State, STATE};
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr::{null_mut, with_exposed_provenance_mut};
+use core::slice;
+use libc::{c_char, c_int, c_void};
+use rustix::fd::{AsFd, OwnedFd};
+use rustix::fs::{Mode, OFlags};
+use rustix::io::Errno;
+use rustix::mm::{MapFlags, MprotectFlags, ProtFlags};
+
+#[cfg(target_arch = "x86_64")]
+const MACHINE: u16 = 62; // EM_X86_64
+#[cfg(target_arch = "aarch64")]
+const MACHINE: u16 = 183; // EM_AARCH64
+#[cfg(target_arch = "riscv64")]
+const MACHINE: u16 = 243; // EM_RISCV
+
+/// The directories searched after `LD_LIBRARY_PATH` and `DT_RUNPATH`.
+#[cfg(target_arch = "x86_64")]
+const DEFAULT_PATH: &[u8] =
+    b"/lib/x86_64-linux-gnu:/usr/lib/x86_64-linux-gnu:/lib64:/usr/lib64:/lib:/usr/lib";
+#[cfg(target_arch = "aarch64")]
+const DEFAULT_PATH: &[u8] =
+    b"/lib/aarch64-linux-gnu:/usr/lib/aarch64-linux-gnu:/lib64:/usr/lib64:/lib:/usr/lib";
+#[cfg(target_arch = "riscv64")]
+const DEFAULT_PATH: &[u8] =
+    b"/lib/riscv64-linux-gnu:/usr/lib/riscv64-linux-gnu:/lib64:/usr/lib64:/lib:/usr/lib";
+
+/// Libraries whose contents c-scape provides.
+const PROVIDED: &[&[u8]] = &[
+    b"libc.so",
+    b"libm.so",
+    b"libpthread.so",
+    b"libdl.so",
+    b"librt.so",
+    b"libutil.so",
+    b"ld-linux",
+    b"ld-musl",
+];
+
+#[no_mangle]
+unsafe extern "C" fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void {
+    libc!(libc::dlopen(filename, flags));
+
+    if flags & (libc::RTLD_LAZY | libc::RTLD_NOW) == 0 {
+        set_error(&[b"invalid mode for dlopen()"]);
+        return null_mut();
+    }
+    if filename.is_null() {
+        return handle(executable());
+    }
+    let name = CStr::from_ptr(filename);
+
+    let mut state = STATE.lock();
+    let start = state.loaded.len();
+    let object = match open(&mut state, name, executable(), flags) {
+        Some(object) if link(&mut state, start, flags & libc::RTLD_NOW == 0).is_some() => object,
+        _ => {
+            rollback(&mut state, start);
+            return null_mut();
+        }
+    };
+    if object.is_executable() {
+        return handle(object);
+    }
+
+    let index = state.find(handle(object)).unwrap();
+    if flags & libc::RTLD_NODELETE != 0 {
+        state.loaded[index].nodelete = true;
+    }
+    if flags & libc::RTLD_GLOBAL != 0 {
+        for member in state.loaded[index].scope.clone() {
+            let member = state.find(handle(member)).unwrap();
+            state.loaded[member].global = true;
+        }
+    }
+
+    let mut inits = Vec::new();
+    schedule_init(&mut state, index, &mut inits);
+    state.adds += (state.loaded.len() - start) as u64;
+    if !inits.is_empty() {
+        register_exit_finalizers();
+    }
+    drop(state);
+
+    for object in inits {
+        run_init(object);
+    }
+    handle(object)
+}
+
+#[no_mangle]
+unsafe extern "C" fn dlclose(handle: *mut c_void) -> c_int {
+    libc!(libc::dlclose(handle));
+
+    if handle == self::handle(executable()) {
+        return 0;
+    }
+
+    let mut state = STATE.lock();
+    let Some(index) = state.find(handle) else {
+        set_error(&[b"shared object not open"]);
+        return -1;
+    };
+    let mut finis = Vec::new();
+    release(&mut state, index, &mut finis);
+    drop(state);
+
+    for object in finis {
+        run_fini(object);
+    }
+    0
+}
+
+/// Find or load the object `name`, which `requester` needs, and take a
+/// reference to it.
+unsafe fn open(
+    state: &mut State,
+    name: &CStr,
+    requester: &Object,
+    flags: c_int,
+) -> Option<&'static Object> {
+    let file_name = name.to_bytes().rsplit(|c| *c == b'/').next().unwrap();
+    if PROVIDED.iter().any(|lib| file_name.starts_with(lib)) {
+        return Some(executable());
+    }
+
+    let by_name = |loaded: &Loaded| {
+        loaded.object.name == name
+            || loaded
+                .object
+                .dynamic
+                .soname
+                .is_some_and(|soname| loaded.object.dynamic.string(soname) == name)
+    };
+    if let Some(index) = state.loaded.iter().position(|l| !l.closed && by_name(l)) {
+        state.loaded[index].refs += 1;
+        return Some(state.loaded[index].object);
+    }
+    if flags & libc::RTLD_NOLOAD != 0 {
+        return None;
+    }
+
+    let (path, fd) = search(name, requester)?;
+    let stat = match rustix::fs::fstat(&fd) {
+        Ok(stat) => stat,
+        Err(err) => return cannot_open(name, err),
+    };
+    let (dev, ino) = (stat.st_dev as u64, stat.st_ino as u64);
+    if let Some(index) = state
+        .loaded
+        .iter()
+        .position(|l| !l.closed && l.dev == dev && l.ino == ino)
+    {
+        state.loaded[index].refs += 1;
+        return Some(state.loaded[index].object);
+    }
+
+    let (object, map) = map(path, &fd)?;
+    drop(fd);
+    let index = state.loaded.len();
+    state.loaded.push(Loaded {
+        object,
+        dev,
+        ino,
+        map,
+        refs: 1,
+        global: false,
+        nodelete: object.dynamic.flags_1 & DF_1_NODELETE != 0,
+        closed: false,
+        initialized: false,
+        deps: Vec::new(),
+        scope: Vec::new(),
+    });
+
+    for needed in &object.dynamic.needed {
+        let dep = open(state, object.dynamic.string(*needed), object, flags)?;
+        if !dep.is_executable() {
+            state.loaded[index].deps.push(dep);
+        }
+    }
+    Some(object)
+}
+
+/// Compute the lookup scopes of the objects loaded since `start`, and
+/// relocate them.
+unsafe fn link(state: &mut State, start: usize, lazy: bool) -> Option<()> {
+    for index in start..state.loaded.len() {
+        let mut scope = Vec::from([state.loaded[index].object]);
+        let mut next = 0;
+        while next < scope.len() {
+            let at = state.find(handle(scope[next])).unwrap();
+            for dep in &state.loaded[at].deps {
+                if !scope.iter().any(|member| core::ptr::eq(*member, *dep)) {
+                    scope.push(dep);
+                }
+            }
+            next += 1;
+        }
+        state.loaded[index].scope = scope;
+    }
+
+    // Relocate dependencies before the objects that need them, so that
+    // `IRELATIVE` resolvers can call into them.
+    let state = &*state;
+    for loaded in state.loaded[start..].iter().rev() {
+        let object = loaded.object;
+        let resolve =
+            |name: &CStr| find_symbol(state.global().chain(loaded.scope.iter().copied()), name);
+        reloc::relocate(object, lazy, &resolve)?;
+        protect_relro(object);
+    }
+    Some(())
+}
+
+/// Undo a failed `dlopen`, unmapping the objects loaded since `start`, and
+/// releasing the references they took to objects loaded before.
+unsafe fn rollback(state: &mut State, start: usize) {
+    for loaded in state.loaded.drain(start..).collect::<Vec<_>>() {
+        for dep in loaded.deps {
+            if let Some(index) = state.find(handle(dep)) {
+                state.loaded[index].refs -= 1;
+            }
+        }
+        remove_tls(loaded.object);
+        let _ = rustix::mm::munmap(with_exposed_provenance_mut(loaded.map.0), loaded.map.1);
+    }
+}
+
+/// Mark the object at `index`, and the objects it needs, as initialized,
+/// and append the ones that weren't already to `inits`, dependencies first.
+fn schedule_init(state: &mut State, index: usize, inits: &mut Vec<&'static Object>) {
+    if state.loaded[index].initialized {
+        return;
+    }
+    state.loaded[index].initialized = true;
+    for dep in state.loaded[index].deps.clone() {
+        let dep = state.find(handle(dep)).unwrap();
+        schedule_init(state, dep, inits);
+    }
+    let object = state.loaded[index].object;
+    inits.push(object);
+    state.inits.push(object);
+}
+
+/// Release a reference to the object at `index`. If it was the last one,
+/// close the object, append it to `finis`, and release the objects it needs.
+fn release(state: &mut State, index: usize, finis: &mut Vec<&'static Object>) {
+    let loaded = &mut state.loaded[index];
+    loaded.refs -= 1;
+    if loaded.refs != 0 || loaded.nodelete {
+        return;
+    }
+    loaded.closed = true;
+    if loaded.initialized {
+        finis.push(loaded.object);
+    }
+    remove_tls(loaded.object);
+    state.subs += 1;
+    for dep in state.loaded[index].deps.clone() {
+        if let Some(dep) = state.find(handle(dep)) {
+            release(state, dep, finis);
+        }
+    }
+}
+
+/// Arrange for the objects still open at exit to be finalized.
+#[cfg(feature = "take-charge")]
+fn register_exit_finalizers() {
+    static ONCE: rustix_futex_sync::Once = rustix_futex_sync::Once::new();
+    ONCE.call_once(|| {
+        origin::program::at_exit(Box::new(|| {
+            let mut state = STATE.lock();
+            let mut finis = Vec::new();
+            for object in core::mem::take(&mut state.inits).into_iter().rev() {
+                let index = state.find(handle(object));
+                if let Some(index) = index {
+                    state.loaded[index].closed = true;
+                    finis.push(object);
+                }
+            }
+            drop(state);
+            for object in finis {
+                unsafe { run_fini(object) };
+            }
+        }));
+    });
+}
+
+#[cfg(not(feature = "take-charge"))]
+fn register_exit_finalizers() {}
+
+/// Run `object`'s initialization functions. As in glibc, they're passed
+/// the program's `argc`, `argv`, and `environ`.
+unsafe fn run_init(object: &Object) {
+    type Init = unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char);
+
+    let dynamic = &object.dynamic;
+    let (argc, argv) = crate::env::get::load_args();
+    let environ = crate::env::set::load_environ();
+    if dynamic.init != 0 {
+        let init: Init = core::mem::transmute(dynamic.init);
+        init(argc, argv, environ);
+    }
+    for init in functions(dynamic.init_array, dynamic.init_arraysz) {
+        let init: Init = core::mem::transmute(init);
+        init(argc, argv, environ);
+    }
+}
+
+/// Run `object`'s finalization functions.
+unsafe fn run_fini(object: &Object) {
+    let dynamic = &object.dynamic;
+    for fini in functions(dynamic.fini_array, dynamic.fini_arraysz).rev() {
+        let fini: unsafe extern "C" fn() = core::mem::transmute(fini);
+        fini();
+    }
+    if dynamic.fini != 0 {
+        let fini: unsafe extern "C" fn() = core::mem::transmute(dynamic.fini);
+        fini();
+    }
+}
+
+/// Iterate over an array of function pointers, skipping the 0 and -1
+/// entries that mean there's no function.
+unsafe fn functions(addr: usize, size: usize) -> impl DoubleEndedIterator<Item = usize> {
+    let entries: &[usize] = if addr == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(addr as *const usize, size / size_of::<usize>())
+    };
+    entries
+        .iter()
+        .copied()
+        .filter(|entry| *entry != 0 && *entry != usize::MAX)
+}
+
+/// Find and open the file for `name`, which `requester` needs.
+unsafe fn search(name: &CStr, requester: &Object) -> Option<(&'static CStr, OwnedFd)> {
+    if name.to_bytes().contains(&b'/') {
+        return match open_file(name) {
+            Ok(fd) => Some((leak(name.to_bytes()), fd)),
+            Err(err) => cannot_open(name, err),
+        };
+    }
+
+    let dynamic = &requester.dynamic;
+    let mut paths = Vec::new();
+    if dynamic.runpath.is_none() {
+        paths.extend(dynamic.rpath.map(|rpath| dynamic.string(rpath).to_bytes()));
+    }
+    if !rustix::runtime::linux_secure() {
+        let library_path = crate::env::get::_getenv(b"LD_LIBRARY_PATH");
+        if !library_path.is_null() {
+            paths.push(CStr::from_ptr(library_path).to_bytes());
+        }
+    }
+    paths.extend(
+        dynamic
+            .runpath
+            .map(|runpath| dynamic.string(runpath).to_bytes()),
+    );
+    paths.push(DEFAULT_PATH);
+
+    let mut path = Vec::new();
+    for dir in paths.iter().flat_map(|list| list.split(|c| *c == b':')) {
+        path.clear();
+        expand_origin(dir, requester, &mut path);
+        if path.is_empty() {
+            path.push(b'.');
+        }
+        path.push(b'/');
+        path.extend_from_slice(name.to_bytes());
+        path.push(b'\0');
+        let candidate = CStr::from_bytes_with_nul(&path).unwrap();
+        match open_file(candidate) {
+            Ok(fd) => return Some((leak(&path[..path.len() - 1]), fd)),
+            Err(Errno::NOENT | Errno::NOTDIR | Errno::ACCESS) => {}
+            Err(err) => return cannot_open(name, err),
+        }
+    }
+    cannot_open(name, Errno::NOENT)
+}
+
+/// Append `dir` to `path`, replacing `$ORIGIN` with the directory containing
+/// `requester`.
+fn expand_origin(mut dir: &[u8], requester: &Object, path: &mut Vec<u8>) {
+    while let Some(at) = dir.iter().position(|c| *c == b'$') {
+        path.extend_from_slice(&dir[..at]);
+        let rest = &dir[at..];
+        let skip = if rest.starts_with(b"$ORIGIN") {
+            7
+        } else if rest.starts_with(b"${ORIGIN}") {
+            9
+        } else {
+            path.push(b'$');
+            dir = &rest[1..];
+            continue;
+        };
+        path.extend_from_slice(origin(requester).as_slice());
+        dir = &rest[skip..];
+    }
+    path.extend_from_slice(dir);
+}
+
+/// Return the directory containing `object`.
+fn origin(object: &Object) -> Vec<u8> {
+    let name = if object.is_executable() {
+        match rustix::fs::readlink(object.name, Vec::new()) {
+            Ok(name) => name.into_bytes(),
+            Err(_) => return Vec::from(*b"."),
+        }
+    } else {
+        object.name.to_bytes().to_vec()
+    };
+    match name.iter().rposition(|c| *c == b'/') {
+        Some(0) => Vec::from(*b"/"),
+        Some(at) => name[..at].to_vec(),
+        None => Vec::from(*b"."),
+    }
+}
+
+fn open_file(path: &CStr) -> rustix::io::Result<OwnedFd> {
+    rustix::fs::open(path, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty())
+}
+
+fn cannot_open<T>(name: &CStr, err: Errno) -> Option<T> {
+    let message = crate::error_str::error_str(err).unwrap_or("Unknown error");
+    set_error(&[
+        name.to_bytes(),
+        b": cannot open shared object file: ",
+        message.as_bytes(),
+    ]);
+    None
+}
+
+/// Make a `CStr` that lives as long as the objects do.
+fn leak(bytes: &[u8]) -> &'static CStr {
+    Box::leak(CString::new(bytes).unwrap().into_boxed_c_str())
+}
+
+/// Read exactly `buf.len()` bytes at `offset`.
+fn read_at(fd: impl AsFd, buf: &mut [MaybeUninit<u8>], offset: u64) -> bool {
+    let fd = fd.as_fd();
+    let mut done = 0;
+    while done < buf.len() {
+        match rustix::io::pread(fd, &mut buf[done..], offset + done as u64) {
+            Ok(([], _)) => return false,
+            Ok((read, _)) => done += read.len(),
+            Err(Errno::INTR) => {}
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Map the shared object in `fd` into memory, and return it along with the
+/// address and length of its mapping.
+unsafe fn map(name: &'static CStr, fd: &OwnedFd) -> Option<(&'static Object, (usize, usize))> {
+    let fail = |message: &str| -> Option<(&'static Object, (usize, usize))> {
+        set_error(&[name.to_bytes(), b": ", message.as_bytes()]);
+        None
+    };
+
+    let mut ehdr = MaybeUninit::<Ehdr>::uninit();
+    let bytes = slice::from_raw_parts_mut(ehdr.as_mut_ptr().cast(), size_of::<Ehdr>());
+    if !read_at(fd, bytes, 0) {
+        return fail("file too short");
+    }
+    let ehdr = ehdr.assume_init();
+    if ehdr.e_ident[..4] != *b"\x7fELF" || ehdr.e_ident[5] != 1 {
+        return fail("invalid ELF header");
+    }
+    if ehdr.e_ident[4] != 2 {
+        return fail("wrong ELF class: ELFCLASS32");
+    }
+    if ehdr.e_machine != MACHINE {
+        return fail("ELF file has the wrong machine type");
+    }
+    if ehdr.e_type != libc::ET_DYN {
+        return fail("cannot dynamically load executable");
+    }
+    if usize::from(ehdr.e_phentsize) != size_of::<Phdr>() {
+        return fail("ELF file's phentsize not the expected size");
+    }
+
+    let phnum = usize::from(ehdr.e_phnum);
+    let mut phdrs = Vec::<Phdr>::with_capacity(phnum);
+    let bytes = slice::from_raw_parts_mut(phdrs.as_mut_ptr().cast(), phnum * size_of::<Phdr>());
+    if !read_at(fd, bytes, ehdr.e_phoff) {
+        return fail("cannot read program headers");
+    }
+    phdrs.set_len(phnum);
+
+    let Some(pt_dynamic) = phdrs.iter().find(|phdr| phdr.p_type == libc::PT_DYNAMIC) else {
+        return fail("object has no dynamic section");
+    };
+    let loads = || phdrs.iter().filter(|phdr| phdr.p_type == libc::PT_LOAD);
+    let page = rustix::param::page_size();
+    let floor = |addr: usize| addr & !(page - 1);
+    let ceil = |addr: usize| (addr + page - 1) & !(page - 1);
+    let Some(low) = loads().map(|phdr| floor(phdr.p_vaddr as usize)).min() else {
+        return fail("object has no loadable segments");
+    };
+    let high = loads()
+        .map(|phdr| ceil((phdr.p_vaddr + phdr.p_memsz) as usize))
+        .max()
+        .unwrap();
+
+    // Reserve the whole range first, so that the segments stay in place
+    // relative to each other.
+    let span = high - low;
+    let Ok(base) = rustix::mm::mmap_anonymous(
+        null_mut(),
+        span,
+        ProtFlags::empty(),
+        MapFlags::PRIVATE | MapFlags::NORESERVE,
+    ) else {
+        return fail("cannot map object");
+    };
+    let bias = base.addr().wrapping_sub(low);
+
+    for phdr in loads() {
+        let mut prot = ProtFlags::empty();
+        if phdr.p_flags & libc::PF_R != 0 {
+            prot |= ProtFlags::READ;
+        }
+        if phdr.p_flags & libc::PF_W != 0 {
+            prot |= ProtFlags::WRITE;
+        }
+        if phdr.p_flags & libc::PF_X != 0 {
+            prot |= ProtFlags::EXEC;
+        }
+
+        let start = floor(phdr.p_vaddr as usize);
+        let file_end = (phdr.p_vaddr + phdr.p_filesz) as usize;
+        let mem_end = (phdr.p_vaddr + phdr.p_memsz) as usize;
+        if phdr.p_filesz != 0 {
+            let offset = phdr.p_offset as usize - (phdr.p_vaddr as usize - start);
+            let mapped = rustix::mm::mmap(
+                with_exposed_provenance_mut(bias + start),
+                ceil(file_end) - start,
+                prot,
+                MapFlags::PRIVATE | MapFlags::FIXED,
+                fd,
+                offset as u64,
+            );
+            if mapped.is_err() {
+                let _ = rustix::mm::munmap(base, span);
+                return fail("failed to map segment from shared object");
+            }
+        }
+        if mem_end > file_end {
+            // Zero the end of the last page of file data, and map anonymous
+            // pages for the rest.
+            let anon_start = if phdr.p_filesz != 0 {
+                let zero_end = ceil(file_end).min(mem_end);
+                if zero_end > file_end {
+                    let page_addr = with_exposed_provenance_mut(bias + floor(file_end));
+                    let writable = prot.contains(ProtFlags::WRITE);
+                    if !writable {
+                        let rw = MprotectFlags::READ | MprotectFlags::WRITE;
+                        let _ = rustix::mm::mprotect(page_addr, page, rw);
+                    }
+                    with_exposed_provenance_mut::<u8>(bias + file_end)
+                        .write_bytes(0, zero_end - file_end);
+                    if !writable {
+                        let prot = MprotectFlags::from_bits_retain(prot.bits());
+                        let _ = rustix::mm::mprotect(page_addr, page, prot);
+                    }
+                }
+                ceil(file_end)
+            } else {
+                start
+            };
+            if ceil(mem_end) > anon_start {
+                let mapped = rustix::mm::mmap_anonymous(
+                    with_exposed_provenance_mut(bias + anon_start),
+                    ceil(mem_end) - anon_start,
+                    prot,
+                    MapFlags::PRIVATE | MapFlags::FIXED,
+                );
+                if mapped.is_err() {
+                    let _ = rustix::mm::munmap(base, span);
+                    return fail("cannot map zero-fill pages");
+                }
+            }
+        }
+    }
+
+    let dynamic_addr = bias + pt_dynamic.p_vaddr as usize;
+    let dynamic = Dynamic::read(with_exposed_provenance_mut(dynamic_addr), |addr| {
+        bias.wrapping_add(addr)
+    });
+    if dynamic.flags_1 & DF_1_PIE != 0 {
+        let _ = rustix::mm::munmap(base, span);
+        return fail("cannot dynamically load position-independent executable");
+    }
+    let tls_module = match phdrs.iter().find(|phdr| phdr.p_type == libc::PT_TLS) {
+        Some(phdr) => match add_tls(bias, phdr) {
+            Some(module) => Some(module),
+            None => {
+                let _ = rustix::mm::munmap(base, span);
+                return fail("cannot handle TLS data");
+            }
+        },
+        None => None,
+    };
+    let symbols = Symbols::from_dynamic(&dynamic);
+
+    let object = Box::leak(Box::new(Object {
+        link_map: LinkMap {
+            l_addr: bias,
+            l_name: name.as_ptr(),
+            l_ld: with_exposed_provenance_mut(dynamic_addr),
+            l_next: null_mut(),
+            l_prev: null_mut(),
+        },
+        name,
+        bias,
+        phdrs: phdrs.leak(),
+        dynamic,
+        symbols,
+        tls_module,
+    }));
+    Some((object, (base.addr(), span)))
+}
+
+/// Give an object with the `PT_TLS` segment `phdr` a TLS module ID.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+fn add_tls(bias: usize, phdr: &Phdr) -> Option<usize> {
+    let size = (phdr.p_memsz as usize).max(1);
+    let layout = Layout::from_size_align(size, (phdr.p_align as usize).max(1)).ok()?;
+    Some(tls::register(tls::Image {
+        addr: bias + phdr.p_vaddr as usize,
+        filesz: phdr.p_filesz as usize,
+        layout,
+    }))
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+fn add_tls(_bias: usize, _phdr: &Phdr) -> Option<usize> {
+    None
+}
+
+/// Release the TLS module ID of an object that's being closed.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+fn remove_tls(object: &Object) {
+    if let Some(module) = object.tls_module {
+        tls::unregister(module);
+    }
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+fn remove_tls(_object: &Object) {}
+
+/// Make the object's `PT_GNU_RELRO` region read-only, now that it's been
+/// relocated.
+unsafe fn protect_relro(object: &Object) {
+    let page = rustix::param::page_size();
+    for phdr in object.phdrs {
+        if phdr.p_type == libc::PT_GNU_RELRO {
+            let start = object.bias + phdr.p_vaddr as usize;
+            let end = (start + phdr.p_memsz as usize) & !(page - 1);
+            let start = start & !(page - 1);
+            if end > start {
+                let _ = rustix::mm::mprotect(
+                    with_exposed_provenance_mut(start),
+                    end - start,
+                    MprotectFlags::READ,
+                );
+            }
+        }
+    }
+}
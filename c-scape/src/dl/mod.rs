@@ -0,0 +1,654 @@
+//! Dynamic linking: `dlopen`, `dlsym`, `dladdr`, `dlerror`, and
+//! `dl_iterate_phdr`.
+//!
+//! The executable is always the first object. Its symbols are looked up in
+//! its dynamic symbol table, and then in its full symbol table, which we read
+//! from `/proc/self/exe` the first time it's needed, so that symbols can be
+//! found whether or not the executable was linked with `-rdynamic`. Shared
+//! objects loaded by `dlopen` follow it.
+
+mod elf;
+mod error;
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+mod load;
+#[cfg(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+))]
+mod reloc;
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+mod tls;
+
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+pub(crate) use tls::get_addr as tls_get_addr;
+
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::iter::once;
+use core::mem::{align_of, size_of};
+use core::ptr::{addr_of, null, null_mut, with_exposed_provenance_mut};
+use core::slice;
+use elf::{
+    Dyn, Dynamic, Ehdr, Phdr, Shdr, Sym, Symbols, DTV_OFFSET, SHN_ABS, SHT_SYMTAB, STT_GNU_IFUNC,
+    STT_TLS,
+};
+use error::set_error;
+use libc::{c_char, c_int, c_void};
+use rustix::fs::{Mode, OFlags};
+use rustix::mm::{MapFlags, ProtFlags};
+use rustix_futex_sync::{Mutex, OnceLock};
+
+/// `dladdr1` flags.
+const RTLD_DL_SYMENT: c_int = 1;
+const RTLD_DL_LINKMAP: c_int = 2;
+
+/// The name we report for the executable.
+const EXE_NAME: &CStr = c"/proc/self/exe";
+
+/// glibc's `struct link_map`, which `dladdr1` can return.
+#[repr(C)]
+struct LinkMap {
+    l_addr: usize,
+    l_name: *const c_char,
+    l_ld: *const Dyn,
+    l_next: *mut LinkMap,
+    l_prev: *mut LinkMap,
+}
+
+/// An ELF object in memory. Handles returned by `dlopen` point to these.
+#[repr(C)]
+struct Object {
+    /// This comes first so that, as in glibc, a handle is also a pointer to
+    /// a `link_map`.
+    link_map: LinkMap,
+    name: &'static CStr,
+    /// The difference between run-time addresses and the addresses in the
+    /// ELF headers.
+    bias: usize,
+    phdrs: &'static [Phdr],
+    dynamic: Dynamic,
+    /// The dynamic symbol table, if there is one.
+    symbols: Option<Symbols>,
+    /// The TLS module ID, if the object has a `PT_TLS` segment.
+    tls_module: Option<usize>,
+}
+
+// SAFETY: `Object`s are immutable once they're set up, and describe memory
+// which is never unmapped.
+unsafe impl Send for Object {}
+unsafe impl Sync for Object {}
+
+/// An object loaded by `dlopen`.
+struct Loaded {
+    object: &'static Object,
+    /// The file's device and inode numbers, to recognize it by.
+    dev: u64,
+    ino: u64,
+    /// The address and length of the object's mapping.
+    map: (usize, usize),
+    /// The number of handles and other objects which use this object.
+    refs: usize,
+    /// Whether this object's symbols are visible to every object.
+    global: bool,
+    /// Whether `dlclose` should leave this object open.
+    nodelete: bool,
+    /// Whether this object has been finalized and removed from lookups.
+    closed: bool,
+    /// Whether this object's initialization functions have been run.
+    initialized: bool,
+    /// The objects this object needs, not counting the executable.
+    deps: Vec<&'static Object>,
+    /// This object and its dependencies, breadth-first, which are searched
+    /// after the global objects to resolve its symbols.
+    scope: Vec<&'static Object>,
+}
+
+/// The objects loaded by `dlopen`, in load order.
+struct State {
+    loaded: Vec<Loaded>,
+    /// The number of objects ever loaded and unloaded, for
+    /// `dl_iterate_phdr`.
+    adds: u64,
+    subs: u64,
+    /// Objects in the order their initialization functions ran.
+    inits: Vec<&'static Object>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    loaded: Vec::new(),
+    adds: 1,
+    subs: 0,
+    inits: Vec::new(),
+});
+
+impl State {
+    /// Iterate over the objects that haven't been closed.
+    fn live(&self) -> impl Iterator<Item = &Loaded> {
+        self.loaded.iter().filter(|loaded| !loaded.closed)
+    }
+
+    /// Find the object that `handle` refers to, if it's open.
+    fn find(&self, handle: *const c_void) -> Option<usize> {
+        self.loaded
+            .iter()
+            .position(|loaded| !loaded.closed && self::handle(loaded.object) == handle.cast_mut())
+    }
+
+    /// Iterate over the executable and every open object.
+    fn objects(&self) -> impl Iterator<Item = &'static Object> + '_ {
+        once(executable()).chain(self.live().map(|loaded| loaded.object))
+    }
+
+    /// Iterate over the objects whose symbols are visible to every object.
+    fn global(&self) -> impl Iterator<Item = &'static Object> + '_ {
+        once(executable()).chain(
+            self.live()
+                .filter(|loaded| loaded.global)
+                .map(|loaded| loaded.object),
+        )
+    }
+}
+
+/// Return the handle for `object`.
+fn handle(object: &Object) -> *mut c_void {
+    (object as *const Object).cast_mut().cast()
+}
+
+/// Find the first definition of `name` in `scope`.
+fn find_symbol<'a>(
+    scope: impl Iterator<Item = &'a Object>,
+    name: &CStr,
+) -> Option<(&'a Object, &'a Sym)> {
+    scope
+        .into_iter()
+        .find_map(|object| object.lookup(name).map(|sym| (object, sym)))
+}
+
+impl Object {
+    /// Describe the executable.
+    unsafe fn executable() -> Self {
+        extern "C" {
+            static __executable_start: c_void;
+        }
+
+        let (phdr, phent, phnum) = rustix::runtime::exe_phdrs();
+        // Some ways of getting the auxv don't report `AT_PHENT`.
+        assert!(phent == size_of::<Phdr>() || phent == 0);
+        let phdrs = slice::from_raw_parts(phdr.cast::<Phdr>(), phnum);
+
+        // Compute the bias from `PT_PHDR` if we have it. Otherwise, the ELF
+        // header is at the start of the first segment.
+        let bias = match phdrs.iter().find(|phdr| phdr.p_type == libc::PT_PHDR) {
+            Some(phdr_phdr) => phdr.addr().wrapping_sub(phdr_phdr.p_vaddr as usize),
+            None => {
+                let start = addr_of!(__executable_start).addr();
+                start.wrapping_sub(Self::first_vaddr(phdrs))
+            }
+        };
+
+        let mut object = Self {
+            link_map: LinkMap {
+                l_addr: bias,
+                l_name: EXE_NAME.as_ptr(),
+                l_ld: null(),
+                l_next: null_mut(),
+                l_prev: null_mut(),
+            },
+            name: EXE_NAME,
+            bias,
+            phdrs,
+            dynamic: Dynamic::default(),
+            symbols: None,
+            tls_module: phdrs
+                .iter()
+                .any(|phdr| phdr.p_type == libc::PT_TLS)
+                .then_some(1),
+        };
+
+        if let Some(dynamic) = phdrs.iter().find(|phdr| phdr.p_type == libc::PT_DYNAMIC) {
+            let dynamic = with_exposed_provenance_mut::<Dyn>(bias + dynamic.p_vaddr as usize);
+            object.link_map.l_ld = dynamic;
+
+            // If a dynamic linker relocated us, it may have relocated the
+            // pointers in the dynamic section too.
+            let (start, end) = object.range();
+            let addr = |ptr: usize| {
+                if (start..end).contains(&ptr) {
+                    ptr
+                } else {
+                    ptr.wrapping_add(bias)
+                }
+            };
+            object.dynamic = Dynamic::read(dynamic, addr);
+            object.symbols = Symbols::from_dynamic(&object.dynamic);
+        }
+
+        object
+    }
+
+    /// Test whether this is the executable.
+    fn is_executable(&self) -> bool {
+        core::ptr::eq(self, executable())
+    }
+
+    /// The lowest address in the `PT_LOAD` segments, rounded down to a page
+    /// boundary, before applying the bias.
+    fn first_vaddr(phdrs: &[Phdr]) -> usize {
+        let page = rustix::param::page_size();
+        phdrs
+            .iter()
+            .filter(|phdr| phdr.p_type == libc::PT_LOAD)
+            .map(|phdr| phdr.p_vaddr as usize & !(page - 1))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The range of run-time addresses covered by the `PT_LOAD` segments.
+    fn range(&self) -> (usize, usize) {
+        let end = self
+            .phdrs
+            .iter()
+            .filter(|phdr| phdr.p_type == libc::PT_LOAD)
+            .map(|phdr| (phdr.p_vaddr + phdr.p_memsz) as usize)
+            .max()
+            .unwrap_or(0);
+        (
+            self.bias.wrapping_add(Self::first_vaddr(self.phdrs)),
+            self.bias.wrapping_add(end),
+        )
+    }
+
+    /// Test whether `addr` is in one of our `PT_LOAD` segments.
+    fn contains(&self, addr: usize) -> bool {
+        self.phdrs.iter().any(|phdr| {
+            let start = self.bias.wrapping_add(phdr.p_vaddr as usize);
+            phdr.p_type == libc::PT_LOAD && (start..start + phdr.p_memsz as usize).contains(&addr)
+        })
+    }
+
+    /// The symbol tables to search, in order.
+    fn symbol_tables(&self) -> impl Iterator<Item = &Symbols> {
+        let symtab = if self.is_executable() {
+            exe_symtab()
+        } else {
+            None
+        };
+        self.symbols.iter().chain(symtab)
+    }
+
+    /// Find this object's exported definition of `name`.
+    fn lookup(&self, name: &CStr) -> Option<&Sym> {
+        self.symbol_tables()
+            .find_map(|symbols| symbols.lookup(name))
+    }
+
+    /// Record an error about this object for `dlerror`, and return `None`.
+    fn fail<T>(&self, message: &str) -> Option<T> {
+        self.fail_with(&[message.as_bytes()])
+    }
+
+    /// Like `fail`, with a message made of `parts`.
+    fn fail_with<T>(&self, parts: &[&[u8]]) -> Option<T> {
+        let mut message = Vec::from([self.name.to_bytes(), b": "]);
+        message.extend_from_slice(parts);
+        set_error(&message);
+        None
+    }
+
+    /// Compute the address that `sym` refers to.
+    unsafe fn symbol_addr(&self, sym: &Sym) -> *mut c_void {
+        let value = sym.st_value as usize;
+        match sym.st_info & 0xf {
+            STT_TLS => match self.tls_module {
+                Some(module) => tls_addr(module, value.wrapping_sub(DTV_OFFSET)),
+                None => null_mut(),
+            },
+            STT_GNU_IFUNC => {
+                let resolver: unsafe extern "C" fn() -> *mut c_void =
+                    core::mem::transmute(with_exposed_provenance_mut::<c_void>(self.bias + value));
+                resolver()
+            }
+            _ if sym.st_shndx == SHN_ABS => with_exposed_provenance_mut(value),
+            _ => with_exposed_provenance_mut(self.bias + value),
+        }
+    }
+}
+
+/// Return the address of the thread-local variable at `offset` in module
+/// `module`'s TLS block, for the current thread.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+unsafe fn tls_addr(module: usize, offset: usize) -> *mut c_void {
+    tls::get_addr(module, offset)
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+unsafe fn tls_addr(_module: usize, _offset: usize) -> *mut c_void {
+    null_mut()
+}
+
+/// Return the start of the current thread's TLS block for `module`, or null
+/// if it hasn't been allocated.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+unsafe fn tls_block(module: usize) -> *mut c_void {
+    tls::block(module)
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+unsafe fn tls_block(_module: usize) -> *mut c_void {
+    null_mut()
+}
+
+/// Return the executable's `Object`.
+fn executable() -> &'static Object {
+    static EXE: OnceLock<Object> = OnceLock::new();
+    EXE.get_or_init(|| unsafe { Object::executable() })
+}
+
+/// Return the executable's full symbol table, if we can read it.
+fn exe_symtab() -> Option<&'static Symbols> {
+    static SYMTAB: OnceLock<Option<Symbols>> = OnceLock::new();
+    SYMTAB.get_or_init(|| unsafe { read_symtab() }).as_ref()
+}
+
+/// Map the executable file, and find its `.symtab` section. The mapping is
+/// kept for the life of the process.
+unsafe fn read_symtab() -> Option<Symbols> {
+    let fd = rustix::fs::open(EXE_NAME, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()).ok()?;
+    let len = rustix::fs::fstat(&fd).ok()?.st_size as usize;
+    let map = rustix::mm::mmap(null_mut(), len, ProtFlags::READ, MapFlags::PRIVATE, &fd, 0).ok()?;
+    let file = slice::from_raw_parts(map.cast::<u8>(), len);
+    let symbols = parse_symtab(file);
+    if symbols.is_none() {
+        let _ = rustix::mm::munmap(map, len);
+    }
+    symbols
+}
+
+fn parse_symtab(file: &'static [u8]) -> Option<Symbols> {
+    let ehdr = &table::<Ehdr>(file, 0, 1)?[0];
+    if ehdr.e_ident[..4] != *b"\x7fELF" || usize::from(ehdr.e_shentsize) != size_of::<Shdr>() {
+        return None;
+    }
+    let shoff = ehdr.e_shoff as usize;
+    let mut shnum = usize::from(ehdr.e_shnum);
+    if shnum == 0 && shoff != 0 {
+        // The count is too big for the ELF header, so it's in the first
+        // section header.
+        shnum = table::<Shdr>(file, shoff, 1)?[0].sh_size as usize;
+    }
+    let shdrs = table::<Shdr>(file, shoff, shnum)?;
+
+    let symtab = shdrs.iter().find(|shdr| shdr.sh_type == SHT_SYMTAB)?;
+    let strtab = shdrs.get(symtab.sh_link as usize)?;
+    let syms = table::<Sym>(
+        file,
+        symtab.sh_offset as usize,
+        symtab.sh_size as usize / size_of::<Sym>(),
+    )?;
+    let start = strtab.sh_offset as usize;
+    let strs = file.get(start..start.checked_add(strtab.sh_size as usize)?)?;
+    if strs.last() != Some(&0) {
+        return None;
+    }
+    Some(Symbols::from_sections(syms, strs))
+}
+
+/// Return `count` `T`s at `offset` in `file`, if they're in bounds and
+/// aligned.
+fn table<T>(file: &[u8], offset: usize, count: usize) -> Option<&[T]> {
+    let size = count.checked_mul(size_of::<T>())?;
+    let bytes = file.get(offset..offset.checked_add(size)?)?;
+    if bytes.as_ptr().addr() % align_of::<T>() != 0 {
+        return None;
+    }
+    Some(unsafe { slice::from_raw_parts(bytes.as_ptr().cast(), count) })
+}
+
+/// Symbols that `std` looks up to detect features. These are available even
+/// if the linker dropped them from the executable because nothing else
+/// referred to them.
+fn builtin(symbol: &[u8]) -> *mut c_void {
+    match symbol {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        #[cfg(not(target_env = "musl"))]
+        b"statx" => libc::statx as _,
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        b"getrandom" => libc::getrandom as _,
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        b"copy_file_range" => libc::copy_file_range as _,
+        #[cfg(target_env = "gnu")]
+        b"gnu_get_libc_version" => libc::gnu_get_libc_version as _,
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        b"epoll_create1" => libc::epoll_create1 as _,
+        b"pipe2" => libc::pipe2 as _,
+        _ => null_mut(),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn dl_iterate_phdr(
+    callback: Option<
+        unsafe extern "C" fn(
+            info: *mut libc::dl_phdr_info,
+            size: usize,
+            data: *mut c_void,
+        ) -> c_int,
+    >,
+    data: *mut c_void,
+) -> c_int {
+    libc!(libc::dl_iterate_phdr(callback, data));
+
+    let callback = callback.unwrap();
+
+    // Collect the objects' information, and release the lock before calling
+    // `callback`, so that it can call `dlsym`, `dlopen`, and so on. Objects
+    // are never unmapped, so their headers stay valid.
+    let state = STATE.lock();
+    let infos = state
+        .objects()
+        .map(|object| libc::dl_phdr_info {
+            dlpi_addr: object.bias as _,
+            dlpi_name: object.name.as_ptr(),
+            dlpi_phdr: object.phdrs.as_ptr().cast(),
+            dlpi_phnum: object.phdrs.len().try_into().unwrap(),
+            dlpi_adds: state.adds,
+            dlpi_subs: state.subs,
+            dlpi_tls_modid: object.tls_module.unwrap_or(0),
+            dlpi_tls_data: object
+                .tls_module
+                .map_or(null_mut(), |module| tls_block(module)),
+        })
+        .collect::<Vec<_>>();
+    drop(state);
+
+    for mut info in infos {
+        let r = callback(&mut info, size_of::<libc::dl_phdr_info>(), data);
+        if r != 0 {
+            return r;
+        }
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void {
+    libc!(libc::dlsym(handle, symbol));
+
+    let caller = core::arch::return_address!().addr();
+    let symbol = CStr::from_ptr(symbol.cast());
+    let state = STATE.lock();
+
+    if handle == libc::RTLD_DEFAULT || handle == self::handle(executable()) {
+        match symbol.to_bytes() {
+            // Let's just say we don't support these for now.
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            b"clone3" => {}
+            b"__pthread_get_minstack" => {}
+
+            _ => {
+                // As in glibc, search the global objects, and then, for
+                // `RTLD_DEFAULT`, the local scope of the object that called
+                // us.
+                let local = state
+                    .live()
+                    .find(|loaded| handle == libc::RTLD_DEFAULT && loaded.object.contains(caller))
+                    .map(|loaded| loaded.scope.iter().copied());
+                let scope = state.global().chain(local.into_iter().flatten());
+                if let Some((object, sym)) = find_symbol(scope, symbol) {
+                    return object.symbol_addr(sym);
+                }
+                let addr = builtin(symbol.to_bytes());
+                if !addr.is_null() {
+                    return addr;
+                }
+            }
+        }
+        executable().fail_with::<()>(&[b"undefined symbol: ", symbol.to_bytes()]);
+    } else if handle == libc::RTLD_NEXT {
+        // This isn't supported. For callers in the executable, the objects
+        // after the caller would include c-scape, which is in the executable
+        // too, so we couldn't tell which definitions come after the caller's.
+        executable().fail_with::<()>(&[b"undefined symbol: ", symbol.to_bytes()]);
+    } else if let Some(index) = state.find(handle) {
+        let loaded = &state.loaded[index];
+        if let Some((object, sym)) = find_symbol(loaded.scope.iter().copied(), symbol) {
+            return object.symbol_addr(sym);
+        }
+        loaded
+            .object
+            .fail_with::<()>(&[b"undefined symbol: ", symbol.to_bytes()]);
+    } else {
+        set_error(&[b"dlsym: invalid handle"]);
+    }
+    null_mut()
+}
+
+#[no_mangle]
+unsafe extern "C" fn dladdr(addr: *const c_void, info: *mut libc::Dl_info) -> c_int {
+    libc!(libc::dladdr(addr, info));
+
+    dladdr1(addr, info, null_mut(), 0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn dladdr1(
+    addr: *const c_void,
+    info: *mut libc::Dl_info,
+    extra_info: *mut *mut c_void,
+    flags: c_int,
+) -> c_int {
+    //libc!(libc::dladdr1(addr, info, extra_info, flags));
+
+    let addr = addr.addr();
+    let state = STATE.lock();
+    let Some(object) = state.objects().find(|object| object.contains(addr)) else {
+        return 0;
+    };
+
+    // Find the closest symbol at or before `addr` which contains it, or
+    // which has no size and is exactly at it, the way glibc does.
+    let mut best: Option<(&Symbols, &Sym)> = None;
+    for symbols in object.symbol_tables() {
+        for sym in symbols.all() {
+            let value = sym.st_value as usize;
+            let start = object.bias.wrapping_add(value);
+            let usable = symbols.is_exported(sym)
+                && value != 0
+                && sym.st_shndx != SHN_ABS
+                && sym.st_info & 0xf != STT_TLS;
+            let hit = addr >= start
+                && (addr < start + sym.st_size as usize || (sym.st_size == 0 && addr == start));
+            if usable && hit && best.is_none_or(|(_, best)| best.st_value < sym.st_value) {
+                best = Some((symbols, sym));
+            }
+        }
+    }
+
+    let info = &mut *info;
+    info.dli_fname = object.name.as_ptr();
+    info.dli_fbase = with_exposed_provenance_mut(object.range().0);
+    info.dli_sname = null();
+    info.dli_saddr = null_mut();
+    if let Some((symbols, sym)) = best {
+        info.dli_sname = symbols.name(sym).as_ptr();
+        info.dli_saddr =
+            with_exposed_provenance_mut(object.bias.wrapping_add(sym.st_value as usize));
+    }
+
+    match flags {
+        RTLD_DL_SYMENT => {
+            *extra_info = best.map_or(null_mut(), |(_, sym)| (sym as *const Sym).cast_mut().cast())
+        }
+        RTLD_DL_LINKMAP => *extra_info = addr_of!(object.link_map).cast_mut().cast(),
+        _ => {}
+    }
+    1
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+#[no_mangle]
+unsafe extern "C" fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void {
+    libc!(libc::dlopen(filename, flags));
+
+    if filename.is_null() {
+        return handle(executable());
+    }
+    set_error(&[
+        CStr::from_ptr(filename).to_bytes(),
+        b": loading shared objects isn't supported on this architecture",
+    ]);
+    null_mut()
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+#[no_mangle]
+unsafe extern "C" fn dlclose(handle: *mut c_void) -> c_int {
+    libc!(libc::dlclose(handle));
+
+    if handle == self::handle(executable()) {
+        return 0;
+    }
+    set_error(&[b"shared object not open"]);
+    -1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dl_iterate_phdr_reentrant() {
+        unsafe extern "C" fn callback(
+            info: *mut libc::dl_phdr_info,
+            _size: usize,
+            data: *mut c_void,
+        ) -> c_int {
+            // The callback may call other `dl` functions.
+            let mut dl_info = core::mem::zeroed::<libc::Dl_info>();
+            *data.cast::<c_int>() = dladdr((*info).dlpi_phdr.cast(), &mut dl_info);
+            1
+        }
+
+        unsafe {
+            let mut found = 0;
+            assert_eq!(
+                dl_iterate_phdr(Some(callback), (&mut found as *mut c_int).cast()),
+                1
+            );
+            assert_eq!(found, 1);
+        }
+    }
+}
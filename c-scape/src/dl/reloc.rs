@@ -0,0 +1,487 @@
+//! Applying the relocations of a loaded object.
+//!
+//! Relocations are always bound immediately. With `RTLD_LAZY`, a PLT slot
+//! whose symbol can't be found is pointed at a function that reports the
+//! error when it's called, instead of making `dlopen` fail.
+
+use super::elf::{
+    Rela, Sym, Symbols, DF_TEXTREL, DTV_OFFSET, DT_RELA, STB_LOCAL, STB_WEAK, STT_TLS,
+};
+use super::Object;
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+use alloc::boxed::Box;
+use core::ffi::CStr;
+use core::mem::size_of;
+use core::ptr::with_exposed_provenance_mut;
+use core::slice;
+
+/// What a relocation computes, in terms of the symbol value `S`, the addend
+/// `A`, and the load bias `B`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    None,
+    /// `B + A`.
+    Relative,
+    /// `S + A`.
+    Absolute,
+    /// `S`, for a GOT or PLT slot.
+    Slot,
+    /// The result of calling the resolver at `B + A`.
+    IRelative,
+    /// The TLS module ID of the symbol's object.
+    DtpMod,
+    /// The offset of the symbol in its object's TLS block, plus `A`.
+    DtpOff,
+    /// The offset of the symbol from the thread pointer, plus `A`.
+    TpOff,
+    /// A two-word TLS descriptor.
+    TlsDesc,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn kind(r_type: u32) -> Option<Kind> {
+    Some(match r_type {
+        0 => Kind::None,
+        1 => Kind::Absolute,   // R_X86_64_64
+        6 | 7 => Kind::Slot,   // R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT
+        8 => Kind::Relative,   // R_X86_64_RELATIVE
+        16 => Kind::DtpMod,    // R_X86_64_DTPMOD64
+        17 => Kind::DtpOff,    // R_X86_64_DTPOFF64
+        18 => Kind::TpOff,     // R_X86_64_TPOFF64
+        36 => Kind::TlsDesc,   // R_X86_64_TLSDESC
+        37 => Kind::IRelative, // R_X86_64_IRELATIVE
+        _ => return None,
+    })
+}
+
+#[cfg(target_arch = "aarch64")]
+fn kind(r_type: u32) -> Option<Kind> {
+    Some(match r_type {
+        0 => Kind::None,
+        257 => Kind::Absolute,         // R_AARCH64_ABS64
+        1025 | 1026 => Kind::Absolute, // R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT
+        1027 => Kind::Relative,        // R_AARCH64_RELATIVE
+        1028 => Kind::DtpMod,          // R_AARCH64_TLS_DTPMOD
+        1029 => Kind::DtpOff,          // R_AARCH64_TLS_DTPREL
+        1030 => Kind::TpOff,           // R_AARCH64_TLS_TPREL
+        1031 => Kind::TlsDesc,         // R_AARCH64_TLSDESC
+        1032 => Kind::IRelative,       // R_AARCH64_IRELATIVE
+        _ => return None,
+    })
+}
+
+#[cfg(target_arch = "riscv64")]
+fn kind(r_type: u32) -> Option<Kind> {
+    Some(match r_type {
+        0 => Kind::None,
+        2 => Kind::Absolute,   // R_RISCV_64
+        3 => Kind::Relative,   // R_RISCV_RELATIVE
+        5 => Kind::Slot,       // R_RISCV_JUMP_SLOT
+        7 => Kind::DtpMod,     // R_RISCV_TLS_DTPMOD64
+        9 => Kind::DtpOff,     // R_RISCV_TLS_DTPREL64
+        11 => Kind::TpOff,     // R_RISCV_TLS_TPREL64
+        58 => Kind::IRelative, // R_RISCV_IRELATIVE
+        _ => return None,
+    })
+}
+
+/// Apply all of `object`'s relocations. `resolve` finds the definition of a
+/// symbol in the object's lookup scope.
+pub(super) unsafe fn relocate<'a>(
+    object: &'a Object,
+    lazy: bool,
+    resolve: &dyn Fn(&CStr) -> Option<(&'a Object, &'a Sym)>,
+) -> Option<()> {
+    let dynamic = &object.dynamic;
+    if dynamic.flags & DF_TEXTREL != 0 {
+        return object.fail("text relocations are not supported");
+    }
+    if dynamic.rel || (dynamic.pltrelsz != 0 && dynamic.pltrel != DT_RELA) {
+        return object.fail("only RELA relocations are supported");
+    }
+
+    relocate_relr(object);
+
+    let rela = table(dynamic.rela, dynamic.relasz);
+    let jmprel = table(dynamic.jmprel, dynamic.pltrelsz);
+
+    // Apply `IRELATIVE` relocations last, so that their resolvers can use
+    // anything else in the object.
+    for irelative in [false, true] {
+        for (table, plt) in [(rela, false), (jmprel, true)] {
+            for rela in table {
+                let r_type = rela.r_info as u32;
+                let Some(kind) = kind(r_type) else {
+                    let mut buf = [0_u8; 10];
+                    return object.fail_with(&[
+                        b"unsupported relocation type ",
+                        format_u32(r_type, &mut buf),
+                    ]);
+                };
+                if (kind == Kind::IRelative) == irelative {
+                    relocate_one(object, rela, kind, lazy && plt, resolve)?;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+unsafe fn table<'a>(addr: usize, size: usize) -> &'a [Rela] {
+    if addr == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(addr as *const Rela, size / size_of::<Rela>())
+}
+
+/// Apply the packed relative relocations in `DT_RELR`.
+unsafe fn relocate_relr(object: &Object) {
+    let dynamic = &object.dynamic;
+    if dynamic.relr == 0 {
+        return;
+    }
+    let bias = object.bias;
+    let entries = slice::from_raw_parts(
+        dynamic.relr as *const usize,
+        dynamic.relrsz / size_of::<usize>(),
+    );
+    let mut next = 0;
+    for entry in entries {
+        if entry & 1 == 0 {
+            // An address to relocate.
+            let place = with_exposed_provenance_mut::<usize>(bias + entry);
+            *place = (*place).wrapping_add(bias);
+            next = bias + entry + size_of::<usize>();
+        } else {
+            // A bitmap of which of the following words to relocate.
+            let mut bits = entry >> 1;
+            let mut place = with_exposed_provenance_mut::<usize>(next);
+            while bits != 0 {
+                if bits & 1 != 0 {
+                    *place = (*place).wrapping_add(bias);
+                }
+                bits >>= 1;
+                place = place.add(1);
+            }
+            next += (usize::BITS as usize - 1) * size_of::<usize>();
+        }
+    }
+}
+
+unsafe fn relocate_one<'a>(
+    object: &'a Object,
+    rela: &Rela,
+    kind: Kind,
+    lazy: bool,
+    resolve: &dyn Fn(&CStr) -> Option<(&'a Object, &'a Sym)>,
+) -> Option<()> {
+    let place = with_exposed_provenance_mut::<usize>(object.bias + rela.r_offset);
+    let addend = rela.r_addend as usize;
+    let index = rela.r_info >> 32;
+
+    // Find the symbol's definition. `None` means a missing weak symbol,
+    // which has the value 0. Index 0 means the object itself.
+    let symbols = object.symbols.as_ref();
+    let definition = match (index, symbols) {
+        (0, _) => Some((object, None)),
+        (_, None) => return object.fail("relocation refers to a missing symbol table"),
+        (index, Some(symbols)) => {
+            let sym = symbols.get(index);
+            if sym.st_info >> 4 == STB_LOCAL {
+                Some((object, Some(sym)))
+            } else if let Some((definer, sym)) = resolve(symbols.name(sym)) {
+                Some((definer, Some(sym)))
+            } else if sym.st_info >> 4 == STB_WEAK {
+                None
+            } else if lazy {
+                *place = undefined_function as *const () as usize;
+                return Some(());
+            } else {
+                return undefined(object, symbols, sym);
+            }
+        }
+    };
+
+    let value = |definition: Option<(&Object, Option<&Sym>)>| match definition {
+        Some((definer, Some(sym))) => definer.symbol_addr(sym).addr(),
+        _ => 0,
+    };
+
+    match kind {
+        Kind::None => {}
+        Kind::Relative => *place = object.bias.wrapping_add(addend),
+        Kind::Absolute => *place = value(definition).wrapping_add(addend),
+        Kind::Slot => *place = value(definition),
+        Kind::IRelative => {
+            let resolver: unsafe extern "C" fn() -> usize =
+                core::mem::transmute(object.bias.wrapping_add(addend));
+            *place = resolver();
+        }
+        Kind::DtpMod | Kind::DtpOff | Kind::TpOff | Kind::TlsDesc => {
+            let (definer, offset) = match definition {
+                Some((definer, Some(sym))) if sym.st_info & 0xf == STT_TLS => {
+                    (definer, sym.st_value as usize)
+                }
+                Some((definer, None)) => (definer, 0),
+                _ => return object.fail("TLS relocation refers to a non-TLS symbol"),
+            };
+            let Some(module) = definer.tls_module else {
+                return object.fail("TLS relocation refers to an object without TLS");
+            };
+            let offset = offset.wrapping_add(addend);
+            match kind {
+                Kind::DtpMod => *place = module,
+                Kind::DtpOff => *place = offset.wrapping_sub(DTV_OFFSET),
+                Kind::TpOff => *place = tp_offset(object, module, offset)?,
+                Kind::TlsDesc => {
+                    let (resolver, arg) = tlsdesc(object, module, offset)?;
+                    *place = resolver;
+                    *place.add(1) = arg;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+    Some(())
+}
+
+/// Report a symbol that couldn't be found.
+unsafe fn undefined(object: &Object, symbols: &Symbols, sym: &Sym) -> Option<()> {
+    object.fail_with(&[b"undefined symbol: ", symbols.name(sym).to_bytes()])
+}
+
+/// What a lazily bound PLT slot calls if its symbol couldn't be found.
+unsafe extern "C" fn undefined_function() {
+    let message = b"symbol lookup error: call to an undefined function in a dlopen'd object\n";
+    let _ = rustix::io::write(rustix::stdio::stderr(), message);
+    libc::abort();
+}
+
+/// Compute the offset from the thread pointer to `offset` in `module`'s TLS
+/// block. This is only possible for the executable, which is the only module
+/// with static TLS.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+unsafe fn tp_offset(object: &Object, module: usize, offset: usize) -> Option<usize> {
+    if module != 1 {
+        return object.fail("cannot allocate memory in static TLS block");
+    }
+    let addr = super::tls::get_addr(1, offset.wrapping_sub(DTV_OFFSET)).addr();
+    Some(addr.wrapping_sub(thread_pointer()))
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+unsafe fn tp_offset(object: &Object, _module: usize, _offset: usize) -> Option<usize> {
+    object.fail("cannot handle TLS data")
+}
+
+/// Compute the resolver function and argument of a TLS descriptor for
+/// `offset` in `module`'s TLS block.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+unsafe fn tlsdesc(object: &Object, module: usize, offset: usize) -> Option<(usize, usize)> {
+    if module == 1 {
+        let resolver = tlsdesc_static as *const () as usize;
+        return Some((resolver, tp_offset(object, module, offset)?));
+    }
+
+    // The argument is a `__tls_get_addr` argument, which is never freed,
+    // because the object is never unmapped.
+    let index = Box::leak(Box::new([module, offset.wrapping_sub(DTV_OFFSET)]));
+    let resolver = tlsdesc_dynamic as *const () as usize;
+    Some((resolver, (index as *mut [usize; 2]).addr()))
+}
+
+#[cfg(not(all(feature = "take-charge", feature = "thread")))]
+unsafe fn tlsdesc(object: &Object, _module: usize, _offset: usize) -> Option<(usize, usize)> {
+    object.fail("cannot handle TLS data")
+}
+
+/// Return the value of the thread pointer register.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+fn thread_pointer() -> usize {
+    let tp: usize;
+    // SAFETY: These just read the thread pointer. On x86_64, the word the
+    // thread pointer points to holds its own value.
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("mov {}, fs:0", out(reg) tp, options(nostack, preserves_flags, readonly));
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("mrs {}, tpidr_el0", out(reg) tp, options(nostack, preserves_flags, nomem));
+        #[cfg(target_arch = "riscv64")]
+        core::arch::asm!("mv {}, tp", out(reg) tp, options(nostack, preserves_flags, nomem));
+    }
+    tp
+}
+
+/// The resolver function for TLS descriptors of variables in static TLS. It
+/// returns the thread-pointer offset stored in the second word of the
+/// descriptor, and preserves every other register.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+#[unsafe(naked)]
+unsafe extern "C" fn tlsdesc_static() {
+    #[cfg(target_arch = "x86_64")]
+    core::arch::naked_asm!("mov rax, [rax + 8]", "ret");
+
+    #[cfg(target_arch = "aarch64")]
+    core::arch::naked_asm!("ldr x0, [x0, #8]", "ret");
+
+    // riscv64 has no TLS descriptor relocations that we support.
+    #[cfg(target_arch = "riscv64")]
+    core::arch::naked_asm!("unimp");
+}
+
+/// The resolver function for TLS descriptors of variables in dynamic TLS.
+/// The second word of the descriptor points to a `__tls_get_addr` argument.
+/// This saves every register that a call to `tlsdesc_offset` could clobber,
+/// other than the one holding the result.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+#[unsafe(naked)]
+unsafe extern "C" fn tlsdesc_dynamic() {
+    #[cfg(target_arch = "x86_64")]
+    core::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "and rsp, -16",
+        "sub rsp, 320",
+        "movaps [rsp], xmm0",
+        "movaps [rsp + 16], xmm1",
+        "movaps [rsp + 32], xmm2",
+        "movaps [rsp + 48], xmm3",
+        "movaps [rsp + 64], xmm4",
+        "movaps [rsp + 80], xmm5",
+        "movaps [rsp + 96], xmm6",
+        "movaps [rsp + 112], xmm7",
+        "movaps [rsp + 128], xmm8",
+        "movaps [rsp + 144], xmm9",
+        "movaps [rsp + 160], xmm10",
+        "movaps [rsp + 176], xmm11",
+        "movaps [rsp + 192], xmm12",
+        "movaps [rsp + 208], xmm13",
+        "movaps [rsp + 224], xmm14",
+        "movaps [rsp + 240], xmm15",
+        "mov [rsp + 256], rcx",
+        "mov [rsp + 264], rdx",
+        "mov [rsp + 272], rsi",
+        "mov [rsp + 280], rdi",
+        "mov [rsp + 288], r8",
+        "mov [rsp + 296], r9",
+        "mov [rsp + 304], r10",
+        "mov [rsp + 312], r11",
+        "mov rdi, [rax + 8]",
+        "call {tlsdesc_offset}",
+        "movaps xmm0, [rsp]",
+        "movaps xmm1, [rsp + 16]",
+        "movaps xmm2, [rsp + 32]",
+        "movaps xmm3, [rsp + 48]",
+        "movaps xmm4, [rsp + 64]",
+        "movaps xmm5, [rsp + 80]",
+        "movaps xmm6, [rsp + 96]",
+        "movaps xmm7, [rsp + 112]",
+        "movaps xmm8, [rsp + 128]",
+        "movaps xmm9, [rsp + 144]",
+        "movaps xmm10, [rsp + 160]",
+        "movaps xmm11, [rsp + 176]",
+        "movaps xmm12, [rsp + 192]",
+        "movaps xmm13, [rsp + 208]",
+        "movaps xmm14, [rsp + 224]",
+        "movaps xmm15, [rsp + 240]",
+        "mov rcx, [rsp + 256]",
+        "mov rdx, [rsp + 264]",
+        "mov rsi, [rsp + 272]",
+        "mov rdi, [rsp + 280]",
+        "mov r8, [rsp + 288]",
+        "mov r9, [rsp + 296]",
+        "mov r10, [rsp + 304]",
+        "mov r11, [rsp + 312]",
+        "mov rsp, rbp",
+        "pop rbp",
+        "ret",
+        tlsdesc_offset = sym tlsdesc_offset
+    );
+
+    #[cfg(target_arch = "aarch64")]
+    core::arch::naked_asm!(
+        "stp x29, x30, [sp, #-16]!",
+        "mov x29, sp",
+        "sub sp, sp, #656",
+        "stp x1, x2, [sp, #0]",
+        "stp x3, x4, [sp, #16]",
+        "stp x5, x6, [sp, #32]",
+        "stp x7, x8, [sp, #48]",
+        "stp x9, x10, [sp, #64]",
+        "stp x11, x12, [sp, #80]",
+        "stp x13, x14, [sp, #96]",
+        "stp x15, x16, [sp, #112]",
+        "stp x17, x18, [sp, #128]",
+        "stp q0, q1, [sp, #144]",
+        "stp q2, q3, [sp, #176]",
+        "stp q4, q5, [sp, #208]",
+        "stp q6, q7, [sp, #240]",
+        "stp q8, q9, [sp, #272]",
+        "stp q10, q11, [sp, #304]",
+        "stp q12, q13, [sp, #336]",
+        "stp q14, q15, [sp, #368]",
+        "stp q16, q17, [sp, #400]",
+        "stp q18, q19, [sp, #432]",
+        "stp q20, q21, [sp, #464]",
+        "stp q22, q23, [sp, #496]",
+        "stp q24, q25, [sp, #528]",
+        "stp q26, q27, [sp, #560]",
+        "stp q28, q29, [sp, #592]",
+        "stp q30, q31, [sp, #624]",
+        "ldr x0, [x0, #8]",
+        "bl {tlsdesc_offset}",
+        "ldp x1, x2, [sp, #0]",
+        "ldp x3, x4, [sp, #16]",
+        "ldp x5, x6, [sp, #32]",
+        "ldp x7, x8, [sp, #48]",
+        "ldp x9, x10, [sp, #64]",
+        "ldp x11, x12, [sp, #80]",
+        "ldp x13, x14, [sp, #96]",
+        "ldp x15, x16, [sp, #112]",
+        "ldp x17, x18, [sp, #128]",
+        "ldp q0, q1, [sp, #144]",
+        "ldp q2, q3, [sp, #176]",
+        "ldp q4, q5, [sp, #208]",
+        "ldp q6, q7, [sp, #240]",
+        "ldp q8, q9, [sp, #272]",
+        "ldp q10, q11, [sp, #304]",
+        "ldp q12, q13, [sp, #336]",
+        "ldp q14, q15, [sp, #368]",
+        "ldp q16, q17, [sp, #400]",
+        "ldp q18, q19, [sp, #432]",
+        "ldp q20, q21, [sp, #464]",
+        "ldp q22, q23, [sp, #496]",
+        "ldp q24, q25, [sp, #528]",
+        "ldp q26, q27, [sp, #560]",
+        "ldp q28, q29, [sp, #592]",
+        "ldp q30, q31, [sp, #624]",
+        "mov sp, x29",
+        "ldp x29, x30, [sp], #16",
+        "ret",
+        tlsdesc_offset = sym tlsdesc_offset
+    );
+
+    #[cfg(target_arch = "riscv64")]
+    core::arch::naked_asm!("unimp");
+}
+
+/// Compute the offset from the thread pointer to the variable that `index`
+/// describes, for `tlsdesc_dynamic`.
+#[cfg(all(feature = "take-charge", feature = "thread"))]
+unsafe extern "C" fn tlsdesc_offset(index: &[usize; 2]) -> usize {
+    let addr = super::tls::get_addr(index[0], index[1]).addr();
+    addr.wrapping_sub(thread_pointer())
+}
+
+/// Format `value` in decimal, for error messages.
+fn format_u32(mut value: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buf[i..];
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Dynamic TLS, for objects loaded by `dlopen`.
+//!
+//! The executable's TLS block is module 1, which origin allocates along with
+//! each thread, at a fixed offset from the thread pointer. Objects loaded by
+//! `dlopen` get the following module IDs, which aren't reused. Each thread
+//! has a DTV (dynamic thread vector) holding its blocks for those modules,
+//! each of which is allocated and initialized the first time the thread asks
+//! for an address in it.
+//!
+//! The generation counts the modules added and removed. When a thread sees
+//! that its DTV is from an older generation, it frees its blocks for modules
+//! that have been removed since.
+
+use super::elf::DTV_OFFSET;
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::ptr::{copy_nonoverlapping, null_mut, with_exposed_provenance, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use libc::c_void;
+use rustix_futex_sync::Mutex;
+
+/// The module ID of the first object after the executable.
+const FIRST: usize = 2;
+
+/// The initialization image of a module's TLS block.
+pub(super) struct Image {
+    /// The address of the initialized data in the `PT_TLS` segment.
+    pub(super) addr: usize,
+    /// The size of the initialized data. The rest of the block is zeroed.
+    pub(super) filesz: usize,
+    /// The size and alignment of the block.
+    pub(super) layout: Layout,
+}
+
+/// The images of the modules after the executable, indexed by module ID
+/// minus `FIRST`. Removed modules are `None`.
+static MODULES: Mutex<Vec<Option<Image>>> = Mutex::new(Vec::new());
+
+/// The number of times a module has been added or removed.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// A thread's TLS blocks for the modules after the executable.
+struct Dtv {
+    /// The generation that `blocks` is up to date with.
+    generation: usize,
+    /// The blocks, indexed by module ID minus `FIRST`. Blocks the thread
+    /// hasn't used yet are `None`.
+    blocks: Vec<Option<(NonNull<u8>, Layout)>>,
+}
+
+#[thread_local]
+static DTV: Cell<*mut Dtv> = Cell::new(null_mut());
+
+/// Add a module with the TLS image `image`, and return its ID.
+pub(super) fn register(image: Image) -> usize {
+    let mut modules = MODULES.lock();
+    modules.push(Some(image));
+    GENERATION.fetch_add(1, Ordering::Release);
+    FIRST + modules.len() - 1
+}
+
+/// Remove the module `module`. Threads free their blocks for it lazily.
+pub(super) fn unregister(module: usize) {
+    let mut modules = MODULES.lock();
+    modules[module - FIRST] = None;
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+/// Return the address of the variable at `offset` in module `module`'s TLS
+/// block, for the current thread, allocating the block if need be. As with
+/// `__tls_get_addr`, `offset` is biased by `DTV_OFFSET`.
+pub(crate) unsafe fn get_addr(module: usize, offset: usize) -> *mut c_void {
+    if module == 1 {
+        return origin::thread::current_tls_addr(module, offset);
+    }
+
+    let dtv = dtv();
+    let generation = GENERATION.load(Ordering::Acquire);
+    if dtv.generation != generation {
+        update(dtv, generation);
+    }
+    let block = match dtv.blocks.get(module - FIRST) {
+        Some(Some((block, _))) => *block,
+        _ => allocate(dtv, module - FIRST),
+    };
+    block
+        .as_ptr()
+        .wrapping_add(offset.wrapping_add(DTV_OFFSET))
+        .cast()
+}
+
+/// Return the start of the current thread's TLS block for `module`, or null
+/// if the thread hasn't allocated it yet.
+pub(super) unsafe fn block(module: usize) -> *mut c_void {
+    if module == 1 {
+        return origin::thread::current_tls_addr(module, DTV_OFFSET.wrapping_neg());
+    }
+
+    let Some(dtv) = DTV.get().as_ref() else {
+        return null_mut();
+    };
+    match dtv.blocks.get(module - FIRST) {
+        Some(Some((block, _))) => block.as_ptr().cast(),
+        _ => null_mut(),
+    }
+}
+
+/// Return the current thread's DTV, creating it if need be.
+unsafe fn dtv() -> &'static mut Dtv {
+    let mut dtv = DTV.get();
+    if dtv.is_null() {
+        dtv = Box::into_raw(Box::new(Dtv {
+            generation: 0,
+            blocks: Vec::new(),
+        }));
+        DTV.set(dtv);
+        origin::thread::at_exit(Box::new(|| unsafe { free_dtv() }));
+    }
+    &mut *dtv
+}
+
+/// Free the current thread's DTV and its blocks, when the thread exits.
+unsafe fn free_dtv() {
+    let dtv = Box::from_raw(DTV.replace(null_mut()));
+    for (block, layout) in dtv.blocks.into_iter().flatten() {
+        dealloc(block.as_ptr(), layout);
+    }
+}
+
+/// Free `dtv`'s blocks for modules that have been removed.
+unsafe fn update(dtv: &mut Dtv, generation: usize) {
+    let modules = MODULES.lock();
+    for (block, image) in dtv.blocks.iter_mut().zip(modules.iter()) {
+        if image.is_none() {
+            if let Some((block, layout)) = block.take() {
+                dealloc(block.as_ptr(), layout);
+            }
+        }
+    }
+    dtv.generation = generation;
+}
+
+/// Allocate and initialize the block at `index` in `dtv`.
+unsafe fn allocate(dtv: &mut Dtv, index: usize) -> NonNull<u8> {
+    let modules = MODULES.lock();
+    let Some(Some(image)) = modules.get(index) else {
+        panic!("TLS access to a module that isn't loaded");
+    };
+    let Some(block) = NonNull::new(alloc(image.layout)) else {
+        handle_alloc_error(image.layout);
+    };
+    let data = with_exposed_provenance::<u8>(image.addr);
+    copy_nonoverlapping(data, block.as_ptr(), image.filesz);
+    block
+        .as_ptr()
+        .add(image.filesz)
+        .write_bytes(0, image.layout.size() - image.filesz);
+
+    if dtv.blocks.len() <= index {
+        dtv.blocks.resize(index + 1, None);
+    }
+    dtv.blocks[index] = Some((block, image.layout));
+    block
+}
@@ -59,6 +59,15 @@ unsafe extern "C" fn getlogin() -> *mut c_char {
     _getenv(b"LOGNAME")
 }
 
+/// The program's arguments, saved for the initialization functions of
+/// objects loaded by `dlopen`.
+static mut ARGS: (c_int, *mut *mut c_char) = (0, null_mut());
+
+/// Return the `argc` and `argv` that the program was started with.
+pub(crate) unsafe fn load_args() -> (c_int, *mut *mut c_char) {
+    ARGS
+}
+
 /// GLIBC and origin pass argc, argv, and envp to functions in .init_array, as
 /// a non-standard extension. Use priority 98 so that we run before any
 /// normal user-defined constructor functions and our own functions which
@@ -67,7 +76,8 @@ unsafe extern "C" fn getlogin() -> *mut c_char {
 #[link_section = ".init_array.00098"]
 #[used]
 static INIT_ARRAY: unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char) = {
-    unsafe extern "C" fn function(_argc: c_int, _argv: *mut *mut c_char, envp: *mut *mut c_char) {
+    unsafe extern "C" fn function(argc: c_int, argv: *mut *mut c_char, envp: *mut *mut c_char) {
+        ARGS = (argc, argv);
         super::set::init_from_envp(envp);
     }
     function
@@ -0,0 +1,302 @@
+//! `fnmatch`.
+//!
+//! This matches bytes, so multibyte characters aren't treated as single
+//! characters, and classes and case folding are ASCII-only.
+
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use libc::{c_char, c_int};
+
+/// glibc's flag for ignoring anything after a `/` following the match.
+const FNM_LEADING_DIR: c_int = 1 << 3;
+/// glibc's flag for ksh-style `?(...)`, `*(...)`, `+(...)`, `@(...)`, and
+/// `!(...)` patterns.
+const FNM_EXTMATCH: c_int = 1 << 5;
+
+#[no_mangle]
+unsafe extern "C" fn fnmatch(pattern: *const c_char, string: *const c_char, flags: c_int) -> c_int {
+    libc!(libc::fnmatch(pattern, string, flags));
+
+    let pattern = CStr::from_ptr(pattern).to_bytes();
+    let string = CStr::from_ptr(string).to_bytes();
+    let matcher = Matcher { string, flags };
+    if matcher.matches(pattern, 0, string.len()) {
+        0
+    } else {
+        libc::FNM_NOMATCH
+    }
+}
+
+struct Matcher<'a> {
+    string: &'a [u8],
+    flags: c_int,
+}
+
+impl Matcher<'_> {
+    fn flag(&self, flag: c_int) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// Test whether `pattern` matches `string[i..end]`.
+    fn matches(&self, mut pattern: &[u8], mut i: usize, end: usize) -> bool {
+        loop {
+            let Some(&p) = pattern.first() else {
+                return i == end
+                    || (self.flag(FNM_LEADING_DIR)
+                        && end == self.string.len()
+                        && self.string[i] == b'/');
+            };
+
+            if self.flag(FNM_EXTMATCH) && pattern.get(1) == Some(&b'(') && b"?*+@!".contains(&p) {
+                if let Some((alternatives, rest)) =
+                    group(&pattern[1..], self.flag(libc::FNM_NOESCAPE))
+                {
+                    return self.ext(p, &alternatives, rest, i, end);
+                }
+            }
+
+            match p {
+                b'?' => {
+                    if i == end || !self.wildcard_matches(i) {
+                        return false;
+                    }
+                    pattern = &pattern[1..];
+                    i += 1;
+                }
+                b'*' => {
+                    while pattern.first() == Some(&b'*')
+                        && !(self.flag(FNM_EXTMATCH) && pattern.get(1) == Some(&b'('))
+                    {
+                        pattern = &pattern[1..];
+                    }
+                    if i < end && self.leading_period(i) {
+                        return false;
+                    }
+                    for k in i..=end {
+                        if k > i && self.flag(libc::FNM_PATHNAME) && self.string[k - 1] == b'/' {
+                            break;
+                        }
+                        if self.matches(pattern, k, end) {
+                            return true;
+                        }
+                    }
+                    return false;
+                }
+                b'[' => match self.bracket(&pattern[1..], i, end) {
+                    Some((matched, len)) => {
+                        if !matched {
+                            return false;
+                        }
+                        pattern = &pattern[1 + len..];
+                        i += 1;
+                    }
+                    // An unterminated bracket expression is an ordinary `[`.
+                    None => {
+                        if i == end || self.string[i] != b'[' {
+                            return false;
+                        }
+                        pattern = &pattern[1..];
+                        i += 1;
+                    }
+                },
+                _ => {
+                    let (literal, len) = match (p, pattern.get(1)) {
+                        (b'\\', Some(&next)) if !self.flag(libc::FNM_NOESCAPE) => (next, 2),
+                        // A trailing backslash doesn't escape anything.
+                        (b'\\', None) if !self.flag(libc::FNM_NOESCAPE) => return false,
+                        _ => (p, 1),
+                    };
+                    if i == end || !self.same(literal, self.string[i]) {
+                        return false;
+                    }
+                    pattern = &pattern[len..];
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Test whether a ksh-style pattern of kind `kind` with `alternatives`,
+    /// followed by `rest`, matches `string[i..end]`.
+    fn ext(&self, kind: u8, alternatives: &[&[u8]], rest: &[u8], i: usize, end: usize) -> bool {
+        let any = |from: usize, to: usize| alternatives.iter().any(|a| self.matches(a, from, to));
+        for k in i..=end {
+            if k > i && self.flag(libc::FNM_PATHNAME) && self.string[k - 1] == b'/' {
+                break;
+            }
+            let matched = match kind {
+                b'?' => k == i || any(i, k),
+                b'@' => any(i, k),
+                b'*' => self.repeat(alternatives, i, k),
+                b'+' => k > i && self.repeat(alternatives, i, k),
+                b'!' => !any(i, k) && !(i < end && k > i && self.leading_period(i)),
+                _ => unreachable!(),
+            };
+            if matched && self.matches(rest, k, end) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Test whether `string[i..end]` is a sequence of zero or more matches of
+    /// `alternatives`.
+    fn repeat(&self, alternatives: &[&[u8]], i: usize, end: usize) -> bool {
+        i == end
+            || (i + 1..=end).any(|k| {
+                alternatives.iter().any(|a| self.matches(a, i, k))
+                    && self.repeat(alternatives, k, end)
+            })
+    }
+
+    /// Match the bracket expression after a `[` in `pattern` against
+    /// `string[i]`. Returns whether it matched and the length of the rest of
+    /// the expression, or `None` if it isn't terminated.
+    fn bracket(&self, pattern: &[u8], i: usize, end: usize) -> Option<(bool, usize)> {
+        let noescape = self.flag(libc::FNM_NOESCAPE);
+        let mut j = 0;
+        let negate = matches!(pattern.first(), Some(b'!' | b'^'));
+        if negate {
+            j += 1;
+        }
+
+        let c = self.string.get(i).copied().filter(|_| i < end);
+        let mut matched = false;
+        let mut first = true;
+        loop {
+            let &p = pattern.get(j)?;
+            if p == b']' && !first {
+                j += 1;
+                break;
+            }
+            first = false;
+
+            if p == b'[' && matches!(pattern.get(j + 1), Some(b':' | b'=' | b'.')) {
+                let delimiter = pattern[j + 1];
+                let start = j + 2;
+                let len = pattern[start..]
+                    .windows(2)
+                    .position(|w| w[0] == delimiter && w[1] == b']')?;
+                let name = &pattern[start..start + len];
+                j = start + len + 2;
+                if let Some(c) = c {
+                    matched |= if delimiter == b':' {
+                        self.in_class(name, c)
+                    } else {
+                        name.len() == 1 && self.same(name[0], c)
+                    };
+                }
+                continue;
+            }
+
+            let (low, len) = escaped(&pattern[j..], noescape)?;
+            j += len;
+            if pattern.get(j) == Some(&b'-') && pattern.get(j + 1).is_some_and(|p| *p != b']') {
+                let (high, len) = escaped(&pattern[j + 1..], noescape)?;
+                j += 1 + len;
+                if let Some(c) = c {
+                    matched |= self.in_range(low, high, c);
+                }
+            } else if let Some(c) = c {
+                matched |= self.same(low, c);
+            }
+        }
+
+        let Some(_) = c else {
+            return Some((false, j));
+        };
+        if !self.wildcard_matches(i) {
+            return Some((false, j));
+        }
+        Some((matched != negate, j))
+    }
+
+    /// Test whether a wildcard may match `string[i]`, which it can't if it's
+    /// a `/` with `FNM_PATHNAME` or a leading period with `FNM_PERIOD`.
+    fn wildcard_matches(&self, i: usize) -> bool {
+        !(self.flag(libc::FNM_PATHNAME) && self.string[i] == b'/') && !self.leading_period(i)
+    }
+
+    /// Test whether `string[i]` is a period that only a literal period may
+    /// match.
+    fn leading_period(&self, i: usize) -> bool {
+        self.flag(libc::FNM_PERIOD)
+            && self.string[i] == b'.'
+            && (i == 0 || (self.flag(libc::FNM_PATHNAME) && self.string[i - 1] == b'/'))
+    }
+
+    fn same(&self, a: u8, b: u8) -> bool {
+        if self.flag(libc::FNM_CASEFOLD) {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    }
+
+    fn in_range(&self, low: u8, high: u8, c: u8) -> bool {
+        if self.flag(libc::FNM_CASEFOLD) {
+            [c.to_ascii_lowercase(), c.to_ascii_uppercase()]
+                .iter()
+                .any(|c| (low..=high).contains(c))
+        } else {
+            (low..=high).contains(&c)
+        }
+    }
+
+    fn in_class(&self, name: &[u8], c: u8) -> bool {
+        let casefold = self.flag(libc::FNM_CASEFOLD);
+        match name {
+            b"alnum" => c.is_ascii_alphanumeric(),
+            b"alpha" => c.is_ascii_alphabetic(),
+            b"blank" => c == b' ' || c == b'\t',
+            b"cntrl" => c.is_ascii_control(),
+            b"digit" => c.is_ascii_digit(),
+            b"graph" => c.is_ascii_graphic(),
+            b"lower" => c.is_ascii_lowercase() || (casefold && c.is_ascii_uppercase()),
+            b"print" => c.is_ascii_graphic() || c == b' ',
+            b"punct" => c.is_ascii_punctuation(),
+            b"space" => c.is_ascii_whitespace() || c == b'\x0b',
+            b"upper" => c.is_ascii_uppercase() || (casefold && c.is_ascii_lowercase()),
+            b"xdigit" => c.is_ascii_hexdigit(),
+            _ => false,
+        }
+    }
+}
+
+/// Return the possibly escaped character at the start of `pattern`, and its
+/// length.
+fn escaped(pattern: &[u8], noescape: bool) -> Option<(u8, usize)> {
+    match pattern {
+        [b'\\', c, ..] if !noescape => Some((*c, 2)),
+        [c, ..] => Some((*c, 1)),
+        [] => None,
+    }
+}
+
+/// Split the parenthesized group at the start of `pattern` into its
+/// `|`-separated alternatives, and return them along with the rest of the
+/// pattern, or `None` if it isn't terminated.
+fn group(pattern: &[u8], noescape: bool) -> Option<(Vec<&[u8]>, &[u8])> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 1;
+    let mut j = 1;
+    while let Some(&p) = pattern.get(j) {
+        match p {
+            b'\\' if !noescape => j += 1,
+            b'(' => depth += 1,
+            b')' if depth == 0 => {
+                alternatives.push(&pattern[start..j]);
+                return Some((alternatives, &pattern[j + 1..]));
+            }
+            b')' => depth -= 1,
+            b'|' if depth == 0 => {
+                alternatives.push(&pattern[start..j]);
+                start = j + 1;
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
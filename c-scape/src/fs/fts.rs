@@ -0,0 +1,810 @@
+//! `fts_open`, `fts_read`, and friends.
+//!
+//! This follows the 4.4BSD implementation that glibc uses, except that each
+//! `FTSENT` holds its own copy of its path, rather than pointing into a
+//! buffer shared by the whole stream, so `FTS`'s `fts_path` and
+//! `fts_pathlen` fields aren't used.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::mem::{align_of, offset_of, size_of, transmute, zeroed};
+use core::ptr::{copy_nonoverlapping, null, null_mut, write_bytes};
+use errno::{errno, set_errno, Errno};
+use libc::{c_char, c_int, c_long, c_short, c_ushort, c_void};
+
+// Options for `fts_open`, from glibc.
+const FTS_COMFOLLOW: c_int = 0x0001;
+const FTS_LOGICAL: c_int = 0x0002;
+const FTS_NOCHDIR: c_int = 0x0004;
+const FTS_NOSTAT: c_int = 0x0008;
+const FTS_PHYSICAL: c_int = 0x0010;
+const FTS_SEEDOT: c_int = 0x0020;
+const FTS_XDEV: c_int = 0x0040;
+const FTS_OPTIONMASK: c_int = 0x00ff;
+const FTS_NAMEONLY: c_int = 0x0100;
+const FTS_STOP: c_int = 0x0200;
+
+const FTS_ROOTPARENTLEVEL: c_short = -1;
+const FTS_ROOTLEVEL: c_short = 0;
+
+// Values of `fts_info`.
+const FTS_D: c_ushort = 1;
+const FTS_DC: c_ushort = 2;
+const FTS_DEFAULT: c_ushort = 3;
+const FTS_DNR: c_ushort = 4;
+const FTS_DOT: c_ushort = 5;
+const FTS_DP: c_ushort = 6;
+const FTS_ERR: c_ushort = 7;
+const FTS_F: c_ushort = 8;
+const FTS_INIT: c_ushort = 9;
+const FTS_NS: c_ushort = 10;
+const FTS_NSOK: c_ushort = 11;
+const FTS_SL: c_ushort = 12;
+const FTS_SLNONE: c_ushort = 13;
+
+// Values of `fts_flags`.
+const FTS_DONTCHDIR: c_ushort = 0x01;
+const FTS_SYMFOLLOW: c_ushort = 0x02;
+
+// Instructions for `fts_set`.
+const FTS_AGAIN: c_ushort = 1;
+const FTS_FOLLOW: c_ushort = 2;
+const FTS_NOINSTR: c_ushort = 3;
+const FTS_SKIP: c_ushort = 4;
+
+type Compar = unsafe extern "C" fn(*const *const FtsEnt, *const *const FtsEnt) -> c_int;
+
+/// glibc's `FTS`.
+#[repr(C)]
+struct Fts {
+    fts_cur: *mut FtsEnt,
+    fts_child: *mut FtsEnt,
+    fts_array: *mut *mut FtsEnt,
+    fts_dev: libc::dev_t,
+    fts_path: *mut c_char,
+    fts_rfd: c_int,
+    fts_pathlen: c_int,
+    fts_nitems: c_int,
+    fts_compar: Option<Compar>,
+    fts_options: c_int,
+}
+
+impl Fts {
+    fn flag(&self, flag: c_int) -> bool {
+        self.fts_options & flag == flag
+    }
+
+    /// `fchdir` to `fd`, unless we're not changing directories.
+    unsafe fn fchdir(&self, fd: c_int) -> bool {
+        self.flag(FTS_NOCHDIR) || libc::fchdir(fd) == 0
+    }
+}
+
+/// glibc's `FTSENT`.
+#[repr(C)]
+struct FtsEnt {
+    fts_cycle: *mut FtsEnt,
+    fts_parent: *mut FtsEnt,
+    fts_link: *mut FtsEnt,
+    fts_number: c_long,
+    fts_pointer: *mut c_void,
+    fts_accpath: *mut c_char,
+    fts_path: *mut c_char,
+    fts_errno: c_int,
+    fts_symfd: c_int,
+    fts_pathlen: c_ushort,
+    fts_namelen: c_ushort,
+    fts_ino: libc::ino_t,
+    fts_dev: libc::dev_t,
+    fts_nlink: libc::nlink_t,
+    fts_level: c_short,
+    fts_info: c_ushort,
+    fts_flags: c_ushort,
+    fts_instr: c_ushort,
+    fts_statp: *mut libc::stat,
+    fts_name: [c_char; 1],
+}
+
+#[no_mangle]
+unsafe extern "C" fn fts_open(
+    argv: *const *mut c_char,
+    options: c_int,
+    compar: Option<Compar>,
+) -> *mut Fts {
+    //libc!(libc::fts_open(argv, options, compar));
+
+    if options & !FTS_OPTIONMASK != 0 {
+        set_errno(Errno(libc::EINVAL));
+        return null_mut();
+    }
+
+    let mut sp = Box::new(Fts {
+        fts_cur: null_mut(),
+        fts_child: null_mut(),
+        fts_array: null_mut(),
+        fts_dev: 0,
+        fts_path: null_mut(),
+        fts_rfd: -1,
+        fts_pathlen: 0,
+        fts_nitems: 0,
+        fts_compar: compar,
+        fts_options: options,
+    });
+
+    // Logical walks follow symlinks, so we can't reliably find our way back
+    // up with `..`.
+    if sp.flag(FTS_LOGICAL) {
+        sp.fts_options |= FTS_NOCHDIR;
+    }
+
+    let parent = alloc(b"", b"");
+    if parent.is_null() {
+        return null_mut();
+    }
+    (*parent).fts_level = FTS_ROOTPARENTLEVEL;
+
+    let mut roots = Vec::new();
+    let mut argv = argv;
+    while !(*argv).is_null() {
+        let arg = CStr::from_ptr(*argv).to_bytes();
+        argv = argv.add(1);
+        let p = if arg.is_empty() || arg.len() >= c_ushort::MAX as usize {
+            set_errno(Errno(if arg.is_empty() {
+                libc::ENOENT
+            } else {
+                libc::ENAMETOOLONG
+            }));
+            null_mut()
+        } else {
+            alloc(arg, arg)
+        };
+        if p.is_null() {
+            roots
+                .iter()
+                .for_each(|p: &*mut FtsEnt| libc::free(p.cast()));
+            libc::free(parent.cast());
+            return null_mut();
+        }
+        (*p).fts_level = FTS_ROOTLEVEL;
+        (*p).fts_parent = parent;
+        (*p).fts_accpath = (*p).fts_name.as_mut_ptr();
+        (*p).fts_info = stat(&sp, p, sp.flag(FTS_COMFOLLOW));
+
+        // "." and ".." named as roots are real directories.
+        if (*p).fts_info == FTS_DOT {
+            (*p).fts_info = FTS_D;
+        }
+        roots.push(p);
+    }
+    for pair in roots.windows(2) {
+        (*pair[0]).fts_link = pair[1];
+    }
+    let root = sort(&sp, roots.first().copied().unwrap_or(null_mut()));
+
+    // Start with a dummy entry linked to the roots, as if we'd just visited
+    // the entry before them.
+    let cur = alloc(b"", b"");
+    if cur.is_null() {
+        free_list(root);
+        libc::free(parent.cast());
+        return null_mut();
+    }
+    (*cur).fts_link = root;
+    (*cur).fts_parent = parent;
+    (*cur).fts_info = FTS_INIT;
+    sp.fts_cur = cur;
+
+    // Remember where we started, so that we can get back to it.
+    if !sp.flag(FTS_NOCHDIR) {
+        sp.fts_rfd = libc::open(c".".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+        if sp.fts_rfd < 0 {
+            sp.fts_options |= FTS_NOCHDIR;
+        }
+    }
+
+    Box::into_raw(sp)
+}
+
+#[no_mangle]
+unsafe extern "C" fn fts_read(sp: *mut Fts) -> *mut FtsEnt {
+    //libc!(libc::fts_read(sp));
+
+    let sp = &mut *sp;
+    if sp.fts_cur.is_null() || sp.flag(FTS_STOP) {
+        return null_mut();
+    }
+
+    let mut p = sp.fts_cur;
+    let instr = (*p).fts_instr;
+    (*p).fts_instr = FTS_NOINSTR;
+
+    // Any kind of file may be visited again.
+    if instr == FTS_AGAIN {
+        (*p).fts_info = stat(sp, p, false);
+        return p;
+    }
+
+    // Follow a symlink, remembering where we are if it's to a directory.
+    if instr == FTS_FOLLOW && ((*p).fts_info == FTS_SL || (*p).fts_info == FTS_SLNONE) {
+        follow(sp, p);
+        return p;
+    }
+
+    if (*p).fts_info == FTS_D {
+        // If we're skipping the directory, or it's on another device, go
+        // straight to its postorder visit.
+        if instr == FTS_SKIP || (sp.flag(FTS_XDEV) && (*p).fts_dev != sp.fts_dev) {
+            if (*p).fts_flags & FTS_SYMFOLLOW != 0 {
+                libc::close((*p).fts_symfd);
+            }
+            free_list(sp.fts_child);
+            sp.fts_child = null_mut();
+            (*p).fts_info = FTS_DP;
+            return p;
+        }
+
+        // If `fts_children` only read the names, read the entries properly.
+        if !sp.fts_child.is_null() && sp.flag(FTS_NAMEONLY) {
+            sp.fts_options &= !FTS_NAMEONLY;
+            free_list(sp.fts_child);
+            sp.fts_child = null_mut();
+        }
+
+        if !sp.fts_child.is_null() {
+            // `fts_children` already read the entries; we just need to
+            // change to the directory. If we can't, access the entries
+            // through the directory's path instead.
+            if !safe_changedir(sp, p, -1, (*p).fts_accpath) {
+                (*p).fts_errno = errno().0;
+                (*p).fts_flags |= FTS_DONTCHDIR;
+                let mut child = sp.fts_child;
+                while !child.is_null() {
+                    (*child).fts_accpath = (*(*child).fts_parent).fts_accpath;
+                    child = (*child).fts_link;
+                }
+            }
+        } else {
+            sp.fts_child = build(sp, Build::Read);
+            if sp.fts_child.is_null() {
+                if sp.flag(FTS_STOP) {
+                    return null_mut();
+                }
+                return p;
+            }
+        }
+        p = sp.fts_child;
+        sp.fts_child = null_mut();
+        sp.fts_cur = p;
+        return p;
+    }
+
+    // Move to the next entry in this directory.
+    loop {
+        let tmp = p;
+        p = (*p).fts_link;
+        if p.is_null() {
+            p = tmp;
+            break;
+        }
+        sp.fts_cur = p;
+        libc::free(tmp.cast());
+
+        // At the next root, go back to where we started.
+        if (*p).fts_level == FTS_ROOTLEVEL {
+            if !sp.fchdir(sp.fts_rfd) {
+                sp.fts_options |= FTS_STOP;
+                return null_mut();
+            }
+            load(sp, p);
+            return p;
+        }
+
+        // Honor any `fts_set` calls made on the entry while it was in the
+        // list returned by `fts_children`.
+        if (*p).fts_instr == FTS_SKIP {
+            continue;
+        }
+        if (*p).fts_instr == FTS_FOLLOW {
+            follow(sp, p);
+            (*p).fts_instr = FTS_NOINSTR;
+        }
+        return p;
+    }
+
+    // Move up to the parent directory.
+    let tmp = p;
+    p = (*tmp).fts_parent;
+    sp.fts_cur = p;
+    libc::free(tmp.cast());
+
+    if (*p).fts_level == FTS_ROOTPARENTLEVEL {
+        // We're done. Set `errno` to zero to distinguish this from an error.
+        libc::free(p.cast());
+        set_errno(Errno(0));
+        sp.fts_cur = null_mut();
+        return null_mut();
+    }
+
+    // Return to the parent, through the descriptor we saved if we got here
+    // through the root or a symlink, or through `..` otherwise.
+    if (*p).fts_level == FTS_ROOTLEVEL {
+        if !sp.fchdir(sp.fts_rfd) {
+            sp.fts_options |= FTS_STOP;
+            return null_mut();
+        }
+    } else if (*p).fts_flags & FTS_SYMFOLLOW != 0 {
+        let ok = sp.fchdir((*p).fts_symfd);
+        close((*p).fts_symfd);
+        if !ok {
+            sp.fts_options |= FTS_STOP;
+            return null_mut();
+        }
+    } else if (*p).fts_flags & FTS_DONTCHDIR == 0
+        && !safe_changedir(sp, (*p).fts_parent, -1, c"..".as_ptr())
+    {
+        sp.fts_options |= FTS_STOP;
+        return null_mut();
+    }
+    (*p).fts_info = if (*p).fts_errno != 0 { FTS_ERR } else { FTS_DP };
+    p
+}
+
+#[no_mangle]
+unsafe extern "C" fn fts_children(sp: *mut Fts, instr: c_int) -> *mut FtsEnt {
+    //libc!(libc::fts_children(sp, instr));
+
+    let sp = &mut *sp;
+    if instr != 0 && instr != FTS_NAMEONLY {
+        set_errno(Errno(libc::EINVAL));
+        return null_mut();
+    }
+
+    let p = sp.fts_cur;
+    set_errno(Errno(0));
+    if sp.flag(FTS_STOP) {
+        return null_mut();
+    }
+
+    // Before the first `fts_read`, the children are the roots.
+    if (*p).fts_info == FTS_INIT {
+        return (*p).fts_link;
+    }
+
+    // Only directories in preorder have children we can read.
+    if (*p).fts_info != FTS_D {
+        return null_mut();
+    }
+
+    free_list(sp.fts_child);
+    sp.fts_child = null_mut();
+
+    let kind = if instr == FTS_NAMEONLY {
+        sp.fts_options |= FTS_NAMEONLY;
+        Build::Names
+    } else {
+        Build::Child
+    };
+
+    // If `fts_read` hasn't yet changed to the directory containing a root
+    // with a relative path, we need to find our way back after reading it.
+    if (*p).fts_level != FTS_ROOTLEVEL
+        || *(*p).fts_accpath == b'/' as c_char
+        || sp.flag(FTS_NOCHDIR)
+    {
+        sp.fts_child = build(sp, kind);
+        return sp.fts_child;
+    }
+    let fd = libc::open(c".".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return null_mut();
+    }
+    sp.fts_child = build(sp, kind);
+    let ok = libc::fchdir(fd) == 0;
+    close(fd);
+    if !ok {
+        return null_mut();
+    }
+    sp.fts_child
+}
+
+#[no_mangle]
+unsafe extern "C" fn fts_set(sp: *mut Fts, p: *mut FtsEnt, instr: c_int) -> c_int {
+    //libc!(libc::fts_set(sp, p, instr));
+
+    let _ = sp;
+    match c_ushort::try_from(instr) {
+        Ok(instr @ (0 | FTS_AGAIN | FTS_FOLLOW | FTS_NOINSTR | FTS_SKIP)) => {
+            (*p).fts_instr = instr;
+            0
+        }
+        _ => {
+            set_errno(Errno(libc::EINVAL));
+            1
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn fts_close(sp: *mut Fts) -> c_int {
+    //libc!(libc::fts_close(sp));
+
+    let sp = Box::from_raw(sp);
+
+    // Free the current entry, the rest of its directory, and its ancestors.
+    // Before the first `fts_read`, the current entry is linked to the roots,
+    // which are linked to their parent, so this works then too.
+    let mut p = sp.fts_cur;
+    if !p.is_null() {
+        while (*p).fts_level >= FTS_ROOTLEVEL {
+            let next = if (*p).fts_link.is_null() {
+                (*p).fts_parent
+            } else {
+                (*p).fts_link
+            };
+            libc::free(p.cast());
+            p = next;
+        }
+        libc::free(p.cast());
+    }
+    free_list(sp.fts_child);
+
+    // Return to where we started.
+    if !sp.flag(FTS_NOCHDIR) {
+        let ok = libc::fchdir(sp.fts_rfd) == 0;
+        close(sp.fts_rfd);
+        if !ok {
+            return -1;
+        }
+    }
+    0
+}
+
+#[derive(PartialEq, Eq)]
+enum Build {
+    /// Read the entries of the current directory for `fts_read`, changing
+    /// to it.
+    Read,
+    /// Read the entries for `fts_children`.
+    Child,
+    /// Read just the names of the entries for `fts_children`.
+    Names,
+}
+
+/// Read the entries of the directory `sp.fts_cur` into a list.
+unsafe fn build(sp: &mut Fts, kind: Build) -> *mut FtsEnt {
+    let cur = sp.fts_cur;
+    let mut dirp = libc::opendir((*cur).fts_accpath);
+    if dirp.is_null() {
+        if kind == Build::Read {
+            (*cur).fts_info = FTS_DNR;
+            (*cur).fts_errno = errno().0;
+        }
+        return null_mut();
+    }
+
+    // `nlinks` is the number of subdirectories left to find if we're using
+    // the link count to avoid `stat` calls, zero if we're not calling `stat`
+    // at all, or -1 if we're calling `stat` on everything.
+    let (mut nlinks, nostat) = if kind == Build::Names {
+        (0, false)
+    } else if sp.flag(FTS_NOSTAT) && sp.flag(FTS_PHYSICAL) {
+        let dots = if sp.flag(FTS_SEEDOT) { 0 } else { 2 };
+        ((*cur).fts_nlink as i64 - dots, true)
+    } else {
+        (-1, false)
+    };
+
+    // Change to the directory if we need to `stat` its entries or descend
+    // into it. If we can't, we can still return the names, and report the
+    // error after the postorder visit.
+    let mut cderrno = 0;
+    let mut descend = false;
+    if nlinks != 0 || kind == Build::Read {
+        if safe_changedir(sp, cur, libc::dirfd(dirp), null()) {
+            descend = true;
+        } else {
+            cderrno = errno().0;
+            if nlinks != 0 && kind == Build::Read {
+                (*cur).fts_errno = cderrno;
+            }
+            (*cur).fts_flags |= FTS_DONTCHDIR;
+            libc::closedir(dirp);
+            dirp = null_mut();
+        }
+    }
+
+    let level = (*cur).fts_level + 1;
+    let prefix = {
+        let path = CStr::from_ptr((*cur).fts_path).to_bytes();
+        path.strip_suffix(b"/").unwrap_or(path)
+    };
+    let mut head: *mut FtsEnt = null_mut();
+    let mut tail: *mut FtsEnt = null_mut();
+    let mut path = Vec::new();
+    while !dirp.is_null() {
+        let dp = libc::readdir64(dirp);
+        if dp.is_null() {
+            break;
+        }
+        let name = CStr::from_ptr((*dp).d_name.as_ptr()).to_bytes();
+        if !sp.flag(FTS_SEEDOT) && (name == b"." || name == b"..") {
+            continue;
+        }
+
+        path.clear();
+        path.extend_from_slice(prefix);
+        path.push(b'/');
+        path.extend_from_slice(name);
+        let p = if path.len() >= c_ushort::MAX as usize {
+            set_errno(Errno(libc::ENAMETOOLONG));
+            null_mut()
+        } else {
+            alloc(name, &path)
+        };
+        if p.is_null() {
+            let err = errno();
+            free_list(head);
+            libc::closedir(dirp);
+            (*cur).fts_info = FTS_ERR;
+            sp.fts_options |= FTS_STOP;
+            set_errno(err);
+            return null_mut();
+        }
+        (*p).fts_level = level;
+        (*p).fts_parent = cur;
+
+        let accpath = if sp.flag(FTS_NOCHDIR) {
+            (*p).fts_path
+        } else {
+            (*p).fts_name.as_mut_ptr()
+        };
+        if cderrno != 0 {
+            if nlinks != 0 {
+                (*p).fts_info = FTS_NS;
+                (*p).fts_errno = cderrno;
+            } else {
+                (*p).fts_info = FTS_NSOK;
+            }
+            (*p).fts_accpath = (*cur).fts_accpath;
+        } else if nlinks == 0
+            || (nostat && (*dp).d_type != libc::DT_DIR && (*dp).d_type != libc::DT_UNKNOWN)
+        {
+            (*p).fts_accpath = accpath;
+            (*p).fts_info = FTS_NSOK;
+        } else {
+            (*p).fts_accpath = accpath;
+            (*p).fts_info = stat(sp, p, false);
+            if nlinks > 0 && matches!((*p).fts_info, FTS_D | FTS_DC | FTS_DOT) {
+                nlinks -= 1;
+            }
+        }
+
+        // Keep the entries in directory order.
+        if head.is_null() {
+            head = p;
+        } else {
+            (*tail).fts_link = p;
+        }
+        tail = p;
+    }
+    if !dirp.is_null() {
+        libc::closedir(dirp);
+    }
+
+    // If we changed to the directory just to read it for `fts_children`, or
+    // it's empty, go back.
+    if descend
+        && (kind == Build::Child || head.is_null())
+        && !(if (*cur).fts_level == FTS_ROOTLEVEL {
+            sp.fchdir(sp.fts_rfd)
+        } else {
+            safe_changedir(sp, (*cur).fts_parent, -1, c"..".as_ptr())
+        })
+    {
+        free_list(head);
+        (*cur).fts_info = FTS_ERR;
+        sp.fts_options |= FTS_STOP;
+        return null_mut();
+    }
+
+    if head.is_null() {
+        if kind == Build::Read {
+            (*cur).fts_info = FTS_DP;
+        }
+        return null_mut();
+    }
+
+    sort(sp, head)
+}
+
+/// `stat` the entry `p`, following symlinks if `follow` is set or we're
+/// doing a logical walk, and return its `fts_info`.
+unsafe fn stat(sp: &Fts, p: *mut FtsEnt, follow: bool) -> c_ushort {
+    let statp = (*p).fts_statp;
+    let accpath = (*p).fts_accpath;
+
+    if sp.flag(FTS_LOGICAL) || follow {
+        if libc::stat(accpath, statp) != 0 {
+            let err = errno();
+            if libc::lstat(accpath, statp) == 0 {
+                set_errno(Errno(0));
+                return FTS_SLNONE;
+            }
+            (*p).fts_errno = err.0;
+            statp.write(zeroed());
+            return FTS_NS;
+        }
+    } else if libc::lstat(accpath, statp) != 0 {
+        (*p).fts_errno = errno().0;
+        statp.write(zeroed());
+        return FTS_NS;
+    }
+
+    match (*statp).st_mode & libc::S_IFMT {
+        libc::S_IFDIR => {
+            // Remember the identity of the directory, to detect cycles and
+            // crossing devices, and its link count, to limit `stat` calls.
+            (*p).fts_dev = (*statp).st_dev;
+            (*p).fts_ino = (*statp).st_ino;
+            (*p).fts_nlink = (*statp).st_nlink;
+
+            let name = CStr::from_ptr((*p).fts_name.as_ptr()).to_bytes();
+            if name == b"." || name == b".." {
+                return FTS_DOT;
+            }
+
+            let mut t = (*p).fts_parent;
+            while (*t).fts_level >= FTS_ROOTLEVEL {
+                if (*t).fts_dev == (*p).fts_dev && (*t).fts_ino == (*p).fts_ino {
+                    (*p).fts_cycle = t;
+                    return FTS_DC;
+                }
+                t = (*t).fts_parent;
+            }
+            FTS_D
+        }
+        libc::S_IFLNK => FTS_SL,
+        libc::S_IFREG => FTS_F,
+        _ => FTS_DEFAULT,
+    }
+}
+
+/// Follow the symlink `p` for `FTS_FOLLOW`, remembering where we are if it
+/// leads to a directory so that we can get back.
+unsafe fn follow(sp: &Fts, p: *mut FtsEnt) {
+    (*p).fts_info = stat(sp, p, true);
+    if (*p).fts_info == FTS_D && !sp.flag(FTS_NOCHDIR) {
+        (*p).fts_symfd = libc::open(c".".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+        if (*p).fts_symfd < 0 {
+            (*p).fts_errno = errno().0;
+            (*p).fts_info = FTS_ERR;
+        } else {
+            (*p).fts_flags |= FTS_SYMFOLLOW;
+        }
+    }
+}
+
+/// Prepare to visit the root `p`. Its path is what was passed to `fts_open`,
+/// and its name is the last component of that.
+unsafe fn load(sp: &mut Fts, p: *mut FtsEnt) {
+    let name = (*p).fts_name.as_mut_ptr();
+    let len = usize::from((*p).fts_namelen);
+    let bytes = core::slice::from_raw_parts(name.cast::<u8>(), len);
+    if let Some(slash) = bytes.iter().rposition(|c| *c == b'/') {
+        if slash != 0 || len > 1 {
+            let rest = len - slash - 1;
+            core::ptr::copy(name.add(slash + 1), name, rest + 1);
+            (*p).fts_namelen = rest as c_ushort;
+        }
+    }
+    (*p).fts_accpath = (*p).fts_path;
+    sp.fts_dev = (*p).fts_dev;
+}
+
+/// Change to the directory `p`, through `fd` if it's not -1 or `path`
+/// otherwise, checking that it's the directory we expect. Return whether we
+/// succeeded.
+unsafe fn safe_changedir(sp: &Fts, p: *mut FtsEnt, fd: c_int, path: *const c_char) -> bool {
+    if sp.flag(FTS_NOCHDIR) {
+        return true;
+    }
+    let newfd = if fd < 0 {
+        libc::open(path, libc::O_RDONLY | libc::O_CLOEXEC)
+    } else {
+        fd
+    };
+    if newfd < 0 {
+        return false;
+    }
+
+    let mut stat: libc::stat = zeroed();
+    let ok = if libc::fstat(newfd, &mut stat) != 0 {
+        false
+    } else if stat.st_dev != (*p).fts_dev || stat.st_ino != (*p).fts_ino {
+        set_errno(Errno(libc::ENOENT));
+        false
+    } else {
+        libc::fchdir(newfd) == 0
+    };
+    if fd < 0 {
+        close(newfd);
+    }
+    ok
+}
+
+/// Sort the list `head` with the stream's comparison function, if it has
+/// one.
+unsafe fn sort(sp: &Fts, head: *mut FtsEnt) -> *mut FtsEnt {
+    let Some(compar) = sp.fts_compar else {
+        return head;
+    };
+
+    let mut entries = Vec::new();
+    let mut p = head;
+    while !p.is_null() {
+        entries.push(p);
+        p = (*p).fts_link;
+    }
+    if entries.len() < 2 {
+        return head;
+    }
+
+    libc::qsort(
+        entries.as_mut_ptr().cast(),
+        entries.len(),
+        size_of::<*mut FtsEnt>(),
+        Some(transmute::<
+            Compar,
+            unsafe extern "C" fn(*const c_void, *const c_void) -> c_int,
+        >(compar)),
+    );
+    for pair in entries.windows(2) {
+        (*pair[0]).fts_link = pair[1];
+    }
+    let last = entries[entries.len() - 1];
+    (*last).fts_link = null_mut();
+    entries[0]
+}
+
+/// Allocate an entry named `name` with path `path`, with room for its `stat`
+/// buffer.
+unsafe fn alloc(name: &[u8], path: &[u8]) -> *mut FtsEnt {
+    let name_offset = offset_of!(FtsEnt, fts_name);
+    let path_offset = name_offset + name.len() + 1;
+    let stat_offset = (path_offset + path.len() + 1).next_multiple_of(align_of::<libc::stat>());
+    let size = (stat_offset + size_of::<libc::stat>()).max(size_of::<FtsEnt>());
+
+    let p = libc::malloc(size).cast::<u8>();
+    if p.is_null() {
+        return null_mut();
+    }
+    write_bytes(p, 0, size);
+    copy_nonoverlapping(name.as_ptr(), p.add(name_offset), name.len());
+    copy_nonoverlapping(path.as_ptr(), p.add(path_offset), path.len());
+
+    let p = p.cast::<FtsEnt>();
+    (*p).fts_path = p.cast::<c_char>().add(path_offset);
+    (*p).fts_pathlen = path.len() as c_ushort;
+    (*p).fts_namelen = name.len() as c_ushort;
+    (*p).fts_statp = p.cast::<u8>().add(stat_offset).cast();
+    (*p).fts_instr = FTS_NOINSTR;
+    (*p).fts_symfd = -1;
+    p
+}
+
+/// Free the entries in the list `head`.
+unsafe fn free_list(mut head: *mut FtsEnt) {
+    while !head.is_null() {
+        let next = (*head).fts_link;
+        libc::free(head.cast());
+        head = next;
+    }
+}
+
+/// Close `fd`, preserving `errno`.
+unsafe fn close(fd: c_int) {
+    let err = errno();
+    libc::close(fd);
+    set_errno(err);
+}
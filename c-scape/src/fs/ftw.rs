@@ -0,0 +1,436 @@
+//! `ftw` and `nftw`.
+//!
+//! We hold one directory descriptor open per level of the tree being walked,
+//! rather than limiting ourselves to the `descriptors` the caller passes.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::mem::zeroed;
+use core::ptr::null_mut;
+use errno::{errno, set_errno, Errno};
+use libc::{c_char, c_int};
+
+// Values passed to the callback, from glibc.
+const FTW_F: c_int = 0;
+const FTW_D: c_int = 1;
+const FTW_DNR: c_int = 2;
+const FTW_NS: c_int = 3;
+const FTW_SL: c_int = 4;
+const FTW_DP: c_int = 5;
+const FTW_SLN: c_int = 6;
+
+// Flags for `nftw`.
+const FTW_PHYS: c_int = 1;
+const FTW_MOUNT: c_int = 2;
+const FTW_CHDIR: c_int = 4;
+const FTW_DEPTH: c_int = 8;
+const FTW_ACTIONRETVAL: c_int = 16;
+
+// Callback return values with `FTW_ACTIONRETVAL`.
+const FTW_STOP: c_int = 1;
+const FTW_SKIP_SUBTREE: c_int = 2;
+const FTW_SKIP_SIBLINGS: c_int = 3;
+
+/// glibc's `struct FTW`.
+#[repr(C)]
+struct Ftw {
+    base: c_int,
+    level: c_int,
+}
+
+type FtwFn<S> = unsafe extern "C" fn(*const c_char, *const S, c_int) -> c_int;
+type NftwFn<S> = unsafe extern "C" fn(*const c_char, *const S, c_int, *mut Ftw) -> c_int;
+
+#[no_mangle]
+unsafe extern "C" fn ftw(dir: *const c_char, func: FtwFn<libc::stat>, descriptors: c_int) -> c_int {
+    //libc!(libc::ftw(dir, func, descriptors));
+
+    let _ = descriptors;
+    walk(dir, Func::Ftw(func), 0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn ftw64(
+    dir: *const c_char,
+    func: FtwFn<libc::stat64>,
+    descriptors: c_int,
+) -> c_int {
+    //libc!(libc::ftw64(dir, func, descriptors));
+
+    let _ = descriptors;
+    walk(dir, Func::Ftw(func), 0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn nftw(
+    dir: *const c_char,
+    func: NftwFn<libc::stat>,
+    descriptors: c_int,
+    flags: c_int,
+) -> c_int {
+    //libc!(libc::nftw(dir, func, descriptors, flags));
+
+    let _ = descriptors;
+    walk(dir, Func::Nftw(func), flags)
+}
+
+#[no_mangle]
+unsafe extern "C" fn nftw64(
+    dir: *const c_char,
+    func: NftwFn<libc::stat64>,
+    descriptors: c_int,
+    flags: c_int,
+) -> c_int {
+    //libc!(libc::nftw64(dir, func, descriptors, flags));
+
+    let _ = descriptors;
+    walk(dir, Func::Nftw(func), flags)
+}
+
+enum Func<S> {
+    Ftw(FtwFn<S>),
+    Nftw(NftwFn<S>),
+}
+
+/// The `stat` and `stat64` structs the callbacks are passed.
+trait Stat: Sized {
+    type Ino: Ord;
+
+    unsafe fn at(dirfd: c_int, name: *const c_char, flags: c_int) -> Option<Self>;
+    fn mode(&self) -> libc::mode_t;
+    fn id(&self) -> (libc::dev_t, Self::Ino);
+
+    fn is_dir(&self) -> bool {
+        self.mode() & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    fn is_link(&self) -> bool {
+        self.mode() & libc::S_IFMT == libc::S_IFLNK
+    }
+}
+
+impl Stat for libc::stat {
+    type Ino = libc::ino_t;
+
+    unsafe fn at(dirfd: c_int, name: *const c_char, flags: c_int) -> Option<Self> {
+        let mut stat = zeroed();
+        (libc::fstatat(dirfd, name, &mut stat, flags) == 0).then_some(stat)
+    }
+
+    fn mode(&self) -> libc::mode_t {
+        self.st_mode
+    }
+
+    fn id(&self) -> (libc::dev_t, Self::Ino) {
+        (self.st_dev, self.st_ino)
+    }
+}
+
+impl Stat for libc::stat64 {
+    type Ino = libc::ino64_t;
+
+    unsafe fn at(dirfd: c_int, name: *const c_char, flags: c_int) -> Option<Self> {
+        let mut stat = zeroed();
+        (libc::fstatat64(dirfd, name, &mut stat, flags) == 0).then_some(stat)
+    }
+
+    fn mode(&self) -> libc::mode_t {
+        self.st_mode
+    }
+
+    fn id(&self) -> (libc::dev_t, Self::Ino) {
+        (self.st_dev, self.st_ino)
+    }
+}
+
+unsafe fn walk<S: Stat>(dir: *const c_char, func: Func<S>, flags: c_int) -> c_int {
+    let dir = CStr::from_ptr(dir);
+    let bytes = dir.to_bytes();
+    if bytes.is_empty() {
+        set_errno(Errno(libc::ENOENT));
+        return -1;
+    }
+
+    // Trailing slashes aren't part of the paths passed to the callback.
+    let mut len = bytes.len();
+    while len > 1 && bytes[len - 1] == b'/' {
+        len -= 1;
+    }
+    let base = bytes[..len]
+        .iter()
+        .rposition(|c| *c == b'/')
+        .map_or(0, |i| i + 1);
+    let mut path = bytes[..len].to_vec();
+    path.push(0);
+
+    let mut walk = Walk {
+        func,
+        flags,
+        path,
+        ftw: Ftw {
+            base: base as c_int,
+            level: 0,
+        },
+        dev: 0,
+        visited: BTreeSet::new(),
+    };
+
+    // With `FTW_CHDIR`, start in the directory containing `dir`, and
+    // remember where to return to.
+    let mut result = 0;
+    let mut cwd = -1;
+    let mut name = dir.to_bytes_with_nul().to_vec();
+    if walk.flag(FTW_CHDIR) {
+        cwd = libc::open(
+            c".".as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        );
+        if cwd < 0 {
+            return -1;
+        }
+        if base > 0 {
+            let mut parent = if base == 1 {
+                b"/".to_vec()
+            } else {
+                walk.path[..base - 1].to_vec()
+            };
+            parent.push(0);
+            result = libc::chdir(parent.as_ptr().cast());
+        }
+        name = walk.path[base..].to_vec();
+        if name == b"\0" {
+            name = b".\0".to_vec();
+        }
+    }
+
+    if result == 0 {
+        let name = name.as_ptr().cast::<c_char>();
+        let follow = if walk.flag(FTW_PHYS) {
+            libc::AT_SYMLINK_NOFOLLOW
+        } else {
+            0
+        };
+        result = match S::at(libc::AT_FDCWD, name, follow) {
+            Some(stat) if stat.is_dir() => {
+                walk.dev = stat.id().0;
+                if !walk.flag(FTW_PHYS) {
+                    walk.visited.insert(stat.id());
+                }
+                walk.dir(&stat, libc::AT_FDCWD, name)
+            }
+            Some(stat) => {
+                let flag = if stat.is_link() { FTW_SL } else { FTW_F };
+                walk.call(&stat, flag)
+            }
+            None => {
+                let dangling = if !walk.flag(FTW_PHYS) && errno().0 == libc::ENOENT {
+                    S::at(libc::AT_FDCWD, name, libc::AT_SYMLINK_NOFOLLOW)
+                        .filter(|stat| stat.is_link())
+                } else {
+                    None
+                };
+                match dangling {
+                    Some(stat) => walk.call(&stat, FTW_SLN),
+                    None => -1,
+                }
+            }
+        };
+        if walk.flag(FTW_ACTIONRETVAL)
+            && (result == FTW_SKIP_SUBTREE || result == FTW_SKIP_SIBLINGS)
+        {
+            result = 0;
+        }
+    }
+
+    if cwd >= 0 {
+        let err = errno();
+        libc::fchdir(cwd);
+        libc::close(cwd);
+        set_errno(err);
+    }
+
+    result
+}
+
+struct Walk<S: Stat> {
+    func: Func<S>,
+    flags: c_int,
+    /// The NUL-terminated path of the current file.
+    path: Vec<u8>,
+    ftw: Ftw,
+    /// The device of the starting directory, for `FTW_MOUNT`.
+    dev: libc::dev_t,
+    /// The directories we've visited, so that following symlinks doesn't
+    /// take us around in cycles.
+    visited: BTreeSet<(libc::dev_t, S::Ino)>,
+}
+
+impl<S: Stat> Walk<S> {
+    fn flag(&self, flag: c_int) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// Call the callback for the current path.
+    unsafe fn call(&mut self, stat: &S, flag: c_int) -> c_int {
+        let path = self.path.as_ptr().cast();
+        match &self.func {
+            Func::Ftw(func) => {
+                // `ftw` has fewer kinds of file than `nftw`.
+                let flag = match flag {
+                    FTW_SL => FTW_F,
+                    FTW_DP => FTW_D,
+                    FTW_SLN => FTW_NS,
+                    flag => flag,
+                };
+                func(path, stat, flag)
+            }
+            Func::Nftw(func) => func(path, stat, flag, &mut self.ftw),
+        }
+    }
+
+    /// Visit `name`, an entry in the directory open as `dirfd`.
+    unsafe fn entry(&mut self, dirfd: c_int, name: &CStr) -> c_int {
+        if name.to_bytes() == b"." || name.to_bytes() == b".." {
+            return 0;
+        }
+        self.path.truncate(self.ftw.base as usize);
+        self.path.extend_from_slice(name.to_bytes_with_nul());
+
+        let follow = if self.flag(FTW_PHYS) {
+            libc::AT_SYMLINK_NOFOLLOW
+        } else {
+            0
+        };
+        let mut result = 0;
+        let (stat, flag) = match S::at(dirfd, name.as_ptr(), follow) {
+            Some(stat) if stat.is_dir() => (stat, FTW_D),
+            Some(stat) if stat.is_link() => (stat, FTW_SL),
+            Some(stat) => (stat, FTW_F),
+            None => {
+                let err = errno().0;
+                if err != libc::EACCES && err != libc::ENOENT {
+                    result = -1;
+                }
+                let dangling = if result == 0 && !self.flag(FTW_PHYS) {
+                    S::at(dirfd, name.as_ptr(), libc::AT_SYMLINK_NOFOLLOW)
+                        .filter(|stat| stat.is_link())
+                } else {
+                    None
+                };
+                match dangling {
+                    Some(stat) => (stat, FTW_SLN),
+                    None => (zeroed(), FTW_NS),
+                }
+            }
+        };
+
+        if result == 0 && (flag == FTW_NS || !self.flag(FTW_MOUNT) || stat.id().0 == self.dev) {
+            if flag == FTW_D {
+                if self.flag(FTW_PHYS) || self.visited.insert(stat.id()) {
+                    result = self.dir(&stat, dirfd, name.as_ptr());
+                }
+            } else {
+                result = self.call(&stat, flag);
+            }
+        }
+
+        if self.flag(FTW_ACTIONRETVAL) && result == FTW_SKIP_SUBTREE {
+            result = 0;
+        }
+        result
+    }
+
+    /// Visit the directory `name`, relative to `dirfd`, and its contents.
+    unsafe fn dir(&mut self, stat: &S, dirfd: c_int, name: *const c_char) -> c_int {
+        let fd = libc::openat(
+            dirfd,
+            name,
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        );
+        let stream = if fd < 0 {
+            null_mut()
+        } else {
+            let stream = libc::fdopendir(fd);
+            if stream.is_null() {
+                close(fd);
+            }
+            stream
+        };
+        if stream.is_null() {
+            if errno().0 == libc::EACCES {
+                return self.call(stat, FTW_DNR);
+            }
+            return -1;
+        }
+
+        let mut result = 0;
+        if !self.flag(FTW_DEPTH) {
+            result = self.call(stat, FTW_D);
+        }
+        if result == 0 && self.flag(FTW_CHDIR) && libc::fchdir(fd) < 0 {
+            result = -1;
+        }
+        if result != 0 {
+            closedir(stream);
+            return result;
+        }
+
+        let (base, len) = (self.ftw.base, self.path.len());
+        self.path.pop();
+        if self.path.last() != Some(&b'/') {
+            self.path.push(b'/');
+        }
+        self.ftw.base = self.path.len() as c_int;
+        self.ftw.level += 1;
+
+        loop {
+            let entry = libc::readdir64(stream);
+            if entry.is_null() {
+                break;
+            }
+            result = self.entry(fd, CStr::from_ptr((*entry).d_name.as_ptr()));
+            if result != 0 {
+                break;
+            }
+        }
+        closedir(stream);
+        if self.flag(FTW_ACTIONRETVAL) && result == FTW_SKIP_SIBLINGS {
+            result = 0;
+        }
+
+        self.path.truncate(len - 1);
+        self.path.push(0);
+        self.ftw.base = base;
+        self.ftw.level -= 1;
+
+        if result == 0 && self.flag(FTW_DEPTH) {
+            result = self.call(stat, FTW_DP);
+        }
+
+        // Return to the parent, unless we're at the top, or stopping.
+        if dirfd != libc::AT_FDCWD
+            && self.flag(FTW_CHDIR)
+            && (result == 0 || (self.flag(FTW_ACTIONRETVAL) && result != -1 && result != FTW_STOP))
+            && libc::fchdir(dirfd) < 0
+        {
+            result = -1;
+        }
+
+        result
+    }
+}
+
+/// Close `fd`, preserving `errno`.
+unsafe fn close(fd: c_int) {
+    let err = errno();
+    libc::close(fd);
+    set_errno(err);
+}
+
+/// Close `stream`, preserving `errno`.
+unsafe fn closedir(stream: *mut libc::DIR) {
+    let err = errno();
+    libc::closedir(stream);
+    set_errno(err);
+}
@@ -7,6 +7,10 @@ mod fallocate;
 mod fchown;
 mod fcntl;
 mod flock;
+#[cfg(not(target_os = "wasi"))]
+mod fts;
+#[cfg(not(target_os = "wasi"))]
+mod ftw;
 mod inotify;
 mod link;
 mod lseek;
@@ -0,0 +1,633 @@
+//! `getopt`, `getopt_long`, and `getopt_long_only`, with GNU semantics.
+//!
+//! Unless `POSIXLY_CORRECT` is set or `optstring` starts with `+`, options
+//! may follow operands. As we scan, the operands we skip over are permuted
+//! so that they end up after all the options, and when we're done, `optind`
+//! points to the first of them.
+
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::ptr::null_mut;
+use core::slice;
+use libc::{c_char, c_int};
+use rustix_futex_sync::Mutex;
+
+#[no_mangle]
+static mut optarg: *mut c_char = null_mut();
+#[no_mangle]
+static mut optind: c_int = 1;
+#[no_mangle]
+static mut opterr: c_int = 1;
+#[no_mangle]
+static mut optopt: c_int = b'?' as c_int;
+
+/// The `has_arg` values of options that take no argument, and of options
+/// that require one.
+const NO_ARGUMENT: c_int = 0;
+const REQUIRED_ARGUMENT: c_int = 1;
+
+extern "C" {
+    static mut stderr: *mut libc::FILE;
+}
+
+#[no_mangle]
+unsafe extern "C" fn getopt(
+    argc: c_int,
+    argv: *const *mut c_char,
+    optstring: *const c_char,
+) -> c_int {
+    libc!(libc::getopt(argc, argv, optstring));
+
+    getopt_internal(argc, argv, optstring, null_mut(), null_mut(), false)
+}
+
+#[no_mangle]
+unsafe extern "C" fn getopt_long(
+    argc: c_int,
+    argv: *const *mut c_char,
+    optstring: *const c_char,
+    longopts: *const libc::option,
+    longindex: *mut c_int,
+) -> c_int {
+    libc!(libc::getopt_long(
+        argc, argv, optstring, longopts, longindex
+    ));
+
+    getopt_internal(argc, argv, optstring, longopts, longindex, false)
+}
+
+#[no_mangle]
+unsafe extern "C" fn getopt_long_only(
+    argc: c_int,
+    argv: *const *mut c_char,
+    optstring: *const c_char,
+    longopts: *const libc::option,
+    longindex: *mut c_int,
+) -> c_int {
+    //libc!(libc::getopt_long_only(argc, argv, optstring, longopts, longindex));
+
+    getopt_internal(argc, argv, optstring, longopts, longindex, true)
+}
+
+/// How to handle operands.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Ordering {
+    /// Stop at the first operand, as POSIX requires.
+    RequireOrder,
+    /// Move operands after the options.
+    Permute,
+    /// Return each operand as the argument of an option with code 1.
+    ReturnInOrder,
+}
+
+/// The scanning state that persists between calls.
+struct State {
+    initialized: bool,
+    /// The next option character to look at in the current element of
+    /// `argv`, or null to move on to the next element.
+    nextchar: *mut c_char,
+    ordering: Ordering,
+    /// The operands we've skipped over and haven't yet moved after the
+    /// options that follow them are `argv[first_nonopt..last_nonopt]`.
+    first_nonopt: c_int,
+    last_nonopt: c_int,
+    /// The value for `optopt`. As in glibc, this is ours, and we just copy it
+    /// out, so it's 0 until there's an error.
+    optopt: c_int,
+}
+
+// SAFETY: `nextchar` points into the application's arguments, which any
+// thread may scan.
+unsafe impl Send for State {}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    initialized: false,
+    nextchar: null_mut(),
+    ordering: Ordering::Permute,
+    first_nonopt: 1,
+    last_nonopt: 1,
+    optopt: 0,
+});
+
+unsafe fn getopt_internal(
+    argc: c_int,
+    argv: *const *mut c_char,
+    optstring: *const c_char,
+    longopts: *const libc::option,
+    longindex: *mut c_int,
+    long_only: bool,
+) -> c_int {
+    let mut state = STATE.lock();
+    let mut getopt = Getopt {
+        state: &mut state,
+        argc,
+        argv: argv.cast_mut(),
+        optstring: CStr::from_ptr(optstring).to_bytes(),
+        longopts,
+        longindex,
+        long_only,
+        print_errors: opterr != 0,
+        optind,
+        optarg: null_mut(),
+    };
+    let result = getopt.next();
+    optind = getopt.optind;
+    optarg = getopt.optarg;
+    optopt = getopt.state.optopt;
+    result
+}
+
+/// One call to one of the `getopt` functions.
+struct Getopt<'a> {
+    state: &'a mut State,
+    argc: c_int,
+    argv: *mut *mut c_char,
+    optstring: &'a [u8],
+    longopts: *const libc::option,
+    longindex: *mut c_int,
+    long_only: bool,
+    print_errors: bool,
+    optind: c_int,
+    optarg: *mut c_char,
+}
+
+impl Getopt<'_> {
+    unsafe fn next(&mut self) -> c_int {
+        if self.argc < 1 {
+            return -1;
+        }
+
+        if self.optind == 0 || !self.state.initialized {
+            if self.optind == 0 {
+                self.optind = 1;
+            }
+            self.initialize();
+        }
+        if let Some(b'-' | b'+') = self.optstring.first() {
+            self.optstring = &self.optstring[1..];
+        }
+        if self.optstring.first() == Some(&b':') {
+            self.print_errors = false;
+        }
+
+        if self.state.nextchar.is_null() || *self.state.nextchar == 0 {
+            // Move on to the next element of `argv`.
+            if self.state.last_nonopt > self.optind {
+                self.state.last_nonopt = self.optind;
+            }
+            if self.state.first_nonopt > self.optind {
+                self.state.first_nonopt = self.optind;
+            }
+
+            if self.state.ordering == Ordering::Permute {
+                // Move the operands we skipped after the options we've
+                // scanned since, and skip the operands that follow.
+                if self.state.first_nonopt != self.state.last_nonopt
+                    && self.state.last_nonopt != self.optind
+                {
+                    self.exchange();
+                } else if self.state.last_nonopt != self.optind {
+                    self.state.first_nonopt = self.optind;
+                }
+                while self.optind < self.argc && self.is_operand() {
+                    self.optind += 1;
+                }
+                self.state.last_nonopt = self.optind;
+            }
+
+            // `--` means that everything after it is an operand. Skip it,
+            // and treat the rest as operands we skipped.
+            if self.optind != self.argc && CStr::from_ptr(self.arg(self.optind)) == c"--" {
+                self.optind += 1;
+                if self.state.first_nonopt != self.state.last_nonopt
+                    && self.state.last_nonopt != self.optind
+                {
+                    self.exchange();
+                } else if self.state.first_nonopt == self.state.last_nonopt {
+                    self.state.first_nonopt = self.optind;
+                }
+                self.state.last_nonopt = self.argc;
+                self.optind = self.argc;
+            }
+
+            if self.optind == self.argc {
+                // Point `optind` at the operands we skipped, if any.
+                if self.state.first_nonopt != self.state.last_nonopt {
+                    self.optind = self.state.first_nonopt;
+                }
+                return -1;
+            }
+
+            if self.is_operand() {
+                if self.state.ordering == Ordering::RequireOrder {
+                    return -1;
+                }
+                self.optarg = self.arg(self.optind);
+                self.optind += 1;
+                return 1;
+            }
+
+            if !self.longopts.is_null() {
+                let arg = self.arg(self.optind);
+                if *arg.add(1) == b'-' as c_char {
+                    self.state.nextchar = arg.add(2);
+                    return self.long_option(b"--");
+                }
+
+                // With `getopt_long_only`, `-name` is a long option,
+                // unless it's a single valid short option character.
+                if self.long_only && (*arg.add(2) != 0 || !self.is_short(*arg.add(1) as u8)) {
+                    self.state.nextchar = arg.add(1);
+                    let code = self.long_option(b"-");
+                    if code != -1 {
+                        return code;
+                    }
+                }
+            }
+
+            self.state.nextchar = self.arg(self.optind).add(1);
+        }
+
+        // Look at the next short option character.
+        let c = *self.state.nextchar as u8;
+        self.state.nextchar = self.state.nextchar.add(1);
+        let spec = self
+            .optstring
+            .iter()
+            .position(|b| *b == c)
+            .map(|i| &self.optstring[i..]);
+
+        if *self.state.nextchar == 0 {
+            self.optind += 1;
+        }
+
+        let spec = match spec {
+            Some(spec) if c != b':' && c != b';' => spec,
+            _ => {
+                self.error(&[b"invalid option -- '", &[c], b"'"]);
+                self.state.optopt = c_int::from(c);
+                return c_int::from(b'?');
+            }
+        };
+
+        // As POSIX reserves, `-W foo` means `--foo`.
+        if spec.starts_with(b"W;") && !self.longopts.is_null() {
+            if *self.state.nextchar == 0 {
+                if self.optind == self.argc {
+                    return self.missing_argument(c);
+                }
+                self.state.nextchar = self.arg(self.optind);
+            }
+            // `long_option` consumes the element holding the name.
+            self.long_only = false;
+            return self.long_option(b"-W ");
+        }
+
+        if spec.get(1) != Some(&b':') {
+            return c_int::from(c);
+        }
+        if *self.state.nextchar != 0 {
+            // The argument is the rest of this element.
+            self.optarg = self.state.nextchar;
+            self.optind += 1;
+        } else if spec.get(2) == Some(&b':') {
+            // The option's argument is optional, and must be attached.
+        } else if self.optind == self.argc {
+            self.state.nextchar = null_mut();
+            return self.missing_argument(c);
+        } else {
+            // The argument is the next element.
+            self.optarg = self.arg(self.optind);
+            self.optind += 1;
+        }
+        self.state.nextchar = null_mut();
+        c_int::from(c)
+    }
+
+    /// Start scanning a new `argv`, whose options start at `optind`.
+    unsafe fn initialize(&mut self) {
+        self.state.first_nonopt = self.optind;
+        self.state.last_nonopt = self.optind;
+        self.state.nextchar = null_mut();
+        self.state.ordering = match self.optstring.first() {
+            Some(b'-') => Ordering::ReturnInOrder,
+            Some(b'+') => Ordering::RequireOrder,
+            _ if !crate::env::get::_getenv(b"POSIXLY_CORRECT").is_null() => Ordering::RequireOrder,
+            _ => Ordering::Permute,
+        };
+        self.state.initialized = true;
+    }
+
+    /// Handle the long option at `nextchar`, which followed `prefix`.
+    ///
+    /// Returns -1 if `getopt_long_only` should treat it as short options
+    /// instead.
+    unsafe fn long_option(&mut self, prefix: &[u8]) -> c_int {
+        let nextchar = self.state.nextchar;
+        let rest = CStr::from_ptr(nextchar).to_bytes();
+        let name_len = rest.iter().position(|b| *b == b'=').unwrap_or(rest.len());
+        let name = &rest[..name_len];
+
+        let mut options = Vec::new();
+        let mut p = self.longopts;
+        while !(*p).name.is_null() {
+            options.push(&*p);
+            p = p.add(1);
+        }
+        let option_name = |option: &libc::option| CStr::from_ptr(option.name).to_bytes();
+
+        // Look for an exact match, and failing that, a unique abbreviation.
+        // Abbreviations of several options that do the same thing aren't
+        // ambiguous, except in `getopt_long_only`.
+        let mut found = options
+            .iter()
+            .position(|option| option_name(option) == name);
+        if found.is_none() {
+            let mut ambiguous = Vec::new();
+            for (index, option) in options.iter().enumerate() {
+                if !option_name(option).starts_with(name) {
+                    continue;
+                }
+                let Some(first) = found else {
+                    found = Some(index);
+                    continue;
+                };
+                let first = options[first];
+                if self.long_only
+                    || first.has_arg != option.has_arg
+                    || first.flag != option.flag
+                    || first.val != option.val
+                {
+                    if ambiguous.is_empty() {
+                        ambiguous.push(first);
+                    }
+                    ambiguous.push(*option);
+                }
+            }
+
+            if !ambiguous.is_empty() {
+                if self.print_errors {
+                    let mut message = Vec::new();
+                    message.extend_from_slice(b"option '");
+                    message.extend_from_slice(prefix);
+                    message.extend_from_slice(rest);
+                    message.extend_from_slice(b"' is ambiguous; possibilities:");
+                    for option in ambiguous {
+                        message.extend_from_slice(b" '");
+                        message.extend_from_slice(prefix);
+                        message.extend_from_slice(option_name(option));
+                        message.push(b'\'');
+                    }
+                    self.error(&[&message]);
+                }
+                self.state.nextchar = nextchar.add(rest.len());
+                self.optind += 1;
+                self.state.optopt = 0;
+                return c_int::from(b'?');
+            }
+        }
+
+        let Some(index) = found else {
+            // With `getopt_long_only`, `-abc` may still be short options.
+            let arg = self.arg(self.optind);
+            if !self.long_only || *arg.add(1) == b'-' as c_char || !self.is_short(rest[0]) {
+                self.error(&[b"unrecognized option '", prefix, rest, b"'"]);
+                self.state.nextchar = null_mut();
+                self.optind += 1;
+                self.state.optopt = 0;
+                return c_int::from(b'?');
+            }
+            return -1;
+        };
+        let option = options[index];
+
+        self.optind += 1;
+        self.state.nextchar = null_mut();
+        if name_len < rest.len() {
+            if option.has_arg == NO_ARGUMENT {
+                self.error(&[
+                    b"option '",
+                    prefix,
+                    option_name(option),
+                    b"' doesn't allow an argument",
+                ]);
+                self.state.optopt = option.val;
+                return c_int::from(b'?');
+            }
+            self.optarg = nextchar.add(name_len + 1);
+        } else if option.has_arg == REQUIRED_ARGUMENT {
+            if self.optind == self.argc {
+                self.error(&[
+                    b"option '",
+                    prefix,
+                    option_name(option),
+                    b"' requires an argument",
+                ]);
+                self.state.optopt = option.val;
+                return self.missing_argument_code();
+            }
+            self.optarg = self.arg(self.optind);
+            self.optind += 1;
+        }
+
+        if !self.longindex.is_null() {
+            *self.longindex = index as c_int;
+        }
+        if !option.flag.is_null() {
+            *option.flag = option.val;
+            return 0;
+        }
+        option.val
+    }
+
+    /// Report that the short option `c` is missing its argument.
+    unsafe fn missing_argument(&mut self, c: u8) -> c_int {
+        self.error(&[b"option requires an argument -- '", &[c], b"'"]);
+        self.state.optopt = c_int::from(c);
+        self.missing_argument_code()
+    }
+
+    /// Return the code for a missing argument, which is `:` if `optstring`
+    /// asks to tell it apart from an invalid option.
+    fn missing_argument_code(&self) -> c_int {
+        if self.optstring.first() == Some(&b':') {
+            c_int::from(b':')
+        } else {
+            c_int::from(b'?')
+        }
+    }
+
+    /// Move the operands in `argv[first_nonopt..last_nonopt]` after the
+    /// options in `argv[last_nonopt..optind]`.
+    unsafe fn exchange(&mut self) {
+        let first = self.state.first_nonopt as usize;
+        let args = slice::from_raw_parts_mut(self.argv.add(first), self.optind as usize - first);
+        args.rotate_left(self.state.last_nonopt as usize - first);
+        self.state.first_nonopt += self.optind - self.state.last_nonopt;
+        self.state.last_nonopt = self.optind;
+    }
+
+    unsafe fn arg(&self, index: c_int) -> *mut c_char {
+        *self.argv.add(index as usize)
+    }
+
+    /// Test whether `argv[optind]` is an operand rather than options.
+    unsafe fn is_operand(&self) -> bool {
+        let arg = self.arg(self.optind);
+        *arg != b'-' as c_char || *arg.add(1) == 0
+    }
+
+    /// Test whether `c` is a short option character in `optstring`.
+    fn is_short(&self, c: u8) -> bool {
+        self.optstring.contains(&c)
+    }
+
+    /// Print `parts` to stderr after the program name, unless error messages
+    /// are turned off.
+    unsafe fn error(&self, parts: &[&[u8]]) {
+        if !self.print_errors {
+            return;
+        }
+        let mut message = Vec::new();
+        message.extend_from_slice(CStr::from_ptr(self.arg(0)).to_bytes());
+        message.extend_from_slice(b": ");
+        for part in parts {
+            message.extend_from_slice(part);
+        }
+        message.extend_from_slice(b"\n\0");
+        libc::fputs(message.as_ptr().cast(), stderr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use core::fmt::Write;
+    use core::ptr::{addr_of, addr_of_mut, null};
+
+    /// `getopt`'s state is global, so run one test at a time.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Parse `args` from the start, and describe the options returned, the
+    /// final `optind`, and the final order of the arguments.
+    unsafe fn run(args: &[&CStr], optstring: &CStr, longopts: *const libc::option) -> String {
+        let mut argv = args
+            .iter()
+            .map(|arg| arg.as_ptr().cast_mut())
+            .collect::<Vec<_>>();
+        argv.push(null_mut());
+        let argc = args.len() as c_int;
+
+        *addr_of_mut!(optind) = 0;
+        let mut out = String::new();
+        loop {
+            let c = getopt_internal(
+                argc,
+                argv.as_ptr(),
+                optstring.as_ptr(),
+                longopts,
+                null_mut(),
+                false,
+            );
+            if c == -1 {
+                break;
+            }
+            match c {
+                1 => out.push_str(" 1"),
+                _ => write!(out, " {}", char::from(c as u8)).unwrap(),
+            }
+            let arg = *addr_of!(optarg);
+            if !arg.is_null() {
+                write!(out, "={}", CStr::from_ptr(arg).to_str().unwrap()).unwrap();
+            }
+        }
+        write!(out, " | {} |", *addr_of!(optind)).unwrap();
+        for arg in &argv[1..args.len()] {
+            write!(out, " {}", CStr::from_ptr(*arg).to_str().unwrap()).unwrap();
+        }
+        out
+    }
+
+    const ARGS: &[&CStr] = &[
+        c"prog", c"a", c"-x", c"b", c"-yval", c"-y", c"v2", c"c", c"--", c"-z", c"d",
+    ];
+
+    #[test]
+    fn test_getopt_permute() {
+        let _lock = LOCK.lock();
+        unsafe {
+            assert_eq!(
+                run(ARGS, c"xy:z", null()),
+                " x y=val y=v2 | 6 | -x -yval -y v2 -- a b c -z d"
+            );
+            // `+` stops at the first operand.
+            assert_eq!(
+                run(ARGS, c"+xy:z", null()),
+                " | 1 | a -x b -yval -y v2 c -- -z d"
+            );
+            // `-` returns operands in order, as the arguments of option 1.
+            assert_eq!(
+                run(ARGS, c"-xy:z", null()),
+                " 1=a x 1=b y=val y=v2 1=c | 9 | a -x b -yval -y v2 c -- -z d"
+            );
+        }
+    }
+
+    #[test]
+    fn test_getopt_posixly_correct() {
+        let _lock = LOCK.lock();
+        unsafe {
+            assert_eq!(
+                libc::setenv(c"POSIXLY_CORRECT".as_ptr(), c"1".as_ptr(), 1),
+                0
+            );
+            let require_order = run(ARGS, c"xy:z", null());
+            let return_in_order = run(ARGS, c"-xy:z", null());
+            assert_eq!(libc::unsetenv(c"POSIXLY_CORRECT".as_ptr()), 0);
+
+            assert_eq!(require_order, " | 1 | a -x b -yval -y v2 c -- -z d");
+            // `-` takes precedence over `POSIXLY_CORRECT`.
+            assert_eq!(
+                return_in_order,
+                " 1=a x 1=b y=val y=v2 1=c | 9 | a -x b -yval -y v2 c -- -z d"
+            );
+        }
+    }
+
+    #[test]
+    fn test_getopt_long_permute() {
+        let _lock = LOCK.lock();
+        let longopts = [
+            libc::option {
+                name: c"verbose".as_ptr(),
+                has_arg: NO_ARGUMENT,
+                flag: null_mut(),
+                val: c_int::from(b'v'),
+            },
+            libc::option {
+                name: c"name".as_ptr(),
+                has_arg: REQUIRED_ARGUMENT,
+                flag: null_mut(),
+                val: c_int::from(b'n'),
+            },
+            unsafe { core::mem::zeroed() },
+        ];
+        let args = [
+            c"prog",
+            c"file",
+            c"--verbose",
+            c"other",
+            c"--name",
+            c"n",
+            c"-x",
+            c"rest",
+        ];
+        unsafe {
+            assert_eq!(
+                run(&args, c"x", longopts.as_ptr()),
+                " v n=n x | 5 | --verbose --name n -x file other rest"
+            );
+        }
+    }
+}
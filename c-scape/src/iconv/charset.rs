@@ -0,0 +1,652 @@
+//! The character sets `iconv` knows how to convert between.
+//!
+//! The Unicode encodings, ASCII, and Latin-1 are always available. With the
+//! "iconv-encoding-rs" feature, ISO-2022-JP is implemented here too, and
+//! everything else is delegated to `encoding_rs`, one character at a time,
+//! so that we can report exactly where a conversion stopped.
+
+#[cfg(feature = "iconv-encoding-rs")]
+use encoding_rs::{DecoderResult, EncoderResult, Encoding};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Endian {
+    Big,
+    Little,
+}
+
+impl Endian {
+    #[cfg(target_endian = "big")]
+    pub(super) const NATIVE: Self = Self::Big;
+    #[cfg(target_endian = "little")]
+    pub(super) const NATIVE: Self = Self::Little;
+
+    fn read_u16(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1]];
+        u32::from(match self {
+            Self::Big => u16::from_be_bytes(bytes),
+            Self::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn read_u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            Self::Big => u32::from_be_bytes(bytes),
+            Self::Little => u32::from_le_bytes(bytes),
+        }
+    }
+
+    fn write_u16(self, unit: u32, out: &mut [u8]) -> usize {
+        let unit = unit as u16;
+        out[..2].copy_from_slice(&match self {
+            Self::Big => unit.to_be_bytes(),
+            Self::Little => unit.to_le_bytes(),
+        });
+        2
+    }
+
+    fn write_u32(self, c: u32, out: &mut [u8]) -> usize {
+        out[..4].copy_from_slice(&match self {
+            Self::Big => c.to_be_bytes(),
+            Self::Little => c.to_le_bytes(),
+        });
+        4
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum Charset {
+    Ascii,
+    Latin1,
+    Utf8,
+    /// UTF-16, using a byte-order mark if no byte order is specified.
+    Utf16(Option<Endian>),
+    /// UTF-32, using a byte-order mark if no byte order is specified.
+    Utf32(Option<Endian>),
+    Ucs2(Endian),
+    Ucs4(Endian),
+    #[cfg(feature = "iconv-encoding-rs")]
+    Iso2022Jp,
+    /// A character set implemented by `encoding_rs`. If `c1` is set, bytes
+    /// 0x80 through 0x9f are the C1 control characters, which is how the
+    /// ISO-8859 family differs from the corresponding Windows code pages;
+    /// otherwise single-byte sets don't represent the C1 control characters.
+    #[cfg(feature = "iconv-encoding-rs")]
+    Other {
+        encoding: &'static Encoding,
+        c1: bool,
+    },
+}
+
+/// The ISO-2022-JP character set currently designated into G0.
+#[cfg(feature = "iconv-encoding-rs")]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum Mode {
+    #[default]
+    Ascii,
+    Roman,
+    Jis0208,
+}
+
+/// The shift state of a conversion in one direction.
+#[derive(Clone, Copy, Default)]
+pub(super) struct State {
+    /// When decoding UTF-16 or UTF-32 without a specified byte order, the
+    /// byte order, once we've seen whether there's a byte-order mark.
+    endian: Option<Endian>,
+    /// When encoding UTF-16 or UTF-32, whether we've written the byte-order
+    /// mark.
+    bom: bool,
+    /// The ISO-2022-JP mode.
+    #[cfg(feature = "iconv-encoding-rs")]
+    mode: Mode,
+}
+
+/// The characters decoded from one input sequence. This is usually one
+/// character, but a few Big5 sequences decode to a pair.
+#[derive(Clone, Copy)]
+pub(super) struct Chars {
+    chars: [char; 2],
+    len: usize,
+}
+
+impl Chars {
+    fn one(c: char) -> Self {
+        Self {
+            chars: [c, '\0'],
+            len: 1,
+        }
+    }
+
+    pub(super) fn as_slice(&self) -> &[char] {
+        &self.chars[..self.len]
+    }
+}
+
+pub(super) enum Decoded {
+    /// Characters, and the number of input bytes they were decoded from.
+    Chars(Chars, usize),
+    /// Input bytes which don't encode a character, such as a byte-order
+    /// mark or an escape sequence.
+    Skip(usize),
+    /// The input ends partway through a sequence.
+    Incomplete,
+    /// The input starts with an invalid sequence of the given length.
+    Invalid(usize),
+}
+
+impl Charset {
+    /// Look up a character set by name. Names are compared ignoring case and
+    /// punctuation, as glibc does.
+    pub(super) fn for_name(name: &[u8]) -> Option<Self> {
+        let mut buf = [0_u8; 32];
+        let mut len = 0;
+        for &b in name {
+            if b == b':' {
+                break;
+            }
+            if b == b'-' || b == b'_' {
+                continue;
+            }
+            *buf.get_mut(len)? = b.to_ascii_uppercase();
+            len += 1;
+        }
+        let normalized = &buf[..len];
+
+        match normalized {
+            b"" | b"UTF8" => Some(Self::Utf8),
+            b"ASCII" | b"USASCII" | b"ANSIX3.41968" | b"646" | b"US" | b"ISO646US" => {
+                Some(Self::Ascii)
+            }
+            b"ISO88591" | b"ISO8859" | b"LATIN1" | b"L1" | b"CP819" | b"IBM819" => {
+                Some(Self::Latin1)
+            }
+            b"UTF16" => Some(Self::Utf16(None)),
+            b"UTF16BE" => Some(Self::Utf16(Some(Endian::Big))),
+            b"UTF16LE" => Some(Self::Utf16(Some(Endian::Little))),
+            b"UTF32" => Some(Self::Utf32(None)),
+            b"UTF32BE" => Some(Self::Utf32(Some(Endian::Big))),
+            b"UTF32LE" => Some(Self::Utf32(Some(Endian::Little))),
+            b"UCS2" => Some(Self::Ucs2(Endian::NATIVE)),
+            b"UCS2BE" | b"UNICODEBIG" => Some(Self::Ucs2(Endian::Big)),
+            b"UCS2LE" | b"UNICODELITTLE" => Some(Self::Ucs2(Endian::Little)),
+            b"UCS4" | b"UCS4BE" | b"ISO10646/UCS4" => Some(Self::Ucs4(Endian::Big)),
+            b"UCS4LE" => Some(Self::Ucs4(Endian::Little)),
+            b"WCHART" => Some(Self::Ucs4(Endian::NATIVE)),
+            #[cfg(feature = "iconv-encoding-rs")]
+            _ => Self::for_encoding_rs_name(name, normalized),
+            #[cfg(not(feature = "iconv-encoding-rs"))]
+            _ => None,
+        }
+    }
+
+    /// Look up a character set implemented with `encoding_rs`, falling back
+    /// to the WHATWG labels it knows.
+    #[cfg(feature = "iconv-encoding-rs")]
+    fn for_encoding_rs_name(name: &[u8], normalized: &[u8]) -> Option<Self> {
+        let other = |encoding| {
+            Some(Self::Other {
+                encoding,
+                c1: false,
+            })
+        };
+        let iso_8859 = |encoding| Some(Self::Other { encoding, c1: true });
+
+        match normalized {
+            b"ISO2022JP" | b"CSISO2022JP" => Some(Self::Iso2022Jp),
+            b"ISO88592" | b"LATIN2" | b"L2" => iso_8859(encoding_rs::ISO_8859_2),
+            b"ISO88593" | b"LATIN3" | b"L3" => iso_8859(encoding_rs::ISO_8859_3),
+            b"ISO88594" | b"LATIN4" | b"L4" => iso_8859(encoding_rs::ISO_8859_4),
+            b"ISO88595" | b"CYRILLIC" => iso_8859(encoding_rs::ISO_8859_5),
+            b"ISO88596" | b"ARABIC" => iso_8859(encoding_rs::ISO_8859_6),
+            b"ISO88597" | b"GREEK" => iso_8859(encoding_rs::ISO_8859_7),
+            b"ISO88598" | b"HEBREW" => iso_8859(encoding_rs::ISO_8859_8),
+            b"ISO88599" | b"LATIN5" | b"L5" => iso_8859(encoding_rs::WINDOWS_1254),
+            b"ISO885910" | b"LATIN6" | b"L6" => iso_8859(encoding_rs::ISO_8859_10),
+            b"ISO885911" | b"TIS620" => iso_8859(encoding_rs::WINDOWS_874),
+            b"ISO885913" | b"LATIN7" | b"L7" => iso_8859(encoding_rs::ISO_8859_13),
+            b"ISO885914" | b"LATIN8" | b"L8" => iso_8859(encoding_rs::ISO_8859_14),
+            b"ISO885915" | b"LATIN9" | b"LATIN0" => iso_8859(encoding_rs::ISO_8859_15),
+            b"ISO885916" | b"LATIN10" => iso_8859(encoding_rs::ISO_8859_16),
+            b"SJIS" | b"SHIFTJIS" | b"MSKANJI" | b"CP932" | b"WINDOWS31J" => {
+                other(encoding_rs::SHIFT_JIS)
+            }
+            b"EUCJP" | b"UJIS" => other(encoding_rs::EUC_JP),
+            b"EUCKR" | b"CP949" | b"UHC" => other(encoding_rs::EUC_KR),
+            b"GBK" | b"CP936" | b"GB2312" | b"EUCCN" => other(encoding_rs::GBK),
+            b"GB18030" => other(encoding_rs::GB18030),
+            b"BIG5" | b"BIG5HKSCS" | b"CP950" => other(encoding_rs::BIG5),
+            b"KOI8R" => other(encoding_rs::KOI8_R),
+            b"KOI8U" => other(encoding_rs::KOI8_U),
+            b"CP866" | b"IBM866" => other(encoding_rs::IBM866),
+            b"CP874" => other(encoding_rs::WINDOWS_874),
+            b"CP1250" => other(encoding_rs::WINDOWS_1250),
+            b"CP1251" => other(encoding_rs::WINDOWS_1251),
+            b"CP1252" => other(encoding_rs::WINDOWS_1252),
+            b"CP1253" => other(encoding_rs::WINDOWS_1253),
+            b"CP1254" => other(encoding_rs::WINDOWS_1254),
+            b"CP1255" => other(encoding_rs::WINDOWS_1255),
+            b"CP1256" => other(encoding_rs::WINDOWS_1256),
+            b"CP1257" => other(encoding_rs::WINDOWS_1257),
+            b"CP1258" => other(encoding_rs::WINDOWS_1258),
+            b"MAC" | b"MACINTOSH" => other(encoding_rs::MACINTOSH),
+            _ => {
+                let encoding = Encoding::for_label(name)?;
+                // WHATWG maps the ASCII and Latin-1 labels to windows-1252,
+                // and a few ISO-8859 labels to the Windows code pages, and
+                // has some encodings that aren't character sets at all.
+                if encoding == encoding_rs::REPLACEMENT
+                    || encoding == encoding_rs::X_USER_DEFINED
+                    || encoding == encoding_rs::UTF_8
+                    || encoding == encoding_rs::UTF_16BE
+                    || encoding == encoding_rs::UTF_16LE
+                {
+                    None
+                } else if encoding == encoding_rs::ISO_2022_JP {
+                    Some(Self::Iso2022Jp)
+                } else if encoding == encoding_rs::WINDOWS_1252 {
+                    if normalized.starts_with(b"WINDOWS") || normalized.starts_with(b"XCP") {
+                        other(encoding)
+                    } else {
+                        Some(Self::Latin1)
+                    }
+                } else if encoding == encoding_rs::WINDOWS_1254
+                    || encoding == encoding_rs::WINDOWS_874
+                {
+                    Some(Self::Other {
+                        encoding,
+                        c1: !normalized.starts_with(b"WINDOWS") && !normalized.starts_with(b"DOS"),
+                    })
+                } else {
+                    other(encoding)
+                }
+            }
+        }
+    }
+
+    /// Decode the first character of `input`.
+    pub(super) fn decode(self, state: &mut State, input: &[u8]) -> Decoded {
+        let char = |c: u32, len| match char::from_u32(c) {
+            Some(c) => Decoded::Chars(Chars::one(c), len),
+            None => Decoded::Invalid(len),
+        };
+
+        match self {
+            Self::Ascii if input[0] >= 0x80 => Decoded::Invalid(1),
+            Self::Ascii => char(u32::from(input[0]), 1),
+            Self::Latin1 => char(u32::from(input[0]), 1),
+            Self::Utf8 => {
+                let len = match input[0] {
+                    0x00..=0x7f => 1,
+                    0xc2..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf4 => 4,
+                    _ => return Decoded::Invalid(1),
+                };
+                match core::str::from_utf8(&input[..len.min(input.len())]) {
+                    Ok(s) if s.len() == len => char(u32::from(s.chars().next().unwrap()), len),
+                    Ok(_) => Decoded::Incomplete,
+                    Err(err) => match err.error_len() {
+                        Some(len) => Decoded::Invalid(len),
+                        None => Decoded::Incomplete,
+                    },
+                }
+            }
+            Self::Utf16(endian) => {
+                if input.len() < 2 {
+                    return Decoded::Incomplete;
+                }
+                let endian = match endian.or(state.endian) {
+                    Some(endian) => endian,
+                    None => {
+                        let endian = match input[..2] {
+                            [0xfe, 0xff] => Some(Endian::Big),
+                            [0xff, 0xfe] => Some(Endian::Little),
+                            _ => None,
+                        };
+                        state.endian = Some(endian.unwrap_or(Endian::NATIVE));
+                        if endian.is_some() {
+                            return Decoded::Skip(2);
+                        }
+                        Endian::NATIVE
+                    }
+                };
+                match endian.read_u16(input) {
+                    high @ 0xd800..=0xdbff => {
+                        if input.len() < 4 {
+                            return Decoded::Incomplete;
+                        }
+                        match endian.read_u16(&input[2..]) {
+                            low @ 0xdc00..=0xdfff => {
+                                char(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00), 4)
+                            }
+                            _ => Decoded::Invalid(2),
+                        }
+                    }
+                    unit => char(unit, 2),
+                }
+            }
+            Self::Utf32(endian) => {
+                if input.len() < 4 {
+                    return Decoded::Incomplete;
+                }
+                let endian = match endian.or(state.endian) {
+                    Some(endian) => endian,
+                    None => {
+                        let endian = match input[..4] {
+                            [0, 0, 0xfe, 0xff] => Some(Endian::Big),
+                            [0xff, 0xfe, 0, 0] => Some(Endian::Little),
+                            _ => None,
+                        };
+                        state.endian = Some(endian.unwrap_or(Endian::NATIVE));
+                        if endian.is_some() {
+                            return Decoded::Skip(4);
+                        }
+                        Endian::NATIVE
+                    }
+                };
+                char(endian.read_u32(input), 4)
+            }
+            Self::Ucs2(endian) => {
+                if input.len() < 2 {
+                    return Decoded::Incomplete;
+                }
+                char(endian.read_u16(input), 2)
+            }
+            Self::Ucs4(endian) => {
+                if input.len() < 4 {
+                    return Decoded::Incomplete;
+                }
+                char(endian.read_u32(input), 4)
+            }
+            #[cfg(feature = "iconv-encoding-rs")]
+            Self::Iso2022Jp => decode_iso_2022_jp(state, input),
+            #[cfg(feature = "iconv-encoding-rs")]
+            Self::Other { encoding, c1 } => {
+                if c1 && (0x80..=0x9f).contains(&input[0]) {
+                    return char(u32::from(input[0]), 1);
+                }
+                let Some(len) = sequence_len(encoding, input) else {
+                    return Decoded::Incomplete;
+                };
+                if input.len() < len {
+                    return Decoded::Incomplete;
+                }
+                match decode_other(encoding, &input[..len]) {
+                    Some(chars)
+                        if !c1
+                            && encoding.is_single_byte()
+                            && chars.as_slice().iter().any(|c| is_c1(*c)) =>
+                    {
+                        Decoded::Invalid(len)
+                    }
+                    Some(chars) => Decoded::Chars(chars, len),
+                    // If the second byte of a malformed pair is ASCII, it's
+                    // a character of its own.
+                    None if len == 2 && input[1] < 0x80 => Decoded::Invalid(1),
+                    None => Decoded::Invalid(len),
+                }
+            }
+        }
+    }
+
+    /// Encode `c` into `out`, returning the number of bytes written, or
+    /// `None` if `c` isn't representable. `out` must have room for at least
+    /// 16 bytes.
+    pub(super) fn encode(self, state: &mut State, c: char, out: &mut [u8]) -> Option<usize> {
+        let code = u32::from(c);
+        match self {
+            Self::Ascii => {
+                if code >= 0x80 {
+                    return None;
+                }
+                out[0] = code as u8;
+                Some(1)
+            }
+            Self::Latin1 => {
+                if code >= 0x100 {
+                    return None;
+                }
+                out[0] = code as u8;
+                Some(1)
+            }
+            Self::Utf8 => Some(c.encode_utf8(out).len()),
+            Self::Utf16(endian) => {
+                let mut n = 0;
+                let endian = match endian {
+                    Some(endian) => endian,
+                    None => {
+                        if !state.bom {
+                            n += Endian::NATIVE.write_u16(0xfeff, out);
+                            state.bom = true;
+                        }
+                        Endian::NATIVE
+                    }
+                };
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    n += endian.write_u16(u32::from(*unit), &mut out[n..]);
+                }
+                Some(n)
+            }
+            Self::Utf32(endian) => {
+                let mut n = 0;
+                let endian = match endian {
+                    Some(endian) => endian,
+                    None => {
+                        if !state.bom {
+                            n += Endian::NATIVE.write_u32(0xfeff, out);
+                            state.bom = true;
+                        }
+                        Endian::NATIVE
+                    }
+                };
+                Some(n + endian.write_u32(code, &mut out[n..]))
+            }
+            Self::Ucs2(endian) => {
+                if code > 0xffff {
+                    return None;
+                }
+                Some(endian.write_u16(code, out))
+            }
+            Self::Ucs4(endian) => Some(endian.write_u32(code, out)),
+            #[cfg(feature = "iconv-encoding-rs")]
+            Self::Iso2022Jp => encode_iso_2022_jp(state, c, out),
+            #[cfg(feature = "iconv-encoding-rs")]
+            Self::Other { encoding, c1 } => {
+                if is_c1(c) {
+                    if c1 {
+                        out[0] = code as u8;
+                        return Some(1);
+                    }
+                    if encoding.is_single_byte() {
+                        return None;
+                    }
+                }
+                let n = encode_other(encoding, c, out)?;
+                // If a Windows code page maps this character into the C1
+                // range, it isn't in the ISO-8859 character set.
+                if c1 && n == 1 && (0x80..=0x9f).contains(&out[0]) {
+                    return None;
+                }
+                Some(n)
+            }
+        }
+    }
+
+    /// Write the bytes needed to return to the initial shift state, and
+    /// return their length.
+    #[cfg_attr(not(feature = "iconv-encoding-rs"), allow(unused_variables))]
+    pub(super) fn reset(self, state: &State, out: &mut [u8]) -> usize {
+        match self {
+            #[cfg(feature = "iconv-encoding-rs")]
+            Self::Iso2022Jp if state.mode != Mode::Ascii => {
+                out[..3].copy_from_slice(b"\x1b(B");
+                3
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(feature = "iconv-encoding-rs")]
+fn is_c1(c: char) -> bool {
+    ('\u{80}'..='\u{9f}').contains(&c)
+}
+
+/// Determine the length of the multi-byte sequence at the start of `input`
+/// from its lead byte, or `None` if we need more input to tell.
+#[cfg(feature = "iconv-encoding-rs")]
+fn sequence_len(encoding: &'static Encoding, input: &[u8]) -> Option<usize> {
+    let lead = input[0];
+    let len = if encoding.is_single_byte() {
+        1
+    } else if encoding == encoding_rs::SHIFT_JIS {
+        match lead {
+            0x81..=0x9f | 0xe0..=0xfc => 2,
+            _ => 1,
+        }
+    } else if encoding == encoding_rs::EUC_JP {
+        match lead {
+            0x8e | 0xa1..=0xfe => 2,
+            0x8f => 3,
+            _ => 1,
+        }
+    } else if encoding == encoding_rs::GBK || encoding == encoding_rs::GB18030 {
+        match lead {
+            0x81..=0xfe => match input.get(1)? {
+                0x30..=0x39 => 4,
+                _ => 2,
+            },
+            _ => 1,
+        }
+    } else {
+        // Big5 and EUC-KR.
+        match lead {
+            0x81..=0xfe => 2,
+            _ => 1,
+        }
+    };
+    Some(len)
+}
+
+/// Decode one complete sequence with `encoding_rs`.
+#[cfg(feature = "iconv-encoding-rs")]
+fn decode_other(encoding: &'static Encoding, input: &[u8]) -> Option<Chars> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut buf = [0_u8; 16];
+    let (result, _read, written) =
+        decoder.decode_to_utf8_without_replacement(input, &mut buf, true);
+    if !matches!(result, DecoderResult::InputEmpty) {
+        return None;
+    }
+    let s = core::str::from_utf8(&buf[..written]).ok()?;
+    let mut chars = Chars {
+        chars: ['\0'; 2],
+        len: 0,
+    };
+    for c in s.chars() {
+        *chars.chars.get_mut(chars.len)? = c;
+        chars.len += 1;
+    }
+    if chars.len == 0 {
+        return None;
+    }
+    Some(chars)
+}
+
+/// Encode one character with `encoding_rs`.
+#[cfg(feature = "iconv-encoding-rs")]
+fn encode_other(encoding: &'static Encoding, c: char, out: &mut [u8]) -> Option<usize> {
+    let mut encoder = encoding.new_encoder();
+    let mut utf8 = [0_u8; 4];
+    let (result, _read, written) =
+        encoder.encode_from_utf8_without_replacement(c.encode_utf8(&mut utf8), out, true);
+    match result {
+        EncoderResult::InputEmpty => Some(written),
+        EncoderResult::Unmappable(_) | EncoderResult::OutputFull => None,
+    }
+}
+
+/// Decode ISO-2022-JP, which we implement by mapping JIS X 0208 pairs onto
+/// EUC-JP.
+#[cfg(feature = "iconv-encoding-rs")]
+fn decode_iso_2022_jp(state: &mut State, input: &[u8]) -> Decoded {
+    let b = input[0];
+    if b == 0x1b {
+        const ESCAPES: [(&[u8], Mode); 4] = [
+            (b"\x1b(B", Mode::Ascii),
+            (b"\x1b(J", Mode::Roman),
+            (b"\x1b$@", Mode::Jis0208),
+            (b"\x1b$B", Mode::Jis0208),
+        ];
+        for (escape, mode) in ESCAPES {
+            if input.starts_with(escape) {
+                state.mode = mode;
+                return Decoded::Skip(escape.len());
+            }
+            if escape.starts_with(input) {
+                return Decoded::Incomplete;
+            }
+        }
+        return Decoded::Invalid(1);
+    }
+    if b >= 0x80 {
+        return Decoded::Invalid(1);
+    }
+
+    let c = match state.mode {
+        // Control characters are the same in every mode.
+        _ if b < 0x21 || b == 0x7f => char::from(b),
+        Mode::Ascii => char::from(b),
+        Mode::Roman => match b {
+            0x5c => '\u{a5}',
+            0x7e => '\u{203e}',
+            _ => char::from(b),
+        },
+        Mode::Jis0208 => {
+            let Some(&trail) = input.get(1) else {
+                return Decoded::Incomplete;
+            };
+            if !(0x21..=0x7e).contains(&trail) {
+                return Decoded::Invalid(1);
+            }
+            return match decode_other(encoding_rs::EUC_JP, &[b | 0x80, trail | 0x80]) {
+                Some(chars) => Decoded::Chars(chars, 2),
+                None => Decoded::Invalid(2),
+            };
+        }
+    };
+    Decoded::Chars(Chars::one(c), 1)
+}
+
+#[cfg(feature = "iconv-encoding-rs")]
+fn encode_iso_2022_jp(state: &mut State, c: char, out: &mut [u8]) -> Option<usize> {
+    let (mode, bytes, len) = if c.is_ascii() {
+        (Mode::Ascii, [c as u8, 0], 1)
+    } else if c == '\u{a5}' {
+        (Mode::Roman, [0x5c, 0], 1)
+    } else if c == '\u{203e}' {
+        (Mode::Roman, [0x7e, 0], 1)
+    } else {
+        let mut euc = [0_u8; 4];
+        match encode_other(encoding_rs::EUC_JP, c, &mut euc)? {
+            2 if euc[0] >= 0xa1 => (Mode::Jis0208, [euc[0] & 0x7f, euc[1] & 0x7f], 2),
+            _ => return None,
+        }
+    };
+
+    let mut n = 0;
+    if mode != state.mode {
+        let escape: &[u8] = match mode {
+            Mode::Ascii => b"\x1b(B",
+            Mode::Roman => b"\x1b(J",
+            Mode::Jis0208 => b"\x1b$B",
+        };
+        out[..escape.len()].copy_from_slice(escape);
+        n = escape.len();
+        state.mode = mode;
+    }
+    out[n..n + len].copy_from_slice(&bytes[..len]);
+    Some(n + len)
+}
@@ -0,0 +1,407 @@
+//! `iconv` and friends.
+
+mod charset;
+mod translit;
+
+use alloc::boxed::Box;
+use charset::{Charset, Decoded, State};
+use core::ffi::CStr;
+use core::slice;
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int, size_t};
+
+struct Converter {
+    from: Charset,
+    to: Charset,
+    /// Whether `//TRANSLIT` was requested.
+    translit: bool,
+    /// Whether `//IGNORE` was requested.
+    ignore: bool,
+    decode: State,
+    encode: State,
+}
+
+impl Converter {
+    /// Convert as much of `input` into `output` as we can, returning the
+    /// number of bytes read and written, and either the number of
+    /// irreversible conversions or an errno value.
+    fn convert(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> (usize, usize, Result<size_t, c_int>) {
+        let mut read = 0;
+        let mut written = 0;
+        let mut irreversible = 0;
+        let mut ignored = false;
+
+        while read < input.len() {
+            let mut decode = self.decode;
+            let (chars, len) = match self.from.decode(&mut decode, &input[read..]) {
+                Decoded::Chars(chars, len) => (chars, len),
+                Decoded::Skip(len) => {
+                    self.decode = decode;
+                    read += len;
+                    continue;
+                }
+                Decoded::Incomplete => return (read, written, Err(libc::EINVAL)),
+                Decoded::Invalid(len) => {
+                    if !self.ignore {
+                        return (read, written, Err(libc::EILSEQ));
+                    }
+                    self.decode = decode;
+                    read += len;
+                    ignored = true;
+                    continue;
+                }
+            };
+
+            // Encode into a scratch buffer, so that we don't commit to
+            // anything if the output doesn't have room.
+            let mut encode = self.encode;
+            let mut buf = [0_u8; 64];
+            let mut n = 0;
+            let mut lossy = 0;
+            for &c in chars.as_slice() {
+                match self.encode_char(&mut encode, c, &mut buf[n..]) {
+                    Some((len, exact)) => {
+                        n += len;
+                        if !exact {
+                            lossy += 1;
+                        }
+                    }
+                    None if self.ignore => ignored = true,
+                    None => return (read, written, Err(libc::EILSEQ)),
+                }
+            }
+            if n > output.len() - written {
+                return (read, written, Err(libc::E2BIG));
+            }
+
+            output[written..written + n].copy_from_slice(&buf[..n]);
+            self.decode = decode;
+            self.encode = encode;
+            read += len;
+            written += n;
+            irreversible += lossy;
+        }
+
+        // Like glibc, report skipped input as an error once we've converted
+        // everything else.
+        if ignored {
+            return (read, written, Err(libc::EILSEQ));
+        }
+        (read, written, Ok(irreversible))
+    }
+
+    /// Encode `c`, transliterating it if needed and requested. Returns the
+    /// number of bytes written and whether the conversion was exact.
+    fn encode_char(&self, state: &mut State, c: char, out: &mut [u8]) -> Option<(usize, bool)> {
+        if let Some(n) = self.to.encode(state, c, out) {
+            return Some((n, true));
+        }
+        if !self.translit {
+            return None;
+        }
+
+        if let Some(replacement) = translit::lookup(c) {
+            let mut replacement_state = *state;
+            let mut n = 0;
+            let complete = replacement.chars().all(|r| {
+                match self.to.encode(&mut replacement_state, r, &mut out[n..]) {
+                    Some(len) => {
+                        n += len;
+                        true
+                    }
+                    None => false,
+                }
+            });
+            if complete {
+                *state = replacement_state;
+                return Some((n, false));
+            }
+        }
+
+        let n = self.to.encode(state, '?', out)?;
+        Some((n, false))
+    }
+}
+
+/// Parse a character set name with optional `//TRANSLIT` and `//IGNORE`
+/// suffixes, returning the character set and whether each suffix was
+/// present.
+fn parse_name(name: &[u8]) -> Option<(Charset, bool, bool)> {
+    let (charset, suffixes) = match name.windows(2).position(|w| w == b"//") {
+        Some(i) => (&name[..i], &name[i + 2..]),
+        None => (name, &b""[..]),
+    };
+
+    let mut translit = false;
+    let mut ignore = false;
+    for suffix in suffixes.split(|b| *b == b'/' || *b == b',') {
+        if suffix.eq_ignore_ascii_case(b"TRANSLIT") {
+            translit = true;
+        } else if suffix.eq_ignore_ascii_case(b"IGNORE") {
+            ignore = true;
+        }
+    }
+
+    Some((Charset::for_name(charset)?, translit, ignore))
+}
+
+#[no_mangle]
+unsafe extern "C" fn iconv_open(tocode: *const c_char, fromcode: *const c_char) -> libc::iconv_t {
+    libc!(libc::iconv_open(tocode, fromcode));
+
+    let to = parse_name(CStr::from_ptr(tocode).to_bytes());
+    let from = parse_name(CStr::from_ptr(fromcode).to_bytes());
+    match (to, from) {
+        (Some((to, translit, ignore)), Some((from, _, _))) => Box::into_raw(Box::new(Converter {
+            from,
+            to,
+            translit,
+            ignore,
+            decode: State::default(),
+            encode: State::default(),
+        }))
+        .cast(),
+        _ => {
+            set_errno(Errno(libc::EINVAL));
+            usize::MAX as libc::iconv_t
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn iconv(
+    cd: libc::iconv_t,
+    inbuf: *mut *mut c_char,
+    inbytesleft: *mut size_t,
+    outbuf: *mut *mut c_char,
+    outbytesleft: *mut size_t,
+) -> size_t {
+    libc!(libc::iconv(cd, inbuf, inbytesleft, outbuf, outbytesleft));
+
+    let cd = &mut *cd.cast::<Converter>();
+
+    let output: &mut [u8] = if outbuf.is_null() || (*outbuf).is_null() {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut((*outbuf).cast(), *outbytesleft)
+    };
+
+    // With no input, return to the initial state, writing out whatever is
+    // needed to get the output there.
+    if inbuf.is_null() || (*inbuf).is_null() {
+        if !outbuf.is_null() && !(*outbuf).is_null() {
+            let mut buf = [0_u8; 8];
+            let n = cd.to.reset(&cd.encode, &mut buf);
+            if n > output.len() {
+                set_errno(Errno(libc::E2BIG));
+                return !0;
+            }
+            output[..n].copy_from_slice(&buf[..n]);
+            *outbuf = (*outbuf).add(n);
+            *outbytesleft -= n;
+        }
+        cd.decode = State::default();
+        cd.encode = State::default();
+        return 0;
+    }
+
+    let input = slice::from_raw_parts((*inbuf).cast::<u8>(), *inbytesleft);
+    let (read, written, result) = cd.convert(input, output);
+
+    *inbuf = (*inbuf).add(read);
+    *inbytesleft -= read;
+    if written != 0 {
+        *outbuf = (*outbuf).add(written);
+        *outbytesleft -= written;
+    }
+
+    match result {
+        Ok(irreversible) => irreversible,
+        Err(err) => {
+            set_errno(Errno(err));
+            !0
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn iconv_close(cd: libc::iconv_t) -> c_int {
+    libc!(libc::iconv_close(cd));
+
+    drop(Box::from_raw(cd.cast::<Converter>()));
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// The result of an `iconv` call: its return value, `errno` if it
+    /// failed, the number of bytes consumed, and the output.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Converted {
+        ret: size_t,
+        errno: c_int,
+        read: usize,
+        out: Vec<u8>,
+    }
+
+    unsafe fn open(to: &CStr, from: &CStr) -> libc::iconv_t {
+        let cd = iconv_open(to.as_ptr(), from.as_ptr());
+        assert_ne!(cd, usize::MAX as libc::iconv_t);
+        cd
+    }
+
+    /// Convert `input` into an output buffer with room for `cap` bytes,
+    /// checking that nothing is written past what `iconv` reports.
+    unsafe fn convert(cd: libc::iconv_t, input: &[u8], cap: usize) -> Converted {
+        let mut buf = [0xaa_u8; 64];
+        let mut inbuf = input.as_ptr().cast_mut().cast::<c_char>();
+        let mut inbytesleft = input.len();
+        let mut outbuf = buf.as_mut_ptr().cast::<c_char>();
+        let mut outbytesleft = cap;
+        set_errno(Errno(0));
+        let ret = iconv(
+            cd,
+            &mut inbuf,
+            &mut inbytesleft,
+            &mut outbuf,
+            &mut outbytesleft,
+        );
+        let errno = if ret == !0 { errno::errno().0 } else { 0 };
+        let written = cap - outbytesleft;
+        assert_eq!(outbuf, buf.as_mut_ptr().add(written).cast());
+        assert!(buf[written..].iter().all(|b| *b == 0xaa));
+        Converted {
+            ret,
+            errno,
+            read: input.len() - inbytesleft,
+            out: buf[..written].to_vec(),
+        }
+    }
+
+    /// Return `cd` to its initial state, returning the bytes written to get
+    /// the output there.
+    #[cfg(feature = "iconv-encoding-rs")]
+    unsafe fn reset(cd: libc::iconv_t) -> Vec<u8> {
+        let mut buf = [0_u8; 8];
+        let mut outbuf = buf.as_mut_ptr().cast::<c_char>();
+        let mut outbytesleft = buf.len();
+        assert_eq!(
+            iconv(
+                cd,
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+                &mut outbuf,
+                &mut outbytesleft
+            ),
+            0
+        );
+        buf[..buf.len() - outbytesleft].to_vec()
+    }
+
+    fn ok(ret: size_t, read: usize, out: &[u8]) -> Converted {
+        Converted {
+            ret,
+            errno: 0,
+            read,
+            out: out.to_vec(),
+        }
+    }
+
+    fn err(errno: c_int, read: usize, out: &[u8]) -> Converted {
+        Converted {
+            ret: !0,
+            errno,
+            read,
+            out: out.to_vec(),
+        }
+    }
+
+    // The expected results are from glibc in the C locale.
+
+    #[test]
+    fn test_iconv_utf16_bom() {
+        unsafe {
+            let cd = open(c"UTF-16", c"UTF-8");
+            let bom: &[u8] = if cfg!(target_endian = "little") {
+                b"\xff\xfeh\0\xe9\0"
+            } else {
+                b"\xfe\xff\0h\0\xe9"
+            };
+            assert_eq!(convert(cd, "hé".as_bytes(), 64), ok(0, 3, bom));
+            assert_eq!(iconv_close(cd), 0);
+
+            // An explicit byte order mark is consumed when decoding.
+            let cd = open(c"UTF-8", c"UTF-16");
+            assert_eq!(convert(cd, b"\xfe\xff\0h", 64), ok(0, 4, b"h"));
+            assert_eq!(iconv_close(cd), 0);
+        }
+    }
+
+    #[test]
+    fn test_iconv_partial() {
+        unsafe {
+            let cd = open(c"UTF-16LE", c"UTF-8");
+
+            // A truncated sequence is left unconsumed.
+            assert_eq!(convert(cd, b"a\xc3", 64), err(libc::EINVAL, 1, b"a\0"));
+
+            // A character that doesn't fit is left unconsumed, and none of
+            // it is written.
+            assert_eq!(convert(cd, "a€".as_bytes(), 3), err(libc::E2BIG, 1, b"a\0"));
+            assert_eq!(convert(cd, "€".as_bytes(), 64), ok(0, 3, b"\xac\x20"));
+            assert_eq!(iconv_close(cd), 0);
+
+            let cd = open(c"ASCII", c"UTF-8");
+            assert_eq!(
+                convert(cd, "aéb".as_bytes(), 64),
+                err(libc::EILSEQ, 1, b"a")
+            );
+            assert_eq!(iconv_close(cd), 0);
+        }
+    }
+
+    #[test]
+    fn test_iconv_translit_ignore() {
+        unsafe {
+            // Characters without a transliteration become `?`.
+            let cd = open(c"ASCII//TRANSLIT", c"UTF-8");
+            assert_eq!(convert(cd, "€ café".as_bytes(), 64), ok(2, 9, b"EUR caf?"));
+            assert_eq!(iconv_close(cd), 0);
+
+            // Skipped input is reported once everything else is converted.
+            let cd = open(c"ASCII//IGNORE", c"UTF-8");
+            assert_eq!(
+                convert(cd, "aéb".as_bytes(), 64),
+                err(libc::EILSEQ, 4, b"ab")
+            );
+            assert_eq!(iconv_close(cd), 0);
+        }
+    }
+
+    #[cfg(feature = "iconv-encoding-rs")]
+    #[test]
+    fn test_iconv_iso_2022_jp() {
+        unsafe {
+            let cd = open(c"ISO-2022-JP", c"UTF-8");
+            assert_eq!(convert(cd, "aあ".as_bytes(), 64), ok(0, 4, b"a\x1b$B$\""));
+            // Resetting shifts back to ASCII.
+            assert_eq!(reset(cd), b"\x1b(B");
+            assert_eq!(reset(cd), b"");
+            assert_eq!(iconv_close(cd), 0);
+
+            let cd = open(c"UTF-8", c"ISO-2022-JP");
+            assert_eq!(
+                convert(cd, b"a\x1b$B$\"\x1b(B", 64),
+                ok(0, 9, "aあ".as_bytes())
+            );
+            assert_eq!(iconv_close(cd), 0);
+        }
+    }
+}
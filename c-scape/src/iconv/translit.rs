@@ -0,0 +1,889 @@
+//! The locale-independent transliterations used by `//TRANSLIT`.
+//!
+//! This is glibc's table for the C locale, restricted to the Basic
+//! Multilingual Plane.
+
+/// Pairs of characters and their replacements, sorted by character. The
+/// fullwidth forms of the ASCII characters are handled separately.
+static TABLE: &[(char, &str)] = &[
+    ('\u{a0}', " "),
+    ('\u{a9}', "(C)"),
+    ('\u{ab}', "<<"),
+    ('\u{ad}', "-"),
+    ('\u{ae}', "(R)"),
+    ('\u{b5}', "u"),
+    ('\u{b8}', ","),
+    ('\u{bb}', ">>"),
+    ('\u{bc}', " 1/4 "),
+    ('\u{bd}', " 1/2 "),
+    ('\u{be}', " 3/4 "),
+    ('\u{c6}', "AE"),
+    ('\u{d7}', "x"),
+    ('\u{df}', "ss"),
+    ('\u{e6}', "ae"),
+    ('\u{132}', "IJ"),
+    ('\u{133}', "ij"),
+    ('\u{149}', "'n"),
+    ('\u{152}', "OE"),
+    ('\u{153}', "oe"),
+    ('\u{17f}', "s"),
+    ('\u{1c7}', "LJ"),
+    ('\u{1c8}', "Lj"),
+    ('\u{1c9}', "lj"),
+    ('\u{1ca}', "NJ"),
+    ('\u{1cb}', "Nj"),
+    ('\u{1cc}', "nj"),
+    ('\u{1f1}', "DZ"),
+    ('\u{1f2}', "Dz"),
+    ('\u{1f3}', "dz"),
+    ('\u{2bc}', "'"),
+    ('\u{2c6}', "^"),
+    ('\u{2c8}', "'"),
+    ('\u{2cb}', "`"),
+    ('\u{2cd}', "_"),
+    ('\u{2d0}', ":"),
+    ('\u{2dc}', "~"),
+    ('\u{370}', "H"),
+    ('\u{371}', "h"),
+    ('\u{372}', "SS"),
+    ('\u{373}', "ss"),
+    ('\u{374}', "#"),
+    ('\u{375}', "#`"),
+    ('\u{376}', "W"),
+    ('\u{377}', "w"),
+    ('\u{37a}', "i"),
+    ('\u{37b}', "s"),
+    ('\u{37c}', "s"),
+    ('\u{37d}', "s"),
+    ('\u{37f}', "J"),
+    ('\u{384}', "`"),
+    ('\u{385}', "`"),
+    ('\u{386}', "A"),
+    ('\u{387}', ";"),
+    ('\u{388}', "E"),
+    ('\u{389}', "I"),
+    ('\u{38a}', "I"),
+    ('\u{38c}', "O"),
+    ('\u{38e}', "Y"),
+    ('\u{38f}', "O"),
+    ('\u{390}', "I"),
+    ('\u{391}', "A"),
+    ('\u{392}', "V"),
+    ('\u{393}', "G"),
+    ('\u{394}', "D"),
+    ('\u{395}', "E"),
+    ('\u{396}', "Z"),
+    ('\u{397}', "I"),
+    ('\u{398}', "TH"),
+    ('\u{399}', "I"),
+    ('\u{39a}', "K"),
+    ('\u{39b}', "L"),
+    ('\u{39c}', "M"),
+    ('\u{39d}', "N"),
+    ('\u{39e}', "X"),
+    ('\u{39f}', "O"),
+    ('\u{3a0}', "P"),
+    ('\u{3a1}', "R"),
+    ('\u{3a3}', "S"),
+    ('\u{3a4}', "T"),
+    ('\u{3a5}', "Y"),
+    ('\u{3a6}', "F"),
+    ('\u{3a7}', "CH"),
+    ('\u{3a8}', "PS"),
+    ('\u{3a9}', "O"),
+    ('\u{3aa}', "I"),
+    ('\u{3ab}', "Y"),
+    ('\u{3ac}', "a"),
+    ('\u{3ad}', "e"),
+    ('\u{3ae}', "i"),
+    ('\u{3af}', "i"),
+    ('\u{3b0}', "y"),
+    ('\u{3b1}', "a"),
+    ('\u{3b2}', "v"),
+    ('\u{3b3}', "g"),
+    ('\u{3b4}', "d"),
+    ('\u{3b5}', "e"),
+    ('\u{3b6}', "z"),
+    ('\u{3b7}', "i"),
+    ('\u{3b8}', "th"),
+    ('\u{3b9}', "i"),
+    ('\u{3ba}', "k"),
+    ('\u{3bb}', "l"),
+    ('\u{3bc}', "m"),
+    ('\u{3bd}', "n"),
+    ('\u{3be}', "x"),
+    ('\u{3bf}', "o"),
+    ('\u{3c0}', "p"),
+    ('\u{3c1}', "r"),
+    ('\u{3c2}', "s"),
+    ('\u{3c3}', "s"),
+    ('\u{3c4}', "t"),
+    ('\u{3c5}', "y"),
+    ('\u{3c6}', "f"),
+    ('\u{3c7}', "ch"),
+    ('\u{3c8}', "ps"),
+    ('\u{3c9}', "o"),
+    ('\u{3ca}', "i"),
+    ('\u{3cb}', "y"),
+    ('\u{3cc}', "o"),
+    ('\u{3cd}', "y"),
+    ('\u{3ce}', "o"),
+    ('\u{3cf}', "&"),
+    ('\u{3d0}', "b"),
+    ('\u{3d1}', "th"),
+    ('\u{3d2}', "Y`"),
+    ('\u{3d3}', "Y`"),
+    ('\u{3d4}', "Y`"),
+    ('\u{3d5}', "f"),
+    ('\u{3d6}', "p"),
+    ('\u{3d7}', "&"),
+    ('\u{3d8}', "Q"),
+    ('\u{3d9}', "q"),
+    ('\u{3da}', "6"),
+    ('\u{3db}', "6"),
+    ('\u{3dc}', "W"),
+    ('\u{3dd}', "w"),
+    ('\u{3de}', "90"),
+    ('\u{3df}', "90"),
+    ('\u{3e0}', "900"),
+    ('\u{3e1}', "900"),
+    ('\u{3e2}', "SH"),
+    ('\u{3e3}', "sh"),
+    ('\u{3e4}', "F"),
+    ('\u{3e5}', "f"),
+    ('\u{3e6}', "KH"),
+    ('\u{3e7}', "kh"),
+    ('\u{3e8}', "H"),
+    ('\u{3e9}', "h"),
+    ('\u{3ea}', "DJ"),
+    ('\u{3eb}', "dj"),
+    ('\u{3ec}', "GJ"),
+    ('\u{3ed}', "gj"),
+    ('\u{3ee}', "TI"),
+    ('\u{3ef}', "ti"),
+    ('\u{3f0}', "k"),
+    ('\u{3f1}', "r"),
+    ('\u{3f2}', "s"),
+    ('\u{3f3}', "j"),
+    ('\u{3f4}', "TH"),
+    ('\u{3f5}', "e"),
+    ('\u{3f6}', "e"),
+    ('\u{3f7}', "SH"),
+    ('\u{3f8}', "sh"),
+    ('\u{3f9}', "S"),
+    ('\u{3fa}', "S"),
+    ('\u{3fb}', "s"),
+    ('\u{3fc}', "r"),
+    ('\u{3fd}', "S"),
+    ('\u{3fe}', "S"),
+    ('\u{3ff}', "S"),
+    ('\u{401}', "YO"),
+    ('\u{402}', "DJ"),
+    ('\u{403}', "G`"),
+    ('\u{404}', "YE"),
+    ('\u{405}', "Z`"),
+    ('\u{406}', "I"),
+    ('\u{407}', "YI"),
+    ('\u{408}', "J"),
+    ('\u{409}', "L`"),
+    ('\u{40a}', "N`"),
+    ('\u{40b}', "TSH"),
+    ('\u{40c}', "K`"),
+    ('\u{40e}', "U`"),
+    ('\u{40f}', "DH"),
+    ('\u{410}', "A"),
+    ('\u{411}', "B"),
+    ('\u{412}', "V"),
+    ('\u{413}', "G"),
+    ('\u{414}', "D"),
+    ('\u{415}', "E"),
+    ('\u{416}', "ZH"),
+    ('\u{417}', "Z"),
+    ('\u{418}', "I"),
+    ('\u{419}', "J"),
+    ('\u{41a}', "K"),
+    ('\u{41b}', "L"),
+    ('\u{41c}', "M"),
+    ('\u{41d}', "N"),
+    ('\u{41e}', "O"),
+    ('\u{41f}', "P"),
+    ('\u{420}', "R"),
+    ('\u{421}', "S"),
+    ('\u{422}', "T"),
+    ('\u{423}', "U"),
+    ('\u{424}', "F"),
+    ('\u{425}', "X"),
+    ('\u{426}', "CZ"),
+    ('\u{427}', "CH"),
+    ('\u{428}', "SH"),
+    ('\u{429}', "SHH"),
+    ('\u{42a}', "A`"),
+    ('\u{42b}', "Y`"),
+    ('\u{42c}', "`"),
+    ('\u{42d}', "E`"),
+    ('\u{42e}', "YU"),
+    ('\u{42f}', "YA"),
+    ('\u{430}', "a"),
+    ('\u{431}', "b"),
+    ('\u{432}', "v"),
+    ('\u{433}', "g"),
+    ('\u{434}', "d"),
+    ('\u{435}', "e"),
+    ('\u{436}', "zh"),
+    ('\u{437}', "z"),
+    ('\u{438}', "i"),
+    ('\u{439}', "j"),
+    ('\u{43a}', "k"),
+    ('\u{43b}', "l"),
+    ('\u{43c}', "m"),
+    ('\u{43d}', "n"),
+    ('\u{43e}', "o"),
+    ('\u{43f}', "p"),
+    ('\u{440}', "r"),
+    ('\u{441}', "s"),
+    ('\u{442}', "t"),
+    ('\u{443}', "u"),
+    ('\u{444}', "f"),
+    ('\u{445}', "x"),
+    ('\u{446}', "cz"),
+    ('\u{447}', "ch"),
+    ('\u{448}', "sh"),
+    ('\u{449}', "shh"),
+    ('\u{44a}', "``"),
+    ('\u{44b}', "y`"),
+    ('\u{44c}', "`"),
+    ('\u{44d}', "e`"),
+    ('\u{44e}', "yu"),
+    ('\u{44f}', "ya"),
+    ('\u{451}', "yo"),
+    ('\u{452}', "dj"),
+    ('\u{453}', "g`"),
+    ('\u{454}', "ye"),
+    ('\u{455}', "z`"),
+    ('\u{456}', "i"),
+    ('\u{457}', "yi"),
+    ('\u{458}', "j"),
+    ('\u{459}', "l`"),
+    ('\u{45a}', "n`"),
+    ('\u{45b}', "tsh"),
+    ('\u{45c}', "k`"),
+    ('\u{45e}', "u`"),
+    ('\u{45f}', "dh"),
+    ('\u{46a}', "O`"),
+    ('\u{46b}', "o`"),
+    ('\u{472}', "FH"),
+    ('\u{473}', "fh"),
+    ('\u{474}', "YH"),
+    ('\u{475}', "yh"),
+    ('\u{48c}', "E`"),
+    ('\u{48d}', "e`"),
+    ('\u{490}', "G`"),
+    ('\u{491}', "g`"),
+    ('\u{492}', "GH"),
+    ('\u{493}', "gh"),
+    ('\u{494}', "GH"),
+    ('\u{495}', "gh"),
+    ('\u{496}', "ZH`"),
+    ('\u{497}', "zh`"),
+    ('\u{49a}', "K`"),
+    ('\u{49b}', "k`"),
+    ('\u{49e}', "K`"),
+    ('\u{49f}', "k`"),
+    ('\u{4a2}', "N`"),
+    ('\u{4a3}', "n`"),
+    ('\u{4a4}', "NG"),
+    ('\u{4a5}', "ng"),
+    ('\u{4a6}', "P`"),
+    ('\u{4a7}', "p`"),
+    ('\u{4a8}', "O`"),
+    ('\u{4a9}', "o`"),
+    ('\u{4aa}', "C`"),
+    ('\u{4ab}', "C`"),
+    ('\u{4ac}', "T`"),
+    ('\u{4ad}', "t`"),
+    ('\u{4ae}', "U"),
+    ('\u{4af}', "u"),
+    ('\u{4b2}', "H`"),
+    ('\u{4b3}', "h`"),
+    ('\u{4b4}', "TCZ"),
+    ('\u{4b5}', "tcz"),
+    ('\u{4ba}', "SH`"),
+    ('\u{4bb}', "sh`"),
+    ('\u{4bc}', "CH`"),
+    ('\u{4bd}', "ch`"),
+    ('\u{4be}', "CH`"),
+    ('\u{4bf}', "ch`"),
+    ('\u{4c0}', "i"),
+    ('\u{4c1}', "ZH`"),
+    ('\u{4c2}', "zh`"),
+    ('\u{4cb}', "CH`"),
+    ('\u{4cc}', "ch`"),
+    ('\u{4d0}', "A`"),
+    ('\u{4d1}', "a`"),
+    ('\u{4d2}', "A`"),
+    ('\u{4d3}', "a`"),
+    ('\u{4d6}', "E`"),
+    ('\u{4d7}', "e`"),
+    ('\u{4d8}', "A`"),
+    ('\u{4d9}', "a`"),
+    ('\u{4dc}', "ZH`"),
+    ('\u{4dd}', "zh`"),
+    ('\u{4de}', "Z`"),
+    ('\u{4df}', "z`"),
+    ('\u{4e0}', "Z`"),
+    ('\u{4e1}', "z`"),
+    ('\u{4e4}', "I`"),
+    ('\u{4e5}', "i`"),
+    ('\u{4e6}', "O`"),
+    ('\u{4e7}', "o`"),
+    ('\u{4e8}', "O`"),
+    ('\u{4e9}', "o`"),
+    ('\u{4f0}', "U`"),
+    ('\u{4f1}', "u`"),
+    ('\u{4f2}', "U`"),
+    ('\u{4f3}', "u`"),
+    ('\u{4f4}', "CH`"),
+    ('\u{4f5}', "ch`"),
+    ('\u{4f8}', "Y`"),
+    ('\u{4f9}', "y`"),
+    ('\u{5f3}', "'"),
+    ('\u{5f4}', "\""),
+    ('\u{2002}', " "),
+    ('\u{2003}', " "),
+    ('\u{2004}', " "),
+    ('\u{2005}', " "),
+    ('\u{2006}', " "),
+    ('\u{2008}', " "),
+    ('\u{2009}', " "),
+    ('\u{200a}', " "),
+    ('\u{200b}', ""),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2014}', "--"),
+    ('\u{2015}', "-"),
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201a}', ","),
+    ('\u{201b}', "'"),
+    ('\u{201c}', "\""),
+    ('\u{201d}', "\""),
+    ('\u{201e}', ",,"),
+    ('\u{201f}', "\""),
+    ('\u{2020}', "+"),
+    ('\u{2022}', "o"),
+    ('\u{2024}', "."),
+    ('\u{2025}', ".."),
+    ('\u{2026}', "..."),
+    ('\u{202f}', " "),
+    ('\u{2035}', "`"),
+    ('\u{2036}', "``"),
+    ('\u{2037}', "```"),
+    ('\u{2039}', "<"),
+    ('\u{203a}', ">"),
+    ('\u{203c}', "!!"),
+    ('\u{2044}', "/"),
+    ('\u{2047}', "??"),
+    ('\u{2048}', "?!"),
+    ('\u{2049}', "!?"),
+    ('\u{205f}', " "),
+    ('\u{2060}', ""),
+    ('\u{2061}', ""),
+    ('\u{2062}', ""),
+    ('\u{2063}', ""),
+    ('\u{20a1}', "C="),
+    ('\u{20a8}', "Rs"),
+    ('\u{20ac}', "EUR"),
+    ('\u{20b9}', "INR"),
+    ('\u{2100}', "a/c"),
+    ('\u{2101}', "a/s"),
+    ('\u{2102}', "C"),
+    ('\u{2105}', "c/o"),
+    ('\u{2106}', "c/u"),
+    ('\u{210a}', "g"),
+    ('\u{210b}', "H"),
+    ('\u{210c}', "H"),
+    ('\u{210d}', "H"),
+    ('\u{210e}', "h"),
+    ('\u{2110}', "I"),
+    ('\u{2111}', "I"),
+    ('\u{2112}', "L"),
+    ('\u{2113}', "l"),
+    ('\u{2115}', "N"),
+    ('\u{2116}', "No"),
+    ('\u{2119}', "P"),
+    ('\u{211a}', "Q"),
+    ('\u{211b}', "R"),
+    ('\u{211c}', "R"),
+    ('\u{211d}', "R"),
+    ('\u{2121}', "TEL"),
+    ('\u{2122}', "(TM)"),
+    ('\u{2124}', "Z"),
+    ('\u{2126}', "Ohm"),
+    ('\u{2128}', "Z"),
+    ('\u{212c}', "B"),
+    ('\u{212d}', "C"),
+    ('\u{212e}', "e"),
+    ('\u{212f}', "e"),
+    ('\u{2130}', "E"),
+    ('\u{2131}', "F"),
+    ('\u{2133}', "M"),
+    ('\u{2134}', "o"),
+    ('\u{2139}', "i"),
+    ('\u{2145}', "D"),
+    ('\u{2146}', "d"),
+    ('\u{2147}', "e"),
+    ('\u{2148}', "i"),
+    ('\u{2149}', "j"),
+    ('\u{2153}', " 1/3 "),
+    ('\u{2154}', " 2/3 "),
+    ('\u{2155}', " 1/5 "),
+    ('\u{2156}', " 2/5 "),
+    ('\u{2157}', " 3/5 "),
+    ('\u{2158}', " 4/5 "),
+    ('\u{2159}', " 1/6 "),
+    ('\u{215a}', " 5/6 "),
+    ('\u{215b}', " 1/8 "),
+    ('\u{215c}', " 3/8 "),
+    ('\u{215d}', " 5/8 "),
+    ('\u{215e}', " 7/8 "),
+    ('\u{215f}', " 1/"),
+    ('\u{2160}', "I"),
+    ('\u{2161}', "II"),
+    ('\u{2162}', "III"),
+    ('\u{2163}', "IV"),
+    ('\u{2164}', "V"),
+    ('\u{2165}', "VI"),
+    ('\u{2166}', "VII"),
+    ('\u{2167}', "VIII"),
+    ('\u{2168}', "IX"),
+    ('\u{2169}', "X"),
+    ('\u{216a}', "XI"),
+    ('\u{216b}', "XII"),
+    ('\u{216c}', "L"),
+    ('\u{216d}', "C"),
+    ('\u{216e}', "D"),
+    ('\u{216f}', "M"),
+    ('\u{2170}', "i"),
+    ('\u{2171}', "ii"),
+    ('\u{2172}', "iii"),
+    ('\u{2173}', "iv"),
+    ('\u{2174}', "v"),
+    ('\u{2175}', "vi"),
+    ('\u{2176}', "vii"),
+    ('\u{2177}', "viii"),
+    ('\u{2178}', "ix"),
+    ('\u{2179}', "x"),
+    ('\u{217a}', "xi"),
+    ('\u{217b}', "xii"),
+    ('\u{217c}', "l"),
+    ('\u{217d}', "c"),
+    ('\u{217e}', "d"),
+    ('\u{217f}', "m"),
+    ('\u{2190}', "<-"),
+    ('\u{2192}', "->"),
+    ('\u{2194}', "<->"),
+    ('\u{21d0}', "<="),
+    ('\u{21d2}', "=>"),
+    ('\u{21d4}', "<=>"),
+    ('\u{2212}', "-"),
+    ('\u{2215}', "/"),
+    ('\u{2216}', "\\"),
+    ('\u{2217}', "*"),
+    ('\u{2223}', "|"),
+    ('\u{2236}', ":"),
+    ('\u{223c}', "~"),
+    ('\u{2264}', "<="),
+    ('\u{2265}', ">="),
+    ('\u{226a}', "<<"),
+    ('\u{226b}', ">>"),
+    ('\u{22d8}', "<<<"),
+    ('\u{22d9}', ">>>"),
+    ('\u{2400}', "NUL"),
+    ('\u{2401}', "SOH"),
+    ('\u{2402}', "STX"),
+    ('\u{2403}', "ETX"),
+    ('\u{2404}', "EOT"),
+    ('\u{2405}', "ENQ"),
+    ('\u{2406}', "ACK"),
+    ('\u{2407}', "BEL"),
+    ('\u{2408}', "BS"),
+    ('\u{2409}', "HT"),
+    ('\u{240a}', "LF"),
+    ('\u{240b}', "VT"),
+    ('\u{240c}', "FF"),
+    ('\u{240d}', "CR"),
+    ('\u{240e}', "SO"),
+    ('\u{240f}', "SI"),
+    ('\u{2410}', "DLE"),
+    ('\u{2411}', "DC1"),
+    ('\u{2412}', "DC2"),
+    ('\u{2413}', "DC3"),
+    ('\u{2414}', "DC4"),
+    ('\u{2415}', "NAK"),
+    ('\u{2416}', "SYN"),
+    ('\u{2417}', "ETB"),
+    ('\u{2418}', "CAN"),
+    ('\u{2419}', "EM"),
+    ('\u{241a}', "SUB"),
+    ('\u{241b}', "ESC"),
+    ('\u{241c}', "FS"),
+    ('\u{241d}', "GS"),
+    ('\u{241e}', "RS"),
+    ('\u{241f}', "US"),
+    ('\u{2420}', "SP"),
+    ('\u{2421}', "DEL"),
+    ('\u{2423}', "_"),
+    ('\u{2424}', "NL"),
+    ('\u{2460}', "(1)"),
+    ('\u{2461}', "(2)"),
+    ('\u{2462}', "(3)"),
+    ('\u{2463}', "(4)"),
+    ('\u{2464}', "(5)"),
+    ('\u{2465}', "(6)"),
+    ('\u{2466}', "(7)"),
+    ('\u{2467}', "(8)"),
+    ('\u{2468}', "(9)"),
+    ('\u{2469}', "(10)"),
+    ('\u{246a}', "(11)"),
+    ('\u{246b}', "(12)"),
+    ('\u{246c}', "(13)"),
+    ('\u{246d}', "(14)"),
+    ('\u{246e}', "(15)"),
+    ('\u{246f}', "(16)"),
+    ('\u{2470}', "(17)"),
+    ('\u{2471}', "(18)"),
+    ('\u{2472}', "(19)"),
+    ('\u{2473}', "(20)"),
+    ('\u{2474}', "(1)"),
+    ('\u{2475}', "(2)"),
+    ('\u{2476}', "(3)"),
+    ('\u{2477}', "(4)"),
+    ('\u{2478}', "(5)"),
+    ('\u{2479}', "(6)"),
+    ('\u{247a}', "(7)"),
+    ('\u{247b}', "(8)"),
+    ('\u{247c}', "(9)"),
+    ('\u{247d}', "(10)"),
+    ('\u{247e}', "(11)"),
+    ('\u{247f}', "(12)"),
+    ('\u{2480}', "(13)"),
+    ('\u{2481}', "(14)"),
+    ('\u{2482}', "(15)"),
+    ('\u{2483}', "(16)"),
+    ('\u{2484}', "(17)"),
+    ('\u{2485}', "(18)"),
+    ('\u{2486}', "(19)"),
+    ('\u{2487}', "(20)"),
+    ('\u{2488}', "1."),
+    ('\u{2489}', "2."),
+    ('\u{248a}', "3."),
+    ('\u{248b}', "4."),
+    ('\u{248c}', "5."),
+    ('\u{248d}', "6."),
+    ('\u{248e}', "7."),
+    ('\u{248f}', "8."),
+    ('\u{2490}', "9."),
+    ('\u{2491}', "10."),
+    ('\u{2492}', "11."),
+    ('\u{2493}', "12."),
+    ('\u{2494}', "13."),
+    ('\u{2495}', "14."),
+    ('\u{2496}', "15."),
+    ('\u{2497}', "16."),
+    ('\u{2498}', "17."),
+    ('\u{2499}', "18."),
+    ('\u{249a}', "19."),
+    ('\u{249b}', "20."),
+    ('\u{249c}', "(a)"),
+    ('\u{249d}', "(b)"),
+    ('\u{249e}', "(c)"),
+    ('\u{249f}', "(d)"),
+    ('\u{24a0}', "(e)"),
+    ('\u{24a1}', "(f)"),
+    ('\u{24a2}', "(g)"),
+    ('\u{24a3}', "(h)"),
+    ('\u{24a4}', "(i)"),
+    ('\u{24a5}', "(j)"),
+    ('\u{24a6}', "(k)"),
+    ('\u{24a7}', "(l)"),
+    ('\u{24a8}', "(m)"),
+    ('\u{24a9}', "(n)"),
+    ('\u{24aa}', "(o)"),
+    ('\u{24ab}', "(p)"),
+    ('\u{24ac}', "(q)"),
+    ('\u{24ad}', "(r)"),
+    ('\u{24ae}', "(s)"),
+    ('\u{24af}', "(t)"),
+    ('\u{24b0}', "(u)"),
+    ('\u{24b1}', "(v)"),
+    ('\u{24b2}', "(w)"),
+    ('\u{24b3}', "(x)"),
+    ('\u{24b4}', "(y)"),
+    ('\u{24b5}', "(z)"),
+    ('\u{24b6}', "(A)"),
+    ('\u{24b7}', "(B)"),
+    ('\u{24b8}', "(C)"),
+    ('\u{24b9}', "(D)"),
+    ('\u{24ba}', "(E)"),
+    ('\u{24bb}', "(F)"),
+    ('\u{24bc}', "(G)"),
+    ('\u{24bd}', "(H)"),
+    ('\u{24be}', "(I)"),
+    ('\u{24bf}', "(J)"),
+    ('\u{24c0}', "(K)"),
+    ('\u{24c1}', "(L)"),
+    ('\u{24c2}', "(M)"),
+    ('\u{24c3}', "(N)"),
+    ('\u{24c4}', "(O)"),
+    ('\u{24c5}', "(P)"),
+    ('\u{24c6}', "(Q)"),
+    ('\u{24c7}', "(R)"),
+    ('\u{24c8}', "(S)"),
+    ('\u{24c9}', "(T)"),
+    ('\u{24ca}', "(U)"),
+    ('\u{24cb}', "(V)"),
+    ('\u{24cc}', "(W)"),
+    ('\u{24cd}', "(X)"),
+    ('\u{24ce}', "(Y)"),
+    ('\u{24cf}', "(Z)"),
+    ('\u{24d0}', "(a)"),
+    ('\u{24d1}', "(b)"),
+    ('\u{24d2}', "(c)"),
+    ('\u{24d3}', "(d)"),
+    ('\u{24d4}', "(e)"),
+    ('\u{24d5}', "(f)"),
+    ('\u{24d6}', "(g)"),
+    ('\u{24d7}', "(h)"),
+    ('\u{24d8}', "(i)"),
+    ('\u{24d9}', "(j)"),
+    ('\u{24da}', "(k)"),
+    ('\u{24db}', "(l)"),
+    ('\u{24dc}', "(m)"),
+    ('\u{24dd}', "(n)"),
+    ('\u{24de}', "(o)"),
+    ('\u{24df}', "(p)"),
+    ('\u{24e0}', "(q)"),
+    ('\u{24e1}', "(r)"),
+    ('\u{24e2}', "(s)"),
+    ('\u{24e3}', "(t)"),
+    ('\u{24e4}', "(u)"),
+    ('\u{24e5}', "(v)"),
+    ('\u{24e6}', "(w)"),
+    ('\u{24e7}', "(x)"),
+    ('\u{24e8}', "(y)"),
+    ('\u{24e9}', "(z)"),
+    ('\u{24ea}', "(0)"),
+    ('\u{2500}', "-"),
+    ('\u{2502}', "|"),
+    ('\u{250c}', "+"),
+    ('\u{2510}', "+"),
+    ('\u{2514}', "+"),
+    ('\u{2518}', "+"),
+    ('\u{251c}', "+"),
+    ('\u{2524}', "+"),
+    ('\u{252c}', "+"),
+    ('\u{2534}', "+"),
+    ('\u{253c}', "+"),
+    ('\u{25e6}', "o"),
+    ('\u{2a74}', "::="),
+    ('\u{2a75}', "=="),
+    ('\u{2a76}', "==="),
+    ('\u{3000}', " "),
+    ('\u{30a0}', "="),
+    ('\u{3251}', "(21)"),
+    ('\u{3252}', "(22)"),
+    ('\u{3253}', "(23)"),
+    ('\u{3254}', "(24)"),
+    ('\u{3255}', "(25)"),
+    ('\u{3256}', "(26)"),
+    ('\u{3257}', "(27)"),
+    ('\u{3258}', "(28)"),
+    ('\u{3259}', "(29)"),
+    ('\u{325a}', "(30)"),
+    ('\u{325b}', "(31)"),
+    ('\u{325c}', "(32)"),
+    ('\u{325d}', "(33)"),
+    ('\u{325e}', "(34)"),
+    ('\u{325f}', "(35)"),
+    ('\u{32b1}', "(36)"),
+    ('\u{32b2}', "(37)"),
+    ('\u{32b3}', "(38)"),
+    ('\u{32b4}', "(39)"),
+    ('\u{32b5}', "(40)"),
+    ('\u{32b6}', "(41)"),
+    ('\u{32b7}', "(42)"),
+    ('\u{32b8}', "(43)"),
+    ('\u{32b9}', "(44)"),
+    ('\u{32ba}', "(45)"),
+    ('\u{32bb}', "(46)"),
+    ('\u{32bc}', "(47)"),
+    ('\u{32bd}', "(48)"),
+    ('\u{32be}', "(49)"),
+    ('\u{32bf}', "(50)"),
+    ('\u{3371}', "hPa"),
+    ('\u{3372}', "da"),
+    ('\u{3373}', "AU"),
+    ('\u{3374}', "bar"),
+    ('\u{3375}', "oV"),
+    ('\u{3376}', "pc"),
+    ('\u{3380}', "pA"),
+    ('\u{3381}', "nA"),
+    ('\u{3382}', "uA"),
+    ('\u{3383}', "mA"),
+    ('\u{3384}', "kA"),
+    ('\u{3385}', "KB"),
+    ('\u{3386}', "MB"),
+    ('\u{3387}', "GB"),
+    ('\u{3388}', "cal"),
+    ('\u{3389}', "kcal"),
+    ('\u{338a}', "pF"),
+    ('\u{338b}', "nF"),
+    ('\u{338c}', "uF"),
+    ('\u{338d}', "ug"),
+    ('\u{338e}', "mg"),
+    ('\u{338f}', "kg"),
+    ('\u{3390}', "Hz"),
+    ('\u{3391}', "kHz"),
+    ('\u{3392}', "MHz"),
+    ('\u{3393}', "GHz"),
+    ('\u{3394}', "THz"),
+    ('\u{3395}', "ul"),
+    ('\u{3396}', "ml"),
+    ('\u{3397}', "dl"),
+    ('\u{3398}', "kl"),
+    ('\u{3399}', "fm"),
+    ('\u{339a}', "nm"),
+    ('\u{339b}', "um"),
+    ('\u{339c}', "mm"),
+    ('\u{339d}', "cm"),
+    ('\u{339e}', "km"),
+    ('\u{339f}', "mm^2"),
+    ('\u{33a0}', "cm^2"),
+    ('\u{33a1}', "m^2"),
+    ('\u{33a2}', "km^2"),
+    ('\u{33a3}', "mm^3"),
+    ('\u{33a4}', "cm^3"),
+    ('\u{33a5}', "m^3"),
+    ('\u{33a6}', "km^3"),
+    ('\u{33a7}', "m/s"),
+    ('\u{33a8}', "m/s^2"),
+    ('\u{33a9}', "Pa"),
+    ('\u{33aa}', "kPa"),
+    ('\u{33ab}', "MPa"),
+    ('\u{33ac}', "GPa"),
+    ('\u{33ad}', "rad"),
+    ('\u{33ae}', "rad/s"),
+    ('\u{33af}', "rad/s^2"),
+    ('\u{33b0}', "ps"),
+    ('\u{33b1}', "ns"),
+    ('\u{33b2}', "us"),
+    ('\u{33b3}', "ms"),
+    ('\u{33b4}', "pV"),
+    ('\u{33b5}', "nV"),
+    ('\u{33b6}', "uV"),
+    ('\u{33b7}', "mV"),
+    ('\u{33b8}', "kV"),
+    ('\u{33b9}', "MV"),
+    ('\u{33ba}', "pW"),
+    ('\u{33bb}', "nW"),
+    ('\u{33bc}', "uW"),
+    ('\u{33bd}', "mW"),
+    ('\u{33be}', "kW"),
+    ('\u{33bf}', "MW"),
+    ('\u{33c2}', "a.m."),
+    ('\u{33c3}', "Bq"),
+    ('\u{33c4}', "cc"),
+    ('\u{33c5}', "cd"),
+    ('\u{33c6}', "C/kg"),
+    ('\u{33c7}', "Co."),
+    ('\u{33c8}', "dB"),
+    ('\u{33c9}', "Gy"),
+    ('\u{33ca}', "ha"),
+    ('\u{33cb}', "HP"),
+    ('\u{33cc}', "in"),
+    ('\u{33cd}', "KK"),
+    ('\u{33ce}', "KM"),
+    ('\u{33cf}', "kt"),
+    ('\u{33d0}', "lm"),
+    ('\u{33d1}', "ln"),
+    ('\u{33d2}', "log"),
+    ('\u{33d3}', "lx"),
+    ('\u{33d4}', "mb"),
+    ('\u{33d5}', "mil"),
+    ('\u{33d6}', "mol"),
+    ('\u{33d7}', "PH"),
+    ('\u{33d8}', "p.m."),
+    ('\u{33d9}', "PPM"),
+    ('\u{33da}', "PR"),
+    ('\u{33db}', "sr"),
+    ('\u{33dc}', "Sv"),
+    ('\u{33dd}', "Wb"),
+    ('\u{fb00}', "ff"),
+    ('\u{fb01}', "fi"),
+    ('\u{fb02}', "fl"),
+    ('\u{fb03}', "ffi"),
+    ('\u{fb04}', "ffl"),
+    ('\u{fb06}', "st"),
+    ('\u{fb29}', "+"),
+    ('\u{fe00}', ""),
+    ('\u{fe01}', ""),
+    ('\u{fe02}', ""),
+    ('\u{fe03}', ""),
+    ('\u{fe04}', ""),
+    ('\u{fe05}', ""),
+    ('\u{fe06}', ""),
+    ('\u{fe07}', ""),
+    ('\u{fe08}', ""),
+    ('\u{fe09}', ""),
+    ('\u{fe0a}', ""),
+    ('\u{fe0b}', ""),
+    ('\u{fe0c}', ""),
+    ('\u{fe0d}', ""),
+    ('\u{fe0e}', ""),
+    ('\u{fe0f}', ""),
+    ('\u{fe4d}', "_"),
+    ('\u{fe4e}', "_"),
+    ('\u{fe4f}', "_"),
+    ('\u{fe50}', ","),
+    ('\u{fe52}', "."),
+    ('\u{fe54}', ";"),
+    ('\u{fe55}', ":"),
+    ('\u{fe57}', "!"),
+    ('\u{fe59}', "("),
+    ('\u{fe5a}', ")"),
+    ('\u{fe5b}', "{"),
+    ('\u{fe5c}', "}"),
+    ('\u{fe5f}', "#"),
+    ('\u{fe60}', "&"),
+    ('\u{fe61}', "*"),
+    ('\u{fe62}', "+"),
+    ('\u{fe63}', "-"),
+    ('\u{fe64}', "<"),
+    ('\u{fe65}', ">"),
+    ('\u{fe66}', "="),
+    ('\u{fe68}', "\\"),
+    ('\u{fe69}', "$"),
+    ('\u{fe6a}', "%"),
+    ('\u{fe6b}', "@"),
+    ('\u{feff}', ""),
+];
+
+/// The printable ASCII characters, which the fullwidth forms U+FF01 through
+/// U+FF5E map onto.
+const PRINTABLE: &str = "!\"#$%&'()*+,-./0123456789:;<=>?@\
+                         ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`\
+                         abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Return the transliteration of `c`, if there is one.
+pub(super) fn lookup(c: char) -> Option<&'static str> {
+    if ('\u{ff01}'..='\u{ff5e}').contains(&c) {
+        let i = c as usize - 0xff01;
+        return Some(&PRINTABLE[i..i + 1]);
+    }
+    TABLE
+        .binary_search_by_key(&c, |(c, _)| *c)
+        .ok()
+        .map(|i| TABLE[i].1)
+}
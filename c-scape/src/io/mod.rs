@@ -7,6 +7,8 @@ mod pipe;
 mod poll;
 mod read;
 mod select;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod signalfd;
 mod splice;
 #[cfg(any(target_os = "android", target_os = "linux"))]
 mod timerfd;
@@ -23,3 +23,62 @@ unsafe extern "C" fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: c
         None => -1,
     }
 }
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn ppoll(
+    fds: *mut libc::pollfd,
+    nfds: libc::nfds_t,
+    timeout: *const libc::timespec,
+    sigmask: *const libc::sigset_t,
+) -> c_int {
+    //libc!(libc::ppoll(fds, nfds, timeout, sigmask));
+
+    // Linux writes the unslept time back into the timeout, which POSIX
+    // doesn't allow, so give it a copy.
+    let mut timeout_copy;
+    let timeout = if timeout.is_null() {
+        core::ptr::null_mut()
+    } else {
+        timeout_copy = timeout.read();
+        core::ptr::addr_of_mut!(timeout_copy)
+    };
+
+    // Let the kernel install `sigmask` for the duration of the wait, so that
+    // there's no window in which a signal could be missed.
+    match convert_res(crate::raw_syscall::syscall5(
+        libc::SYS_ppoll,
+        fds.expose_provenance(),
+        nfds as usize,
+        timeout.expose_provenance(),
+        sigmask.expose_provenance(),
+        core::mem::size_of::<rustix::runtime::KernelSigSet>(),
+    )) {
+        Some(num) => num as c_int,
+        None => -1,
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+extern "C" {
+    #[cold]
+    fn __chk_fail() -> !;
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn __ppoll_chk(
+    fds: *mut libc::pollfd,
+    nfds: libc::nfds_t,
+    timeout: *const libc::timespec,
+    sigmask: *const libc::sigset_t,
+    fdslen: libc::size_t,
+) -> c_int {
+    //libc!(libc::__ppoll_chk(fds, nfds, timeout, sigmask, fdslen));
+
+    if fdslen / core::mem::size_of::<libc::pollfd>() < nfds as usize {
+        __chk_fail();
+    }
+
+    ppoll(fds, nfds, timeout, sigmask)
+}
@@ -81,3 +81,55 @@ unsafe fn set_contains(fds: *mut libc::fd_set, fd: i32) -> bool {
 
     libc::FD_ISSET(fd, fds)
 }
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn pselect(
+    nfds: c_int,
+    readfds: *mut libc::fd_set,
+    writefds: *mut libc::fd_set,
+    exceptfds: *mut libc::fd_set,
+    timeout: *const libc::timespec,
+    sigmask: *const libc::sigset_t,
+) -> c_int {
+    libc!(libc::pselect(
+        nfds, readfds, writefds, exceptfds, timeout, sigmask
+    ));
+
+    if nfds < 0 || nfds > libc::FD_SETSIZE as c_int {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    };
+
+    // Linux writes the unslept time back into the timeout, which POSIX
+    // doesn't allow, so give it a copy.
+    let mut timeout_copy;
+    let timeout = if timeout.is_null() {
+        core::ptr::null_mut()
+    } else {
+        timeout_copy = timeout.read();
+        core::ptr::addr_of_mut!(timeout_copy)
+    };
+
+    // `pselect6` takes the signal mask and its size packed together, since
+    // it's out of argument registers. The kernel installs the mask for the
+    // duration of the wait, so that there's no window in which a signal could
+    // be missed.
+    let sigmask = [
+        sigmask.expose_provenance(),
+        core::mem::size_of::<rustix::runtime::KernelSigSet>(),
+    ];
+
+    match convert_res(crate::raw_syscall::syscall6(
+        libc::SYS_pselect6,
+        nfds as usize,
+        readfds.expose_provenance(),
+        writefds.expose_provenance(),
+        exceptfds.expose_provenance(),
+        timeout.expose_provenance(),
+        sigmask.as_ptr().expose_provenance(),
+    )) {
+        Some(num) => num as c_int,
+        None => -1,
+    }
+}
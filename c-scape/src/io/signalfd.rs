@@ -0,0 +1,28 @@
+use crate::convert_res;
+use crate::raw_syscall::syscall4;
+use core::mem::size_of;
+use libc::c_int;
+use rustix::runtime::KernelSigSet;
+
+// Reads from a signalfd produce `signalfd_siginfo` records directly from the
+// kernel, so the libc crate's definition needs to match the kernel's.
+const _: () = assert!(size_of::<libc::signalfd_siginfo>() == 128);
+
+#[no_mangle]
+unsafe extern "C" fn signalfd(fd: c_int, mask: *const libc::sigset_t, flags: c_int) -> c_int {
+    libc!(libc::signalfd(fd, mask, flags));
+
+    // The kernel only reads the part of `mask` that it knows about, which is
+    // a `KernelSigSet`. Passing an `fd` of -1 creates a new signalfd;
+    // otherwise, this replaces the mask of an existing one.
+    match convert_res(syscall4(
+        libc::SYS_signalfd4,
+        fd as usize,
+        mask.expose_provenance(),
+        size_of::<KernelSigSet>(),
+        flags as usize,
+    )) {
+        Some(fd) => fd as c_int,
+        None => -1,
+    }
+}
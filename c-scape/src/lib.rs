@@ -6,6 +6,7 @@
 #![feature(c_variadic)] // for `printf`, `ioctl`, etc.
 #![feature(sync_unsafe_cell)] // for lots of libc static variables
 #![feature(linkage)] // for `malloc` etc.
+#![feature(return_address)] // for `dlsym` callers and `malloc` allocation sites
 // Disable some common warnings.
 #![allow(unexpected_cfgs)]
 // Don't warn if `try_into()` is fallible on some targets.
@@ -48,19 +49,28 @@ mod sync_ptr;
 use core::ptr::addr_of;
 use errno::{set_errno, Errno};
 
+#[cfg(not(target_os = "wasi"))]
+#[cfg(feature = "thread")]
+mod aio;
 mod arpa_inet;
 mod atoi;
 mod base64;
 mod brk;
+mod crypt;
 mod ctype;
+#[cfg(not(target_os = "wasi"))]
+mod dl;
 mod env;
 mod errno_;
 mod error;
 mod exec;
 #[cfg(feature = "take-charge")]
 mod exit;
+mod fnmatch;
 mod fs;
+mod getopt;
 mod glibc_versioning;
+mod iconv;
 mod int;
 mod io;
 mod jmp;
@@ -72,6 +82,7 @@ mod mem;
 mod mkostemps;
 #[cfg(not(target_os = "wasi"))]
 mod mm;
+mod mntent_;
 mod net;
 mod nss;
 mod path;
@@ -84,7 +95,10 @@ mod pty;
 mod rand;
 mod rand48;
 mod rand_;
+#[cfg(not(target_os = "wasi"))]
+mod raw_syscall;
 mod regex;
+mod search;
 mod shm;
 #[cfg(not(target_os = "wasi"))]
 #[cfg(feature = "take-charge")]
@@ -0,0 +1,335 @@
+//! A checking layer over the heap, enabled by the "malloc-debug" feature and
+//! the `MALLOC_CHECK_` environment variable.
+//!
+//! Each block is laid out like this:
+//!
+//! ```text
+//! [padding][Header][front redzone][user memory][back redzone]
+//! ```
+//!
+//! The redzones are filled with canary bytes, which are checked when the
+//! block is freed or resized. Freed blocks are filled with a poison pattern
+//! and kept in a quarantine for a while before being returned to the heap,
+//! so that double frees can be recognized, and writes after the free can be
+//! detected when the block leaves the quarantine.
+//!
+//! `MALLOC_CHECK_=1` reports problems on stderr and carries on, and any other
+//! non-zero value reports them and aborts.
+
+use super::{heap, Site};
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::ptr::{null, null_mut, write_bytes};
+use core::sync::atomic::{AtomicU8, Ordering};
+use libc::{c_char, c_int};
+use rustix_futex_sync::Mutex;
+
+const OFF: u8 = 0;
+const REPORT: u8 = 1;
+const ABORT: u8 = 2;
+
+static MODE: AtomicU8 = AtomicU8::new(OFF);
+
+/// Is the debug allocator in use?
+#[inline]
+pub(super) fn enabled() -> bool {
+    MODE.load(Ordering::Relaxed) != OFF
+}
+
+/// Read `MALLOC_CHECK_` from the environment. This runs before
+/// `env/get.rs`'s initialization, so we read `envp` directly. Blocks from the
+/// plain heap can't be freed by the debug allocator, so it's too late to
+/// switch if anything has been allocated already.
+#[link_section = ".init_array.00097"]
+#[used]
+static INIT_ARRAY: unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char) = {
+    unsafe extern "C" fn function(_argc: c_int, _argv: *mut *mut c_char, envp: *mut *mut c_char) {
+        let mut ptr = envp;
+        while !ptr.is_null() && !(*ptr).is_null() {
+            let var = core::ffi::CStr::from_ptr(*ptr).to_bytes();
+            if let Some(value) = var.strip_prefix(b"MALLOC_CHECK_=") {
+                let mode = match value {
+                    b"" | b"0" => OFF,
+                    b"1" => REPORT,
+                    _ => ABORT,
+                };
+                let stats = heap::stats();
+                if mode != OFF && (stats.in_use != 0 || stats.mapped_chunks != 0) {
+                    rustix::io::write(
+                        rustix::stdio::stderr(),
+                        b"malloc: ignoring MALLOC_CHECK_ because the heap is already in use\n",
+                    )
+                    .ok();
+                } else {
+                    MODE.store(mode, Ordering::Relaxed);
+                }
+            }
+            ptr = ptr.add(1);
+        }
+    }
+    function
+};
+
+/// The metadata at the start of each block.
+#[repr(C)]
+struct Header {
+    state: usize,
+    size: usize,
+    /// The distance from the start of the underlying allocation to the user
+    /// memory.
+    offset: usize,
+    alloc_site: Site,
+    free_site: Site,
+}
+
+// `Header::state` values.
+const ALLOCATED: usize = 0xa110_c8ed;
+const FREED: usize = 0xdead_f4ee;
+
+const REDZONE: usize = 16;
+const CANARY: u8 = 0xcb;
+/// Fresh allocations are filled with this, to make reads of uninitialized
+/// memory stand out.
+const JUNK: u8 = 0xaa;
+/// Freed memory is filled with this.
+const POISON: u8 = 0xdf;
+
+/// Blocks are released to the heap once the quarantine holds more than this
+/// many bytes, or this many blocks.
+const QUARANTINE_BYTES: usize = 4 * 1024 * 1024;
+const QUARANTINE_BLOCKS: usize = 1024;
+
+struct Quarantine {
+    blocks: [*mut u8; QUARANTINE_BLOCKS],
+    head: usize,
+    len: usize,
+    bytes: usize,
+}
+
+// SAFETY: The quarantined blocks aren't tied to any thread.
+unsafe impl Send for Quarantine {}
+
+static QUARANTINE: Mutex<Quarantine> = Mutex::new(Quarantine {
+    blocks: [null_mut(); QUARANTINE_BLOCKS],
+    head: 0,
+    len: 0,
+    bytes: 0,
+});
+
+unsafe fn header(ptr: *mut u8) -> *mut Header {
+    ptr.sub(REDZONE).cast::<Header>().sub(1)
+}
+
+pub(super) unsafe fn alloc(size: usize, align: usize, site: Site) -> *mut u8 {
+    let ptr = alloc_block(size, align, site);
+    if !ptr.is_null() {
+        write_bytes(ptr, JUNK, size);
+    }
+    ptr
+}
+
+pub(super) unsafe fn alloc_zeroed(size: usize, align: usize, site: Site) -> *mut u8 {
+    let ptr = alloc_block(size, align, site);
+    if !ptr.is_null() {
+        write_bytes(ptr, 0, size);
+    }
+    ptr
+}
+
+unsafe fn alloc_block(size: usize, align: usize, site: Site) -> *mut u8 {
+    let offset = (size_of::<Header>() + REDZONE).next_multiple_of(align);
+    let total = match offset
+        .checked_add(size)
+        .and_then(|total| total.checked_add(REDZONE))
+    {
+        Some(total) => total,
+        None => return null_mut(),
+    };
+    let base = heap::alloc(total, align);
+    if base.is_null() {
+        return base;
+    }
+
+    let ptr = base.add(offset);
+    header(ptr).write(Header {
+        state: ALLOCATED,
+        size,
+        offset,
+        alloc_site: site,
+        free_site: null(),
+    });
+    write_bytes(ptr.sub(REDZONE), CANARY, REDZONE);
+    write_bytes(ptr.add(size), CANARY, REDZONE);
+    ptr
+}
+
+pub(super) unsafe fn dealloc(ptr: *mut u8, site: Site) {
+    if check(ptr, site) {
+        retire(ptr, site);
+    }
+}
+
+/// Poison a checked block and put it in the quarantine.
+unsafe fn retire(ptr: *mut u8, site: Site) {
+    let header = &mut *header(ptr);
+    header.state = FREED;
+    header.free_site = site;
+    write_bytes(ptr, POISON, header.size);
+
+    // Blocks too big for the quarantine go straight back to the heap.
+    if header.size > QUARANTINE_BYTES / 4 {
+        heap::dealloc(ptr.sub(header.offset));
+        return;
+    }
+
+    let mut quarantine = QUARANTINE.lock();
+    if quarantine.len == QUARANTINE_BLOCKS {
+        quarantine.release_oldest();
+    }
+    let tail = (quarantine.head + quarantine.len) % QUARANTINE_BLOCKS;
+    quarantine.blocks[tail] = ptr;
+    quarantine.len += 1;
+    quarantine.bytes += header.size;
+    while quarantine.bytes > QUARANTINE_BYTES {
+        quarantine.release_oldest();
+    }
+}
+
+impl Quarantine {
+    /// Return the oldest block to the heap, checking that it hasn't been
+    /// written to since it was freed.
+    unsafe fn release_oldest(&mut self) {
+        let ptr = self.blocks[self.head];
+        self.head = (self.head + 1) % QUARANTINE_BLOCKS;
+        self.len -= 1;
+
+        let header = &*header(ptr);
+        self.bytes -= header.size;
+        let memory = core::slice::from_raw_parts(ptr, header.size);
+        if let Some(at) = memory.iter().position(|byte| *byte != POISON) {
+            fail("use after free", ptr.add(at), header, null());
+        }
+        heap::dealloc(ptr.sub(header.offset));
+    }
+}
+
+pub(super) unsafe fn realloc(ptr: *mut u8, size: usize, align: usize, site: Site) -> *mut u8 {
+    if !check(ptr, site) {
+        return null_mut();
+    }
+
+    // Always move the block, so that uses of the old pointer are caught.
+    let new = alloc_block(size, align, site);
+    if new.is_null() {
+        return new;
+    }
+    let old_size = (*header(ptr)).size;
+    core::ptr::copy_nonoverlapping(ptr, new, old_size.min(size));
+    if size > old_size {
+        write_bytes(new.add(old_size), JUNK, size - old_size);
+    }
+    retire(ptr, site);
+    new
+}
+
+pub(super) unsafe fn usable_size(ptr: *mut u8, site: Site) -> usize {
+    if !check(ptr, site) {
+        return 0;
+    }
+
+    // Report the exact size, so that writing up to the usable size doesn't
+    // look like an overflow.
+    (*header(ptr)).size
+}
+
+/// Check that `ptr` is a live block with intact redzones. If it isn't a live
+/// block, report it and return `false`.
+unsafe fn check(ptr: *mut u8, site: Site) -> bool {
+    if ptr.align_offset(size_of::<usize>()) != 0 {
+        fail_foreign(ptr, site);
+        return false;
+    }
+
+    let header = &*header(ptr);
+    match header.state {
+        ALLOCATED => {}
+        FREED => {
+            fail("double free", ptr, header, site);
+            return false;
+        }
+        _ => {
+            fail_foreign(ptr, site);
+            return false;
+        }
+    }
+
+    let front = core::slice::from_raw_parts(ptr.sub(REDZONE), REDZONE);
+    if let Some(at) = front.iter().position(|byte| *byte != CANARY) {
+        fail(
+            "heap buffer underflow",
+            ptr.sub(REDZONE).add(at),
+            header,
+            site,
+        );
+    }
+    let back = core::slice::from_raw_parts(ptr.add(header.size), REDZONE);
+    if let Some(at) = back.iter().position(|byte| *byte != CANARY) {
+        fail(
+            "heap buffer overflow",
+            ptr.add(header.size).add(at),
+            header,
+            site,
+        );
+    }
+    true
+}
+
+/// Report a problem with a block, and abort unless `MALLOC_CHECK_=1`.
+#[cold]
+unsafe fn fail(what: &str, at: *const u8, header: &Header, site: Site) {
+    let mut out = Stderr;
+    write!(
+        out,
+        "malloc: {} at {:p} in a block of {} bytes allocated at {:p}",
+        what, at, header.size, header.alloc_site
+    )
+    .ok();
+    if header.state == FREED {
+        write!(out, " and freed at {:p}", header.free_site).ok();
+    }
+    if !site.is_null() {
+        write!(out, ", detected at {:p}", site).ok();
+    }
+    out.write_str("\n").ok();
+    finish();
+}
+
+#[cold]
+unsafe fn fail_foreign(ptr: *const u8, site: Site) {
+    writeln!(
+        Stderr,
+        "malloc: {:p} was not allocated by malloc, detected at {:p}",
+        ptr, site
+    )
+    .ok();
+    finish();
+}
+
+unsafe fn finish() {
+    if MODE.load(Ordering::Relaxed) != REPORT {
+        libc::abort();
+    }
+}
+
+/// Writes diagnostics to stderr without allocating.
+struct Stderr;
+
+impl Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if unsafe { libc::write(libc::STDERR_FILENO, s.as_ptr().cast(), s.len()) } < 0 {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
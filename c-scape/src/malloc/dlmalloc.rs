@@ -0,0 +1,408 @@
+//! The `malloc-via-crates` heap, which uses `rustix_dlmalloc` directly.
+//!
+//! dlmalloc keeps the size of each chunk in a header word just before the
+//! memory it hands out, so unlike Rust's `GlobalAlloc` API, we don't need to
+//! be told the size of an allocation in order to free or resize it.
+//!
+//! `rustix_dlmalloc` always carves allocations out of its segments, so large
+//! allocations are mapped directly here, in the style of glibc's
+//! `M_MMAP_THRESHOLD`, which means they're returned to the OS as soon as
+//! they're freed.
+
+use super::{Stats, MAX_MMAP_THRESHOLD};
+use core::cell::Cell;
+use core::mem::size_of;
+use core::ptr::null_mut;
+use rustix::mm::{MapFlags, MprotectFlags, MremapFlags, ProtFlags};
+use rustix_dlmalloc::{Allocator, Dlmalloc};
+use rustix_futex_sync::Mutex;
+
+/// Our heap. This is separate from the `GlobalDlmalloc` heap, which is only
+/// reachable through the `GlobalAlloc` API.
+static HEAP: Mutex<Heap> = Mutex::new(Heap {
+    dl: Dlmalloc::new_with_allocator(Pages {
+        arena: Cell::new(0),
+        max_arena: Cell::new(0),
+        end: Cell::new(null_mut()),
+        reserve_end: Cell::new(null_mut()),
+        trimming: Cell::new(false),
+    }),
+    in_use: 0,
+    mapped: 0,
+    mapped_chunks: 0,
+    max_mapped: 0,
+    max_mapped_chunks: 0,
+    mmap_threshold: DEFAULT_MMAP_THRESHOLD,
+    mmap_max: DEFAULT_MMAP_MAX,
+    trim_threshold: DEFAULT_TRIM_THRESHOLD,
+    trim_floor: 0,
+});
+
+// These are glibc's defaults.
+const DEFAULT_MMAP_THRESHOLD: usize = 128 * 1024;
+const DEFAULT_MMAP_MAX: usize = 65536;
+const DEFAULT_TRIM_THRESHOLD: usize = 128 * 1024;
+
+struct Heap {
+    dl: Dlmalloc<Pages>,
+
+    /// The total size of the dlmalloc chunks currently allocated.
+    in_use: usize,
+
+    /// The total size, and number, of directly mapped allocations.
+    mapped: usize,
+    mapped_chunks: usize,
+    max_mapped: usize,
+    max_mapped_chunks: usize,
+
+    mmap_threshold: usize,
+    mmap_max: usize,
+    trim_threshold: usize,
+
+    /// The amount of free memory left after we last trimmed, so that we
+    /// don't retry on every `free` when fragmentation keeps us from
+    /// releasing it.
+    trim_floor: usize,
+}
+
+impl Heap {
+    fn free_bytes(&self) -> usize {
+        self.dl.allocator().arena.get() - self.in_use
+    }
+
+    /// Return as much memory to the OS as we can, keeping `pad` bytes free
+    /// at the top of the heap.
+    unsafe fn trim(&mut self, pad: usize) -> bool {
+        self.dl.allocator().trimming.set(true);
+        let trimmed = self.dl.trim(pad);
+        self.dl.allocator().trimming.set(false);
+        self.trim_floor = self.free_bytes();
+        trimmed
+    }
+
+    /// Return memory to the OS if enough of the heap is free.
+    unsafe fn maybe_trim(&mut self) {
+        if self.free_bytes() > self.trim_floor.saturating_add(self.trim_threshold) {
+            self.trim(0);
+        }
+    }
+
+    fn note_alloc(&mut self, ptr: *mut u8) {
+        if !ptr.is_null() {
+            self.in_use += unsafe { chunk_size(ptr) };
+            self.trim_floor = self.trim_floor.min(self.free_bytes());
+        }
+    }
+
+    fn note_map(&mut self, len: usize) {
+        self.mapped += len;
+        self.mapped_chunks += 1;
+        self.max_mapped = self.max_mapped.max(self.mapped);
+        self.max_mapped_chunks = self.max_mapped_chunks.max(self.mapped_chunks);
+    }
+
+    fn should_map(&self, size: usize, align: usize) -> bool {
+        size >= self.mmap_threshold
+            && align <= rustix::param::page_size()
+            && self.mapped_chunks < self.mmap_max
+    }
+}
+
+/// The `Allocator` dlmalloc gets its segments from, which keeps count of how
+/// much memory they add up to.
+///
+/// dlmalloc can only return memory to the OS from the top of a segment, or
+/// by unmapping a whole segment, and `mmap` tends to place new mappings
+/// below old ones, which would leave memory freed in newer segments stuck
+/// below chunks still in use in older ones. So, like glibc's heaps, we
+/// reserve a range of address space and commit memory upwards within it,
+/// letting dlmalloc grow its segment as if it were using `sbrk`.
+struct Pages {
+    arena: Cell<usize>,
+    max_arena: Cell<usize>,
+    /// The end of the committed memory in the current reservation, and the
+    /// end of the reservation.
+    end: Cell<*mut u8>,
+    reserve_end: Cell<*mut u8>,
+    /// dlmalloc decides on its own when to return memory to the OS, so we
+    /// refuse unless it's doing so because we asked it to trim, so that
+    /// `M_TRIM_THRESHOLD` has the final say.
+    trimming: Cell<bool>,
+}
+
+/// How much address space to reserve at a time, which is glibc's
+/// `HEAP_MAX_SIZE`.
+const RESERVE: usize = 2 * MAX_MMAP_THRESHOLD;
+
+// SAFETY: `end` and `reserve_end` just record addresses, which aren't tied to
+// any thread.
+unsafe impl Send for Pages {}
+
+impl Pages {
+    fn grow(&self, by: usize) {
+        self.arena.set(self.arena.get() + by);
+        self.max_arena
+            .set(self.max_arena.get().max(self.arena.get()));
+    }
+
+    /// Return the memory at `ptr..ptr + len` to the OS. If it's at the end
+    /// of the committed memory, put it back in the reservation.
+    unsafe fn release(&self, ptr: *mut u8, len: usize) -> bool {
+        if !self.trimming.get() {
+            return false;
+        }
+        let released = if ptr.wrapping_add(len) == self.end.get() {
+            // Mapping over the memory discards its contents.
+            let released = rustix::mm::mmap_anonymous(
+                ptr.cast(),
+                len,
+                ProtFlags::empty(),
+                MapFlags::PRIVATE | MapFlags::FIXED | MapFlags::NORESERVE,
+            )
+            .is_ok();
+            if released {
+                self.end.set(ptr);
+            }
+            released
+        } else {
+            rustix::mm::munmap(ptr.cast(), len).is_ok()
+        };
+        if released {
+            self.arena.set(self.arena.get() - len);
+        }
+        released
+    }
+}
+
+unsafe impl Allocator for Pages {
+    fn alloc(&self, size: usize) -> (*mut u8, usize, u32) {
+        unsafe {
+            let end = self.end.get();
+            if end.is_null() || (self.reserve_end.get() as usize - end as usize) < size {
+                // Start a new reservation. Any space left in the old one stays
+                // reserved, and is released along with the memory before it.
+                let len = match size.checked_next_multiple_of(RESERVE) {
+                    Some(len) => len,
+                    None => return (null_mut(), 0, 0),
+                };
+                let base = match rustix::mm::mmap_anonymous(
+                    null_mut(),
+                    len,
+                    ProtFlags::empty(),
+                    MapFlags::PRIVATE | MapFlags::NORESERVE,
+                ) {
+                    Ok(base) => base.cast::<u8>(),
+                    Err(_) => return (null_mut(), 0, 0),
+                };
+                self.end.set(base);
+                self.reserve_end.set(base.add(len));
+            }
+
+            let ptr = self.end.get();
+            if rustix::mm::mprotect(ptr.cast(), size, MprotectFlags::READ | MprotectFlags::WRITE)
+                .is_err()
+            {
+                return (null_mut(), 0, 0);
+            }
+            self.end.set(ptr.add(size));
+            self.grow(size);
+            (ptr, size, 0)
+        }
+    }
+
+    fn remap(&self, _ptr: *mut u8, _oldsize: usize, _newsize: usize, _can_move: bool) -> *mut u8 {
+        // dlmalloc only remaps chunks it mapped directly, which
+        // `rustix_dlmalloc` never does.
+        null_mut()
+    }
+
+    fn free_part(&self, ptr: *mut u8, oldsize: usize, newsize: usize) -> bool {
+        unsafe { self.release(ptr.add(newsize), oldsize - newsize) }
+    }
+
+    fn free(&self, ptr: *mut u8, size: usize) -> bool {
+        unsafe { self.release(ptr, size) }
+    }
+
+    fn can_release_part(&self, _flags: u32) -> bool {
+        true
+    }
+
+    fn allocates_zeros(&self) -> bool {
+        true
+    }
+
+    fn page_size(&self) -> usize {
+        rustix::param::page_size()
+    }
+}
+
+/// The alignment dlmalloc gives every chunk.
+const CHUNK_ALIGN: usize = 2 * size_of::<usize>();
+
+// These mirror dlmalloc's chunk header flags. dlmalloc always sets `CINUSE`
+// in the chunks it hands out and never uses `FLAG4`, so we use `FLAG4` to
+// mark our directly mapped allocations.
+const PINUSE: usize = 1 << 0;
+const CINUSE: usize = 1 << 1;
+const FLAG4: usize = 1 << 2;
+const FLAG_BITS: usize = PINUSE | CINUSE | FLAG4;
+
+/// The size of the dlmalloc chunk holding `ptr`, including its header.
+unsafe fn chunk_size(ptr: *mut u8) -> usize {
+    ptr.cast::<usize>().sub(1).read() & !FLAG_BITS
+}
+
+/// If `ptr` was mapped directly, return the offset of `ptr` from the start
+/// of its mapping, and the mapping's length.
+///
+/// A directly mapped allocation is preceded by the offset, and then by the
+/// length with `FLAG4` set, in place of dlmalloc's chunk header.
+unsafe fn mapping(ptr: *mut u8) -> Option<(usize, usize)> {
+    let head = ptr.cast::<usize>().sub(1).read();
+    if head & FLAG4 == 0 {
+        return None;
+    }
+    let offset = ptr.cast::<usize>().sub(2).read();
+    Some((offset, head & !FLAG_BITS))
+}
+
+/// Map an allocation of `size` bytes aligned to `align`, which must be at
+/// most the page size, returning the pointer and the length of the mapping.
+unsafe fn map(size: usize, align: usize) -> Option<(*mut u8, usize)> {
+    let offset = align.max(2 * size_of::<usize>());
+    let len = offset
+        .checked_add(size)?
+        .checked_next_multiple_of(rustix::param::page_size())?;
+    let base = rustix::mm::mmap_anonymous(
+        null_mut(),
+        len,
+        ProtFlags::READ | ProtFlags::WRITE,
+        MapFlags::PRIVATE,
+    )
+    .ok()?
+    .cast::<u8>();
+
+    let ptr = base.add(offset);
+    ptr.cast::<usize>().sub(2).write(offset);
+    ptr.cast::<usize>().sub(1).write(len | FLAG4);
+    Some((ptr, len))
+}
+
+pub(super) unsafe fn alloc(size: usize, align: usize) -> *mut u8 {
+    let mut heap = HEAP.lock();
+    if heap.should_map(size, align) {
+        // If the mapping fails, fall back to the heap, like glibc does.
+        if let Some((ptr, len)) = map(size, align) {
+            heap.note_map(len);
+            return ptr;
+        }
+    }
+    let ptr = heap.dl.malloc(size, align);
+    heap.note_alloc(ptr);
+    ptr
+}
+
+pub(super) unsafe fn alloc_zeroed(size: usize, align: usize) -> *mut u8 {
+    let mut heap = HEAP.lock();
+    if heap.should_map(size, align) {
+        // Fresh mappings are already zeroed.
+        if let Some((ptr, len)) = map(size, align) {
+            heap.note_map(len);
+            return ptr;
+        }
+    }
+    // This skips the zeroing when the memory is fresh from `mmap`.
+    let ptr = heap.dl.calloc(size, align);
+    heap.note_alloc(ptr);
+    ptr
+}
+
+pub(super) unsafe fn dealloc(ptr: *mut u8) {
+    let mut heap = HEAP.lock();
+    if let Some((offset, len)) = mapping(ptr) {
+        rustix::mm::munmap(ptr.sub(offset).cast(), len).ok();
+        heap.mapped -= len;
+        heap.mapped_chunks -= 1;
+        return;
+    }
+    heap.in_use -= chunk_size(ptr);
+    // dlmalloc doesn't use the alignment when freeing.
+    heap.dl.free(ptr, usable_size(ptr), CHUNK_ALIGN);
+    heap.maybe_trim();
+}
+
+pub(super) unsafe fn realloc(ptr: *mut u8, size: usize, align: usize) -> *mut u8 {
+    let mut heap = HEAP.lock();
+    if let Some((offset, len)) = mapping(ptr) {
+        let new_len = match offset
+            .checked_add(size)
+            .and_then(|len| len.checked_next_multiple_of(rustix::param::page_size()))
+        {
+            Some(new_len) => new_len,
+            None => return null_mut(),
+        };
+        let base =
+            match rustix::mm::mremap(ptr.sub(offset).cast(), len, new_len, MremapFlags::MAYMOVE) {
+                Ok(base) => base.cast::<u8>(),
+                Err(_) => return null_mut(),
+            };
+        heap.mapped = heap.mapped - len + new_len;
+        heap.max_mapped = heap.max_mapped.max(heap.mapped);
+        let ptr = base.add(offset);
+        ptr.cast::<usize>().sub(1).write(new_len | FLAG4);
+        return ptr;
+    }
+
+    let old_chunk_size = chunk_size(ptr);
+    // `realloc` only needs to preserve the fundamental alignment, so report
+    // that as the old alignment. When that's no more than dlmalloc's own
+    // alignment, dlmalloc resizes in place when it can.
+    let new = heap.dl.realloc(ptr, usable_size(ptr), align, size);
+    if !new.is_null() {
+        heap.in_use -= old_chunk_size;
+        heap.note_alloc(new);
+    }
+    new
+}
+
+pub(super) unsafe fn usable_size(ptr: *mut u8) -> usize {
+    match mapping(ptr) {
+        Some((offset, len)) => len - offset,
+        None => chunk_size(ptr) - size_of::<usize>(),
+    }
+}
+
+pub(super) unsafe fn trim(pad: usize) -> bool {
+    HEAP.lock().trim(pad)
+}
+
+pub(super) fn stats() -> Stats {
+    let heap = HEAP.lock();
+    Stats {
+        arena: heap.dl.allocator().arena.get(),
+        max_arena: heap.dl.allocator().max_arena.get(),
+        in_use: heap.in_use,
+        mapped: heap.mapped,
+        mapped_chunks: heap.mapped_chunks,
+        max_mapped: heap.max_mapped,
+        max_mapped_chunks: heap.max_mapped_chunks,
+    }
+}
+
+pub(super) fn set_mmap_threshold(threshold: usize) -> bool {
+    HEAP.lock().mmap_threshold = threshold;
+    true
+}
+
+pub(super) fn set_mmap_max(max: usize) -> bool {
+    HEAP.lock().mmap_max = max;
+    true
+}
+
+pub(super) fn set_trim_threshold(threshold: usize) -> bool {
+    let mut heap = HEAP.lock();
+    heap.trim_threshold = threshold;
+    heap.trim_floor = 0;
+    true
+}
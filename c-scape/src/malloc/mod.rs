@@ -1,12 +1,9 @@
 //! `malloc`/`free`/etc. functions.
-//!
-//! TODO: Use `alloc_zeroed` and `realloc` instead of doing the work
-//! ourselves, which might let allocators be more efficient.
 
+use core::fmt::Write;
 #[cfg(not(target_arch = "riscv64"))]
 use core::mem::align_of;
-use core::mem::size_of;
-use core::ptr::{copy_nonoverlapping, null_mut, write_bytes};
+use core::ptr::null_mut;
 use errno::{set_errno, Errno};
 use libc::{c_int, c_void, size_t};
 
@@ -18,141 +15,131 @@ use libc::{c_int, c_void, size_t};
 )))]
 compile_error!("One of the malloc implementation features must be enabled.");
 
-#[cfg(feature = "malloc-via-rust-global-alloc")]
-use alloc::alloc::{alloc as the_alloc, dealloc as the_dealloc};
-
 #[cfg(feature = "malloc-via-crates")]
-unsafe fn the_alloc(layout: alloc::alloc::Layout) -> *mut u8 {
-    core::alloc::GlobalAlloc::alloc(&rustix_dlmalloc::GlobalDlmalloc, layout)
-}
+mod dlmalloc;
 #[cfg(feature = "malloc-via-crates")]
-unsafe fn the_dealloc(ptr: *mut u8, layout: alloc::alloc::Layout) {
-    core::alloc::GlobalAlloc::dealloc(&rustix_dlmalloc::GlobalDlmalloc, ptr, layout)
-}
+use dlmalloc as heap;
 
-/// Rust's `alloc` API requires the user to pass in the old size and alignment
-/// for resizing and deallocation, while C's `malloc` API doesn't, so we store
-/// the size and alignment next to the allocation memory.
-#[repr(C)]
-#[derive(Clone, Copy, Debug)]
-struct Tag {
-    size: usize,
-    align: usize,
+#[cfg(feature = "malloc-via-rust-global-alloc")]
+mod tagged;
+#[cfg(feature = "malloc-via-rust-global-alloc")]
+use tagged as heap;
+
+#[cfg(feature = "malloc-debug")]
+mod debug;
+
+/// The code that called into the allocator, which the debug allocator
+/// reports as the allocation site.
+type Site = *const ();
+
+macro_rules! site {
+    () => {{
+        #[cfg(feature = "malloc-debug")]
+        let site = core::arch::return_address!();
+        #[cfg(not(feature = "malloc-debug"))]
+        let site: Site = core::ptr::null();
+        site
+    }};
 }
 
-/// Allocate for a given layout, with a tag prepended to the allocation, to
-/// keep track of said layout.
-///
-/// Return null if the allocation failed.
-fn tagged_alloc(type_layout: alloc::alloc::Layout) -> *mut u8 {
-    if type_layout.size() == 0 {
-        return null_mut();
+// These go to the debug allocator when it's enabled, and to the heap
+// otherwise.
+
+#[cfg_attr(not(feature = "malloc-debug"), allow(unused_variables))]
+unsafe fn alloc_at(size: usize, align: usize, site: Site) -> *mut u8 {
+    #[cfg(feature = "malloc-debug")]
+    if debug::enabled() {
+        return debug::alloc(size, align, site);
     }
+    heap::alloc(size, align)
+}
 
-    let tag_layout = alloc::alloc::Layout::new::<Tag>();
-    let tag = Tag {
-        size: type_layout.size(),
-        align: type_layout.align(),
-    };
-    if let Ok((total_layout, offset)) = tag_layout.extend(type_layout) {
-        let total_ptr = unsafe { the_alloc(total_layout) };
-        if total_ptr.is_null() {
-            return total_ptr;
-        }
+#[cfg_attr(not(feature = "malloc-debug"), allow(unused_variables))]
+unsafe fn alloc_zeroed_at(size: usize, align: usize, site: Site) -> *mut u8 {
+    #[cfg(feature = "malloc-debug")]
+    if debug::enabled() {
+        return debug::alloc_zeroed(size, align, site);
+    }
+    heap::alloc_zeroed(size, align)
+}
 
-        let tag_offset = offset - tag_layout.size();
-        unsafe {
-            total_ptr.wrapping_add(tag_offset).cast::<Tag>().write(tag);
-            total_ptr.wrapping_add(offset).cast()
-        }
-    } else {
-        null_mut()
+#[cfg_attr(not(feature = "malloc-debug"), allow(unused_variables))]
+unsafe fn dealloc_at(ptr: *mut u8, site: Site) {
+    #[cfg(feature = "malloc-debug")]
+    if debug::enabled() {
+        return debug::dealloc(ptr, site);
     }
+    heap::dealloc(ptr)
 }
 
-/// Get the layout out of a tagged allocation.
-///
-/// # Safety
-///
-/// The given pointer must be a non-null pointer that was returned from
-/// `tagged_alloc`.
-unsafe fn get_layout(ptr: *mut u8) -> alloc::alloc::Layout {
-    let tag = ptr.wrapping_sub(size_of::<Tag>()).cast::<Tag>().read();
-    alloc::alloc::Layout::from_size_align_unchecked(tag.size, tag.align)
+#[cfg_attr(not(feature = "malloc-debug"), allow(unused_variables))]
+unsafe fn realloc_at(ptr: *mut u8, size: usize, align: usize, site: Site) -> *mut u8 {
+    #[cfg(feature = "malloc-debug")]
+    if debug::enabled() {
+        return debug::realloc(ptr, size, align, site);
+    }
+    heap::realloc(ptr, size, align)
 }
 
-/// get the layout out of a tagged allocation
-///
-/// # Safety
-///
-/// The given pointer must be a non-null pointer that was returned from
-/// `tagged_alloc`.
-unsafe fn tagged_dealloc(ptr: *mut u8) {
-    let tag_layout = alloc::alloc::Layout::new::<Tag>();
-    let type_layout = get_layout(ptr);
-    if let Ok((total_layout, offset)) = tag_layout.extend(type_layout) {
-        let total_ptr = ptr.wrapping_sub(offset);
-        the_dealloc(total_ptr, total_layout);
+#[cfg_attr(not(feature = "malloc-debug"), allow(unused_variables))]
+unsafe fn usable_size_at(ptr: *mut u8, site: Site) -> usize {
+    #[cfg(feature = "malloc-debug")]
+    if debug::enabled() {
+        return debug::usable_size(ptr, site);
     }
+    heap::usable_size(ptr)
 }
 
-#[linkage = "weak"]
-#[no_mangle]
-unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
-    libc!(libc::malloc(size));
+/// The alignment `malloc` guarantees, which is enough for any fundamental
+/// type.
+// TODO: Add `max_align_t` for riscv64 to upstream libc.
+#[cfg(target_arch = "riscv64")]
+const MALLOC_ALIGN: usize = 16;
+#[cfg(not(target_arch = "riscv64"))]
+const MALLOC_ALIGN: usize = align_of::<libc::max_align_t>();
 
+/// Allocate `size` bytes aligned to `align`, which must be a power of two,
+/// setting `errno` on failure.
+unsafe fn alloc_or_enomem(size: usize, align: usize, site: Site) -> *mut c_void {
     // If we're asked to allocate zero bytes, actually allocate 1 byte, so
     // that we can return a non-NULL pointer. Technically the `malloc`
     // spec says we can return NULL in this case, but popular code in the
     // wild interprets NULL as an allocation failure.
-    let size = if size == 0 { size + 1 } else { size };
-
-    // TODO: Add `max_align_t` for riscv64 to upstream libc.
-    #[cfg(target_arch = "riscv64")]
-    let layout = alloc::alloc::Layout::from_size_align(size, 16);
-    #[cfg(not(target_arch = "riscv64"))]
-    let layout = alloc::alloc::Layout::from_size_align(size, align_of::<libc::max_align_t>());
-
-    let layout = match layout {
-        Ok(layout) => layout,
-        Err(_) => {
-            set_errno(Errno(libc::ENOMEM));
-            return null_mut();
-        }
-    };
-
-    let ret = tagged_alloc(layout);
+    let ret = alloc_at(size.max(1), align, site);
     if ret.is_null() {
         set_errno(Errno(libc::ENOMEM));
     }
     ret.cast()
 }
 
+#[linkage = "weak"]
+#[no_mangle]
+unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    libc!(libc::malloc(size));
+
+    alloc_or_enomem(size, MALLOC_ALIGN, site!())
+}
+
 #[linkage = "weak"]
 #[no_mangle]
 unsafe extern "C" fn realloc(old: *mut c_void, size: usize) -> *mut c_void {
     libc!(libc::realloc(old, size));
 
-    if old.is_null() {
-        malloc(size)
-    } else {
-        let old_layout = get_layout(old.cast());
-        if old_layout.size() >= size {
-            return old;
-        }
+    realloc_or_enomem(old, size, site!())
+}
 
-        let new = malloc(size);
+/// Resize `old`, or allocate if it's null, setting `errno` on failure.
+unsafe fn realloc_or_enomem(old: *mut c_void, size: usize, site: Site) -> *mut c_void {
+    if old.is_null() {
+        return alloc_or_enomem(size, MALLOC_ALIGN, site);
+    }
 
-        if !new.is_null() {
-            copy_nonoverlapping(
-                old.cast::<u8>(),
-                new.cast::<u8>(),
-                core::cmp::min(size, old_layout.size()),
-            );
-        }
-        tagged_dealloc(old.cast());
-        new
+    // On failure, `old` is left untouched.
+    let new = realloc_at(old.cast(), size.max(1), MALLOC_ALIGN, site);
+    if new.is_null() {
+        set_errno(Errno(libc::ENOMEM));
     }
+    new.cast()
 }
 
 #[no_mangle]
@@ -167,7 +154,7 @@ unsafe extern "C" fn reallocarray(old: *mut c_void, nmemb: size_t, size: size_t)
         }
     };
 
-    realloc(old, product)
+    realloc_or_enomem(old, product, site!())
 }
 
 #[linkage = "weak"]
@@ -183,9 +170,11 @@ unsafe extern "C" fn calloc(nmemb: usize, size: usize) -> *mut c_void {
         }
     };
 
-    let ptr = malloc(product);
-    write_bytes(ptr, 0, product);
-    ptr
+    let ptr = alloc_zeroed_at(product.max(1), MALLOC_ALIGN, site!());
+    if ptr.is_null() {
+        set_errno(Errno(libc::ENOMEM));
+    }
+    ptr.cast()
 }
 
 #[no_mangle]
@@ -200,13 +189,7 @@ unsafe extern "C" fn posix_memalign(
         return libc::EINVAL;
     }
 
-    let layout = alloc::alloc::Layout::from_size_align(size, alignment);
-    let layout = match layout {
-        Ok(layout) => layout,
-        Err(_) => return libc::ENOMEM,
-    };
-
-    let ptr = tagged_alloc(layout);
+    let ptr = alloc_at(size.max(1), alignment, site!());
     if ptr.is_null() {
         return libc::ENOMEM;
     }
@@ -220,22 +203,12 @@ unsafe extern "C" fn posix_memalign(
 unsafe extern "C" fn memalign(alignment: usize, size: usize) -> *mut c_void {
     libc!(libc::memalign(alignment, size));
 
-    let layout = alloc::alloc::Layout::from_size_align(size, alignment);
-    let layout = match layout {
-        Ok(layout) => layout,
-        Err(_) => {
-            set_errno(Errno(libc::ENOMEM));
-            return null_mut();
-        }
-    };
-
-    let ptr = tagged_alloc(layout);
-    if ptr.is_null() {
-        set_errno(Errno(libc::ENOMEM));
+    if !alignment.is_power_of_two() {
+        set_errno(Errno(libc::EINVAL));
         return null_mut();
     }
 
-    ptr.cast()
+    alloc_or_enomem(size, alignment, site!())
 }
 
 #[linkage = "weak"]
@@ -248,23 +221,15 @@ unsafe extern "C" fn aligned_alloc(alignment: size_t, size: size_t) -> *mut c_vo
         return null_mut();
     }
 
-    let layout = alloc::alloc::Layout::from_size_align(size, alignment).unwrap();
-    let ptr = tagged_alloc(layout);
-    if ptr.is_null() {
-        set_errno(Errno(libc::ENOMEM));
-        return null_mut();
-    }
-
-    ptr.cast()
+    alloc_or_enomem(size, alignment, site!())
 }
 
 #[deprecated]
-#[allow(deprecated)]
 #[no_mangle]
 unsafe extern "C" fn valloc(size: size_t) -> *mut c_void {
     //libc!(libc::valloc(size));
 
-    memalign(rustix::param::page_size(), size)
+    alloc_or_enomem(size, rustix::param::page_size(), site!())
 }
 
 #[linkage = "weak"]
@@ -276,7 +241,7 @@ unsafe extern "C" fn free(ptr: *mut c_void) {
         return;
     }
 
-    tagged_dealloc(ptr.cast());
+    dealloc_at(ptr.cast(), site!());
 }
 
 #[no_mangle]
@@ -287,5 +252,179 @@ unsafe extern "C" fn malloc_usable_size(ptr: *mut c_void) -> size_t {
         return 0;
     }
 
-    get_layout(ptr.cast()).size()
+    usable_size_at(ptr.cast(), site!())
+}
+
+/// A snapshot of the heap's statistics, in bytes except where noted.
+struct Stats {
+    /// Memory obtained from the OS for the heap proper.
+    arena: usize,
+    max_arena: usize,
+    /// How much of `arena` is allocated.
+    in_use: usize,
+    /// Memory in directly mapped allocations, and how many there are.
+    mapped: usize,
+    mapped_chunks: usize,
+    max_mapped: usize,
+    max_mapped_chunks: usize,
+}
+
+/// The largest `M_MMAP_THRESHOLD` glibc accepts.
+#[cfg(target_pointer_width = "64")]
+const MAX_MMAP_THRESHOLD: usize = 4 * 1024 * 1024 * core::mem::size_of::<libc::c_long>();
+#[cfg(target_pointer_width = "32")]
+const MAX_MMAP_THRESHOLD: usize = 512 * 1024;
+
+#[no_mangle]
+unsafe extern "C" fn mallinfo2() -> libc::mallinfo2 {
+    libc!(libc::mallinfo2());
+
+    let stats = heap::stats();
+
+    // We don't track the number of free chunks, or the size of the topmost
+    // free chunk, and there are no fastbins.
+    libc::mallinfo2 {
+        arena: stats.arena,
+        ordblks: 0,
+        smblks: 0,
+        hblks: stats.mapped_chunks,
+        hblkhd: stats.mapped,
+        usmblks: 0,
+        fsmblks: 0,
+        uordblks: stats.in_use,
+        fordblks: stats.arena - stats.in_use,
+        keepcost: 0,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn mallinfo() -> libc::mallinfo {
+    libc!(libc::mallinfo());
+
+    // Like glibc, truncate the fields to `int`.
+    let info = mallinfo2();
+    libc::mallinfo {
+        arena: info.arena as c_int,
+        ordblks: info.ordblks as c_int,
+        smblks: info.smblks as c_int,
+        hblks: info.hblks as c_int,
+        hblkhd: info.hblkhd as c_int,
+        usmblks: info.usmblks as c_int,
+        fsmblks: info.fsmblks as c_int,
+        uordblks: info.uordblks as c_int,
+        fordblks: info.fordblks as c_int,
+        keepcost: info.keepcost as c_int,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn malloc_trim(pad: size_t) -> c_int {
+    libc!(libc::malloc_trim(pad));
+
+    heap::trim(pad).into()
+}
+
+#[no_mangle]
+unsafe extern "C" fn mallopt(param: c_int, value: c_int) -> c_int {
+    libc!(libc::mallopt(param, value));
+
+    let ok = match param {
+        libc::M_MMAP_THRESHOLD => match usize::try_from(value) {
+            Ok(threshold) if threshold <= MAX_MMAP_THRESHOLD => heap::set_mmap_threshold(threshold),
+            _ => false,
+        },
+        libc::M_MMAP_MAX => match usize::try_from(value) {
+            Ok(max) => heap::set_mmap_max(max),
+            Err(_) => false,
+        },
+        // Like glibc, a negative threshold disables trimming.
+        libc::M_TRIM_THRESHOLD => heap::set_trim_threshold(value as isize as usize),
+        // There's only ever one arena. As in glibc, the other parameters are
+        // accepted and have no effect here.
+        _ => true,
+    };
+    ok.into()
+}
+
+/// Writes formatted output to a `FILE`.
+struct FileWriter(*mut libc::FILE);
+
+impl core::fmt::Write for FileWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if unsafe { libc::fwrite(s.as_ptr().cast(), 1, s.len(), self.0) } == s.len() {
+            Ok(())
+        } else {
+            Err(core::fmt::Error)
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn malloc_stats() {
+    libc!(libc::malloc_stats());
+
+    let stats = heap::stats();
+    let mut out = FileWriter(crate::stdio::stderr);
+    write!(
+        out,
+        "Arena 0:\n\
+         system bytes     = {:10}\n\
+         in use bytes     = {:10}\n\
+         Total (incl. mmap):\n\
+         system bytes     = {:10}\n\
+         in use bytes     = {:10}\n\
+         max mmap regions = {:10}\n\
+         max mmap bytes   = {:10}\n",
+        stats.arena,
+        stats.in_use,
+        stats.arena + stats.mapped,
+        stats.in_use + stats.mapped,
+        stats.max_mapped_chunks,
+        stats.max_mapped,
+    )
+    .ok();
+}
+
+#[no_mangle]
+unsafe extern "C" fn malloc_info(options: c_int, stream: *mut libc::FILE) -> c_int {
+    libc!(libc::malloc_info(options, stream));
+
+    if options != 0 {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+
+    let stats = heap::stats();
+    let free = stats.arena - stats.in_use;
+    let mut out = FileWriter(stream);
+    let result = write!(
+        out,
+        "<malloc version=\"1\">\n\
+         <heap nr=\"0\">\n\
+         <sizes>\n\
+         </sizes>\n\
+         <total type=\"fast\" count=\"0\" size=\"0\"/>\n\
+         <total type=\"rest\" count=\"0\" size=\"{free}\"/>\n\
+         <system type=\"current\" size=\"{arena}\"/>\n\
+         <system type=\"max\" size=\"{max_arena}\"/>\n\
+         <aspace type=\"total\" size=\"{arena}\"/>\n\
+         <aspace type=\"mprotect\" size=\"{arena}\"/>\n\
+         </heap>\n\
+         <total type=\"fast\" count=\"0\" size=\"0\"/>\n\
+         <total type=\"rest\" count=\"0\" size=\"{free}\"/>\n\
+         <total type=\"mmap\" count=\"{mapped_chunks}\" size=\"{mapped}\"/>\n\
+         <system type=\"current\" size=\"{arena}\"/>\n\
+         <system type=\"max\" size=\"{max_arena}\"/>\n\
+         <aspace type=\"total\" size=\"{arena}\"/>\n\
+         <aspace type=\"mprotect\" size=\"{arena}\"/>\n\
+         </malloc>\n",
+        arena = stats.arena,
+        max_arena = stats.max_arena,
+        mapped = stats.mapped,
+        mapped_chunks = stats.mapped_chunks,
+    );
+    match result {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
 }
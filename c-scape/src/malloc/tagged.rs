@@ -0,0 +1,153 @@
+//! The `malloc-via-rust-global-alloc` heap, which uses Rust's global
+//! allocator.
+
+use super::Stats;
+use alloc::alloc::Layout;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The total size of the allocations currently live, including their tags,
+/// and the most that's ever been.
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static MAX_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+fn note_alloc(size: usize) {
+    let in_use = IN_USE.fetch_add(size, Ordering::Relaxed) + size;
+    MAX_IN_USE.fetch_max(in_use, Ordering::Relaxed);
+}
+
+fn note_dealloc(size: usize) {
+    IN_USE.fetch_sub(size, Ordering::Relaxed);
+}
+
+/// Rust's `alloc` API requires the user to pass in the old size and alignment
+/// for resizing and deallocation, while C's `malloc` API doesn't, so we store
+/// the size and alignment next to the allocation memory.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Tag {
+    size: usize,
+    align: usize,
+}
+
+/// Allocate for a given size and alignment, with a tag prepended to the
+/// allocation, to keep track of said layout.
+///
+/// Return null if the allocation failed.
+unsafe fn tagged_alloc(
+    size: usize,
+    align: usize,
+    the_alloc: unsafe fn(Layout) -> *mut u8,
+) -> *mut u8 {
+    let type_layout = match Layout::from_size_align(size, align) {
+        Ok(layout) => layout,
+        Err(_) => return null_mut(),
+    };
+    let (total_layout, offset) = match Layout::new::<Tag>().extend(type_layout) {
+        Ok(extended) => extended,
+        Err(_) => return null_mut(),
+    };
+
+    let total_ptr = the_alloc(total_layout);
+    if total_ptr.is_null() {
+        return total_ptr;
+    }
+
+    note_alloc(total_layout.size());
+    let ptr = total_ptr.add(offset);
+    ptr.cast::<Tag>().sub(1).write(Tag { size, align });
+    ptr
+}
+
+/// Get the layout out of a tagged allocation, and the layout and offset of
+/// the allocation including its tag.
+///
+/// # Safety
+///
+/// The given pointer must be a non-null pointer that was returned from
+/// `tagged_alloc`.
+unsafe fn get_layouts(ptr: *mut u8) -> (Layout, Layout, usize) {
+    let tag = ptr.cast::<Tag>().sub(1).read();
+    let type_layout = Layout::from_size_align_unchecked(tag.size, tag.align);
+    let (total_layout, offset) = Layout::new::<Tag>().extend(type_layout).unwrap();
+    (type_layout, total_layout, offset)
+}
+
+pub(super) unsafe fn alloc(size: usize, align: usize) -> *mut u8 {
+    tagged_alloc(size, align, alloc::alloc::alloc)
+}
+
+pub(super) unsafe fn alloc_zeroed(size: usize, align: usize) -> *mut u8 {
+    tagged_alloc(size, align, alloc::alloc::alloc_zeroed)
+}
+
+pub(super) unsafe fn dealloc(ptr: *mut u8) {
+    let (_type_layout, total_layout, offset) = get_layouts(ptr);
+    note_dealloc(total_layout.size());
+    alloc::alloc::dealloc(ptr.sub(offset), total_layout);
+}
+
+pub(super) unsafe fn realloc(ptr: *mut u8, size: usize, _align: usize) -> *mut u8 {
+    let (type_layout, total_layout, offset) = get_layouts(ptr);
+
+    // The tag's offset only depends on the alignment, which isn't changing.
+    let new_total_size = match offset.checked_add(size) {
+        Some(new_total_size) => new_total_size,
+        None => return null_mut(),
+    };
+    if Layout::from_size_align(new_total_size, total_layout.align()).is_err() {
+        return null_mut();
+    }
+
+    let total_ptr = alloc::alloc::realloc(ptr.sub(offset), total_layout, new_total_size);
+    if total_ptr.is_null() {
+        return total_ptr;
+    }
+
+    note_dealloc(total_layout.size());
+    note_alloc(new_total_size);
+    let ptr = total_ptr.add(offset);
+    ptr.cast::<Tag>().sub(1).write(Tag {
+        size,
+        align: type_layout.align(),
+    });
+    ptr
+}
+
+pub(super) unsafe fn usable_size(ptr: *mut u8) -> usize {
+    let (type_layout, _total_layout, _offset) = get_layouts(ptr);
+    type_layout.size()
+}
+
+pub(super) unsafe fn trim(_pad: usize) -> bool {
+    false
+}
+
+/// Rust's global allocator doesn't tell us how much memory it's holding on
+/// to, so we can only report what's in use.
+pub(super) fn stats() -> Stats {
+    let in_use = IN_USE.load(Ordering::Relaxed);
+    Stats {
+        arena: in_use,
+        max_arena: MAX_IN_USE.load(Ordering::Relaxed),
+        in_use,
+        mapped: 0,
+        mapped_chunks: 0,
+        max_mapped: 0,
+        max_mapped_chunks: 0,
+    }
+}
+
+// Rust's global allocator doesn't have any tunables.
+
+pub(super) fn set_mmap_threshold(_threshold: usize) -> bool {
+    false
+}
+
+pub(super) fn set_mmap_max(_max: usize) -> bool {
+    false
+}
+
+pub(super) fn set_trim_threshold(_threshold: usize) -> bool {
+    false
+}
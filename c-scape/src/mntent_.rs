@@ -0,0 +1,397 @@
+//! `setmntent`, `getmntent`, and friends, for reading and writing files in
+//! the format of /etc/fstab and /proc/self/mounts.
+
+use alloc::vec::Vec;
+use core::cell::SyncUnsafeCell;
+use core::ffi::CStr;
+use core::ptr::{addr_of_mut, null_mut};
+use core::slice;
+use libc::{c_char, c_int};
+
+/// The size of the line buffer used by `getmntent`.
+const BUFSIZ: usize = 8192;
+
+#[no_mangle]
+unsafe extern "C" fn setmntent(filename: *const c_char, ty: *const c_char) -> *mut libc::FILE {
+    libc!(libc::setmntent(filename, ty));
+
+    // Like glibc, always open the file close-on-exec.
+    let ty = CStr::from_ptr(ty).to_bytes();
+    let mut mode = Vec::with_capacity(ty.len() + 2);
+    mode.extend_from_slice(ty);
+    mode.extend_from_slice(b"e\0");
+
+    libc::fopen(filename, mode.as_ptr().cast())
+}
+
+#[no_mangle]
+unsafe extern "C" fn endmntent(stream: *mut libc::FILE) -> c_int {
+    libc!(libc::endmntent(stream));
+
+    if !stream.is_null() {
+        libc::fclose(stream);
+    }
+    1
+}
+
+#[no_mangle]
+unsafe extern "C" fn getmntent(stream: *mut libc::FILE) -> *mut libc::mntent {
+    libc!(libc::getmntent(stream));
+
+    static mut ENTRY: libc::mntent = libc::mntent {
+        mnt_fsname: null_mut(),
+        mnt_dir: null_mut(),
+        mnt_type: null_mut(),
+        mnt_opts: null_mut(),
+        mnt_freq: 0,
+        mnt_passno: 0,
+    };
+    static BUF: SyncUnsafeCell<[c_char; BUFSIZ]> = SyncUnsafeCell::new([0; BUFSIZ]);
+
+    getmntent_r(
+        stream,
+        addr_of_mut!(ENTRY),
+        BUF.get().cast(),
+        BUFSIZ as c_int,
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn getmntent_r(
+    stream: *mut libc::FILE,
+    mntbuf: *mut libc::mntent,
+    buf: *mut c_char,
+    buflen: c_int,
+) -> *mut libc::mntent {
+    libc!(libc::getmntent_r(stream, mntbuf, buf, buflen));
+
+    let line = loop {
+        if libc::fgets(buf, buflen, stream).is_null() {
+            return null_mut();
+        }
+
+        let len = libc::strlen(buf);
+        let line = slice::from_raw_parts_mut(buf.cast::<u8>(), len);
+        let len = match line.iter().position(|c| *c == b'\n') {
+            Some(newline) => newline,
+            None => {
+                // The line didn't fit in `buf`; discard the rest of it.
+                let mut c = 0;
+                while c != b'\n' as c_int && c != libc::EOF {
+                    c = libc::fgetc(stream);
+                }
+                len
+            }
+        };
+
+        // Terminate the line in place, so that the last field is
+        // NUL-terminated too.
+        let len = trim_end(&mut line[..len]).len();
+        *buf.add(len) = 0;
+        let line = trim_start(slice::from_raw_parts_mut(buf.cast::<u8>(), len));
+
+        if !line.is_empty() && line[0] != b'#' {
+            break line;
+        }
+    };
+
+    // Each field is terminated in place, so the pointers can refer directly
+    // into `buf`.
+    let mut rest = Some(line);
+    let mut field = || match rest.take() {
+        Some(line) => {
+            let (field, next) = split_field(line);
+            rest = next;
+            decode(field)
+        }
+        None => c"".as_ptr().cast_mut(),
+    };
+    let fsname = field();
+    let dir = field();
+    let ty = field();
+    let opts = field();
+
+    let (freq, passno) = match rest {
+        Some(rest) => {
+            let (freq, rest) = parse_int(rest);
+            let passno = freq.and_then(|_| parse_int(rest).0);
+            (freq, passno)
+        }
+        None => (None, None),
+    };
+
+    *mntbuf = libc::mntent {
+        mnt_fsname: fsname,
+        mnt_dir: dir,
+        mnt_type: ty,
+        mnt_opts: opts,
+        mnt_freq: freq.unwrap_or(0),
+        mnt_passno: passno.unwrap_or(0),
+    };
+    mntbuf
+}
+
+fn is_blank(c: &u8) -> bool {
+    *c == b' ' || *c == b'\t'
+}
+
+fn trim_start(s: &mut [u8]) -> &mut [u8] {
+    let n = s.iter().take_while(|c| is_blank(c)).count();
+    &mut s[n..]
+}
+
+fn trim_end(s: &mut [u8]) -> &mut [u8] {
+    let n = s.iter().rev().take_while(|c| is_blank(c)).count();
+    let len = s.len() - n;
+    &mut s[..len]
+}
+
+/// Split off the first field of `line`, which is followed by a space or tab,
+/// and return it along with the rest of the line, if any.
+///
+/// The separator is overwritten with a NUL, so the field is NUL-terminated
+/// in place.
+fn split_field(line: &mut [u8]) -> (&mut [u8], Option<&mut [u8]>) {
+    match line.iter().position(is_blank) {
+        Some(end) => {
+            let (field, rest) = line.split_at_mut(end);
+            rest[0] = b'\0';
+            (field, Some(trim_start(&mut rest[1..])))
+        }
+        None => (line, None),
+    }
+}
+
+/// Decode the octal escapes which `addmntent` uses for characters which
+/// would otherwise be mistaken for separators, in place, and return a
+/// pointer to the NUL-terminated result.
+fn decode(field: &mut [u8]) -> *mut c_char {
+    let mut out = 0;
+    let mut i = 0;
+    while i < field.len() {
+        let (c, n) = match &field[i..] {
+            [b'\\', b'0', b'4', b'0', ..] => (b' ', 4),
+            [b'\\', b'0', b'1', b'1', ..] => (b'\t', 4),
+            [b'\\', b'0', b'1', b'2', ..] => (b'\n', 4),
+            [b'\\', b'1', b'3', b'4', ..] => (b'\\', 4),
+            [b'\\', b'\\', ..] => (b'\\', 2),
+            [c, ..] => (*c, 1),
+            [] => unreachable!(),
+        };
+        field[out] = c;
+        out += 1;
+        i += n;
+    }
+    if out < field.len() {
+        field[out] = b'\0';
+    }
+    field.as_mut_ptr().cast()
+}
+
+/// Parse an optionally signed decimal integer after optional blanks, as
+/// `sscanf`'s `%d` does, returning it and the remaining text.
+fn parse_int(s: &mut [u8]) -> (Option<c_int>, &mut [u8]) {
+    let s = trim_start(s);
+    let (negative, start) = match s.first() {
+        Some(b'-') => (true, 1),
+        Some(b'+') => (false, 1),
+        _ => (false, 0),
+    };
+    let digits = s[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return (None, s);
+    }
+    let value = s[start..start + digits].iter().fold(0 as c_int, |n, c| {
+        n.wrapping_mul(10).wrapping_add(c_int::from(c - b'0'))
+    });
+    let value = if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    };
+    (Some(value), &mut s[start + digits..])
+}
+
+/// Append `field` to `line`, escaping characters which would otherwise be
+/// mistaken for separators when the entry is read back.
+unsafe fn push_encoded(line: &mut Vec<u8>, field: *const c_char) {
+    for c in CStr::from_ptr(field).to_bytes() {
+        match c {
+            b' ' => line.extend_from_slice(b"\\040"),
+            b'\t' => line.extend_from_slice(b"\\011"),
+            b'\n' => line.extend_from_slice(b"\\012"),
+            b'\\' => line.extend_from_slice(b"\\134"),
+            c => line.push(*c),
+        }
+    }
+    line.push(b' ');
+}
+
+#[no_mangle]
+unsafe extern "C" fn addmntent(stream: *mut libc::FILE, mnt: *const libc::mntent) -> c_int {
+    libc!(libc::addmntent(stream, mnt));
+
+    if libc::fseek(stream, 0, libc::SEEK_END) != 0 {
+        return 1;
+    }
+
+    let mnt = &*mnt;
+    let mut line = Vec::new();
+    push_encoded(&mut line, mnt.mnt_fsname);
+    push_encoded(&mut line, mnt.mnt_dir);
+    push_encoded(&mut line, mnt.mnt_type);
+    push_encoded(&mut line, mnt.mnt_opts);
+    line.extend_from_slice(alloc::format!("{} {}\n", mnt.mnt_freq, mnt.mnt_passno).as_bytes());
+
+    // Write the entry with a single call so that it isn't interleaved with
+    // entries written concurrently.
+    if libc::fwrite(line.as_ptr().cast(), 1, line.len(), stream) != line.len() {
+        return 1;
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn hasmntopt(mnt: *const libc::mntent, opt: *const c_char) -> *mut c_char {
+    libc!(libc::hasmntopt(mnt, opt));
+
+    let opts = (*mnt).mnt_opts;
+    let bytes = CStr::from_ptr(opts).to_bytes();
+    let opt = CStr::from_ptr(opt).to_bytes();
+
+    // Match whole options only, optionally followed by a `=value`.
+    let mut start = 0;
+    for option in bytes.split(|c| *c == b',') {
+        if let Some(rest) = option.strip_prefix(opt) {
+            if rest.is_empty() || rest[0] == b'=' {
+                return opts.add(start);
+            }
+        }
+        start += option.len() + 1;
+    }
+    null_mut()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::String;
+
+    /// Open a file containing `contents` with `setmntent`.
+    unsafe fn open(contents: &[u8], ty: &CStr) -> *mut libc::FILE {
+        let fd = libc::memfd_create(c"mntent".as_ptr(), 0);
+        assert_ne!(fd, -1);
+        assert_eq!(
+            libc::write(fd, contents.as_ptr().cast(), contents.len()),
+            contents.len() as isize
+        );
+        let path = format!("/proc/self/fd/{fd}\0");
+        let file = setmntent(path.as_ptr().cast(), ty.as_ptr());
+        assert!(!file.is_null());
+        libc::close(fd);
+        file
+    }
+
+    unsafe fn field(s: *const c_char) -> &'static str {
+        CStr::from_ptr(s).to_str().unwrap()
+    }
+
+    /// Describe the next entry, and which of `opts` it has and where.
+    unsafe fn next(file: *mut libc::FILE, opts: &[&CStr]) -> Option<String> {
+        let mnt = getmntent(file);
+        if mnt.is_null() {
+            return None;
+        }
+        let m = &*mnt;
+        let mut s = format!(
+            "[{}] [{}] [{}] [{}] {} {}",
+            field(m.mnt_fsname),
+            field(m.mnt_dir),
+            field(m.mnt_type),
+            field(m.mnt_opts),
+            m.mnt_freq,
+            m.mnt_passno
+        );
+        for opt in opts {
+            let found = hasmntopt(mnt, opt.as_ptr());
+            if !found.is_null() {
+                s += &format!(
+                    " {}@{}",
+                    opt.to_str().unwrap(),
+                    found.offset_from(m.mnt_opts)
+                );
+            }
+        }
+        Some(s)
+    }
+
+    // The expected results are from glibc.
+
+    #[test]
+    fn test_getmntent() {
+        let opts = [
+            c"ro",
+            c"vers",
+            c"soft",
+            c"relat",
+            c"rw",
+            c"user",
+            c"defaults",
+            c"noauto",
+        ];
+        unsafe {
+            let file = open(
+                b"# comment\n  \n/dev/sda1 / ext4 rw,relatime 0 1\n\
+                  server:/export\\040dir /mnt/with\\040space nfs ro,vers=4,soft 0 0\n\
+                  tab\\011dev\t/mnt/back\\134slash\\\\x tmpfs defaults\n\
+                  proc /proc proc\n\
+                  weird\\012nl /x y z 12abc 3x\n  \
+                  indented /y auto noauto,user  7   8  \n",
+                c"r",
+            );
+            assert_eq!(
+                next(file, &opts).unwrap(),
+                "[/dev/sda1] [/] [ext4] [rw,relatime] 0 1 rw@0"
+            );
+            assert_eq!(
+                next(file, &opts).unwrap(),
+                "[server:/export dir] [/mnt/with space] [nfs] [ro,vers=4,soft] 0 0 ro@0 vers@3 soft@10"
+            );
+            assert_eq!(
+                next(file, &opts).unwrap(),
+                "[tab\tdev] [/mnt/back\\slash\\x] [tmpfs] [defaults] 0 0 defaults@0"
+            );
+            assert_eq!(next(file, &opts).unwrap(), "[proc] [/proc] [proc] [] 0 0");
+            assert_eq!(next(file, &opts).unwrap(), "[weird\nnl] [/x] [y] [z] 12 0");
+            assert_eq!(
+                next(file, &opts).unwrap(),
+                "[indented] [/y] [auto] [noauto,user] 7 8 user@7 noauto@0"
+            );
+            assert_eq!(next(file, &opts), None);
+            assert_eq!(endmntent(file), 1);
+        }
+    }
+
+    #[test]
+    fn test_addmntent() {
+        unsafe {
+            let file = open(b"", c"r+");
+            let mnt = libc::mntent {
+                mnt_fsname: c"my disk".as_ptr().cast_mut(),
+                mnt_dir: c"/mnt/a\tb\\c".as_ptr().cast_mut(),
+                mnt_type: c"ext4".as_ptr().cast_mut(),
+                mnt_opts: c"rw,noatime".as_ptr().cast_mut(),
+                mnt_freq: 1,
+                mnt_passno: 2,
+            };
+            assert_eq!(addmntent(file, &mnt), 0);
+            assert_eq!(libc::fseek(file, 0, libc::SEEK_SET), 0);
+            assert_eq!(
+                next(file, &[c"noatime"]).unwrap(),
+                "[my disk] [/mnt/a\tb\\c] [ext4] [rw,noatime] 1 2 noatime@3"
+            );
+            assert_eq!(endmntent(file), 1);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! `if_nameindex`, `if_nametoindex`, and friends.
+
+use super::netlink::links;
+use core::ffi::CStr;
+use core::mem::size_of;
+use core::ptr::{copy_nonoverlapping, null_mut};
+use errno::{set_errno, Errno};
+use libc::{c_char, c_uint};
+
+#[no_mangle]
+unsafe extern "C" fn if_nametoindex(name: *const c_char) -> c_uint {
+    libc!(libc::if_nametoindex(name));
+
+    let name = CStr::from_ptr(name).to_bytes();
+    match links() {
+        Ok(links) => match links.iter().find(|link| link.name == name) {
+            Some(link) => link.index,
+            None => {
+                set_errno(Errno(libc::ENODEV));
+                0
+            }
+        },
+        Err(err) => {
+            set_errno(Errno(err.raw_os_error()));
+            0
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn if_indextoname(index: c_uint, name: *mut c_char) -> *mut c_char {
+    libc!(libc::if_indextoname(index, name));
+
+    match links() {
+        Ok(links) => match links.iter().find(|link| link.index == index) {
+            Some(link) => {
+                let len = link.name.len().min(libc::IF_NAMESIZE - 1);
+                copy_nonoverlapping(link.name.as_ptr().cast(), name, len);
+                name.add(len).write(0);
+                name
+            }
+            None => {
+                set_errno(Errno(libc::ENXIO));
+                null_mut()
+            }
+        },
+        Err(err) => {
+            set_errno(Errno(err.raw_os_error()));
+            null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn if_nameindex() -> *mut libc::if_nameindex {
+    libc!(libc::if_nameindex());
+
+    let links = match links() {
+        Ok(links) => links,
+        Err(err) => {
+            set_errno(Errno(err.raw_os_error()));
+            return null_mut();
+        }
+    };
+
+    // One allocation holds the array, with its terminating zero entry,
+    // followed by the NUL-terminated names.
+    let array_size = (links.len() + 1) * size_of::<libc::if_nameindex>();
+    let names_size: usize = links.iter().map(|link| link.name.len() + 1).sum();
+    let array = libc::malloc(array_size + names_size).cast::<libc::if_nameindex>();
+    if array.is_null() {
+        set_errno(Errno(libc::ENOBUFS));
+        return null_mut();
+    }
+    let mut names = array.cast::<c_char>().add(array_size);
+    for (i, link) in links.iter().enumerate() {
+        copy_nonoverlapping(link.name.as_ptr().cast(), names, link.name.len());
+        names.add(link.name.len()).write(0);
+        array.add(i).write(libc::if_nameindex {
+            if_index: link.index,
+            if_name: names,
+        });
+        names = names.add(link.name.len() + 1);
+    }
+    array.add(links.len()).write(libc::if_nameindex {
+        if_index: 0,
+        if_name: null_mut(),
+    });
+    array
+}
+
+#[no_mangle]
+unsafe extern "C" fn if_freenameindex(ptr: *mut libc::if_nameindex) {
+    libc!(libc::if_freenameindex(ptr));
+
+    libc::free(ptr.cast());
+}
@@ -0,0 +1,201 @@
+//! `getifaddrs` and `freeifaddrs`.
+//!
+//! As in glibc, the whole list is one allocation: an array of `Entry`s, each
+//! holding an `ifaddrs` and the data it points to, followed by the link
+//! statistics that the `AF_PACKET` entries' `ifa_data` point to.
+
+use super::netlink::{addresses, links, Address, Link};
+use core::mem::{size_of, zeroed};
+use core::ptr::{addr_of_mut, copy_nonoverlapping, null_mut};
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int};
+
+/// Storage for any of the socket addresses in an `ifaddrs`.
+#[repr(C)]
+union Sockaddr {
+    sa: libc::sockaddr,
+    sin: libc::sockaddr_in,
+    sin6: libc::sockaddr_in6,
+    sll: libc::sockaddr_ll,
+}
+
+/// An `ifaddrs`, followed by the data its pointers point to.
+#[repr(C)]
+struct Entry {
+    ifaddrs: libc::ifaddrs,
+    addr: Sockaddr,
+    netmask: Sockaddr,
+    broadaddr: Sockaddr,
+    name: [c_char; libc::IF_NAMESIZE + 1],
+}
+
+#[no_mangle]
+unsafe extern "C" fn getifaddrs(ifap: *mut *mut libc::ifaddrs) -> c_int {
+    libc!(libc::getifaddrs(ifap));
+
+    let (links, addresses) = match (links(), addresses()) {
+        (Ok(links), Ok(addresses)) => (links, addresses),
+        (Err(err), _) | (_, Err(err)) => {
+            set_errno(Errno(err.raw_os_error()));
+            return -1;
+        }
+    };
+    // Skip addresses of interfaces that went away between the two dumps.
+    let addresses = addresses
+        .iter()
+        .filter_map(|address| {
+            let link = links.iter().find(|link| link.index == address.index)?;
+            Some((link, address))
+        })
+        .collect::<alloc::vec::Vec<_>>();
+
+    let count = links.len() + addresses.len();
+    if count == 0 {
+        *ifap = null_mut();
+        return 0;
+    }
+    let stats_size: usize = links.iter().map(|link| align(link.stats.len())).sum();
+    let size = count * size_of::<Entry>() + stats_size;
+    let entries = libc::malloc(size).cast::<Entry>();
+    if entries.is_null() {
+        set_errno(Errno(libc::ENOMEM));
+        return -1;
+    }
+    entries.cast::<u8>().write_bytes(0, size);
+    let mut stats = entries.add(count).cast::<u8>();
+
+    for (i, link) in links.iter().enumerate() {
+        let entry = &mut *entries.add(i);
+        set_name(entry, &link.name);
+        entry.ifaddrs.ifa_flags = link.flags;
+        if !link.address.is_empty() {
+            entry.ifaddrs.ifa_addr = set_link_addr(&mut entry.addr, link, &link.address);
+        }
+        if !link.broadcast.is_empty() {
+            entry.ifaddrs.ifa_ifu = set_link_addr(&mut entry.broadaddr, link, &link.broadcast);
+        }
+        if !link.stats.is_empty() {
+            copy_nonoverlapping(link.stats.as_ptr(), stats, link.stats.len());
+            entry.ifaddrs.ifa_data = stats.cast();
+            stats = stats.add(align(link.stats.len()));
+        }
+    }
+
+    for (i, (link, address)) in addresses.iter().enumerate() {
+        let entry = &mut *entries.add(links.len() + i);
+        set_name(entry, address.label.as_deref().unwrap_or(&link.name));
+        entry.ifaddrs.ifa_flags = link.flags;
+
+        // As in glibc, if there's an `IFA_LOCAL`, it's the address, and
+        // `IFA_ADDRESS` is the point-to-point destination, unless there's a
+        // broadcast address.
+        let (addr, dest) = match (&address.local, &address.address) {
+            (Some(local), dest) => (Some(local), dest.as_ref()),
+            (None, addr) => (addr.as_ref(), None),
+        };
+        if let Some(addr) = addr {
+            entry.ifaddrs.ifa_addr = set_inet_addr(&mut entry.addr, address, addr, true);
+        }
+        if let Some(broadcast) = address.broadcast.as_ref().or(dest) {
+            entry.ifaddrs.ifa_ifu = set_inet_addr(&mut entry.broadaddr, address, broadcast, true);
+        }
+        entry.ifaddrs.ifa_netmask = set_netmask(&mut entry.netmask, address);
+    }
+
+    for i in 1..count {
+        (*entries.add(i - 1)).ifaddrs.ifa_next = addr_of_mut!((*entries.add(i)).ifaddrs);
+    }
+    *ifap = addr_of_mut!((*entries).ifaddrs);
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn freeifaddrs(ifa: *mut libc::ifaddrs) {
+    libc!(libc::freeifaddrs(ifa));
+
+    // `ifa` is the start of the allocation, since `ifaddrs` is the first
+    // field of the first `Entry`.
+    libc::free(ifa.cast());
+}
+
+/// Set the name of `entry`, truncating it to `IF_NAMESIZE` bytes.
+fn set_name(entry: &mut Entry, name: &[u8]) {
+    let len = name.len().min(libc::IF_NAMESIZE);
+    for (to, from) in entry.name.iter_mut().zip(&name[..len]) {
+        *to = *from as c_char;
+    }
+    entry.ifaddrs.ifa_name = entry.name.as_mut_ptr();
+}
+
+/// Store the hardware address `address` of `link` in `storage` as a
+/// `sockaddr_ll`.
+unsafe fn set_link_addr(
+    storage: &mut Sockaddr,
+    link: &Link,
+    address: &[u8],
+) -> *mut libc::sockaddr {
+    let mut sll: libc::sockaddr_ll = zeroed();
+    sll.sll_family = libc::AF_PACKET as _;
+    sll.sll_ifindex = link.index as c_int;
+    sll.sll_hatype = link.kind;
+    let len = address.len().min(sll.sll_addr.len());
+    sll.sll_halen = len as u8;
+    sll.sll_addr[..len].copy_from_slice(&address[..len]);
+    storage.sll = sll;
+    addr_of_mut!(storage.sa)
+}
+
+/// Store the IPv4 or IPv6 address `bytes` of `address` in `storage`. If
+/// `scoped` is set, link-local IPv6 addresses get the interface as their
+/// scope ID, since they're only meaningful with it.
+unsafe fn set_inet_addr(
+    storage: &mut Sockaddr,
+    address: &Address,
+    bytes: &[u8],
+    scoped: bool,
+) -> *mut libc::sockaddr {
+    if i32::from(address.family) == libc::AF_INET {
+        let mut sin: libc::sockaddr_in = zeroed();
+        sin.sin_family = libc::AF_INET as _;
+        let mut addr = [0_u8; 4];
+        let len = bytes.len().min(addr.len());
+        addr[..len].copy_from_slice(&bytes[..len]);
+        sin.sin_addr.s_addr = u32::from_ne_bytes(addr);
+        storage.sin = sin;
+    } else {
+        let mut sin6: libc::sockaddr_in6 = zeroed();
+        sin6.sin6_family = libc::AF_INET6 as _;
+        let len = bytes.len().min(sin6.sin6_addr.s6_addr.len());
+        sin6.sin6_addr.s6_addr[..len].copy_from_slice(&bytes[..len]);
+        let s6_addr = sin6.sin6_addr.s6_addr;
+        let link_local = (s6_addr[0] == 0xfe && s6_addr[1] & 0xc0 == 0x80)
+            || (s6_addr[0] == 0xff && s6_addr[1] & 0x0f == 0x02);
+        if scoped && link_local {
+            sin6.sin6_scope_id = address.index;
+        }
+        storage.sin6 = sin6;
+    }
+    addr_of_mut!(storage.sa)
+}
+
+/// Store the netmask for `address`'s prefix length in `storage`.
+unsafe fn set_netmask(storage: &mut Sockaddr, address: &Address) -> *mut libc::sockaddr {
+    let mut mask = [0_u8; 16];
+    let max = if i32::from(address.family) == libc::AF_INET {
+        32
+    } else {
+        128
+    };
+    let prefix_len = usize::from(address.prefix_len).min(max);
+    for (i, byte) in mask.iter_mut().enumerate() {
+        let bits = prefix_len.saturating_sub(i * 8).min(8);
+        *byte = !(0xff_u8.checked_shr(bits as u32).unwrap_or(0));
+    }
+    set_inet_addr(storage, address, &mask[..max / 8], false)
+}
+
+/// Round `len` up to a multiple of 4, which is the alignment of the link
+/// statistics.
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
@@ -1,4 +1,10 @@
+#[cfg(not(target_os = "wasi"))]
+mod if_index;
+#[cfg(not(target_os = "wasi"))]
+mod ifaddrs;
 mod inet;
+#[cfg(not(target_os = "wasi"))]
+mod netlink;
 
 use core::cmp::min;
 use core::ffi::c_void;
@@ -10,6 +16,8 @@ use core::ptr::{addr_of, copy_nonoverlapping};
 use core::slice;
 use errno::{set_errno, Errno};
 use libc::{c_int, c_uint, ssize_t};
+#[cfg(not(target_os = "wasi"))]
+use rustix::fd::AsRawFd;
 use rustix::fd::{BorrowedFd, IntoRawFd};
 use rustix::io;
 use rustix::net::addr::SocketAddrArg;
@@ -303,7 +311,7 @@ unsafe extern "C" fn getsockopt(
                 optlen,
             ),
             libc::SO_PEERCRED => write_ucred(sockopt::socket_peercred(fd), optval, optlen),
-            _ => unimplemented!("unimplemented getsockopt SOL_SOCKET optname {:?}", optname),
+            _ => passthrough_getsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_IP => match optname {
             libc::IP_TTL => write_i32(sockopt::ip_ttl(fd).map(|ttl| ttl as i32), optval, optlen),
@@ -327,7 +335,7 @@ unsafe extern "C" fn getsockopt(
                 }
                 Err(err) => Err(err),
             },
-            _ => unimplemented!("unimplemented getsockopt IPPROTO_IP optname {:?}", optname),
+            _ => passthrough_getsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_IPV6 => match optname {
             libc::IPV6_MULTICAST_LOOP => {
@@ -357,10 +365,7 @@ unsafe extern "C" fn getsockopt(
                 optval,
                 optlen,
             ),
-            _ => unimplemented!(
-                "unimplemented getsockopt IPPROTO_IPV6 optname {:?}",
-                optname
-            ),
+            _ => passthrough_getsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_TCP => match optname {
             libc::TCP_NODELAY => write_bool(sockopt::tcp_nodelay(fd), optval, optlen),
@@ -396,13 +401,9 @@ unsafe extern "C" fn getsockopt(
                 write_bool(sockopt::tcp_thin_linear_timeouts(fd), optval, optlen)
             }
             libc::TCP_CORK => write_bool(sockopt::tcp_cork(fd), optval, optlen),
-            _ => unimplemented!("unimplemented getsockopt IPPROTO_TCP optname {:?}", optname),
+            _ => passthrough_getsockopt(fd, level, optname, optval, optlen),
         },
-        _ => unimplemented!(
-            "unimplemented getsockopt level {:?} optname {:?}",
-            level,
-            optname
-        ),
+        _ => passthrough_getsockopt(fd, level, optname, optval, optlen),
     };
     match convert_res(result) {
         Some(()) => 0,
@@ -517,7 +518,7 @@ unsafe extern "C" fn setsockopt(
             libc::SO_OOBINLINE => sockopt::set_socket_oobinline(fd, read_bool(optval, optlen)),
             libc::SO_REUSEPORT => sockopt::set_socket_reuseport(fd, read_bool(optval, optlen)),
             libc::SO_INCOMING_CPU => sockopt::set_socket_incoming_cpu(fd, read_u32(optval, optlen)),
-            _ => unimplemented!("unimplemented setsockopt SOL_SOCKET optname {:?}", optname),
+            _ => passthrough_setsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_IP => match optname {
             libc::IP_TTL => sockopt::set_ip_ttl(fd, read_i32(optval, optlen) as u32),
@@ -582,7 +583,7 @@ unsafe extern "C" fn setsockopt(
             }
             libc::IP_RECVTOS => sockopt::set_ip_recvtos(fd, read_bool(optval, optlen)),
             libc::IP_FREEBIND => sockopt::set_ip_freebind(fd, read_bool(optval, optlen)),
-            _ => unimplemented!("unimplemented setsockopt IPPROTO_IP optname {:?}", optname),
+            _ => passthrough_setsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_IPV6 => match optname {
             libc::IPV6_MULTICAST_LOOP => {
@@ -617,10 +618,7 @@ unsafe extern "C" fn setsockopt(
             libc::IPV6_RECVTCLASS => sockopt::set_ipv6_recvtclass(fd, read_bool(optval, optlen)),
             libc::IPV6_FREEBIND => sockopt::set_ipv6_freebind(fd, read_bool(optval, optlen)),
             libc::IPV6_TCLASS => sockopt::set_ipv6_tclass(fd, read_u32(optval, optlen)),
-            _ => unimplemented!(
-                "unimplemented setsockopt IPPROTO_IPV6 optname {:?}",
-                optname
-            ),
+            _ => passthrough_setsockopt(fd, level, optname, optval, optlen),
         },
         libc::IPPROTO_TCP => match optname {
             libc::TCP_NODELAY => sockopt::set_tcp_nodelay(fd, read_bool(optval, optlen)),
@@ -662,13 +660,9 @@ unsafe extern "C" fn setsockopt(
                 sockopt::set_tcp_thin_linear_timeouts(fd, read_bool(optval, optlen))
             }
             libc::TCP_CORK => sockopt::set_tcp_cork(fd, read_bool(optval, optlen)),
-            _ => unimplemented!("unimplemented setsockopt IPPROTO_TCP optname {:?}", optname),
+            _ => passthrough_setsockopt(fd, level, optname, optval, optlen),
         },
-        _ => unimplemented!(
-            "unimplemented setsockopt level {:?} optname {:?}",
-            level,
-            optname
-        ),
+        _ => passthrough_setsockopt(fd, level, optname, optval, optlen),
     };
     match convert_res(result) {
         Some(()) => 0,
@@ -676,6 +670,70 @@ unsafe extern "C" fn setsockopt(
     }
 }
 
+/// Pass a `getsockopt` call that we don't translate into a `sockopt` call
+/// straight to the kernel, which knows every option and reports
+/// `ENOPROTOOPT` for the ones it doesn't support.
+#[cfg(not(target_os = "wasi"))]
+unsafe fn passthrough_getsockopt(
+    fd: BorrowedFd<'_>,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut libc::socklen_t,
+) -> rustix::io::Result<()> {
+    crate::raw_syscall::syscall5(
+        libc::SYS_getsockopt,
+        fd.as_raw_fd() as usize,
+        level as usize,
+        optname as usize,
+        optval.expose_provenance(),
+        optlen.expose_provenance(),
+    )
+    .map(|_| ())
+}
+
+#[cfg(target_os = "wasi")]
+unsafe fn passthrough_getsockopt(
+    _fd: BorrowedFd<'_>,
+    _level: c_int,
+    _optname: c_int,
+    _optval: *mut c_void,
+    _optlen: *mut libc::socklen_t,
+) -> rustix::io::Result<()> {
+    Err(rustix::io::Errno::NOPROTOOPT)
+}
+
+/// Like `passthrough_getsockopt`, for `setsockopt`.
+#[cfg(not(target_os = "wasi"))]
+unsafe fn passthrough_setsockopt(
+    fd: BorrowedFd<'_>,
+    level: c_int,
+    optname: c_int,
+    optval: *const c_void,
+    optlen: libc::socklen_t,
+) -> rustix::io::Result<()> {
+    crate::raw_syscall::syscall5(
+        libc::SYS_setsockopt,
+        fd.as_raw_fd() as usize,
+        level as usize,
+        optname as usize,
+        optval.expose_provenance(),
+        optlen as usize,
+    )
+    .map(|_| ())
+}
+
+#[cfg(target_os = "wasi")]
+unsafe fn passthrough_setsockopt(
+    _fd: BorrowedFd<'_>,
+    _level: c_int,
+    _optname: c_int,
+    _optval: *const c_void,
+    _optlen: libc::socklen_t,
+) -> rustix::io::Result<()> {
+    Err(rustix::io::Errno::NOPROTOOPT)
+}
+
 #[no_mangle]
 unsafe extern "C" fn listen(fd: c_int, backlog: c_int) -> c_int {
     libc!(libc::listen(fd, backlog));
@@ -0,0 +1,210 @@
+//! Listing network interfaces and their addresses over `NETLINK_ROUTE`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+use rustix::fd::OwnedFd;
+use rustix::io::{self, Errno};
+use rustix::net::{AddressFamily, RecvFlags, SendFlags, SocketFlags, SocketType};
+
+/// The size of the buffer we receive replies in, which is the most the
+/// kernel puts in one dump reply.
+const BUFFER_SIZE: usize = 32768;
+
+/// A network interface, from an `RTM_NEWLINK` message.
+pub(super) struct Link {
+    pub(super) index: u32,
+    /// The `ARPHRD_*` hardware type.
+    pub(super) kind: u16,
+    /// The `IFF_*` flags.
+    pub(super) flags: u32,
+    pub(super) name: Vec<u8>,
+    pub(super) address: Vec<u8>,
+    pub(super) broadcast: Vec<u8>,
+    /// The `struct rtnl_link_stats` from `IFLA_STATS`.
+    pub(super) stats: Vec<u8>,
+}
+
+/// An IPv4 or IPv6 address of an interface, from an `RTM_NEWADDR` message.
+pub(super) struct Address {
+    pub(super) family: u8,
+    pub(super) prefix_len: u8,
+    pub(super) index: u32,
+    /// `IFA_ADDRESS`, which is the peer address on point-to-point links.
+    pub(super) address: Option<Vec<u8>>,
+    /// `IFA_LOCAL`, which is the local address if it's present.
+    pub(super) local: Option<Vec<u8>>,
+    pub(super) broadcast: Option<Vec<u8>>,
+    pub(super) label: Option<Vec<u8>>,
+}
+
+/// List the network interfaces.
+pub(super) fn links() -> io::Result<Vec<Link>> {
+    let mut links = Vec::new();
+    dump(libc::RTM_GETLINK, libc::RTM_NEWLINK, |body| {
+        if body.len() < size_of::<libc::ifinfomsg>() {
+            return Err(Errno::IO);
+        }
+        // SAFETY: We checked the length, and `ifinfomsg` is plain data.
+        let info = unsafe { read_unaligned(body.as_ptr().cast::<libc::ifinfomsg>()) };
+        let mut link = Link {
+            index: info.ifi_index as u32,
+            kind: info.ifi_type,
+            flags: info.ifi_flags,
+            name: Vec::new(),
+            address: Vec::new(),
+            broadcast: Vec::new(),
+            stats: Vec::new(),
+        };
+        for (kind, payload) in attributes(&body[size_of::<libc::ifinfomsg>()..]) {
+            match kind {
+                libc::IFLA_IFNAME => link.name = c_str(payload).to_vec(),
+                libc::IFLA_ADDRESS => link.address = payload.to_vec(),
+                libc::IFLA_BROADCAST => link.broadcast = payload.to_vec(),
+                libc::IFLA_STATS => link.stats = payload.to_vec(),
+                _ => {}
+            }
+        }
+        links.push(link);
+        Ok(())
+    })?;
+    Ok(links)
+}
+
+/// List the IPv4 and IPv6 addresses of every network interface.
+pub(super) fn addresses() -> io::Result<Vec<Address>> {
+    let mut addresses = Vec::new();
+    dump(libc::RTM_GETADDR, libc::RTM_NEWADDR, |body| {
+        if body.len() < size_of::<libc::ifaddrmsg>() {
+            return Err(Errno::IO);
+        }
+        // SAFETY: We checked the length, and `ifaddrmsg` is plain data.
+        let msg = unsafe { read_unaligned(body.as_ptr().cast::<libc::ifaddrmsg>()) };
+        let family = i32::from(msg.ifa_family);
+        if family != libc::AF_INET && family != libc::AF_INET6 {
+            return Ok(());
+        }
+        let mut address = Address {
+            family: msg.ifa_family,
+            prefix_len: msg.ifa_prefixlen,
+            index: msg.ifa_index,
+            address: None,
+            local: None,
+            broadcast: None,
+            label: None,
+        };
+        for (kind, payload) in attributes(&body[size_of::<libc::ifaddrmsg>()..]) {
+            match kind {
+                libc::IFA_ADDRESS => address.address = Some(payload.to_vec()),
+                libc::IFA_LOCAL => address.local = Some(payload.to_vec()),
+                libc::IFA_BROADCAST => address.broadcast = Some(payload.to_vec()),
+                libc::IFA_LABEL => address.label = Some(c_str(payload).to_vec()),
+                _ => {}
+            }
+        }
+        addresses.push(address);
+        Ok(())
+    })?;
+    Ok(addresses)
+}
+
+/// Send a dump request of type `request`, and call `each` with the body of
+/// each reply of type `reply`.
+fn dump(request: u16, reply: u16, mut each: impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+    const SEQ: u32 = 1;
+
+    let fd: OwnedFd = rustix::net::socket_with(
+        AddressFamily::NETLINK,
+        SocketType::RAW,
+        SocketFlags::CLOEXEC,
+        None,
+    )?;
+
+    // A `nlmsghdr` followed by a `rtgenmsg` holding the address family,
+    // padded to a multiple of 4 bytes.
+    let header = libc::nlmsghdr {
+        nlmsg_len: (size_of::<libc::nlmsghdr>() + 4) as u32,
+        nlmsg_type: request,
+        nlmsg_flags: (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16,
+        nlmsg_seq: SEQ,
+        nlmsg_pid: 0,
+    };
+    let mut message = [0_u8; size_of::<libc::nlmsghdr>() + 4];
+    // SAFETY: `message` has room for a `nlmsghdr`.
+    unsafe {
+        message
+            .as_mut_ptr()
+            .cast::<libc::nlmsghdr>()
+            .write_unaligned(header)
+    };
+    message[size_of::<libc::nlmsghdr>()] = libc::AF_UNSPEC as u8;
+    rustix::net::send(&fd, &message, SendFlags::empty())?;
+
+    let mut buf = vec![0_u8; BUFFER_SIZE];
+    loop {
+        let (len, _) = rustix::net::recv(&fd, &mut buf[..], RecvFlags::empty())?;
+        let mut messages = &buf[..len];
+        while messages.len() >= size_of::<libc::nlmsghdr>() {
+            // SAFETY: We checked the length, and `nlmsghdr` is plain data.
+            let header = unsafe { read_unaligned(messages.as_ptr().cast::<libc::nlmsghdr>()) };
+            let len = header.nlmsg_len as usize;
+            if len < size_of::<libc::nlmsghdr>() || len > messages.len() {
+                return Err(Errno::IO);
+            }
+            let body = &messages[size_of::<libc::nlmsghdr>()..len];
+            messages = &messages[align(len).min(messages.len())..];
+
+            if header.nlmsg_seq != SEQ {
+                continue;
+            }
+            match i32::from(header.nlmsg_type) {
+                libc::NLMSG_DONE => return Ok(()),
+                libc::NLMSG_ERROR => {
+                    let Some(error) = body.get(..4) else {
+                        return Err(Errno::IO);
+                    };
+                    let error = i32::from_ne_bytes(error.try_into().unwrap());
+                    if error != 0 {
+                        return Err(Errno::from_raw_os_error(-error));
+                    }
+                }
+                _ if header.nlmsg_type == reply => each(body)?,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Iterate over the `rtattr` attributes in `bytes`, yielding their types and
+/// payloads.
+fn attributes(mut bytes: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    core::iter::from_fn(move || {
+        if bytes.len() < size_of::<libc::rtattr>() {
+            return None;
+        }
+        // SAFETY: We checked the length, and `rtattr` is plain data.
+        let attr = unsafe { read_unaligned(bytes.as_ptr().cast::<libc::rtattr>()) };
+        let len = usize::from(attr.rta_len);
+        if len < size_of::<libc::rtattr>() || len > bytes.len() {
+            return None;
+        }
+        let payload = &bytes[size_of::<libc::rtattr>()..len];
+        bytes = &bytes[align(len).min(bytes.len())..];
+        Some((attr.rta_type, payload))
+    })
+}
+
+/// Round `len` up to the 4-byte alignment of netlink messages and
+/// attributes.
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Return the part of `bytes` before the first NUL.
+fn c_str(bytes: &[u8]) -> &[u8] {
+    match bytes.iter().position(|b| *b == 0) {
+        Some(nul) => &bytes[..nul],
+        None => bytes,
+    }
+}
@@ -1,7 +1,25 @@
-use libc::gid_t;
+use super::setxid::{set_xid, SetXid};
+use crate::convert_res;
+use errno::{set_errno, Errno};
+use libc::{c_int, gid_t};
 
 #[no_mangle]
 unsafe extern "C" fn getegid() -> gid_t {
     libc!(libc::getegid());
     rustix::process::getegid().as_raw()
 }
+
+#[no_mangle]
+unsafe extern "C" fn setegid(egid: gid_t) -> c_int {
+    libc!(libc::setegid(egid));
+
+    if egid == !0 {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+
+    match convert_res(set_xid(SetXid::ResGid(!0, egid, !0))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
@@ -1,7 +1,39 @@
-use libc::uid_t;
+use super::setxid::{set_xid, SetXid};
+use crate::convert_res;
+use errno::{set_errno, Errno};
+use libc::{c_int, uid_t};
 
 #[no_mangle]
 unsafe extern "C" fn geteuid() -> uid_t {
     libc!(libc::geteuid());
     rustix::process::geteuid().as_raw()
 }
+
+#[no_mangle]
+unsafe extern "C" fn seteuid(euid: uid_t) -> c_int {
+    libc!(libc::seteuid(euid));
+
+    if euid == !0 {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+
+    match convert_res(set_xid(SetXid::ResUid(!0, euid, !0))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seteuid() {
+        unsafe {
+            // Setting the effective ID to its current value always succeeds.
+            assert_eq!(seteuid(geteuid()), 0);
+            assert_eq!(geteuid(), rustix::process::geteuid().as_raw());
+        }
+    }
+}
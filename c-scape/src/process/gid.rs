@@ -1,3 +1,7 @@
+use super::setxid::{set_xid, SetXid, SYS_GETRESGID, SYS_SETFSGID};
+use crate::convert_res;
+use crate::raw_syscall::{syscall1, syscall3};
+use core::ptr::addr_of_mut;
 use libc::{c_int, gid_t};
 
 #[no_mangle]
@@ -7,11 +11,68 @@ unsafe extern "C" fn getgid() -> gid_t {
 }
 
 #[no_mangle]
-unsafe extern "C" fn setgid(_gid: gid_t) -> c_int {
-    libc!(libc::setgid(_gid));
+unsafe extern "C" fn setgid(gid: gid_t) -> c_int {
+    libc!(libc::setgid(gid));
 
     // rustix has a `set_thread_gid` function, but it just wraps the Linux
     // syscall which sets a per-thread GID rather than the whole process GID.
     // Linux expects libc's to have logic to set the GID for all the threads.
-    todo!("setgid")
+    match convert_res(set_xid(SetXid::Gid(gid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn setregid(rgid: gid_t, egid: gid_t) -> c_int {
+    libc!(libc::setregid(rgid, egid));
+
+    match convert_res(set_xid(SetXid::ReGid(rgid, egid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn setresgid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> c_int {
+    libc!(libc::setresgid(rgid, egid, sgid));
+
+    match convert_res(set_xid(SetXid::ResGid(rgid, egid, sgid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn getresgid(rgid: *mut gid_t, egid: *mut gid_t, sgid: *mut gid_t) -> c_int {
+    libc!(libc::getresgid(rgid, egid, sgid));
+
+    let mut ids: [gid_t; 3] = [0; 3];
+    match convert_res(syscall3(
+        SYS_GETRESGID,
+        addr_of_mut!(ids[0]).expose_provenance(),
+        addr_of_mut!(ids[1]).expose_provenance(),
+        addr_of_mut!(ids[2]).expose_provenance(),
+    )) {
+        Some(_) => {
+            rgid.write(ids[0]);
+            egid.write(ids[1]);
+            sgid.write(ids[2]);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[no_mangle]
+unsafe extern "C" fn setfsgid(gid: gid_t) -> c_int {
+    //libc!(libc::setfsgid(gid));
+
+    // The filesystem GID is meant to be per-thread, and this returns the
+    // previous value rather than reporting errors.
+    match syscall1(SYS_SETFSGID, gid as usize) {
+        Ok(old) => old as c_int,
+        Err(_) => -1,
+    }
 }
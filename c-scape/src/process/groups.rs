@@ -39,7 +39,13 @@ unsafe extern "C" fn getgroups(num: c_int, groups: *mut libc::gid_t) -> c_int {
 
 #[cfg(any(target_os = "android", target_os = "linux"))]
 #[no_mangle]
-unsafe extern "C" fn setgroups() {
-    //libc!(libc::setgroups());
-    todo!("setgroups")
+unsafe extern "C" fn setgroups(size: usize, list: *const libc::gid_t) -> c_int {
+    libc!(libc::setgroups(size, list));
+
+    match convert_res(super::setxid::set_xid(super::setxid::SetXid::Groups(
+        list, size,
+    ))) {
+        Some(()) => 0,
+        None => -1,
+    }
 }
@@ -1,9 +1,12 @@
 use rustix::process::{Pid, Signal};
 
+use core::mem::zeroed;
+use core::ptr::{addr_of, addr_of_mut};
 use errno::{set_errno, Errno};
-use libc::{c_int, pid_t};
+use libc::{c_int, pid_t, siginfo_t, sigval, uid_t, SYS_rt_sigqueueinfo, SI_QUEUE};
 
 use crate::convert_res;
+use crate::raw_syscall::syscall3;
 
 #[no_mangle]
 unsafe extern "C" fn kill(pid: pid_t, sig: c_int) -> c_int {
@@ -51,3 +54,55 @@ unsafe extern "C" fn killpg(pgid: pid_t, sig: c_int) -> c_int {
 
     kill(-pgid, sig)
 }
+
+/// The prefix of `siginfo_t` that `rt_sigqueueinfo` reads for `SI_QUEUE`.
+#[repr(C)]
+struct QueueSiginfo {
+    si_signo: c_int,
+    si_errno: c_int,
+    si_code: c_int,
+    // This is a struct so that it's aligned like the union in `siginfo_t`.
+    rt: QueueSiginfoRt,
+}
+
+#[repr(C)]
+struct QueueSiginfoRt {
+    si_pid: pid_t,
+    si_uid: uid_t,
+    si_value: sigval,
+}
+
+#[no_mangle]
+unsafe extern "C" fn sigqueue(pid: pid_t, sig: c_int, value: sigval) -> c_int {
+    libc!(libc::sigqueue(pid, sig, value));
+
+    queue_signal(pid, sig, SI_QUEUE, value)
+}
+
+/// Queue `sig` for `pid` with the given `si_code`, which is `SI_QUEUE` for
+/// `sigqueue`, and `SI_ASYNCIO` for asynchronous I/O completions.
+pub(crate) unsafe fn queue_signal(pid: pid_t, sig: c_int, code: c_int, value: sigval) -> c_int {
+    let mut info: siginfo_t = zeroed();
+    addr_of_mut!(info)
+        .cast::<QueueSiginfo>()
+        .write(QueueSiginfo {
+            si_signo: sig,
+            si_errno: 0,
+            si_code: code,
+            rt: QueueSiginfoRt {
+                si_pid: rustix::process::getpid().as_raw_nonzero().get(),
+                si_uid: rustix::process::getuid().as_raw(),
+                si_value: value,
+            },
+        });
+
+    match convert_res(syscall3(
+        SYS_rt_sigqueueinfo,
+        pid as usize,
+        sig as usize,
+        addr_of!(info).expose_provenance(),
+    )) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
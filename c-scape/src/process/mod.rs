@@ -7,11 +7,12 @@ mod exec;
 mod getcwd;
 mod gid;
 mod groups;
-mod kill;
+pub(crate) mod kill;
 mod pid;
 mod pidfd;
 mod priority;
 mod rlimit;
+pub(crate) mod setxid;
 mod sid;
 mod system;
 mod uid;
@@ -0,0 +1,94 @@
+//! Linux's system calls for setting user and group IDs only set the IDs for
+//! the calling thread, and POSIX says they should apply to the whole process.
+//!
+//! With c-scape managing threads, [`set_xid`] applies a change on every
+//! thread in the process; see `thread/setxid.rs` for how. Otherwise, c-scape
+//! doesn't know about any other threads, so it just applies the change on
+//! the current thread.
+
+use libc::{gid_t, uid_t};
+use rustix::io;
+use rustix::process::{Gid, Uid};
+
+/// A change to a thread's IDs, corresponding to one of the `set*id`
+/// functions.
+#[derive(Clone, Copy)]
+pub(crate) enum SetXid {
+    Uid(uid_t),
+    Gid(gid_t),
+    ReUid(uid_t, uid_t),
+    ReGid(gid_t, gid_t),
+    ResUid(uid_t, uid_t, uid_t),
+    ResGid(gid_t, gid_t, gid_t),
+    Groups(*const gid_t, usize),
+}
+
+impl SetXid {
+    /// Apply this change to the current thread.
+    ///
+    /// This is called from a signal handler, so it must be async-signal-safe.
+    pub(crate) unsafe fn apply(self) -> io::Result<()> {
+        match self {
+            Self::Uid(uid) => rustix::thread::set_thread_uid(Uid::from_raw(uid)),
+            Self::Gid(gid) => rustix::thread::set_thread_gid(Gid::from_raw(gid)),
+            Self::ReUid(ruid, euid) => {
+                crate::raw_syscall::syscall2(SYS_SETREUID, ruid as usize, euid as usize).map(|_| ())
+            }
+            Self::ReGid(rgid, egid) => {
+                crate::raw_syscall::syscall2(SYS_SETREGID, rgid as usize, egid as usize).map(|_| ())
+            }
+            // `-1` means "unchanged" here, which rustix's `Uid` and `Gid`
+            // don't allow, so use the raw syscalls.
+            Self::ResUid(ruid, euid, suid) => crate::raw_syscall::syscall3(
+                SYS_SETRESUID,
+                ruid as usize,
+                euid as usize,
+                suid as usize,
+            )
+            .map(|_| ()),
+            Self::ResGid(rgid, egid, sgid) => crate::raw_syscall::syscall3(
+                SYS_SETRESGID,
+                rgid as usize,
+                egid as usize,
+                sgid as usize,
+            )
+            .map(|_| ()),
+            Self::Groups(list, size) => {
+                let list = if size == 0 {
+                    &[]
+                } else {
+                    core::slice::from_raw_parts(list.cast::<Gid>(), size)
+                };
+                rustix::thread::set_thread_groups(list)
+            }
+        }
+    }
+}
+
+// On 32-bit x86 and ARM, the syscalls without the `32` suffix take 16-bit IDs.
+#[cfg(not(any(target_arch = "x86", target_arch = "arm")))]
+pub(crate) use libc::{
+    SYS_getresgid as SYS_GETRESGID, SYS_getresuid as SYS_GETRESUID, SYS_setfsgid as SYS_SETFSGID,
+    SYS_setfsuid as SYS_SETFSUID, SYS_setregid as SYS_SETREGID, SYS_setresgid as SYS_SETRESGID,
+    SYS_setresuid as SYS_SETRESUID, SYS_setreuid as SYS_SETREUID,
+};
+#[cfg(any(target_arch = "x86", target_arch = "arm"))]
+pub(crate) use libc::{
+    SYS_getresgid32 as SYS_GETRESGID, SYS_getresuid32 as SYS_GETRESUID,
+    SYS_setfsgid32 as SYS_SETFSGID, SYS_setfsuid32 as SYS_SETFSUID, SYS_setregid32 as SYS_SETREGID,
+    SYS_setresgid32 as SYS_SETRESGID, SYS_setresuid32 as SYS_SETRESUID,
+    SYS_setreuid32 as SYS_SETREUID,
+};
+
+/// Apply `op` to every thread in the process.
+pub(crate) unsafe fn set_xid(op: SetXid) -> io::Result<()> {
+    #[cfg(all(feature = "take-charge", feature = "thread"))]
+    {
+        crate::thread::setxid::broadcast(op)
+    }
+
+    #[cfg(not(all(feature = "take-charge", feature = "thread")))]
+    {
+        op.apply()
+    }
+}
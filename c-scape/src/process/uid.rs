@@ -1,3 +1,7 @@
+use super::setxid::{set_xid, SetXid, SYS_GETRESUID, SYS_SETFSUID};
+use crate::convert_res;
+use crate::raw_syscall::{syscall1, syscall3};
+use core::ptr::addr_of_mut;
 use libc::{c_int, uid_t};
 
 #[no_mangle]
@@ -13,5 +17,83 @@ unsafe extern "C" fn setuid(uid: uid_t) -> c_int {
     // rustix has a `set_thread_uid` function, but it just wraps the Linux
     // syscall which sets a per-thread UID rather than the whole process UID.
     // Linux expects libc's to have logic to set the UID for all the threads.
-    todo!("setuid")
+    match convert_res(set_xid(SetXid::Uid(uid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn setreuid(ruid: uid_t, euid: uid_t) -> c_int {
+    libc!(libc::setreuid(ruid, euid));
+
+    match convert_res(set_xid(SetXid::ReUid(ruid, euid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn setresuid(ruid: uid_t, euid: uid_t, suid: uid_t) -> c_int {
+    libc!(libc::setresuid(ruid, euid, suid));
+
+    match convert_res(set_xid(SetXid::ResUid(ruid, euid, suid))) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn getresuid(ruid: *mut uid_t, euid: *mut uid_t, suid: *mut uid_t) -> c_int {
+    libc!(libc::getresuid(ruid, euid, suid));
+
+    let mut ids: [uid_t; 3] = [0; 3];
+    match convert_res(syscall3(
+        SYS_GETRESUID,
+        addr_of_mut!(ids[0]).expose_provenance(),
+        addr_of_mut!(ids[1]).expose_provenance(),
+        addr_of_mut!(ids[2]).expose_provenance(),
+    )) {
+        Some(_) => {
+            ruid.write(ids[0]);
+            euid.write(ids[1]);
+            suid.write(ids[2]);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[no_mangle]
+unsafe extern "C" fn setfsuid(uid: uid_t) -> c_int {
+    //libc!(libc::setfsuid(uid));
+
+    // The filesystem UID is meant to be per-thread, and this returns the
+    // previous value rather than reporting errors.
+    match syscall1(SYS_SETFSUID, uid as usize) {
+        Ok(old) => old as c_int,
+        Err(_) => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setresuid_unchanged() {
+        unsafe {
+            let mut ids: [uid_t; 3] = [0; 3];
+            assert_eq!(getresuid(&mut ids[0], &mut ids[1], &mut ids[2]), 0);
+
+            // `-1` leaves an ID unchanged.
+            assert_eq!(setresuid(!0, ids[1], !0), 0);
+            assert_eq!(setresuid(!0, !0, !0), 0);
+
+            let mut after: [uid_t; 3] = [0; 3];
+            assert_eq!(getresuid(&mut after[0], &mut after[1], &mut after[2]), 0);
+            assert_eq!(after, ids);
+        }
+    }
 }
@@ -1,7 +1,8 @@
 use crate::convert_res;
+#[cfg(not(target_os = "wasi"))]
+use crate::raw_syscall::{syscall1, syscall2, syscall3};
 use core::ffi::CStr;
-use core::mem::{size_of, zeroed};
-use core::ptr::{addr_of, null_mut};
+use core::ptr;
 use errno::{set_errno, Errno};
 use libc::{c_char, c_int, c_long, c_ulong, c_void};
 
@@ -160,76 +161,6 @@ fn _pathconf(name: c_int) -> c_long {
     }
 }
 
-#[cfg(not(target_os = "wasi"))]
-#[no_mangle]
-unsafe extern "C" fn dl_iterate_phdr(
-    callback: Option<
-        unsafe extern "C" fn(
-            info: *mut libc::dl_phdr_info,
-            size: usize,
-            data: *mut c_void,
-        ) -> c_int,
-    >,
-    data: *mut c_void,
-) -> c_int {
-    extern "C" {
-        static mut __executable_start: c_void;
-    }
-
-    libc!(libc::dl_iterate_phdr(callback, data));
-
-    let (phdr, _phent, phnum) = rustix::runtime::exe_phdrs();
-    let mut info = libc::dl_phdr_info {
-        dlpi_addr: addr_of!(__executable_start).expose_provenance() as _,
-        dlpi_name: c"/proc/self/exe".as_ptr(),
-        dlpi_phdr: phdr.cast(),
-        dlpi_phnum: phnum.try_into().unwrap(),
-        ..zeroed()
-    };
-    callback.unwrap()(&mut info, size_of::<libc::dl_phdr_info>(), data)
-}
-
-#[cfg(not(target_os = "wasi"))]
-#[no_mangle]
-unsafe extern "C" fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void {
-    libc!(libc::dlsym(handle, symbol));
-
-    let symbol = CStr::from_ptr(symbol.cast());
-
-    if handle == libc::RTLD_DEFAULT {
-        // `std` uses `dlsym` to dynamically detect feature availability; recognize
-        // functions it asks for.
-        match symbol.to_bytes() {
-            #[cfg(any(target_os = "android", target_os = "linux"))]
-            #[cfg(not(target_env = "musl"))]
-            b"statx" => libc::statx as _,
-            #[cfg(any(target_os = "android", target_os = "linux"))]
-            b"getrandom" => libc::getrandom as _,
-            #[cfg(any(target_os = "android", target_os = "linux"))]
-            b"copy_file_range" => libc::copy_file_range as _,
-            #[cfg(target_env = "gnu")]
-            b"gnu_get_libc_version" => libc::gnu_get_libc_version as _,
-            #[cfg(any(target_os = "android", target_os = "linux"))]
-            b"epoll_create1" => libc::epoll_create1 as _,
-            b"pipe2" => libc::pipe2 as _,
-
-            // Let's just say we don't support this for now.
-            #[cfg(any(target_os = "android", target_os = "linux"))]
-            b"clone3" => null_mut(),
-            // Let's just say we don't support this for now.
-            b"__pthread_get_minstack" => null_mut(),
-
-            _ => unimplemented!("dlsym(_, {:?})", symbol),
-        }
-    } else if handle == libc::RTLD_NEXT {
-        // We don't support any dynamic linking, so there's no "next" dynamic
-        // library.
-        null_mut()
-    } else {
-        unimplemented!("dlsym with a handle")
-    }
-}
-
 #[no_mangle]
 unsafe extern "C" fn sched_yield() -> c_int {
     libc!(libc::sched_yield());
@@ -253,13 +184,7 @@ unsafe extern "C" fn sched_getaffinity(
         None => return -1,
     };
 
-    mask.write(core::mem::zeroed());
-    libc::CPU_ZERO(&mut *mask);
-    for i in 0..core::cmp::min(rustix::thread::CpuSet::MAX_CPU, cpu_set_size * 8) {
-        if set.is_set(i) {
-            libc::CPU_SET(i, &mut *mask);
-        }
-    }
+    cpu_set_to_libc(&set, cpu_set_size, mask);
     0
 }
 
@@ -272,6 +197,36 @@ unsafe extern "C" fn sched_setaffinity(
 ) -> c_int {
     libc!(libc::sched_setaffinity(pid, cpu_set_size, mask));
 
+    let set = cpu_set_from_libc(cpu_set_size, mask);
+    let pid = rustix::process::Pid::from_raw(pid as _);
+    match convert_res(rustix::thread::sched_setaffinity(pid, &set)) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Convert a rustix `CpuSet` into a `cpu_set_t` of `cpu_set_size` bytes.
+#[cfg(not(target_os = "wasi"))]
+pub(crate) unsafe fn cpu_set_to_libc(
+    set: &rustix::thread::CpuSet,
+    cpu_set_size: libc::size_t,
+    mask: *mut libc::cpu_set_t,
+) {
+    mask.write(core::mem::zeroed());
+    libc::CPU_ZERO(&mut *mask);
+    for i in 0..core::cmp::min(rustix::thread::CpuSet::MAX_CPU, cpu_set_size * 8) {
+        if set.is_set(i) {
+            libc::CPU_SET(i, &mut *mask);
+        }
+    }
+}
+
+/// Convert a `cpu_set_t` of `cpu_set_size` bytes into a rustix `CpuSet`.
+#[cfg(not(target_os = "wasi"))]
+pub(crate) unsafe fn cpu_set_from_libc(
+    cpu_set_size: libc::size_t,
+    mask: *const libc::cpu_set_t,
+) -> rustix::thread::CpuSet {
     let mut set = rustix::thread::CpuSet::new();
     let mask = &*mask;
     for i in 0..core::cmp::min(rustix::thread::CpuSet::MAX_CPU, cpu_set_size * 8) {
@@ -279,12 +234,7 @@ unsafe extern "C" fn sched_setaffinity(
             set.set(i);
         }
     }
-
-    let pid = rustix::process::Pid::from_raw(pid as _);
-    match convert_res(rustix::thread::sched_setaffinity(pid, &set)) {
-        Some(()) => 0,
-        None => -1,
-    }
+    set
 }
 
 #[cfg(not(target_os = "wasi"))]
@@ -326,6 +276,151 @@ unsafe extern "C" fn sched_getcpu() -> c_int {
     rustix::thread::sched_getcpu() as _
 }
 
+// rustix doesn't wrap the scheduling-policy syscalls, so we make them
+// ourselves. These are also used by the `pthread_*sched*` functions, which
+// pass thread ids in place of process ids.
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn set_scheduler(
+    pid: libc::pid_t,
+    policy: c_int,
+    param: &libc::sched_param,
+) -> rustix::io::Result<()> {
+    unsafe {
+        syscall3(
+            libc::SYS_sched_setscheduler,
+            pid as usize,
+            policy as usize,
+            ptr::from_ref(param).expose_provenance(),
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn get_scheduler(pid: libc::pid_t) -> rustix::io::Result<c_int> {
+    unsafe { syscall1(libc::SYS_sched_getscheduler, pid as usize).map(|policy| policy as c_int) }
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn set_param(pid: libc::pid_t, param: &libc::sched_param) -> rustix::io::Result<()> {
+    unsafe {
+        syscall2(
+            libc::SYS_sched_setparam,
+            pid as usize,
+            ptr::from_ref(param).expose_provenance(),
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn get_param(pid: libc::pid_t) -> rustix::io::Result<libc::sched_param> {
+    let mut param = libc::sched_param { sched_priority: 0 };
+    unsafe {
+        syscall2(
+            libc::SYS_sched_getparam,
+            pid as usize,
+            ptr::from_mut(&mut param).expose_provenance(),
+        )?;
+    }
+    Ok(param)
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn priority_min(policy: c_int) -> rustix::io::Result<c_int> {
+    unsafe { syscall1(libc::SYS_sched_get_priority_min, policy as usize).map(|prio| prio as c_int) }
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn priority_max(policy: c_int) -> rustix::io::Result<c_int> {
+    unsafe { syscall1(libc::SYS_sched_get_priority_max, policy as usize).map(|prio| prio as c_int) }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_setscheduler(
+    pid: libc::pid_t,
+    policy: c_int,
+    param: *const libc::sched_param,
+) -> c_int {
+    libc!(libc::sched_setscheduler(pid, policy, param));
+
+    match convert_res(set_scheduler(pid, policy, &*param)) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_getscheduler(pid: libc::pid_t) -> c_int {
+    libc!(libc::sched_getscheduler(pid));
+
+    convert_res(get_scheduler(pid)).unwrap_or(-1)
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_setparam(pid: libc::pid_t, param: *const libc::sched_param) -> c_int {
+    libc!(libc::sched_setparam(pid, param));
+
+    match convert_res(set_param(pid, &*param)) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_getparam(pid: libc::pid_t, param: *mut libc::sched_param) -> c_int {
+    libc!(libc::sched_getparam(pid, param));
+
+    match convert_res(get_param(pid)) {
+        Some(value) => {
+            param.write(value);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_get_priority_min(policy: c_int) -> c_int {
+    libc!(libc::sched_get_priority_min(policy));
+
+    convert_res(priority_min(policy)).unwrap_or(-1)
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_get_priority_max(policy: c_int) -> c_int {
+    libc!(libc::sched_get_priority_max(policy));
+
+    convert_res(priority_max(policy)).unwrap_or(-1)
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[no_mangle]
+unsafe extern "C" fn sched_rr_get_interval(
+    pid: libc::pid_t,
+    interval: *mut libc::timespec,
+) -> c_int {
+    libc!(libc::sched_rr_get_interval(pid, interval));
+
+    // On 32-bit platforms, this is the syscall that uses the same 32-bit
+    // `timespec` as `libc::timespec`.
+    match convert_res(syscall2(
+        libc::SYS_sched_rr_get_interval,
+        pid as usize,
+        interval.expose_provenance(),
+    )) {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
 // In Linux, `prctl`'s arguments are described as `unsigned long`, however we
 // use pointer types in order to preserve provenance.
 #[cfg(any(target_os = "android", target_os = "linux"))]
@@ -0,0 +1,145 @@
+//! Raw syscalls, for the few things rustix doesn't wrap.
+//!
+//! Arguments are passed as `usize`; callers passing pointers should use
+//! `expose_provenance`, since the kernel may access the memory they point to.
+
+use core::arch::asm;
+use libc::c_long;
+use rustix::io::{Errno, Result};
+
+/// Make a syscall with up to six arguments, passing zero for any the
+/// syscall doesn't use.
+pub(crate) unsafe fn syscall6(
+    nr: c_long,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+) -> Result<usize> {
+    let nr = nr as usize;
+    let ret: usize;
+
+    #[cfg(target_arch = "x86_64")]
+    asm!(
+        "syscall",
+        inlateout("rax") nr => ret,
+        in("rdi") a0,
+        in("rsi") a1,
+        in("rdx") a2,
+        in("r10") a3,
+        in("r8") a4,
+        in("r9") a5,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack, preserves_flags)
+    );
+
+    // LLVM reserves `esi` and `ebp`, so we save them ourselves, and pass
+    // their values, along with the syscall number, in memory.
+    #[cfg(target_arch = "x86")]
+    {
+        let rest = [a3, a5, nr];
+        asm!(
+            "push ebp",
+            "push esi",
+            "mov esi, [eax]",
+            "mov ebp, [eax + 4]",
+            "mov eax, [eax + 8]",
+            "int 0x80",
+            "pop esi",
+            "pop ebp",
+            inlateout("eax") rest.as_ptr() => ret,
+            in("ebx") a0,
+            in("ecx") a1,
+            in("edx") a2,
+            in("edi") a4,
+            options(preserves_flags)
+        );
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    asm!(
+        "svc 0",
+        in("x8") nr,
+        inlateout("x0") a0 => ret,
+        in("x1") a1,
+        in("x2") a2,
+        in("x3") a3,
+        in("x4") a4,
+        in("x5") a5,
+        options(nostack, preserves_flags)
+    );
+
+    #[cfg(target_arch = "riscv64")]
+    asm!(
+        "ecall",
+        in("a7") nr,
+        inlateout("a0") a0 => ret,
+        in("a1") a1,
+        in("a2") a2,
+        in("a3") a3,
+        in("a4") a4,
+        in("a5") a5,
+        options(nostack, preserves_flags)
+    );
+
+    #[cfg(target_arch = "arm")]
+    asm!(
+        "svc 0",
+        in("r7") nr,
+        inlateout("r0") a0 => ret,
+        in("r1") a1,
+        in("r2") a2,
+        in("r3") a3,
+        in("r4") a4,
+        in("r5") a5,
+        options(nostack, preserves_flags)
+    );
+
+    // The kernel returns errors as values in `-4095..0`.
+    if ret > -4096_isize as usize {
+        Err(Errno::from_raw_os_error(-(ret as isize) as i32))
+    } else {
+        Ok(ret)
+    }
+}
+
+#[inline]
+pub(crate) unsafe fn syscall1(nr: c_long, a0: usize) -> Result<usize> {
+    syscall4(nr, a0, 0, 0, 0)
+}
+
+#[inline]
+pub(crate) unsafe fn syscall2(nr: c_long, a0: usize, a1: usize) -> Result<usize> {
+    syscall4(nr, a0, a1, 0, 0)
+}
+
+#[inline]
+pub(crate) unsafe fn syscall3(nr: c_long, a0: usize, a1: usize, a2: usize) -> Result<usize> {
+    syscall4(nr, a0, a1, a2, 0)
+}
+
+#[inline]
+pub(crate) unsafe fn syscall4(
+    nr: c_long,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+) -> Result<usize> {
+    syscall6(nr, a0, a1, a2, a3, 0, 0)
+}
+
+#[inline]
+pub(crate) unsafe fn syscall5(
+    nr: c_long,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+) -> Result<usize> {
+    syscall6(nr, a0, a1, a2, a3, a4, 0)
+}
@@ -0,0 +1,301 @@
+//! `hsearch` and friends.
+//!
+//! This is the classic open-addressing table from Knuth that glibc uses, with
+//! a prime size and double hashing.
+
+use core::ffi::CStr;
+use core::mem::size_of;
+use core::ptr::null_mut;
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use rustix_futex_sync::Mutex;
+
+// glibc's `ACTION` value for inserting; the other, `FIND`, is zero.
+const ENTER: c_int = 1;
+
+/// glibc's `ENTRY`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Entry {
+    key: *mut c_char,
+    data: *mut c_void,
+}
+
+/// An entry in the table, with the hash of its key, or zero if it's unused.
+#[repr(C)]
+struct Slot {
+    used: c_uint,
+    entry: Entry,
+}
+
+/// glibc's `struct hsearch_data`.
+#[repr(C)]
+struct HSearchData {
+    table: *mut Slot,
+    size: c_uint,
+    filled: c_uint,
+}
+
+/// The table used by `hcreate`, `hsearch`, and `hdestroy`.
+struct Table(HSearchData);
+
+unsafe impl Send for Table {}
+
+static TABLE: Mutex<Table> = Mutex::new(Table(HSearchData {
+    table: null_mut(),
+    size: 0,
+    filled: 0,
+}));
+
+#[no_mangle]
+unsafe extern "C" fn hcreate(nel: size_t) -> c_int {
+    //libc!(libc::hcreate(nel));
+
+    hcreate_r(nel, &mut TABLE.lock().0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hsearch(item: Entry, action: c_int) -> *mut Entry {
+    //libc!(libc::hsearch(item, action));
+
+    let mut retval = null_mut();
+    hsearch_r(item, action, &mut retval, &mut TABLE.lock().0);
+    retval
+}
+
+#[no_mangle]
+unsafe extern "C" fn hdestroy() {
+    //libc!(libc::hdestroy());
+
+    hdestroy_r(&mut TABLE.lock().0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hcreate_r(nel: size_t, htab: *mut HSearchData) -> c_int {
+    //libc!(libc::hcreate_r(nel, htab));
+
+    if htab.is_null() {
+        set_errno(Errno(libc::EINVAL));
+        return 0;
+    }
+
+    // If there's already a table, leave it in place.
+    if !(*htab).table.is_null() {
+        return 0;
+    }
+
+    // Round the size up to an odd prime, which the double hashing relies on
+    // to visit every slot.
+    let mut size = nel.max(3) | 1;
+    while size <= (c_uint::MAX - 2) as size_t && !is_prime(size) {
+        size += 2;
+    }
+    if size > (c_uint::MAX - 2) as size_t {
+        set_errno(Errno(libc::ENOMEM));
+        return 0;
+    }
+    let size = size as c_uint;
+
+    // Slot zero is unused, so that the hashes can start at one.
+    let table = libc::calloc(size as usize + 1, size_of::<Slot>());
+    if table.is_null() {
+        return 0;
+    }
+    (*htab).table = table.cast();
+    (*htab).size = size;
+    (*htab).filled = 0;
+    1
+}
+
+#[no_mangle]
+unsafe extern "C" fn hsearch_r(
+    item: Entry,
+    action: c_int,
+    retval: *mut *mut Entry,
+    htab: *mut HSearchData,
+) -> c_int {
+    //libc!(libc::hsearch_r(item, action, retval, htab));
+
+    let htab = &mut *htab;
+    let key = CStr::from_ptr(item.key).to_bytes();
+
+    let mut hval = key.len() as c_uint;
+    for c in key.iter().rev() {
+        hval = (hval << 4).wrapping_add(*c as c_char as c_uint);
+    }
+    if hval == 0 {
+        hval = 1;
+    }
+
+    let matches = |idx: c_uint| {
+        let slot = &*htab.table.add(idx as usize);
+        slot.used == hval && CStr::from_ptr(slot.entry.key).to_bytes() == key
+    };
+
+    let mut idx = hval % htab.size + 1;
+    if (*htab.table.add(idx as usize)).used != 0 {
+        if matches(idx) {
+            *retval = &mut (*htab.table.add(idx as usize)).entry;
+            return 1;
+        }
+
+        // Probe with a second hash, which, since the size is prime, visits
+        // every slot before returning to the first.
+        let hval2 = 1 + hval % (htab.size - 2);
+        let first_idx = idx;
+        loop {
+            if idx <= hval2 {
+                idx = htab.size + idx - hval2;
+            } else {
+                idx -= hval2;
+            }
+            if idx == first_idx {
+                break;
+            }
+            if matches(idx) {
+                *retval = &mut (*htab.table.add(idx as usize)).entry;
+                return 1;
+            }
+            if (*htab.table.add(idx as usize)).used == 0 {
+                break;
+            }
+        }
+    }
+
+    if action == ENTER {
+        if htab.filled == htab.size {
+            set_errno(Errno(libc::ENOMEM));
+            *retval = null_mut();
+            return 0;
+        }
+        let slot = &mut *htab.table.add(idx as usize);
+        slot.used = hval;
+        slot.entry = item;
+        htab.filled += 1;
+        *retval = &mut slot.entry;
+        return 1;
+    }
+
+    set_errno(Errno(libc::ESRCH));
+    *retval = null_mut();
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn hdestroy_r(htab: *mut HSearchData) {
+    //libc!(libc::hdestroy_r(htab));
+
+    if htab.is_null() {
+        set_errno(Errno(libc::EINVAL));
+        return;
+    }
+
+    libc::free((*htab).table.cast());
+    (*htab).table = null_mut();
+}
+
+fn is_prime(n: size_t) -> bool {
+    let mut divisor = 3;
+    while divisor <= n / divisor {
+        if n.is_multiple_of(divisor) {
+            return false;
+        }
+        divisor += 2;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::ffi::CString;
+    use alloc::format;
+    use alloc::vec::Vec;
+    use core::ptr::with_exposed_provenance_mut;
+    use errno::errno;
+
+    const FIND: c_int = 0;
+
+    fn entry(key: &CString, data: usize) -> Entry {
+        Entry {
+            key: key.as_ptr().cast_mut(),
+            data: with_exposed_provenance_mut(data),
+        }
+    }
+
+    #[test]
+    fn test_hsearch_r() {
+        unsafe {
+            let mut htab = HSearchData {
+                table: null_mut(),
+                size: 0,
+                filled: 0,
+            };
+            assert_eq!(hcreate_r(10, &mut htab), 1);
+            // The size is rounded up to a prime.
+            assert_eq!(htab.size, 11);
+
+            let keys = (0..12)
+                .map(|i| CString::new(format!("key{i}")).unwrap())
+                .collect::<Vec<_>>();
+            let mut retval = null_mut();
+            for (i, key) in keys[..11].iter().enumerate() {
+                assert_eq!(hsearch_r(entry(key, i), ENTER, &mut retval, &mut htab), 1);
+                assert_eq!((*retval).data.addr(), i);
+            }
+
+            // Entering an existing key finds the existing entry.
+            assert_eq!(
+                hsearch_r(entry(&keys[3], 100), ENTER, &mut retval, &mut htab),
+                1
+            );
+            assert_eq!((*retval).data.addr(), 3);
+
+            // Keys are compared by their contents.
+            for (i, key) in keys[..11].iter().enumerate() {
+                let copy = key.clone();
+                assert_eq!(hsearch_r(entry(&copy, 0), FIND, &mut retval, &mut htab), 1);
+                assert_eq!((*retval).data.addr(), i);
+            }
+
+            assert_eq!(
+                hsearch_r(entry(&keys[11], 0), FIND, &mut retval, &mut htab),
+                0
+            );
+            assert_eq!(errno().0, libc::ESRCH);
+            assert!(retval.is_null());
+
+            // The table is full.
+            assert_eq!(
+                hsearch_r(entry(&keys[11], 0), ENTER, &mut retval, &mut htab),
+                0
+            );
+            assert_eq!(errno().0, libc::ENOMEM);
+
+            hdestroy_r(&mut htab);
+            assert!(htab.table.is_null());
+        }
+    }
+
+    #[test]
+    fn test_hsearch() {
+        unsafe {
+            let apple = CString::new("apple").unwrap();
+            let pear = CString::new("pear").unwrap();
+            assert_eq!(hcreate(4), 1);
+            assert!(!hsearch(entry(&apple, 1), ENTER).is_null());
+            assert!(!hsearch(entry(&pear, 2), ENTER).is_null());
+            assert_eq!((*hsearch(entry(&apple, 0), FIND)).data.addr(), 1);
+            assert_eq!((*hsearch(entry(&pear, 0), FIND)).data.addr(), 2);
+            hdestroy();
+        }
+    }
+
+    #[test]
+    fn test_hcreate_r_null() {
+        unsafe {
+            assert_eq!(hcreate_r(10, null_mut()), 0);
+            assert_eq!(errno().0, libc::EINVAL);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use core::ptr::null_mut;
+use libc::c_void;
+
+/// glibc's `struct qelem`, which is all `insque` and `remque` assume about
+/// the elements.
+#[repr(C)]
+struct QElem {
+    q_forw: *mut QElem,
+    q_back: *mut QElem,
+}
+
+#[no_mangle]
+unsafe extern "C" fn insque(elem: *mut c_void, prev: *mut c_void) {
+    //libc!(libc::insque(elem, prev));
+
+    let elem = elem.cast::<QElem>();
+    let prev = prev.cast::<QElem>();
+
+    // A null `prev` starts a new linear list.
+    if prev.is_null() {
+        (*elem).q_forw = null_mut();
+        (*elem).q_back = null_mut();
+        return;
+    }
+
+    let next = (*prev).q_forw;
+    (*prev).q_forw = elem;
+    if !next.is_null() {
+        (*next).q_back = elem;
+    }
+    (*elem).q_forw = next;
+    (*elem).q_back = prev;
+}
+
+#[no_mangle]
+unsafe extern "C" fn remque(elem: *mut c_void) {
+    //libc!(libc::remque(elem));
+
+    let elem = elem.cast::<QElem>();
+    let next = (*elem).q_forw;
+    let prev = (*elem).q_back;
+    if !next.is_null() {
+        (*next).q_back = prev;
+    }
+    if !prev.is_null() {
+        (*prev).q_forw = next;
+    }
+}
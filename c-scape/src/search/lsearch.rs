@@ -0,0 +1,46 @@
+use core::ptr::{copy_nonoverlapping, null_mut};
+use libc::{c_int, c_void, size_t};
+
+#[no_mangle]
+unsafe extern "C" fn lfind(
+    key: *const c_void,
+    base: *const c_void,
+    nmemb: *mut size_t,
+    width: size_t,
+    compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>,
+) -> *mut c_void {
+    //libc!(libc::lfind(key, base, nmemb, width, compar));
+
+    let compar = compar.unwrap();
+    let base = base.cast::<u8>();
+    for i in 0..*nmemb {
+        let elem = base.add(width * i).cast::<c_void>();
+        if compar(key, elem) == 0 {
+            return elem.cast_mut();
+        }
+    }
+
+    null_mut()
+}
+
+#[no_mangle]
+unsafe extern "C" fn lsearch(
+    key: *const c_void,
+    base: *mut c_void,
+    nmemb: *mut size_t,
+    width: size_t,
+    compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>,
+) -> *mut c_void {
+    //libc!(libc::lsearch(key, base, nmemb, width, compar));
+
+    let found = lfind(key, base, nmemb, width, compar);
+    if !found.is_null() {
+        return found;
+    }
+
+    // Append `key` to the array, which the caller has made room for.
+    let end = base.cast::<u8>().add(width * *nmemb);
+    copy_nonoverlapping(key.cast::<u8>(), end, width);
+    *nmemb += 1;
+    end.cast()
+}
@@ -0,0 +1,4 @@
+mod hsearch;
+mod insque;
+mod lsearch;
+mod tsearch;
@@ -0,0 +1,645 @@
+//! `tsearch` and friends.
+//!
+//! This is a red-black tree using glibc's node layout, in which the low bit
+//! of a node's left pointer holds its color, so that callers which look at
+//! nodes through `twalk` see what they expect. Insertion splits nodes on the
+//! way down, as in a 2-3-4 tree, so that no parent pointers are needed.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::{addr_of_mut, null_mut};
+use libc::{c_int, c_void};
+
+type Compar = unsafe extern "C" fn(*const c_void, *const c_void) -> c_int;
+type Action = unsafe extern "C" fn(*const c_void, c_int, c_int);
+type ActionR = unsafe extern "C" fn(*const c_void, c_int, *mut c_void);
+
+// glibc's `VISIT` values.
+const PREORDER: c_int = 0;
+const POSTORDER: c_int = 1;
+const ENDORDER: c_int = 2;
+const LEAF: c_int = 3;
+
+/// glibc's tree node.
+#[repr(C)]
+struct Node {
+    key: *const c_void,
+    /// The left child, with the low bit set if this node is red.
+    left: usize,
+    right: usize,
+}
+
+/// A pointer to a link to a node, which is either the root pointer or a
+/// child pointer within a node, in which case its low bit is its node's
+/// color.
+type Link = *mut usize;
+
+unsafe fn left(n: *mut Node) -> *mut Node {
+    ((*n).left & !1) as *mut Node
+}
+
+unsafe fn right(n: *mut Node) -> *mut Node {
+    (*n).right as *mut Node
+}
+
+unsafe fn left_link(n: *mut Node) -> Link {
+    addr_of_mut!((*n).left)
+}
+
+unsafe fn right_link(n: *mut Node) -> Link {
+    addr_of_mut!((*n).right)
+}
+
+unsafe fn set_left(n: *mut Node, l: *mut Node) {
+    (*n).left = l as usize | ((*n).left & 1);
+}
+
+unsafe fn set_right(n: *mut Node, r: *mut Node) {
+    (*n).right = r as usize;
+}
+
+unsafe fn is_red(n: *mut Node) -> bool {
+    (*n).left & 1 != 0
+}
+
+/// Test whether `n` is a red node, treating null as black.
+unsafe fn is_red_child(n: *mut Node) -> bool {
+    !n.is_null() && is_red(n)
+}
+
+unsafe fn set_red(n: *mut Node) {
+    (*n).left |= 1;
+}
+
+unsafe fn set_black(n: *mut Node) {
+    (*n).left &= !1;
+}
+
+unsafe fn set_color(n: *mut Node, red: bool) {
+    if red {
+        set_red(n)
+    } else {
+        set_black(n)
+    }
+}
+
+unsafe fn deref(link: Link) -> *mut Node {
+    (*link & !1) as *mut Node
+}
+
+unsafe fn set_link(link: Link, n: *mut Node) {
+    *link = (*link & 1) | n as usize;
+}
+
+#[no_mangle]
+unsafe extern "C" fn tsearch(
+    key: *const c_void,
+    rootp: *mut *mut c_void,
+    compar: Option<Compar>,
+) -> *mut c_void {
+    //libc!(libc::tsearch(key, rootp, compar));
+
+    if rootp.is_null() {
+        return null_mut();
+    }
+    let compar = compar.unwrap();
+
+    let mut rootp: Link = rootp.cast();
+    let mut parentp: Link = null_mut();
+    let mut gparentp: Link = null_mut();
+    let (mut r, mut p_r, mut gp_r) = (0, 0, 0);
+
+    let root = deref(rootp);
+    if !root.is_null() {
+        set_black(root);
+    }
+
+    let mut nextp = rootp;
+    while !deref(nextp).is_null() {
+        let root = deref(rootp);
+        r = compar(key, (*root).key);
+        if r == 0 {
+            return root.cast();
+        }
+
+        // Any rotations this does leave `parentp` and `gparentp` dangling,
+        // but we don't use them again before they're reassigned.
+        maybe_split_for_insert(rootp, parentp, gparentp, p_r, gp_r, false);
+
+        nextp = if r < 0 {
+            left_link(root)
+        } else {
+            right_link(root)
+        };
+        if deref(nextp).is_null() {
+            break;
+        }
+
+        gparentp = parentp;
+        parentp = rootp;
+        rootp = nextp;
+        gp_r = p_r;
+        p_r = r;
+    }
+
+    let q = libc::malloc(size_of::<Node>()).cast::<Node>();
+    if !q.is_null() {
+        set_link(nextp, q);
+        q.write(Node {
+            key,
+            left: 1,
+            right: 0,
+        });
+
+        // The new node is red, so we may now have two red links in a row.
+        if nextp != rootp {
+            maybe_split_for_insert(nextp, rootp, parentp, r, p_r, true);
+        }
+    }
+    q.cast()
+}
+
+/// Split the node at `rootp` if both of its children are red, and then fix
+/// up two red links in a row between `gparentp` and `rootp`. `p_r` and `gp_r`
+/// are the comparison results that took us from the grandparent to the
+/// parent and from the parent to the node. With `force`, skip the split and
+/// just fix up the red links.
+unsafe fn maybe_split_for_insert(
+    rootp: Link,
+    parentp: Link,
+    gparentp: Link,
+    p_r: c_int,
+    gp_r: c_int,
+    force: bool,
+) {
+    let root = deref(rootp);
+    let rp = right_link(root);
+    let rpn = right(root);
+    let lp = left_link(root);
+    let lpn = left(root);
+
+    if !(force || (is_red_child(rpn) && is_red_child(lpn))) {
+        return;
+    }
+
+    // This node becomes red, and its children black.
+    set_red(root);
+    if !rpn.is_null() {
+        set_black(rpn);
+    }
+    if !lpn.is_null() {
+        set_black(lpn);
+    }
+
+    // If the parent is also red, rotate.
+    if parentp.is_null() || !is_red(deref(parentp)) {
+        return;
+    }
+    let gp = deref(gparentp);
+    let p = deref(parentp);
+    if (p_r > 0) != (gp_r > 0) {
+        // The two red links go in different directions, so this node goes to
+        // the top, with its parent and grandparent as its children.
+        set_red(p);
+        set_red(gp);
+        set_black(root);
+        if p_r < 0 {
+            set_left(p, rpn);
+            set_link(rp, p);
+            set_right(gp, lpn);
+            set_link(lp, gp);
+        } else {
+            set_right(p, lpn);
+            set_link(lp, p);
+            set_left(gp, rpn);
+            set_link(rp, gp);
+        }
+        set_link(gparentp, root);
+    } else {
+        // The two red links go in the same direction, so the parent goes to
+        // the top, with this node and its grandparent as its children.
+        set_link(gparentp, p);
+        set_black(p);
+        set_red(gp);
+        if p_r < 0 {
+            set_left(gp, right(p));
+            set_right(p, gp);
+        } else {
+            set_right(gp, left(p));
+            set_left(p, gp);
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn tfind(
+    key: *const c_void,
+    rootp: *const *mut c_void,
+    compar: Option<Compar>,
+) -> *mut c_void {
+    //libc!(libc::tfind(key, rootp, compar));
+
+    if rootp.is_null() {
+        return null_mut();
+    }
+    let compar = compar.unwrap();
+
+    let mut root = deref(rootp.cast_mut().cast());
+    while !root.is_null() {
+        let r = compar(key, (*root).key);
+        if r == 0 {
+            return root.cast();
+        }
+        root = if r < 0 { left(root) } else { right(root) };
+    }
+    null_mut()
+}
+
+#[no_mangle]
+unsafe extern "C" fn tdelete(
+    key: *const c_void,
+    rootp: *mut *mut c_void,
+    compar: Option<Compar>,
+) -> *mut c_void {
+    //libc!(libc::tdelete(key, rootp, compar));
+
+    if rootp.is_null() {
+        return null_mut();
+    }
+    let compar = compar.unwrap();
+
+    let mut rootp: Link = rootp.cast();
+    let mut p = deref(rootp);
+    if p.is_null() {
+        return null_mut();
+    }
+
+    // Find the node, remembering the links to its ancestors.
+    let mut stack: Vec<Link> = Vec::new();
+    let mut root = p;
+    loop {
+        let cmp = compar(key, (*root).key);
+        if cmp == 0 {
+            break;
+        }
+        stack.push(rootp);
+        p = deref(rootp);
+        if cmp < 0 {
+            rootp = left_link(p);
+            root = left(p);
+        } else {
+            rootp = right_link(p);
+            root = right(p);
+        }
+        if root.is_null() {
+            return null_mut();
+        }
+    }
+
+    // Like glibc, return the parent of the deleted node, or the node itself
+    // if it's the root.
+    let retval = p;
+
+    // Rather than unlinking a node with two children, overwrite it with its
+    // successor and unlink that instead.
+    let root = deref(rootp);
+    let unchained = if left(root).is_null() || right(root).is_null() {
+        root
+    } else {
+        let mut parentp = rootp;
+        let mut up = right_link(root);
+        loop {
+            stack.push(parentp);
+            parentp = up;
+            let upn = deref(up);
+            if left(upn).is_null() {
+                break;
+            }
+            up = left_link(upn);
+        }
+        deref(up)
+    };
+
+    // `unchained` has at most one child; link it to `unchained`'s parent.
+    let mut r = left(unchained);
+    if r.is_null() {
+        r = right(unchained);
+    }
+    match stack.last() {
+        None => set_link(rootp, r),
+        Some(&parent) => {
+            let q = deref(parent);
+            if unchained == right(q) {
+                set_right(q, r);
+            } else {
+                set_left(q, r);
+            }
+        }
+    }
+
+    if unchained != root {
+        (*root).key = (*unchained).key;
+    }
+
+    if !is_red(unchained) {
+        // We removed a black link, so the paths through `r` are one black
+        // link short. Rebalance, treating null nodes as black.
+        while !stack.is_empty() && !is_red_child(r) {
+            let mut pp = stack[stack.len() - 1];
+            let p = deref(pp);
+            if r == left(p) {
+                // `q` is `r`'s sibling.
+                let mut q = right(p);
+                if is_red(q) {
+                    // Rotate `p` left, so that `r`'s new sibling is black.
+                    set_black(q);
+                    set_red(p);
+                    set_right(p, left(q));
+                    set_left(q, p);
+                    set_link(pp, q);
+                    pp = left_link(q);
+                    stack.push(pp);
+                    q = right(p);
+                }
+                if !is_red_child(left(q)) && !is_red_child(right(q)) {
+                    // Making `q` red takes the shortage up to `p`.
+                    set_red(q);
+                    r = p;
+                } else {
+                    if !is_red_child(right(q)) {
+                        // `q`'s left child goes to the top.
+                        let q2 = left(q);
+                        set_color(q2, is_red(p));
+                        set_right(p, left(q2));
+                        set_left(q, right(q2));
+                        set_right(q2, q);
+                        set_left(q2, p);
+                        set_link(pp, q2);
+                        set_black(p);
+                    } else {
+                        // Rotate `p` left.
+                        set_color(q, is_red(p));
+                        set_black(p);
+                        set_black(right(q));
+                        set_right(p, left(q));
+                        set_left(q, p);
+                        set_link(pp, q);
+                    }
+                    stack.truncate(1);
+                    r = null_mut();
+                }
+            } else {
+                // The mirror image of the above.
+                let mut q = left(p);
+                if is_red(q) {
+                    set_black(q);
+                    set_red(p);
+                    set_left(p, right(q));
+                    set_right(q, p);
+                    set_link(pp, q);
+                    pp = right_link(q);
+                    stack.push(pp);
+                    q = left(p);
+                }
+                if !is_red_child(right(q)) && !is_red_child(left(q)) {
+                    set_red(q);
+                    r = p;
+                } else {
+                    if !is_red_child(left(q)) {
+                        let q2 = right(q);
+                        set_color(q2, is_red(p));
+                        set_left(p, right(q2));
+                        set_right(q, left(q2));
+                        set_left(q2, q);
+                        set_right(q2, p);
+                        set_link(pp, q2);
+                        set_black(p);
+                    } else {
+                        set_color(q, is_red(p));
+                        set_black(p);
+                        set_black(left(q));
+                        set_left(p, right(q));
+                        set_right(q, p);
+                        set_link(pp, q);
+                    }
+                    stack.truncate(1);
+                    r = null_mut();
+                }
+            }
+            stack.pop();
+        }
+        if !r.is_null() {
+            set_black(r);
+        }
+    }
+
+    libc::free(unchained.cast());
+    retval.cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn twalk(root: *const c_void, action: Option<Action>) {
+    //libc!(libc::twalk(root, action));
+
+    if let Some(action) = action {
+        if !root.is_null() {
+            walk(root.cast_mut().cast(), &mut |node, visit, depth| {
+                action(node.cast(), visit, depth)
+            });
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn twalk_r(root: *const c_void, action: Option<ActionR>, closure: *mut c_void) {
+    //libc!(libc::twalk_r(root, action, closure));
+
+    if let Some(action) = action {
+        if !root.is_null() {
+            walk(root.cast_mut().cast(), &mut |node, visit, _depth| {
+                action(node.cast(), visit, closure)
+            });
+        }
+    }
+}
+
+/// Visit the subtree at `root` in the order `twalk` describes, calling
+/// `action` with each node, the kind of visit, and the node's depth.
+unsafe fn walk(root: *mut Node, action: &mut dyn FnMut(*mut Node, c_int, c_int)) {
+    unsafe fn recurse(
+        root: *mut Node,
+        action: &mut dyn FnMut(*mut Node, c_int, c_int),
+        depth: c_int,
+    ) {
+        let (l, r) = (left(root), right(root));
+        if l.is_null() && r.is_null() {
+            action(root, LEAF, depth);
+            return;
+        }
+        action(root, PREORDER, depth);
+        if !l.is_null() {
+            recurse(l, action, depth + 1);
+        }
+        action(root, POSTORDER, depth);
+        if !r.is_null() {
+            recurse(r, action, depth + 1);
+        }
+        action(root, ENDORDER, depth);
+    }
+
+    recurse(root, action, 0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn tdestroy(
+    root: *mut c_void,
+    free_node: Option<unsafe extern "C" fn(*mut c_void)>,
+) {
+    //libc!(libc::tdestroy(root, free_node));
+
+    unsafe fn recurse(root: *mut Node, free_node: unsafe extern "C" fn(*mut c_void)) {
+        if !left(root).is_null() {
+            recurse(left(root), free_node);
+        }
+        if !right(root).is_null() {
+            recurse(right(root), free_node);
+        }
+        free_node((*root).key.cast_mut());
+        libc::free(root.cast());
+    }
+
+    let free_node = free_node.unwrap();
+    if !root.is_null() {
+        recurse(root.cast(), free_node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeSet;
+    use core::ptr::{null, with_exposed_provenance};
+    use rand::Rng;
+    use rand_core::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    /// Compare keys which are integers stored in the key pointers.
+    unsafe extern "C" fn compare(a: *const c_void, b: *const c_void) -> c_int {
+        a.addr().cmp(&b.addr()) as c_int
+    }
+
+    unsafe extern "C" fn free_nothing(_key: *mut c_void) {}
+
+    fn key(k: usize) -> *const c_void {
+        with_exposed_provenance(k)
+    }
+
+    /// Check that the subtree at `n` is a valid red-black tree with keys in
+    /// `lo..hi`, and return its black height.
+    unsafe fn check(n: *mut Node, lo: usize, hi: usize) -> usize {
+        if n.is_null() {
+            return 1;
+        }
+        let k = (*n).key.addr();
+        assert!(lo <= k && k < hi);
+        if is_red(n) {
+            assert!(!is_red_child(left(n)) && !is_red_child(right(n)));
+        }
+        let l = check(left(n), lo, k);
+        let r = check(right(n), k + 1, hi);
+        assert_eq!(l, r);
+        l + usize::from(!is_red(n))
+    }
+
+    /// Return the keys in the tree, in order.
+    unsafe fn keys(root: *mut c_void) -> Vec<usize> {
+        let mut keys = Vec::new();
+        if !root.is_null() {
+            walk(root.cast(), &mut |node, visit, _depth| {
+                if visit == POSTORDER || visit == LEAF {
+                    keys.push((*node).key.addr());
+                }
+            });
+        }
+        keys
+    }
+
+    #[test]
+    fn test_tsearch_tdelete() {
+        let mut rng = Pcg32::seed_from_u64(0);
+        let mut root: *mut c_void = null_mut();
+        let mut set = BTreeSet::new();
+        unsafe {
+            for _ in 0..5000 {
+                let k = rng.random_range(1..400);
+                if rng.random_bool(0.6) {
+                    let node = tsearch(key(k), &mut root, Some(compare));
+                    assert_eq!((*node.cast::<Node>()).key, key(k));
+                    set.insert(k);
+                } else {
+                    let parent = tdelete(key(k), &mut root, Some(compare));
+                    assert_eq!(!parent.is_null(), set.remove(&k));
+                }
+                // As in glibc, the root may be left red; `tsearch` blackens
+                // it when it's next called.
+                check(root.cast(), 0, usize::MAX);
+
+                let k = rng.random_range(1..400);
+                let found = tfind(key(k), &root, Some(compare));
+                assert_eq!(!found.is_null(), set.contains(&k));
+            }
+            assert_eq!(keys(root), set.iter().copied().collect::<Vec<_>>());
+
+            tdestroy(root, Some(free_nothing));
+        }
+    }
+
+    #[test]
+    fn test_tsearch_existing() {
+        unsafe {
+            let mut root: *mut c_void = null_mut();
+            let a = tsearch(key(5), &mut root, Some(compare));
+            let b = tsearch(key(5), &mut root, Some(compare));
+            assert_eq!(a, b);
+            assert_eq!(tfind(key(6), &root, Some(compare)), null_mut());
+            assert_eq!(tdelete(key(6), &mut root, Some(compare)), null_mut());
+            assert!(!tdelete(key(5), &mut root, Some(compare)).is_null());
+            assert_eq!(root, null_mut());
+            assert_eq!(tsearch(key(5), null_mut(), Some(compare)), null_mut());
+        }
+    }
+
+    #[test]
+    fn test_twalk() {
+        static mut VISITS: Vec<(usize, c_int, c_int)> = Vec::new();
+
+        unsafe extern "C" fn action(node: *const c_void, visit: c_int, depth: c_int) {
+            let key = (*node.cast::<*const c_void>()).addr();
+            (*addr_of_mut!(VISITS)).push((key, visit, depth));
+        }
+
+        unsafe {
+            let mut root: *mut c_void = null_mut();
+            for k in [4, 2, 6, 1, 3, 5, 7] {
+                tsearch(key(k), &mut root, Some(compare));
+            }
+            twalk(root, Some(action));
+            twalk(null(), Some(action));
+            let visits = &*addr_of_mut!(VISITS);
+
+            // Every node is visited three times, except leaves.
+            let leaves = visits.iter().filter(|v| v.1 == LEAF).count();
+            assert_eq!(visits.len(), leaves + 3 * (7 - leaves));
+            assert_eq!(visits[0], (4, PREORDER, 0));
+            assert_eq!(*visits.last().unwrap(), (4, ENDORDER, 0));
+            let in_order = visits
+                .iter()
+                .filter(|v| v.1 == POSTORDER || v.1 == LEAF)
+                .map(|v| v.0)
+                .collect::<Vec<_>>();
+            assert_eq!(in_order, [1, 2, 3, 4, 5, 6, 7]);
+
+            tdestroy(root, Some(free_nothing));
+        }
+    }
+}
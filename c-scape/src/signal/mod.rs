@@ -125,10 +125,10 @@ unsafe extern "C" fn sigprocmask(how: c_int, set: *const sigset_t, oldset: *mut
     let set = if set.is_null() {
         None
     } else {
-        Some(&*set.cast::<KernelSigSet>())
+        Some(without_reserved(&*set.cast::<KernelSigSet>()))
     };
 
-    match convert_res(rustix::runtime::kernel_sigprocmask(how, set)) {
+    match convert_res(rustix::runtime::kernel_sigprocmask(how, set.as_ref())) {
         Some(mut old) => {
             if !oldset.is_null() {
                 // Clear out the signals reserved for libc.
@@ -144,6 +144,16 @@ unsafe extern "C" fn sigprocmask(how: c_int, set: *const sigset_t, oldset: *mut
     }
 }
 
+/// Return a copy of `set` with the signals we reserve removed, so that
+/// they're never blocked.
+pub(crate) fn without_reserved(set: &KernelSigSet) -> KernelSigSet {
+    let mut set = set.clone();
+    for sig in KERNEL_SIGRTMIN..SIGRTMIN {
+        set.remove(unsafe { Signal::from_raw_unchecked(sig) });
+    }
+    set
+}
+
 #[no_mangle]
 unsafe extern "C" fn sigpending(set: *mut sigset_t) -> c_int {
     libc!(libc::sigpending(set));
@@ -294,6 +304,20 @@ unsafe extern "C" fn sigfillset(sigset: *mut sigset_t) -> c_int {
     for index in 0..(size_of::<sigset_t>() / size_of::<usize>()) {
         sigset.cast::<usize>().add(index).write(!0);
     }
+    // Leave out the signals we reserve, so that they aren't blocked or
+    // waited for by accident.
+    for signum in KERNEL_SIGRTMIN..SIGRTMIN {
+        let sig_index = (signum - 1) as usize;
+        let mut x = sigset
+            .cast::<usize>()
+            .add(sig_index / usize::BITS as usize)
+            .read();
+        x &= !(1_usize << (sig_index % usize::BITS as usize));
+        sigset
+            .cast::<usize>()
+            .add(sig_index / usize::BITS as usize)
+            .write(x);
+    }
     0
 }
 
@@ -672,9 +696,9 @@ unsafe extern "C" fn __libc_current_sigrtmax() -> i32 {
     SIGRTMAX
 }
 
-// Reserve 3 RT signals for ourselves. We don't currently implement
-// anything that uses these signals, but we might as well reserve some
-// for when we do.
+// Reserve 3 RT signals for ourselves. `RESERVED1` is used to apply
+// `setuid` and friends to all threads; see `thread/setxid.rs`. We might as
+// well reserve the others for when we need them.
 const SIGRTMIN: i32 = KERNEL_SIGRTMIN as i32 + 3;
 const SIGRTMAX: i32 = KERNEL_SIGRTMAX as i32;
 
@@ -0,0 +1,109 @@
+//! The BSD `heapsort`, which also serves as the fallback for `qsort`.
+
+use super::qsort::swap;
+use errno::{set_errno, Errno};
+use libc::{c_int, c_void, size_t};
+
+#[no_mangle]
+unsafe extern "C" fn heapsort(
+    base: *mut c_void,
+    nmemb: size_t,
+    width: size_t,
+    compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>,
+) -> c_int {
+    //libc!(libc::heapsort(base, nmemb, width, compar));
+
+    if nmemb <= 1 {
+        return 0;
+    }
+    if width == 0 {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+
+    let compar = compar.unwrap();
+    heap_sort(base.cast(), nmemb, width, &mut |a, b| {
+        compar(a.cast(), b.cast())
+    });
+    0
+}
+
+/// Sort `n` elements of `width` bytes at `base` with heapsort.
+pub(super) unsafe fn heap_sort<F>(base: *mut u8, n: usize, width: usize, cmp: &mut F)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    for start in (0..n / 2).rev() {
+        sift_down(base, start, n, width, cmp);
+    }
+    for end in (1..n).rev() {
+        swap(base, base.add(end * width), width);
+        sift_down(base, 0, end, width, cmp);
+    }
+}
+
+/// Move the element at `root` down until it's no less than its children,
+/// considering only the first `n` elements.
+unsafe fn sift_down<F>(base: *mut u8, mut root: usize, n: usize, width: usize, cmp: &mut F)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    let at = |i: usize| base.add(i * width);
+
+    loop {
+        let mut child = 2 * root + 1;
+        if child >= n {
+            break;
+        }
+        if child + 1 < n && cmp(at(child), at(child + 1)) < 0 {
+            child += 1;
+        }
+        if cmp(at(root), at(child)) >= 0 {
+            break;
+        }
+        swap(at(root), at(child), width);
+        root = child;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{compare, random, sorted, LENS};
+    use super::*;
+
+    fn check<const W: usize>() {
+        for (seed, &n) in LENS.iter().enumerate() {
+            let mut data = random(seed as u64, n, W, 4);
+            let expected = sorted(&data, W);
+            unsafe {
+                assert_eq!(
+                    heapsort(data.as_mut_ptr().cast(), n, W, Some(compare::<W>)),
+                    0
+                );
+            }
+            assert_eq!(data, expected, "n={n} width={W}");
+        }
+    }
+
+    #[test]
+    fn test_heapsort() {
+        check::<1>();
+        check::<3>();
+        check::<4>();
+        check::<7>();
+        check::<16>();
+        check::<21>();
+    }
+
+    #[test]
+    fn test_heapsort_zero_width() {
+        let mut data = [0_u8; 2];
+        unsafe {
+            assert_eq!(
+                heapsort(data.as_mut_ptr().cast(), 2, 0, Some(compare::<0>)),
+                -1
+            );
+            assert_eq!(errno::errno().0, libc::EINVAL);
+        }
+    }
+}
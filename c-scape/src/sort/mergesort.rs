@@ -0,0 +1,162 @@
+//! The BSD `mergesort`, a stable sort.
+
+use super::qsort::insertion_sort;
+use core::mem::size_of;
+use core::ptr::copy_nonoverlapping;
+use errno::{set_errno, Errno};
+use libc::{c_int, c_void, size_t};
+
+/// Ranges this short are sorted with insertion sort.
+const INSERTION_MAX: usize = 16;
+
+#[no_mangle]
+unsafe extern "C" fn mergesort(
+    base: *mut c_void,
+    nmemb: size_t,
+    width: size_t,
+    compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>,
+) -> c_int {
+    //libc!(libc::mergesort(base, nmemb, width, compar));
+
+    // BSD requires this, so that it can link elements through their storage.
+    // We don't, but be as strict, so that code tested here works there.
+    if width < size_of::<*const c_void>() / 2 {
+        set_errno(Errno(libc::EINVAL));
+        return -1;
+    }
+    if nmemb <= 1 {
+        return 0;
+    }
+
+    // Merging needs room for the first half of a range.
+    let half = nmemb.div_ceil(2);
+    let buf = match half.checked_mul(width) {
+        Some(size) => libc::malloc(size).cast::<u8>(),
+        None => core::ptr::null_mut(),
+    };
+    if buf.is_null() {
+        set_errno(Errno(libc::ENOMEM));
+        return -1;
+    }
+
+    let compar = compar.unwrap();
+    merge_sort(base.cast(), nmemb, width, buf, &mut |a, b| {
+        compar(a.cast(), b.cast())
+    });
+
+    libc::free(buf.cast());
+    0
+}
+
+/// Sort `n` elements of `width` bytes at `base`, using `buf`, which has room
+/// for `n.div_ceil(2)` elements.
+unsafe fn merge_sort<F>(base: *mut u8, n: usize, width: usize, buf: *mut u8, cmp: &mut F)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    if n <= INSERTION_MAX {
+        insertion_sort(base, n, width, cmp);
+        return;
+    }
+
+    let mid = n.div_ceil(2);
+    let right = base.add(mid * width);
+    merge_sort(base, mid, width, buf, cmp);
+    merge_sort(right, n - mid, width, buf, cmp);
+
+    // If the halves are already in order, there's nothing to merge.
+    if cmp(right.sub(width), right) <= 0 {
+        return;
+    }
+
+    // Move the left half out of the way, and merge it with the right half
+    // back into place. Taking from the left half on ties keeps this stable.
+    copy_nonoverlapping(base, buf, mid * width);
+    let mut left = 0;
+    let mut right = mid;
+    let mut out = base;
+    while left < mid && right < n {
+        let r = base.add(right * width);
+        let l = buf.add(left * width);
+        if cmp(l, r) <= 0 {
+            copy_nonoverlapping(l, out, width);
+            left += 1;
+        } else {
+            copy_nonoverlapping(r, out, width);
+            right += 1;
+        }
+        out = out.add(width);
+    }
+    // Anything left over in the right half is already in place.
+    copy_nonoverlapping(buf.add(left * width), out, (mid - left) * width);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{compare, random, sorted, LENS};
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn check<const W: usize>() {
+        for (seed, &n) in LENS.iter().enumerate() {
+            let mut data = random(seed as u64, n, W, 3);
+            let expected = sorted(&data, W);
+            unsafe {
+                assert_eq!(
+                    mergesort(data.as_mut_ptr().cast(), n, W, Some(compare::<W>)),
+                    0
+                );
+            }
+            assert_eq!(data, expected, "n={n} width={W}");
+        }
+    }
+
+    #[test]
+    fn test_mergesort() {
+        check::<4>();
+        check::<5>();
+        check::<8>();
+        check::<12>();
+        check::<17>();
+    }
+
+    #[test]
+    fn test_mergesort_stable() {
+        /// Compare only the first byte, leaving the rest to tell equal
+        /// elements apart.
+        unsafe extern "C" fn compare_key(a: *const c_void, b: *const c_void) -> c_int {
+            c_int::from(*a.cast::<u8>()) - c_int::from(*b.cast::<u8>())
+        }
+
+        const W: usize = 5;
+        for (seed, &n) in LENS.iter().enumerate() {
+            // Tag each element with its original position.
+            let mut data = random(seed as u64, n, W, 4);
+            for (i, element) in data.chunks_mut(W).enumerate() {
+                element[1..].copy_from_slice(&(i as u32).to_be_bytes());
+            }
+            let mut expected = data.chunks(W).collect::<Vec<_>>();
+            expected.sort_by_key(|element| element[0]);
+            let expected = expected.concat();
+            unsafe {
+                assert_eq!(
+                    mergesort(data.as_mut_ptr().cast(), n, W, Some(compare_key)),
+                    0
+                );
+            }
+            assert_eq!(data, expected, "n={n}");
+        }
+    }
+
+    #[test]
+    fn test_mergesort_narrow() {
+        let mut data = [3_u8, 1, 2];
+        unsafe {
+            assert_eq!(
+                mergesort(data.as_mut_ptr().cast(), 3, 1, Some(compare::<1>)),
+                -1
+            );
+            assert_eq!(errno::errno().0, libc::EINVAL);
+        }
+    }
+}
@@ -1,2 +1,44 @@
 mod bsearch;
+mod heapsort;
+mod mergesort;
 mod qsort;
+
+/// Random arrays to sort, and the results to expect.
+#[cfg(test)]
+mod testing {
+    use alloc::vec::Vec;
+    use core::ffi::c_void;
+    use core::slice;
+    use libc::c_int;
+    use rand::Rng;
+    use rand_core::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    /// The element counts to test, covering the insertion sort cutoffs and
+    /// the ninther.
+    pub(super) const LENS: &[usize] = &[0, 1, 2, 3, 15, 16, 17, 33, 127, 128, 129, 1000, 4099];
+
+    /// Return `n` elements of `width` random bytes. The bytes are all less
+    /// than `keys`, so that there are plenty of duplicates.
+    pub(super) fn random(seed: u64, n: usize, width: usize, keys: u8) -> Vec<u8> {
+        let mut rng = Pcg32::seed_from_u64(seed);
+        (0..n * width).map(|_| rng.random_range(0..keys)).collect()
+    }
+
+    /// Sort the elements of `data` by their bytes.
+    pub(super) fn sorted(data: &[u8], width: usize) -> Vec<u8> {
+        let mut elements = data.chunks(width).collect::<Vec<_>>();
+        elements.sort();
+        elements.concat()
+    }
+
+    /// Compare the elements of `W` bytes at `a` and `b` by their bytes.
+    pub(super) unsafe extern "C" fn compare<const W: usize>(
+        a: *const c_void,
+        b: *const c_void,
+    ) -> c_int {
+        let a = slice::from_raw_parts(a.cast::<u8>(), W);
+        let b = slice::from_raw_parts(b.cast::<u8>(), W);
+        a.cmp(b) as c_int
+    }
+}
@@ -1,5 +1,12 @@
-//! A simple `qsort` implementation based on the Combsort algorithm.
+//! `qsort` and `qsort_r`, implemented as an introsort.
+//!
+//! This is a quicksort with median-of-three or ninther pivots, which falls
+//! back to heapsort when partitioning goes badly, so it's O(n log n) in the
+//! worst case, and finishes small ranges with insertion sort. Every scan is
+//! bounds-checked, so comparators that aren't consistent can produce a
+//! strange order, but can't make us touch memory outside the array.
 
+use super::heapsort::heap_sort;
 use libc::{c_int, c_void, size_t};
 
 #[no_mangle]
@@ -12,33 +19,9 @@ unsafe extern "C" fn qsort(
     libc!(libc::qsort(base, nmemb, width, compar));
 
     let compar = compar.unwrap();
-
-    if nmemb <= 1 {
-        return;
-    }
-
-    let base = base.cast::<u8>();
-    let mut gap = nmemb;
-
-    loop {
-        gap = next_gap(gap);
-
-        let mut any_swapped = false;
-        let mut a = base;
-        let mut b = base.add(gap * width);
-        for _ in 0..nmemb - gap {
-            if compar(a.cast(), b.cast()) > 0 {
-                swap(a, b, width);
-                any_swapped = true;
-            }
-            a = a.add(width);
-            b = b.add(width);
-        }
-
-        if gap <= 1 && !any_swapped {
-            break;
-        }
-    }
+    sort(base.cast(), nmemb, width, &mut |a, b| {
+        compar(a.cast(), b.cast())
+    });
 }
 
 #[cfg(not(target_env = "musl"))]
@@ -53,49 +36,330 @@ unsafe extern "C" fn qsort_r(
     libc!(libc::qsort_r(base, nmemb, width, compar, arg));
 
     let compar = compar.unwrap();
+    sort(base.cast(), nmemb, width, &mut |a, b| {
+        compar(a.cast(), b.cast(), arg)
+    });
+}
+
+/// The BSD `qsort_r`, which takes the argument before the comparison
+/// function, and passes it to the comparison function first. FreeBSD exports
+/// it under this name since adopting the GNU argument order for `qsort_r`.
+#[no_mangle]
+unsafe extern "C" fn __qsort_r_compat(
+    base: *mut c_void,
+    nmemb: size_t,
+    width: size_t,
+    arg: *mut c_void,
+    compar: Option<unsafe extern "C" fn(*mut c_void, *const c_void, *const c_void) -> c_int>,
+) {
+    //libc!(libc::__qsort_r_compat(base, nmemb, width, arg, compar));
 
-    if nmemb <= 1 {
+    let compar = compar.unwrap();
+    sort(base.cast(), nmemb, width, &mut |a, b| {
+        compar(arg, a.cast(), b.cast())
+    });
+}
+
+/// Ranges this short are sorted with insertion sort.
+const INSERTION_MAX: usize = 16;
+
+/// Ranges longer than this use the ninther instead of the median of three.
+const NINTHER_MIN: usize = 128;
+
+/// Sort `nmemb` elements of `width` bytes at `base`.
+pub(super) unsafe fn sort<F>(base: *mut u8, nmemb: usize, width: usize, cmp: &mut F)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    if nmemb <= 1 || width == 0 {
         return;
     }
 
-    let base = base.cast::<u8>();
-    let mut gap = nmemb;
+    // Allow about two bad partitions per level before giving up on
+    // quicksort for a range.
+    let limit = 2 * (usize::BITS - nmemb.leading_zeros());
+    introsort(base, nmemb, width, cmp, limit);
+}
 
+unsafe fn introsort<F>(mut base: *mut u8, mut n: usize, width: usize, cmp: &mut F, mut limit: u32)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
     loop {
-        gap = next_gap(gap);
-
-        let mut any_swapped = false;
-        let mut a = base;
-        let mut b = base.add(gap * width);
-        for _ in 0..nmemb - gap {
-            if compar(a.cast(), b.cast(), arg) > 0 {
-                swap(a, b, width);
-                any_swapped = true;
-            }
-            a = a.add(width);
-            b = b.add(width);
+        if n <= INSERTION_MAX {
+            insertion_sort(base, n, width, cmp);
+            return;
+        }
+        if limit == 0 {
+            heap_sort(base, n, width, cmp);
+            return;
+        }
+        limit -= 1;
+
+        let mid = partition(base, n, width, cmp);
+
+        // Recurse into the smaller side and loop on the larger one, so that
+        // the stack depth is logarithmic.
+        let right = base.add((mid + 1) * width);
+        let right_n = n - mid - 1;
+        if mid < right_n {
+            introsort(base, mid, width, cmp, limit);
+            base = right;
+            n = right_n;
+        } else {
+            introsort(right, right_n, width, cmp, limit);
+            n = mid;
         }
+    }
+}
+
+/// Partition around a pivot, and return the pivot's final index. Everything
+/// before it compares less than or equal to it, and everything after it
+/// compares greater than or equal to it.
+unsafe fn partition<F>(base: *mut u8, n: usize, width: usize, cmp: &mut F) -> usize
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    let at = |i: usize| base.add(i * width);
+
+    let pivot = if n >= NINTHER_MIN {
+        let s = n / 8;
+        let a = median3(base, width, cmp, 0, s, 2 * s);
+        let b = median3(base, width, cmp, n / 2 - s, n / 2, n / 2 + s);
+        let c = median3(base, width, cmp, n - 1 - 2 * s, n - 1 - s, n - 1);
+        median3(base, width, cmp, a, b, c)
+    } else {
+        median3(base, width, cmp, 0, n / 2, n - 1)
+    };
+    swap(at(0), at(pivot), width);
 
-        if gap <= 1 && !any_swapped {
+    // Both scans stop on elements equal to the pivot, so that runs of equal
+    // elements are split evenly.
+    let mut i = 1;
+    let mut j = n - 1;
+    loop {
+        while i <= j && cmp(at(i), at(0)) < 0 {
+            i += 1;
+        }
+        while i <= j && cmp(at(j), at(0)) > 0 {
+            j -= 1;
+        }
+        if i >= j {
             break;
         }
+        swap(at(i), at(j), width);
+        i += 1;
+        j -= 1;
     }
+
+    swap(at(0), at(j), width);
+    j
 }
 
-fn next_gap(gap: size_t) -> size_t {
-    let gap = (gap * 10) / 13;
+/// Return whichever of the elements at indices `a`, `b`, and `c` is the
+/// median.
+unsafe fn median3<F>(
+    base: *mut u8,
+    width: usize,
+    cmp: &mut F,
+    a: usize,
+    b: usize,
+    c: usize,
+) -> usize
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    let at = |i: usize| base.add(i * width);
 
-    if gap == 9 || gap == 10 {
-        11 // apply the "rule of 11"
-    } else if gap <= 1 {
-        1
+    let ab = cmp(at(a), at(b)) < 0;
+    let bc = cmp(at(b), at(c)) < 0;
+    if ab == bc {
+        return b;
+    }
+    let ac = cmp(at(a), at(c)) < 0;
+    if ab == ac {
+        c
     } else {
-        gap
+        a
     }
 }
 
-unsafe fn swap(a: *mut u8, b: *mut u8, width: size_t) {
-    for i in 0..width {
-        core::ptr::swap(a.add(i), b.add(i));
+/// Sort with insertion sort. This only moves an element past ones that
+/// compare greater than it, so it's stable.
+pub(super) unsafe fn insertion_sort<F>(base: *mut u8, n: usize, width: usize, cmp: &mut F)
+where
+    F: FnMut(*const u8, *const u8) -> c_int,
+{
+    for i in 1..n {
+        let mut j = i;
+        while j > 0 {
+            let a = base.add((j - 1) * width);
+            let b = base.add(j * width);
+            if cmp(a, b) <= 0 {
+                break;
+            }
+            swap(a, b, width);
+            j -= 1;
+        }
+    }
+}
+
+/// Swap two non-overlapping elements of `width` bytes, using the widest
+/// loads and stores the width allows.
+#[inline]
+pub(super) unsafe fn swap(a: *mut u8, b: *mut u8, width: usize) {
+    match width {
+        1 => swap_as::<u8>(a, b),
+        2 => swap_as::<u16>(a, b),
+        4 => swap_as::<u32>(a, b),
+        8 => swap_as::<u64>(a, b),
+        16 => swap_as::<u128>(a, b),
+        _ => {
+            let words = width / 8;
+            for i in 0..words {
+                swap_as::<u64>(a.add(i * 8), b.add(i * 8));
+            }
+            for i in words * 8..width {
+                swap_as::<u8>(a.add(i), b.add(i));
+            }
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn swap_as<T>(a: *mut u8, b: *mut u8) {
+    let x = a.cast::<T>().read_unaligned();
+    let y = b.cast::<T>().read_unaligned();
+    a.cast::<T>().write_unaligned(y);
+    b.cast::<T>().write_unaligned(x);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{compare, random, sorted, LENS};
+    use super::*;
+    use core::slice;
+
+    /// Sort random arrays of `W`-byte elements with `sort_fn`, and check
+    /// them against the reference.
+    fn check<const W: usize>(sort_fn: impl Fn(&mut [u8], usize)) {
+        for (seed, &n) in LENS.iter().enumerate() {
+            for keys in [2, 255] {
+                let mut data = random(seed as u64, n, W, keys);
+                let expected = sorted(&data, W);
+                sort_fn(&mut data, n);
+                assert_eq!(data, expected, "n={n} width={W} keys={keys}");
+            }
+        }
+    }
+
+    fn check_qsort<const W: usize>() {
+        check::<W>(|data, n| unsafe {
+            qsort(data.as_mut_ptr().cast(), n, W, Some(compare::<W>));
+        });
+    }
+
+    /// Compare elements whose width is given by `arg`.
+    unsafe fn compare_width(a: *const c_void, b: *const c_void, arg: *mut c_void) -> c_int {
+        let width = *arg.cast::<usize>();
+        let a = slice::from_raw_parts(a.cast::<u8>(), width);
+        let b = slice::from_raw_parts(b.cast::<u8>(), width);
+        a.cmp(b) as c_int
+    }
+
+    #[test]
+    fn test_qsort() {
+        check_qsort::<1>();
+        check_qsort::<2>();
+        check_qsort::<3>();
+        check_qsort::<4>();
+        check_qsort::<5>();
+        check_qsort::<7>();
+        check_qsort::<8>();
+        check_qsort::<13>();
+        check_qsort::<16>();
+        check_qsort::<24>();
+    }
+
+    #[test]
+    fn test_qsort_r() {
+        unsafe extern "C" fn compar(a: *const c_void, b: *const c_void, arg: *mut c_void) -> c_int {
+            compare_width(a, b, arg)
+        }
+
+        check::<11>(|data, n| unsafe {
+            let mut width = 11_usize;
+            qsort_r(
+                data.as_mut_ptr().cast(),
+                n,
+                width,
+                Some(compar),
+                (&mut width as *mut usize).cast(),
+            );
+        });
+    }
+
+    #[test]
+    fn test_qsort_r_compat() {
+        unsafe extern "C" fn compar(arg: *mut c_void, a: *const c_void, b: *const c_void) -> c_int {
+            compare_width(a, b, arg)
+        }
+
+        check::<6>(|data, n| unsafe {
+            let mut width = 6_usize;
+            __qsort_r_compat(
+                data.as_mut_ptr().cast(),
+                n,
+                width,
+                (&mut width as *mut usize).cast(),
+                Some(compar),
+            );
+        });
+    }
+
+    #[test]
+    fn test_introsort_heapsort_fallback() {
+        // With no bad partitions allowed, every range longer than the
+        // insertion sort cutoff goes straight to heapsort.
+        fn check_fallback<const W: usize>() {
+            check::<W>(|data, n| unsafe {
+                if n > 1 {
+                    introsort(
+                        data.as_mut_ptr(),
+                        n,
+                        W,
+                        &mut |a, b| compare::<W>(a.cast(), b.cast()),
+                        0,
+                    );
+                }
+            });
+        }
+
+        check_fallback::<1>();
+        check_fallback::<3>();
+        check_fallback::<8>();
+        check_fallback::<9>();
+    }
+
+    #[test]
+    fn test_qsort_sorted_and_reversed() {
+        let n = 5000_u32;
+        let ascending = (0..n).collect::<alloc::vec::Vec<_>>();
+        for mut data in [
+            ascending.clone(),
+            ascending.iter().rev().copied().collect(),
+            ascending.iter().map(|x| x % 2).collect(),
+        ] {
+            let mut expected = data.clone();
+            expected.sort();
+            unsafe {
+                qsort(data.as_mut_ptr().cast(), data.len(), 4, Some(compare_u32));
+            }
+            assert_eq!(data, expected);
+        }
+    }
+
+    unsafe extern "C" fn compare_u32(a: *const c_void, b: *const c_void) -> c_int {
+        (*a.cast::<u32>()).cmp(&*b.cast::<u32>()) as c_int
     }
 }
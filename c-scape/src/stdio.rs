@@ -1,26 +1,20 @@
 //! The `stdio` family of functions.
 //!
 //! `FILE` is not currently buffered. And the `*_unlocked` functions currently
-//! always lock. And the `printf` family of functions currently always call
-//! `malloc`.
+//! always lock.
 //!
-//! The `printf` family of functions currently uses the `printf_compat` crate,
-//! which [has differences with glibc]. And because we're using it in `no_std`
-//! mode here, it only supports UTF-8 output.
-//!
-//! [has differences with glibc]: https://docs.rs/printf-compat/*/printf_compat/output/fn.fmt_write.html#differences
+//! The `printf` family of functions uses our own formatting engine in
+//! `printf.rs`, which doesn't allocate. Wide characters are converted to
+//! UTF-8.
 
 use crate::convert_res;
 #[cfg(feature = "thread")]
 use crate::GetThreadId;
 use alloc::boxed::Box;
-use alloc::string::String;
-use core::cmp::min;
 use core::ffi::{CStr, VaList};
-use core::ptr::{addr_of, addr_of_mut, copy_nonoverlapping, null_mut};
+use core::ptr::{addr_of, addr_of_mut, null_mut};
 use errno::{set_errno, Errno};
-use libc::{c_char, c_int, c_long, c_void, off64_t, off_t, size_t};
-use printf_compat::{format, output};
+use libc::{c_char, c_double, c_float, c_int, c_long, c_void, off64_t, off_t, size_t};
 use rustix::fd::IntoRawFd;
 use rustix::fs::{Mode, OFlags};
 #[cfg(feature = "thread")]
@@ -31,6 +25,7 @@ use rustix_futex_sync::RawMutex;
 
 mod buf;
 mod chk;
+mod printf;
 
 #[no_mangle]
 unsafe extern "C" fn fputc(c: c_int, file: *mut libc::FILE) -> c_int {
@@ -734,21 +729,7 @@ unsafe extern "C" fn sprintf(ptr: *mut c_char, fmt: *const c_char, args: ...) ->
 unsafe extern "C" fn vsprintf(ptr: *mut c_char, fmt: *const c_char, va_list: VaList<'_>) -> c_int {
     //libc!(libc::vsprintf(ptr, fmt, va_list));
 
-    let mut out = String::new();
-    let num_bytes = format(fmt, va_list, output::fmt_write(&mut out));
-    if num_bytes < 0 {
-        return num_bytes;
-    }
-    debug_assert_eq!(out.len(), num_bytes as usize);
-
-    let copy_len = num_bytes as usize + 1;
-    if copy_len > 0 {
-        out.push('\0');
-
-        copy_nonoverlapping(out.as_ptr(), ptr.cast(), copy_len);
-    }
-
-    num_bytes
+    vsnprintf(ptr, usize::MAX, fmt, va_list)
 }
 
 #[no_mangle]
@@ -770,22 +751,9 @@ unsafe extern "C" fn vsnprintf(
 ) -> c_int {
     //libc!(libc::vsnprintf(ptr, len, fmt, va_list));
 
-    let mut out = String::new();
-    let num_bytes = format(fmt, va_list, output::fmt_write(&mut out));
-    if num_bytes < 0 {
-        return num_bytes;
-    }
-    debug_assert_eq!(out.len(), num_bytes as usize);
-
-    let copy_len = min(num_bytes as usize + 1, len);
-    if copy_len > 0 {
-        out.push('\0');
-        let mut out = out.into_bytes();
-        out[copy_len - 1] = b'\0';
-
-        copy_nonoverlapping(out.as_ptr(), ptr.cast(), copy_len);
-    }
-
+    let mut out = printf::Buffer::new(ptr, len);
+    let num_bytes = printf::format(&mut out, fmt, va_list);
+    out.finish();
     num_bytes
 }
 
@@ -798,26 +766,11 @@ unsafe extern "C" fn dprintf(fd: c_int, fmt: *const c_char, args: ...) -> c_int
 unsafe extern "C" fn vdprintf(fd: c_int, fmt: *const c_char, va_list: VaList<'_>) -> c_int {
     //libc!(libc::vdprintf(fd, fmt, va_list));
 
-    let mut out = String::new();
-    let num_bytes = format(fmt, va_list, output::fmt_write(&mut out));
-    if num_bytes < 0 {
-        return num_bytes;
-    }
-    debug_assert_eq!(out.len(), num_bytes as usize);
-
-    let bytes = out.into_bytes();
-    let mut remaining = &bytes[..];
-    while !remaining.is_empty() {
-        match libc::write(fd, remaining.as_ptr().cast(), remaining.len()) {
-            -1 => {
-                if errno::errno().0 != libc::EINTR {
-                    return -1;
-                }
-            }
-            n => remaining = &remaining[n as usize..],
-        }
+    let mut out = printf::Fd::new(fd);
+    let num_bytes = printf::format(&mut out, fmt, va_list);
+    if !out.flush() {
+        return -1;
     }
-
     num_bytes
 }
 
@@ -870,6 +823,81 @@ unsafe extern "C" fn asprintf(strp: *mut *mut c_char, fmt: *const c_char, args:
     vasprintf(strp, fmt, args)
 }
 
+#[no_mangle]
+unsafe extern "C" fn strfromd(
+    str: *mut c_char,
+    n: size_t,
+    format: *const c_char,
+    fp: c_double,
+) -> c_int {
+    //libc!(libc::strfromd(str, n, format, fp));
+
+    strfrom(str, n, format, printf::Float::from_f64(fp))
+}
+
+#[no_mangle]
+unsafe extern "C" fn strfromf(
+    str: *mut c_char,
+    n: size_t,
+    format: *const c_char,
+    fp: c_float,
+) -> c_int {
+    //libc!(libc::strfromf(str, n, format, fp));
+
+    strfrom(str, n, format, printf::Float::from_f64(fp.into()))
+}
+
+// Rust can't declare a `long double` parameter. On these targets, a `long
+// double` argument is passed the same way whether or not it's variadic, so
+// declare the parameter variadic and read it the way `printf` does.
+#[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+#[no_mangle]
+unsafe extern "C" fn strfroml(
+    str: *mut c_char,
+    n: size_t,
+    format: *const c_char,
+    mut args: ...
+) -> c_int {
+    //libc!(libc::strfroml(str, n, format, fp));
+
+    strfrom(str, n, format, printf::next_long_double(&mut args))
+}
+
+// On RISC-V, a `long double` parameter is passed like a 128-bit integer.
+#[cfg(target_arch = "riscv64")]
+#[no_mangle]
+unsafe extern "C" fn strfroml(
+    str: *mut c_char,
+    n: size_t,
+    format: *const c_char,
+    fp: u128,
+) -> c_int {
+    //libc!(libc::strfroml(str, n, format, fp));
+
+    strfrom(str, n, format, printf::Float::from_quad(fp))
+}
+
+// On 32-bit ARM, `long double` is `double`.
+#[cfg(target_arch = "arm")]
+#[no_mangle]
+unsafe extern "C" fn strfroml(
+    str: *mut c_char,
+    n: size_t,
+    format: *const c_char,
+    fp: c_double,
+) -> c_int {
+    //libc!(libc::strfroml(str, n, format, fp));
+
+    strfrom(str, n, format, printf::Float::from_f64(fp))
+}
+
+unsafe fn strfrom(str: *mut c_char, n: size_t, format: *const c_char, fp: printf::Float) -> c_int {
+    let mut out = printf::Buffer::new(str, n);
+    let num_bytes = printf::format_float(&mut out, format, fp);
+    out.finish();
+    num_bytes
+}
+
 #[no_mangle]
 unsafe extern "C" fn perror(user_message: *const c_char) {
     libc!(libc::perror(user_message));
@@ -994,4 +1022,43 @@ mod tests {
         assert_eq!(r, 15);
         assert_eq!(&buf, b"hello big worl\0_");
     }
+
+    #[test]
+    fn test_snprintf_conversions() {
+        fn check(expected: &str, len: c_int, buf: &[u8; 64]) {
+            assert_eq!(len as usize, expected.len());
+            assert_eq!(&buf[..expected.len()], expected.as_bytes());
+            assert_eq!(buf[expected.len()], 0);
+        }
+
+        let mut buf = [0_u8; 64];
+        let p = buf.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            let r = snprintf(p, 64, c"[%-5d|%05.1f|%+.3e]".as_ptr(), 42, -2.25, 1234.5);
+            check("[42   |-02.2|+1.234e+03]", r, &buf);
+            let r = snprintf(p, 64, c"%g %g %g %#g".as_ptr(), 0.0001, 1e-5, 100000.0, 1.0);
+            check("0.0001 1e-05 100000 1.00000", r, &buf);
+            let r = snprintf(p, 64, c"%a %.1a %A".as_ptr(), 1.0, 1.03125, -0.5);
+            check("0x1p+0 0x1.0p+0 -0X1P-1", r, &buf);
+            let r = snprintf(p, 64, c"%.20f".as_ptr(), 0.1);
+            check("0.10000000000000000555", r, &buf);
+            let r = snprintf(p, 64, c"%2$s %1$*3$d %2$s".as_ptr(), 7, c"x".as_ptr(), 3);
+            check("x   7 x", r, &buf);
+            let mut n = 0;
+            let r = snprintf(
+                p,
+                64,
+                c"%#x%n %#o %p".as_ptr(),
+                255,
+                &mut n,
+                8,
+                null_mut::<c_void>(),
+            );
+            check("0xff 010 (nil)", r, &buf);
+            assert_eq!(n, 4);
+            set_errno(Errno(libc::ENOENT));
+            let r = snprintf(p, 64, c"%m".as_ptr());
+            check("No such file or directory", r, &buf);
+        }
+    }
 }
@@ -103,8 +103,6 @@ unsafe extern "C" fn __vfprintf_chk(
         unimplemented!("__USE_FORTIFY_LEVEL > 0");
     }
 
-    // Our `printf` uses `printf_compat` which doesn't support `%n`.
-
     super::vfprintf(file, fmt, va_list)
 }
 
@@ -120,8 +118,6 @@ unsafe extern "C" fn __vprintf_chk(flag: c_int, fmt: *const c_char, va_list: VaL
         unimplemented!("__USE_FORTIFY_LEVEL > 0");
     }
 
-    // Our `printf` uses `printf_compat` which doesn't support `%n`.
-
     super::vprintf(fmt, va_list)
 }
 
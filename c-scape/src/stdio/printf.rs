@@ -0,0 +1,1012 @@
+//! The `printf` formatting engine.
+//!
+//! This writes directly to its output and never allocates, so the `printf`
+//! family can be used in signal handlers and when memory is exhausted.
+//! Positional arguments are supported up to `NL_ARGMAX`, by recording the
+//! type of each argument on the stack and rereading the `va_list` as needed.
+
+mod float;
+
+use core::cmp::max;
+use core::ffi::{CStr, VaList};
+use errno::{set_errno, Errno};
+use libc::{c_char, c_int, c_long, c_void, intmax_t, ptrdiff_t, size_t};
+
+#[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+pub(super) use float::next_long_double;
+pub(super) use float::Float;
+
+/// C's `wint_t`.
+#[allow(non_camel_case_types)]
+type wint_t = u32;
+
+/// glibc's `NL_ARGMAX`.
+const NL_ARGMAX: usize = 4096;
+
+/// Somewhere for formatted output to go.
+pub(super) trait Output {
+    /// Write `bytes`, returning `false` if the output has failed, in which
+    /// case `errno` is set.
+    fn write(&mut self, bytes: &[u8]) -> bool;
+}
+
+/// Output into a caller-provided buffer of `len` bytes, for `snprintf`.
+/// Output that doesn't fit is discarded.
+pub(super) struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    pos: usize,
+}
+
+impl Buffer {
+    pub(super) fn new(ptr: *mut c_char, len: usize) -> Self {
+        Self {
+            ptr: ptr.cast(),
+            len,
+            pos: 0,
+        }
+    }
+
+    /// NUL-terminate the output, if there's any room at all.
+    pub(super) unsafe fn finish(self) {
+        if self.len != 0 {
+            self.ptr.add(self.pos).write(0);
+        }
+    }
+}
+
+impl Output for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        let room = self.len.saturating_sub(1) - self.pos;
+        let n = bytes.len().min(room);
+        if n != 0 {
+            unsafe {
+                core::ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr.add(self.pos), n);
+            }
+            self.pos += n;
+        }
+        true
+    }
+}
+
+/// Output to a file descriptor, through a small buffer on the stack.
+pub(super) struct Fd {
+    fd: c_int,
+    buf: [u8; 1024],
+    len: usize,
+}
+
+impl Fd {
+    pub(super) fn new(fd: c_int) -> Self {
+        Self {
+            fd,
+            buf: [0; 1024],
+            len: 0,
+        }
+    }
+
+    /// Write out anything that's buffered.
+    pub(super) fn flush(&mut self) -> bool {
+        let len = core::mem::take(&mut self.len);
+        write_all(self.fd, &self.buf[..len])
+    }
+}
+
+impl Output for Fd {
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        if self.len + bytes.len() > self.buf.len() {
+            if !self.flush() {
+                return false;
+            }
+            if bytes.len() > self.buf.len() {
+                return write_all(self.fd, bytes);
+            }
+        }
+        self.buf[self.len..][..bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        true
+    }
+}
+
+fn write_all(fd: c_int, mut bytes: &[u8]) -> bool {
+    while !bytes.is_empty() {
+        match unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) } {
+            -1 => {
+                if errno::errno().0 != libc::EINTR {
+                    return false;
+                }
+            }
+            n => bytes = &bytes[n as usize..],
+        }
+    }
+    true
+}
+
+// Flags.
+const LEFT: u8 = 0x1;
+const PLUS: u8 = 0x2;
+const SPACE: u8 = 0x4;
+const ALT: u8 = 0x8;
+const ZERO: u8 = 0x10;
+const GROUP: u8 = 0x20;
+
+/// Tracks the number of bytes written, and whether anything has failed.
+struct Writer<'a> {
+    out: &'a mut dyn Output,
+    count: usize,
+    failed: bool,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.count = self.count.saturating_add(bytes.len());
+        // Once the count is too big to return, there's no point in producing
+        // any more output.
+        if !self.failed && self.count <= c_int::MAX as usize && !self.out.write(bytes) {
+            self.failed = true;
+        }
+    }
+
+    fn pad(&mut self, byte: u8, mut n: usize) {
+        let chunk = [byte; 32];
+        while n > 0 && self.count <= c_int::MAX as usize {
+            let len = n.min(chunk.len());
+            self.put(&chunk[..len]);
+            n -= len;
+        }
+        self.count = self.count.saturating_add(n);
+    }
+
+    /// Pad with spaces on the left, for right-justified output.
+    fn pad_left(&mut self, flags: u8, width: usize, len: usize) {
+        if flags & (LEFT | ZERO) == 0 && width > len {
+            self.pad(b' ', width - len);
+        }
+    }
+
+    /// Pad with zeros between the prefix and the digits, for the `0` flag.
+    fn pad_zeros(&mut self, flags: u8, width: usize, len: usize) {
+        if flags & (LEFT | ZERO) == ZERO && width > len {
+            self.pad(b'0', width - len);
+        }
+    }
+
+    /// Pad with spaces on the right, for left-justified output.
+    fn pad_right(&mut self, flags: u8, width: usize, len: usize) {
+        if flags & LEFT != 0 && width > len {
+            self.pad(b' ', width - len);
+        }
+    }
+
+    /// Write a string, padded to the field width.
+    fn padded(&mut self, flags: u8, width: usize, bytes: &[u8]) {
+        self.pad_left(flags & !ZERO, width, bytes.len());
+        self.put(bytes);
+        self.pad_right(flags, width, bytes.len());
+    }
+}
+
+/// The locale's digit grouping, for the `'` flag.
+struct Grouping {
+    sep: &'static [u8],
+    groups: &'static [u8],
+}
+
+impl Grouping {
+    /// Is there a separator with `pos` digits to its right?
+    fn boundary(&self, pos: usize) -> bool {
+        let mut at = 0;
+        let mut size = 0;
+        for i in 0.. {
+            if let Some(group) = self.groups.get(i) {
+                size = *group;
+            }
+            // A group size of `CHAR_MAX` means there's no more grouping.
+            if size == 0 || size as c_char == c_char::MAX {
+                return false;
+            }
+            at += usize::from(size);
+            if at >= pos {
+                return at == pos;
+            }
+        }
+        false
+    }
+
+    /// The number of separators in a number with `digits` integer digits.
+    fn separators(&self, digits: usize) -> usize {
+        (1..digits).filter(|pos| self.boundary(*pos)).count()
+    }
+
+    /// Write integer digits, inserting separators. `rest` counts the digits
+    /// not yet written, out of `total`.
+    fn put_digits(&self, w: &mut Writer<'_>, digits: &[u8], rest: &mut usize, total: usize) {
+        for digit in digits {
+            if *rest != total && self.boundary(*rest) {
+                w.put(self.sep);
+            }
+            w.put(core::slice::from_ref(digit));
+            *rest -= 1;
+        }
+    }
+}
+
+/// A count, for a width or precision.
+#[derive(Clone, Copy)]
+enum Count {
+    Fixed(usize),
+    /// `*`, or `*m$`.
+    Arg(Option<usize>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Length {
+    None,
+    Char,
+    Short,
+    Long,
+    LongLong,
+    LongDouble,
+    IntMax,
+    Size,
+    PtrDiff,
+}
+
+/// A parsed conversion specification, with `*` counts resolved.
+struct Spec {
+    flags: u8,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn grouping(&self) -> Option<Grouping> {
+        if self.flags & GROUP == 0 {
+            return None;
+        }
+        let lconv = unsafe { &*libc::localeconv() };
+        let sep = unsafe { CStr::from_ptr(lconv.thousands_sep) }.to_bytes();
+        let groups = unsafe { CStr::from_ptr(lconv.grouping) }.to_bytes();
+        if sep.is_empty() || groups.is_empty() {
+            return None;
+        }
+        Some(Grouping { sep, groups })
+    }
+}
+
+/// A conversion specification as it appears in the format string.
+struct RawSpec {
+    /// The `n$` argument index, if positional.
+    index: Option<usize>,
+    flags: u8,
+    width: Count,
+    precision: Option<Count>,
+    length: Length,
+    conv: u8,
+}
+
+/// Parse a conversion specification starting after a `%`. On success, also
+/// return the position after it. Return `Ok(None)` for a malformed
+/// specification.
+fn parse(fmt: &[u8], mut i: usize) -> Result<Option<(RawSpec, usize)>, Errno> {
+    let number = |i: &mut usize| -> Result<Option<usize>, Errno> {
+        if !fmt.get(*i).is_some_and(u8::is_ascii_digit) {
+            return Ok(None);
+        }
+        let mut n: usize = 0;
+        while let Some(digit) = fmt.get(*i).filter(|c| c.is_ascii_digit()) {
+            n = n * 10 + usize::from(digit - b'0');
+            if n > c_int::MAX as usize {
+                return Err(Errno(libc::EOVERFLOW));
+            }
+            *i += 1;
+        }
+        Ok(Some(n))
+    };
+    // Parse a `m$` argument index.
+    let index = |i: &mut usize| -> Result<Option<usize>, Errno> {
+        let start = *i;
+        match number(i)? {
+            Some(n) if fmt.get(*i) == Some(&b'$') => {
+                *i += 1;
+                Ok(Some(n))
+            }
+            _ => {
+                *i = start;
+                Ok(None)
+            }
+        }
+    };
+
+    let index_ = index(&mut i)?;
+
+    let mut flags = 0;
+    loop {
+        flags |= match fmt.get(i) {
+            Some(b'-') => LEFT,
+            Some(b'+') => PLUS,
+            Some(b' ') => SPACE,
+            Some(b'#') => ALT,
+            Some(b'0') => ZERO,
+            Some(b'\'') => GROUP,
+            _ => break,
+        };
+        i += 1;
+    }
+
+    let width = if fmt.get(i) == Some(&b'*') {
+        i += 1;
+        Count::Arg(index(&mut i)?)
+    } else {
+        Count::Fixed(number(&mut i)?.unwrap_or(0))
+    };
+
+    let precision = if fmt.get(i) == Some(&b'.') {
+        i += 1;
+        if fmt.get(i) == Some(&b'*') {
+            i += 1;
+            Some(Count::Arg(index(&mut i)?))
+        } else {
+            Some(Count::Fixed(number(&mut i)?.unwrap_or(0)))
+        }
+    } else {
+        None
+    };
+
+    let mut length = Length::None;
+    loop {
+        length = match (length, fmt.get(i)) {
+            (Length::None, Some(b'h')) => Length::Short,
+            (Length::Short, Some(b'h')) => Length::Char,
+            (Length::None, Some(b'l')) => Length::Long,
+            (Length::Long, Some(b'l')) => Length::LongLong,
+            (Length::None, Some(b'q')) => Length::LongLong,
+            (Length::None, Some(b'L')) => Length::LongDouble,
+            (Length::None, Some(b'j')) => Length::IntMax,
+            (Length::None, Some(b'z' | b'Z')) => Length::Size,
+            (Length::None, Some(b't')) => Length::PtrDiff,
+            _ => break,
+        };
+        i += 1;
+    }
+
+    let Some(&conv) = fmt.get(i) else {
+        return Ok(None);
+    };
+
+    // Argument indices count from 1.
+    let valid = |index: Option<usize>| index.is_none_or(|n| (1..=NL_ARGMAX).contains(&n));
+    let count_valid = |count: Option<Count>| match count {
+        Some(Count::Arg(index)) => valid(index),
+        _ => true,
+    };
+    if !valid(index_) || !count_valid(Some(width)) || !count_valid(precision) {
+        return Ok(None);
+    }
+    Ok(Some((
+        RawSpec {
+            index: index_,
+            flags,
+            width,
+            precision,
+            length,
+            conv,
+        },
+        i + 1,
+    )))
+}
+
+/// Iterate over the well-formed conversion specifications in `fmt`, and the
+/// positions after them.
+fn specs(fmt: &[u8]) -> impl Iterator<Item = Result<(RawSpec, usize), Errno>> + '_ {
+    let mut i = 0;
+    core::iter::from_fn(move || loop {
+        i += fmt.get(i..)?.iter().position(|c| *c == b'%')? + 1;
+        if fmt.get(i) == Some(&b'%') {
+            i += 1;
+            continue;
+        }
+        return match parse(fmt, i) {
+            Ok(Some((spec, next))) => {
+                i = next;
+                Some(Ok((spec, next)))
+            }
+            Ok(None) => continue,
+            Err(e) => Some(Err(e)),
+        };
+    })
+}
+
+/// The type of a variadic argument, as far as `va_arg` is concerned.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Type {
+    Int,
+    LongLong,
+    Ptr,
+    Double,
+    LongDouble,
+}
+
+impl Type {
+    /// The type that `long`, `size_t` and `ptrdiff_t` are passed as.
+    const WORD: Self = if size_of::<c_long>() == 8 {
+        Self::LongLong
+    } else {
+        Self::Int
+    };
+}
+
+impl RawSpec {
+    /// Does this conversion refer to any arguments by position?
+    fn is_positional(&self) -> bool {
+        let positional = |count: Option<Count>| matches!(count, Some(Count::Arg(Some(_))));
+        self.index.is_some() || positional(Some(self.width)) || positional(self.precision)
+    }
+
+    /// The type of the argument this conversion consumes, if any.
+    fn arg_type(&self) -> Option<Type> {
+        Some(match self.conv {
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' | b'b' | b'B' => match self.length {
+                Length::None | Length::Char | Length::Short => Type::Int,
+                Length::Long | Length::Size | Length::PtrDiff => Type::WORD,
+                Length::LongLong | Length::LongDouble | Length::IntMax => Type::LongLong,
+            },
+            b'c' | b'C' => Type::Int,
+            b's' | b'S' | b'p' | b'n' => Type::Ptr,
+            b'e' | b'E' | b'f' | b'F' | b'g' | b'G' | b'a' | b'A' => {
+                if self.length == Length::LongDouble {
+                    Type::LongDouble
+                } else {
+                    Type::Double
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Arg {
+    Int(i64),
+    Ptr(*mut c_void),
+    Float(Float),
+}
+
+impl Arg {
+    fn int(self) -> i64 {
+        match self {
+            Self::Int(value) => value,
+            Self::Ptr(ptr) => ptr as usize as i64,
+            Self::Float(_) => 0,
+        }
+    }
+
+    fn ptr(self) -> *mut c_void {
+        match self {
+            Self::Ptr(ptr) => ptr,
+            Self::Int(value) => value as usize as *mut c_void,
+            Self::Float(_) => core::ptr::null_mut(),
+        }
+    }
+
+    fn float(self) -> Float {
+        match self {
+            Self::Float(value) => value,
+            _ => Float::from_f64(0.0),
+        }
+    }
+}
+
+unsafe fn read(list: &mut VaList<'_>, ty: Type) -> Arg {
+    match ty {
+        Type::Int => Arg::Int(list.next_arg::<c_int>().into()),
+        Type::LongLong => Arg::Int(list.next_arg::<i64>()),
+        Type::Ptr => Arg::Ptr(list.next_arg::<*mut c_void>()),
+        Type::Double => Arg::Float(Float::from_f64(list.next_arg::<f64>())),
+        Type::LongDouble => Arg::Float(float::next_long_double(list)),
+    }
+}
+
+/// The variadic arguments.
+struct Args<'a, 'f> {
+    list: VaList<'f>,
+    /// For positional arguments, the type of each argument, indexed from 1.
+    types: &'a [Type],
+    /// For positional arguments, the list as it started, and the index of the
+    /// argument that `list` reads next.
+    start: VaList<'f>,
+    next: usize,
+    /// For positional arguments, the index of the last argument taken by a
+    /// conversion without an `n$`.
+    sequential: usize,
+}
+
+impl Args<'_, '_> {
+    unsafe fn get(&mut self, index: Option<usize>, ty: Type) -> Arg {
+        if self.types.is_empty() {
+            return read(&mut self.list, ty);
+        }
+        let index = index.unwrap_or_else(|| next_sequential(&mut self.sequential));
+        if index < self.next {
+            self.list = self.start.clone();
+            self.next = 1;
+        }
+        while self.next < index {
+            read(&mut self.list, self.types[self.next]);
+            self.next += 1;
+        }
+        self.next += 1;
+        read(&mut self.list, self.types[index])
+    }
+}
+
+/// When arguments are positional, glibc gives conversions without an `n$`
+/// the arguments in order, counting separately from the positional ones.
+fn next_sequential(sequential: &mut usize) -> usize {
+    *sequential += 1;
+    *sequential
+}
+
+/// Format `fmt` with `list` into `out`, and return the number of bytes
+/// produced, or -1 with `errno` set on failure.
+pub(super) unsafe fn format(out: &mut dyn Output, fmt: *const c_char, list: VaList<'_>) -> c_int {
+    // `%m` prints the `errno` value from when we're called.
+    let errno = errno::errno().0;
+    let fmt = CStr::from_ptr(fmt).to_bytes();
+
+    let mut w = Writer {
+        out,
+        count: 0,
+        failed: false,
+    };
+
+    let positional = specs(fmt).any(|spec| match spec {
+        Ok((spec, _)) => spec.is_positional(),
+        Err(_) => false,
+    });
+
+    let result = if positional {
+        format_positional(&mut w, fmt, list, errno)
+    } else {
+        let start = list.clone();
+        let mut args = Args {
+            list,
+            types: &[],
+            start,
+            next: 0,
+            sequential: 0,
+        };
+        run(&mut w, fmt, &mut args, errno)
+    };
+
+    if let Err(e) = result {
+        set_errno(e);
+        return -1;
+    }
+    if w.failed {
+        return -1;
+    }
+    if w.count > c_int::MAX as usize {
+        set_errno(Errno(libc::EOVERFLOW));
+        return -1;
+    }
+    w.count as c_int
+}
+
+/// Format `value` for the `strfrom*` functions, whose `fmt` is a `%`, an
+/// optional precision, and one of `aAeEfFgG`. Anything after that is
+/// ignored, and glibc aborts on any other format, so we do too.
+pub(super) unsafe fn format_float(out: &mut dyn Output, fmt: *const c_char, value: Float) -> c_int {
+    let fmt = CStr::from_ptr(fmt).to_bytes();
+    let mut i = 1;
+    let mut precision = None;
+    if fmt.first() != Some(&b'%') {
+        libc::abort();
+    }
+    if fmt.get(i) == Some(&b'.') {
+        i += 1;
+        let mut p: usize = 0;
+        while let Some(c @ b'0'..=b'9') = fmt.get(i) {
+            p = p.saturating_mul(10).saturating_add(usize::from(c - b'0'));
+            i += 1;
+        }
+        precision = Some(p);
+    }
+    let conv = match fmt.get(i) {
+        Some(c @ (b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G')) => *c,
+        _ => libc::abort(),
+    };
+
+    let mut w = Writer {
+        out,
+        count: 0,
+        failed: false,
+    };
+    let spec = Spec {
+        flags: 0,
+        width: 0,
+        precision,
+    };
+    float::format(&mut w, &value, &spec, conv);
+
+    if w.failed {
+        return -1;
+    }
+    if w.count > c_int::MAX as usize {
+        set_errno(Errno(libc::EOVERFLOW));
+        return -1;
+    }
+    w.count as c_int
+}
+
+/// Collect the argument types, and then format. This is separate from
+/// `format` so that only formats with positional arguments need the stack
+/// space for the types.
+#[inline(never)]
+unsafe fn format_positional(
+    w: &mut Writer<'_>,
+    fmt: &[u8],
+    list: VaList<'_>,
+    errno: c_int,
+) -> Result<(), Errno> {
+    // Arguments whose types aren't given by the format are assumed to be
+    // `int`s.
+    let mut types = [Type::Int; NL_ARGMAX + 1];
+    let mut count = 0;
+    let mut sequential = 0;
+
+    for spec in specs(fmt) {
+        let (spec, _) = spec?;
+        let mut record = |index: Option<usize>, ty| {
+            let index = index.unwrap_or_else(|| next_sequential(&mut sequential));
+            if index > NL_ARGMAX {
+                return Err(Errno(libc::EINVAL));
+            }
+            types[index] = ty;
+            count = max(count, index);
+            Ok(())
+        };
+        if let Count::Arg(index) = spec.width {
+            record(index, Type::Int)?;
+        }
+        if let Some(Count::Arg(index)) = spec.precision {
+            record(index, Type::Int)?;
+        }
+        if let Some(ty) = spec.arg_type() {
+            record(spec.index, ty)?;
+        }
+    }
+
+    let start = list.clone();
+    let mut args = Args {
+        list,
+        types: &types[..=count],
+        start,
+        next: 1,
+        sequential: 0,
+    };
+    run(w, fmt, &mut args, errno)
+}
+
+unsafe fn run(
+    w: &mut Writer<'_>,
+    fmt: &[u8],
+    args: &mut Args<'_, '_>,
+    errno: c_int,
+) -> Result<(), Errno> {
+    let mut i = 0;
+    loop {
+        let literal = fmt[i..].iter().position(|c| *c == b'%');
+        let end = literal.map_or(fmt.len(), |offset| i + offset);
+        w.put(&fmt[i..end]);
+        if literal.is_none() {
+            return Ok(());
+        }
+
+        let start = end;
+        i = end + 1;
+        if fmt.get(i) == Some(&b'%') {
+            w.put(b"%");
+            i += 1;
+            continue;
+        }
+        let Some((raw, next)) = parse(fmt, i)? else {
+            // Print a malformed specification as is.
+            w.put(b"%");
+            continue;
+        };
+        i = next;
+
+        let mut flags = raw.flags;
+        let width = match raw.width {
+            Count::Fixed(n) => n,
+            Count::Arg(index) => {
+                let n = args.get(index, Type::Int).int() as c_int;
+                if n < 0 {
+                    flags |= LEFT;
+                }
+                n.unsigned_abs() as usize
+            }
+        };
+        let precision = match raw.precision {
+            None => None,
+            Some(Count::Fixed(n)) => Some(n),
+            Some(Count::Arg(index)) => {
+                let n = args.get(index, Type::Int).int() as c_int;
+                usize::try_from(n).ok()
+            }
+        };
+        if flags & LEFT != 0 {
+            flags &= !ZERO;
+        }
+        let spec = Spec {
+            flags,
+            width,
+            precision,
+        };
+
+        let arg = raw.arg_type().map(|ty| args.get(raw.index, ty));
+        if !convert(w, &spec, &raw, arg, errno)? {
+            // Print an unknown conversion as is.
+            w.put(&fmt[start..i]);
+        }
+    }
+}
+
+/// Perform a conversion, returning `false` if it's not one we know.
+unsafe fn convert(
+    w: &mut Writer<'_>,
+    spec: &Spec,
+    raw: &RawSpec,
+    arg: Option<Arg>,
+    errno: c_int,
+) -> Result<bool, Errno> {
+    let arg = arg.unwrap_or(Arg::Int(0));
+    let long = matches!(raw.length, Length::Long);
+    match raw.conv {
+        b'd' | b'i' => {
+            let value = arg.int();
+            let value = match raw.length {
+                Length::None => i64::from(value as c_int),
+                Length::Char => i64::from(value as i8),
+                Length::Short => i64::from(value as i16),
+                // `long` is the same size as `ptrdiff_t` on all our platforms.
+                Length::Long | Length::Size | Length::PtrDiff => value as ptrdiff_t as i64,
+                Length::LongLong | Length::LongDouble | Length::IntMax => value as intmax_t,
+            };
+            let sign: &[u8] = if value < 0 {
+                b"-"
+            } else if spec.flags & PLUS != 0 {
+                b"+"
+            } else if spec.flags & SPACE != 0 {
+                b" "
+            } else {
+                b""
+            };
+            integer(w, spec, value.unsigned_abs(), 10, false, sign);
+        }
+        b'o' | b'u' | b'x' | b'X' | b'b' | b'B' => {
+            let value = arg.int();
+            let value = match raw.length {
+                Length::None => u64::from(value as u32),
+                Length::Char => u64::from(value as u8),
+                Length::Short => u64::from(value as u16),
+                Length::Long | Length::Size | Length::PtrDiff => value as size_t as u64,
+                Length::LongLong | Length::LongDouble | Length::IntMax => value as u64,
+            };
+            let (base, prefix): (u64, &[u8]) = match raw.conv {
+                b'o' => (8, b""),
+                b'u' => (10, b""),
+                b'x' => (16, b"0x"),
+                b'X' => (16, b"0X"),
+                b'b' => (2, b"0b"),
+                _ => (2, b"0B"),
+            };
+            let prefix = if spec.flags & ALT != 0 && value != 0 {
+                prefix
+            } else {
+                b""
+            };
+            integer(w, spec, value, base, raw.conv == b'X', prefix);
+        }
+        b'p' => {
+            let ptr = arg.ptr();
+            if ptr.is_null() {
+                w.padded(spec.flags, spec.width, b"(nil)");
+            } else {
+                let prefix: &[u8] = if spec.flags & PLUS != 0 {
+                    b"+0x"
+                } else if spec.flags & SPACE != 0 {
+                    b" 0x"
+                } else {
+                    b"0x"
+                };
+                integer(w, spec, ptr as usize as u64, 16, false, prefix);
+            }
+        }
+        b'c' if !long => {
+            w.padded(spec.flags, spec.width, &[arg.int() as u8]);
+        }
+        b'c' | b'C' => {
+            let mut buf = [0; 4];
+            let bytes = encode(arg.int() as wint_t, &mut buf)?;
+            w.padded(spec.flags, spec.width, bytes);
+        }
+        b's' if !long => {
+            let s = arg.ptr().cast::<u8>();
+            let bytes = if s.is_null() {
+                null_str(spec.precision)
+            } else {
+                let limit = spec.precision.unwrap_or(usize::MAX);
+                let mut len = 0;
+                while len < limit && *s.add(len) != 0 {
+                    len += 1;
+                }
+                core::slice::from_raw_parts(s, len)
+            };
+            w.padded(spec.flags, spec.width, bytes);
+        }
+        b's' | b'S' => {
+            let s = arg.ptr().cast::<wint_t>();
+            if s.is_null() {
+                w.padded(spec.flags, spec.width, null_str(spec.precision));
+            } else {
+                wide_str(w, spec, s)?;
+            }
+        }
+        b'm' => {
+            let mut buf = [0_u8; 32];
+            let message = error_message(errno, &mut buf);
+            let len = message.len().min(spec.precision.unwrap_or(usize::MAX));
+            w.padded(spec.flags, spec.width, &message[..len]);
+        }
+        b'n' => {
+            let ptr = arg.ptr();
+            let count = w.count;
+            match raw.length {
+                Length::None => ptr.cast::<c_int>().write(count as c_int),
+                Length::Char => ptr.cast::<i8>().write(count as i8),
+                Length::Short => ptr.cast::<i16>().write(count as i16),
+                Length::Long => ptr.cast::<c_long>().write(count as c_long),
+                Length::Size | Length::PtrDiff => ptr.cast::<size_t>().write(count),
+                Length::LongLong | Length::LongDouble | Length::IntMax => {
+                    ptr.cast::<intmax_t>().write(count as intmax_t)
+                }
+            }
+        }
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' | b'a' | b'A' => {
+            float::format(w, &arg.float(), spec, raw.conv);
+        }
+        // glibc ignores any flags, width or precision given to `%%`.
+        b'%' => w.put(b"%"),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Format an unsigned integer, with `prefix` being the sign or base prefix.
+fn integer(w: &mut Writer<'_>, spec: &Spec, mut value: u64, base: u64, upper: bool, prefix: &[u8]) {
+    let xdigits: &[u8; 16] = if upper {
+        b"0123456789ABCDEF"
+    } else {
+        b"0123456789abcdef"
+    };
+    let mut buf = [0_u8; 64];
+    let mut i = buf.len();
+    while value != 0 {
+        i -= 1;
+        buf[i] = xdigits[(value % base) as usize];
+        value /= base;
+    }
+    let digits = &buf[i..];
+
+    // The precision is the minimum number of digits. With the `#` flag, octal
+    // numbers get a leading zero.
+    let mut min_digits = spec.precision.unwrap_or(1);
+    if base == 8 && spec.flags & ALT != 0 {
+        min_digits = max(min_digits, digits.len() + 1);
+    }
+    let zeros = min_digits.saturating_sub(digits.len());
+    let flags = if spec.precision.is_some() {
+        spec.flags & !ZERO
+    } else {
+        spec.flags
+    };
+
+    let grouping = if base == 10 { spec.grouping() } else { None };
+    let separators = grouping.as_ref().map_or(0, |grouping| {
+        grouping.separators(digits.len()) * grouping.sep.len()
+    });
+
+    let len = prefix.len() + zeros + digits.len() + separators;
+    w.pad_left(flags, spec.width, len);
+    w.put(prefix);
+    w.pad_zeros(flags, spec.width, len);
+    w.pad(b'0', zeros);
+    match &grouping {
+        Some(grouping) => {
+            let mut rest = digits.len();
+            grouping.put_digits(w, digits, &mut rest, digits.len());
+        }
+        None => w.put(digits),
+    }
+    w.pad_right(flags, spec.width, len);
+}
+
+/// glibc prints null strings as `(null)`, unless the precision is too small
+/// for that.
+fn null_str(precision: Option<usize>) -> &'static [u8] {
+    if precision.is_none_or(|precision| precision >= 6) {
+        b"(null)"
+    } else {
+        b""
+    }
+}
+
+/// Encode a wide character as a multibyte character. We only support UTF-8.
+fn encode(c: wint_t, buf: &mut [u8; 4]) -> Result<&[u8], Errno> {
+    match char::from_u32(c) {
+        Some(c) => Ok(c.encode_utf8(buf).as_bytes()),
+        None => Err(Errno(libc::EILSEQ)),
+    }
+}
+
+/// Write a wide string. The precision limits the number of bytes, and only
+/// whole characters are written.
+unsafe fn wide_str(w: &mut Writer<'_>, spec: &Spec, s: *const wint_t) -> Result<(), Errno> {
+    let limit = spec.precision.unwrap_or(usize::MAX);
+    let mut buf = [0; 4];
+
+    // Measure it first, to know how much padding it needs.
+    let mut len = 0;
+    let mut chars = 0;
+    while *s.add(chars) != 0 {
+        let n = encode(*s.add(chars), &mut buf)?.len();
+        if len + n > limit {
+            break;
+        }
+        len += n;
+        chars += 1;
+    }
+
+    w.pad_left(spec.flags & !ZERO, spec.width, len);
+    for i in 0..chars {
+        w.put(encode(*s.add(i), &mut buf)?);
+    }
+    w.pad_right(spec.flags, spec.width, len);
+    Ok(())
+}
+
+/// The `strerror` message for `errno`, without allocating.
+fn error_message(errno: c_int, buf: &mut [u8; 32]) -> &[u8] {
+    if errno == 0 {
+        return b"Success";
+    }
+    // Linux error numbers are below 4096.
+    if (1..4096).contains(&errno) {
+        let errno = rustix::io::Errno::from_raw_os_error(errno);
+        if let Some(message) = crate::error_str::error_str(errno) {
+            return message.as_bytes();
+        }
+    }
+
+    let mut i = buf.len();
+    let mut n = errno.unsigned_abs();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    if errno < 0 {
+        i -= 1;
+        buf[i] = b'-';
+    }
+    let prefix = b"Unknown error ";
+    i -= prefix.len();
+    buf[i..][..prefix.len()].copy_from_slice(prefix);
+    &buf[i..]
+}
@@ -0,0 +1,657 @@
+//! Floating-point conversions for `printf`.
+//!
+//! Decimal conversions produce exact digits, the way glibc does: the value
+//! is expanded into base-10⁹ limbs in a fixed-size array on the stack, so
+//! that nothing is allocated. This is the same scheme musl uses, driven by
+//! an integer significand instead of `long double` arithmetic, so that it
+//! works for `long double` formats Rust can't do arithmetic in.
+
+use super::{Grouping, Spec, Writer, ALT, PLUS, SPACE, ZERO};
+use core::ffi::VaList;
+
+/// A floating-point value, as the raw bits of one of the formats that C's
+/// `double` and `long double` use.
+#[derive(Clone, Copy)]
+pub(in crate::stdio) struct Float {
+    bits: u128,
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// IEEE 754 binary64.
+    Double,
+    /// The x87 80-bit extended format, with an explicit integer bit.
+    #[allow(dead_code)]
+    X87,
+    /// IEEE 754 binary128.
+    #[allow(dead_code)]
+    Quad,
+}
+
+enum Class {
+    Finite,
+    Infinite,
+    Nan,
+}
+
+/// The number of limbs needed to expand any `double`: the integer part of
+/// the largest one, or the fraction of the smallest one.
+const DOUBLE_LIMBS: usize = 128;
+
+/// The number of limbs needed to expand any `long double`. Both the x87 and
+/// binary128 formats have a 15-bit exponent.
+const LONG_DOUBLE_LIMBS: usize = 1848;
+
+const BILLION: u32 = 1_000_000_000;
+
+impl Float {
+    pub(in crate::stdio) fn from_f64(value: f64) -> Self {
+        Self {
+            bits: value.to_bits().into(),
+            format: Format::Double,
+        }
+    }
+
+    /// Make a `Float` from the bits of a binary128 `long double`.
+    #[cfg(target_arch = "riscv64")]
+    pub(in crate::stdio) fn from_quad(bits: u128) -> Self {
+        Self {
+            bits,
+            format: Format::Quad,
+        }
+    }
+
+    fn negative(&self) -> bool {
+        match self.format {
+            Format::Double => self.bits >> 63 != 0,
+            Format::X87 => self.bits >> 79 & 1 != 0,
+            Format::Quad => self.bits >> 127 != 0,
+        }
+    }
+
+    /// The biased exponent, and the significand bits without the sign and
+    /// exponent.
+    fn fields(&self) -> (i32, u128) {
+        match self.format {
+            Format::Double => (
+                (self.bits >> 52 & 0x7ff) as i32,
+                self.bits & ((1 << 52) - 1),
+            ),
+            Format::X87 => (
+                (self.bits >> 64 & 0x7fff) as i32,
+                self.bits & u128::from(u64::MAX),
+            ),
+            Format::Quad => (
+                (self.bits >> 112 & 0x7fff) as i32,
+                self.bits & ((1 << 112) - 1),
+            ),
+        }
+    }
+
+    fn class(&self) -> Class {
+        let (exp, frac) = self.fields();
+        let (max, frac) = match self.format {
+            Format::Double => (0x7ff, frac),
+            // Ignore the explicit integer bit.
+            Format::X87 => (0x7fff, frac & ((1 << 63) - 1)),
+            Format::Quad => (0x7fff, frac),
+        };
+        if exp != max {
+            Class::Finite
+        } else if frac == 0 {
+            Class::Infinite
+        } else {
+            Class::Nan
+        }
+    }
+
+    /// The number of bits in the significand.
+    fn mant_dig(&self) -> u32 {
+        match self.format {
+            Format::Double => 53,
+            Format::X87 => 64,
+            Format::Quad => 113,
+        }
+    }
+
+    /// Return `(m, e)` such that the magnitude is `m * 2^e`.
+    fn decompose(&self) -> (u128, i32) {
+        let (exp, frac) = self.fields();
+        match self.format {
+            Format::Double if exp == 0 => (frac, -1074),
+            Format::Double => (frac | 1 << 52, exp - 1075),
+            Format::X87 => (frac, exp.max(1) - 16383 - 63),
+            Format::Quad if exp == 0 => (frac, -16494),
+            Format::Quad => (frac | 1 << 112, exp - 16383 - 112),
+        }
+    }
+
+    /// Return the leading hex digit, the fraction digits, the number of
+    /// fraction digits, and the binary exponent, laid out the way glibc
+    /// prints them for `%a`. For the x87 format glibc prints the leading
+    /// digit straight from the explicit integer bit and the bits below it,
+    /// so 1.0 is `0x8p-3` rather than `0x1p+0`.
+    fn hex_parts(&self) -> (u32, u128, u32, i32) {
+        let (exp, frac) = self.fields();
+        match self.format {
+            Format::Double if exp == 0 && frac == 0 => (0, 0, 13, 0),
+            Format::Double if exp == 0 => (0, frac, 13, -1022),
+            Format::Double => (1, frac, 13, exp - 1023),
+            Format::X87 if frac == 0 => (0, 0, 15, 0),
+            Format::X87 => (
+                (frac >> 60) as u32,
+                frac & ((1 << 60) - 1),
+                15,
+                exp.max(1) - 16383 - 3,
+            ),
+            Format::Quad if exp == 0 && frac == 0 => (0, 0, 28, 0),
+            Format::Quad if exp == 0 => (0, frac, 28, -16382),
+            Format::Quad => (1, frac, 28, exp - 16383),
+        }
+    }
+}
+
+// `VaList` can't read `long double`s itself, so these follow each ABI's
+// `va_arg` rules by hand, on a mirror of the platform's `va_list`.
+
+/// Read a `long double` argument.
+#[cfg(target_arch = "x86_64")]
+pub(in crate::stdio) unsafe fn next_long_double(list: &mut VaList<'_>) -> Float {
+    #[repr(C)]
+    struct RawVaList {
+        gp_offset: u32,
+        fp_offset: u32,
+        overflow_arg_area: *const u8,
+        reg_save_area: *const u8,
+    }
+
+    // `long double` is always passed in memory, in a 16-byte aligned slot.
+    let raw = &mut *(list as *mut VaList<'_>).cast::<RawVaList>();
+    let addr = raw
+        .overflow_arg_area
+        .map_addr(|addr| addr.next_multiple_of(16));
+    raw.overflow_arg_area = addr.add(16);
+    let mut bytes = [0_u8; 16];
+    bytes[..10].copy_from_slice(&*addr.cast::<[u8; 10]>());
+    Float {
+        bits: u128::from_le_bytes(bytes),
+        format: Format::X87,
+    }
+}
+
+/// Read a `long double` argument.
+#[cfg(target_arch = "x86")]
+pub(in crate::stdio) unsafe fn next_long_double(list: &mut VaList<'_>) -> Float {
+    #[repr(C)]
+    struct RawVaList {
+        ptr: *const u8,
+    }
+
+    // `long double` takes 12 bytes on the stack, with 4-byte alignment.
+    let raw = &mut *(list as *mut VaList<'_>).cast::<RawVaList>();
+    let addr = raw.ptr;
+    raw.ptr = addr.add(12);
+    let mut bytes = [0_u8; 16];
+    bytes[..10].copy_from_slice(&*addr.cast::<[u8; 10]>());
+    Float {
+        bits: u128::from_le_bytes(bytes),
+        format: Format::X87,
+    }
+}
+
+/// Read a `long double` argument.
+#[cfg(target_arch = "aarch64")]
+pub(in crate::stdio) unsafe fn next_long_double(list: &mut VaList<'_>) -> Float {
+    #[repr(C)]
+    struct RawVaList {
+        stack: *const u8,
+        gr_top: *const u8,
+        vr_top: *const u8,
+        gr_offs: i32,
+        vr_offs: i32,
+    }
+
+    // `long double` is passed in a vector register while there are any left,
+    // and in a 16-byte aligned stack slot after that.
+    let raw = &mut *(list as *mut VaList<'_>).cast::<RawVaList>();
+    let offs = raw.vr_offs;
+    let addr = if offs < 0 && offs + 16 <= 0 {
+        raw.vr_offs = offs + 16;
+        raw.vr_top.offset(offs as isize)
+    } else {
+        if offs < 0 {
+            raw.vr_offs = offs + 16;
+        }
+        let addr = raw.stack.map_addr(|addr| addr.next_multiple_of(16));
+        raw.stack = addr.add(16);
+        addr
+    };
+    Float {
+        bits: u128::from_le_bytes(*addr.cast::<[u8; 16]>()),
+        format: Format::Quad,
+    }
+}
+
+/// Read a `long double` argument.
+#[cfg(target_arch = "riscv64")]
+pub(in crate::stdio) unsafe fn next_long_double(list: &mut VaList<'_>) -> Float {
+    #[repr(C)]
+    struct RawVaList {
+        ptr: *const u8,
+    }
+
+    // `long double` is passed in an aligned pair of registers, which the
+    // prologue saves next to the stack arguments, so it's in a 16-byte
+    // aligned slot either way.
+    let raw = &mut *(list as *mut VaList<'_>).cast::<RawVaList>();
+    let addr = raw.ptr.map_addr(|addr| addr.next_multiple_of(16));
+    raw.ptr = addr.add(16);
+    Float {
+        bits: u128::from_le_bytes(*addr.cast::<[u8; 16]>()),
+        format: Format::Quad,
+    }
+}
+
+/// Read a `long double` argument. On 32-bit ARM, `long double` is `double`.
+#[cfg(target_arch = "arm")]
+pub(in crate::stdio) unsafe fn next_long_double(list: &mut VaList<'_>) -> Float {
+    Float::from_f64(list.next_arg::<f64>())
+}
+
+/// Format `value` for the `e`, `f`, `g` and `a` conversions, and their
+/// uppercase variants.
+pub(super) fn format(w: &mut Writer<'_>, value: &Float, spec: &Spec, conv: u8) {
+    let upper = conv.is_ascii_uppercase();
+    let conv = conv.to_ascii_lowercase();
+
+    let sign: &[u8] = if value.negative() {
+        b"-"
+    } else if spec.flags & PLUS != 0 {
+        b"+"
+    } else if spec.flags & SPACE != 0 {
+        b" "
+    } else {
+        b""
+    };
+
+    let special: &[u8] = match (value.class(), upper) {
+        (Class::Finite, _) => b"",
+        (Class::Infinite, false) => b"inf",
+        (Class::Infinite, true) => b"INF",
+        (Class::Nan, false) => b"nan",
+        (Class::Nan, true) => b"NAN",
+    };
+    if !special.is_empty() {
+        let len = sign.len() + 3;
+        w.pad_left(spec.flags & !ZERO, spec.width, len);
+        w.put(sign);
+        w.put(special);
+        w.pad_right(spec.flags, spec.width, len);
+        return;
+    }
+
+    if conv == b'a' {
+        hex(w, value, spec, sign, upper);
+    } else if value.mant_dig() > 53 {
+        decimal::<LONG_DOUBLE_LIMBS>(w, value, spec, sign, conv, upper);
+    } else {
+        decimal::<DOUBLE_LIMBS>(w, value, spec, sign, conv, upper);
+    }
+}
+
+/// Format `value` in hexadecimal, for `%a`.
+fn hex(w: &mut Writer<'_>, value: &Float, spec: &Spec, sign: &[u8], upper: bool) {
+    let (mut lead, mut frac, mut digits, mut exp) = value.hex_parts();
+
+    match spec.precision {
+        Some(p) if p < digits as usize => {
+            // Round to nearest, ties to even.
+            let shift = 4 * (digits - p as u32);
+            let rem = frac & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            frac >>= shift;
+            let odd = if p == 0 { lead & 1 != 0 } else { frac & 1 != 0 };
+            if rem > half || (rem == half && odd) {
+                frac += 1;
+                if frac >> (4 * p) != 0 {
+                    frac = 0;
+                    lead += 1;
+                }
+            }
+            digits = p as u32;
+            if lead > 0xf {
+                lead = 1;
+                exp += 4;
+            }
+        }
+        Some(_) => {}
+        None => {
+            while digits > 0 && frac & 0xf == 0 {
+                frac >>= 4;
+                digits -= 1;
+            }
+        }
+    }
+    let precision = spec.precision.unwrap_or(digits as usize);
+    let point = precision > 0 || spec.flags & ALT != 0;
+
+    let xdigits: &[u8; 16] = if upper {
+        b"0123456789ABCDEF"
+    } else {
+        b"0123456789abcdef"
+    };
+    let mut ebuf = [0_u8; 8];
+    let estr = exponent(&mut ebuf, if upper { b'P' } else { b'p' }, exp, 1);
+
+    let len = sign.len() + 2 + 1 + point as usize + precision + estr.len();
+    w.pad_left(spec.flags, spec.width, len);
+    w.put(sign);
+    w.put(if upper { b"0X" } else { b"0x" });
+    w.pad_zeros(spec.flags, spec.width, len);
+    w.put(&[xdigits[lead as usize]]);
+    if point {
+        w.put(b".");
+    }
+    for i in (0..digits).rev() {
+        w.put(&[xdigits[(frac >> (4 * i) & 0xf) as usize]]);
+    }
+    w.pad(b'0', precision - digits as usize);
+    w.put(estr);
+    w.pad_right(spec.flags, spec.width, len);
+}
+
+/// Format `e` as an exponent, with the given marker and at least
+/// `min_digits` digits.
+fn exponent(buf: &mut [u8; 8], marker: u8, e: i32, min_digits: usize) -> &[u8] {
+    let mut i = buf.len();
+    let mut n = e.unsigned_abs();
+    while n != 0 || buf.len() - i < min_digits {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+    i -= 1;
+    buf[i] = if e < 0 { b'-' } else { b'+' };
+    i -= 1;
+    buf[i] = marker;
+    &buf[i..]
+}
+
+/// Format a limb as up to 9 digits, into the end of `buf`. If `pad` is set,
+/// always produce 9 digits.
+fn limb_digits(buf: &mut [u8; 9], mut limb: u32, pad: bool) -> &[u8] {
+    let mut i = buf.len();
+    while limb != 0 {
+        i -= 1;
+        buf[i] = b'0' + (limb % 10) as u8;
+        limb /= 10;
+    }
+    if pad {
+        buf[..i].fill(b'0');
+        i = 0;
+    }
+    &buf[i..]
+}
+
+/// Format `value` in decimal, for `%e`, `%f` and `%g`. `N` is the number of
+/// limbs to use, which must be enough for the value's format.
+fn decimal<const N: usize>(
+    w: &mut Writer<'_>,
+    value: &Float,
+    spec: &Spec,
+    sign: &[u8],
+    mut conv: u8,
+    upper: bool,
+) {
+    let mut big = [0_u32; N];
+    let mut p = spec.precision.unwrap_or(6) as i64;
+
+    // Put the significand in base 10⁹. `r` is the limb holding the units,
+    // with the integer part in `a..=r` and the fraction after it. Leave room
+    // to grow on the side that the exponent will grow it towards.
+    let (mut m, mut e2) = value.decompose();
+    if m == 0 {
+        e2 = 0;
+    }
+    let mut limbs = [0_u32; 5];
+    let mut k = 0;
+    loop {
+        limbs[k] = (m % u128::from(BILLION)) as u32;
+        m /= u128::from(BILLION);
+        k += 1;
+        if m == 0 {
+            break;
+        }
+    }
+    let mut a = if e2 < 0 { 1 } else { N - k };
+    for (i, limb) in limbs[..k].iter().rev().enumerate() {
+        big[a + i] = *limb;
+    }
+    let mut z = a + k;
+    let r = z - 1;
+
+    // Multiply by 2^e2.
+    while e2 > 0 {
+        let sh = e2.min(29);
+        let mut carry = 0;
+        for d in (a..z).rev() {
+            let x = (u64::from(big[d]) << sh) + carry;
+            big[d] = (x % u64::from(BILLION)) as u32;
+            carry = x / u64::from(BILLION);
+        }
+        if carry != 0 {
+            a -= 1;
+            big[a] = carry as u32;
+        }
+        while z > a && big[z - 1] == 0 {
+            z -= 1;
+        }
+        e2 -= sh;
+    }
+
+    // Divide by 2^-e2, stopping early once there are enough digits.
+    let need = 1 + (p as usize + value.mant_dig() as usize / 3).div_ceil(9);
+    while e2 < 0 {
+        let sh = (-e2).min(9);
+        let mut carry = 0;
+        for limb in &mut big[a..z] {
+            let rm = *limb & ((1 << sh) - 1);
+            *limb = (*limb >> sh) + carry;
+            carry = (BILLION >> sh) * rm;
+        }
+        if big[a] == 0 {
+            a += 1;
+        }
+        if carry != 0 {
+            big[z] = carry;
+            z += 1;
+        }
+        let b = if conv == b'f' { r } else { a };
+        if z - b > need {
+            z = b + need;
+        }
+        e2 += sh;
+    }
+
+    // `e` is the decimal exponent of the leading digit.
+    let leading_exponent = |big: &[u32; N], a: usize| {
+        let mut e = 9 * (r as i64 - a as i64);
+        let mut i = 10;
+        while big[a] >= i {
+            i *= 10;
+            e += 1;
+        }
+        e
+    };
+    let mut e = if a < z { leading_exponent(&big, a) } else { 0 };
+
+    // Round to nearest, ties to even. `j` is the number of digits to keep
+    // after the decimal point, which is negative if rounding happens in the
+    // integer part.
+    let mut j = p;
+    if conv != b'f' {
+        j -= e;
+    }
+    if conv == b'g' && p != 0 {
+        j -= 1;
+    }
+    if j < 9 * (z as i64 - r as i64 - 1) {
+        let mut d = (r as i64 + 1 + j.div_euclid(9)) as usize;
+        let i = 10_u32.pow(9 - j.rem_euclid(9) as u32);
+        let x = big[d] % i;
+        // Are there any significant digits after the ones we keep?
+        if x != 0 || d + 1 != z {
+            let odd = (big[d] / i) & 1 != 0 || (i == BILLION && d > a && big[d - 1] & 1 != 0);
+            let half = i / 2;
+            let up = x > half || (x == half && (d + 1 != z || odd));
+            big[d] -= x;
+            if up {
+                big[d] += i;
+                let mut c = d;
+                while big[c] >= BILLION {
+                    big[c] = 0;
+                    c -= 1;
+                    if c < a {
+                        a -= 1;
+                        big[a] = 0;
+                    }
+                    big[c] += 1;
+                }
+                e = leading_exponent(&big, a);
+            }
+        }
+        d += 1;
+        if z > d {
+            z = d;
+        }
+    }
+    while z > a && big[z - 1] == 0 {
+        z -= 1;
+    }
+
+    if conv == b'g' {
+        if p == 0 {
+            p = 1;
+        }
+        if p > e && e >= -4 {
+            conv = b'f';
+            p -= e + 1;
+        } else {
+            conv = b'e';
+            p -= 1;
+        }
+        if spec.flags & ALT == 0 {
+            // Drop trailing zeros.
+            let mut j = 9;
+            if z > a && big[z - 1] != 0 {
+                j = 0;
+                let mut i = 10;
+                while big[z - 1] % i == 0 {
+                    i *= 10;
+                    j += 1;
+                }
+            }
+            let digits = 9 * (z as i64 - r as i64 - 1) - j;
+            p = if conv == b'f' {
+                p.min(digits.max(0))
+            } else {
+                p.min((digits + e).max(0))
+            };
+        }
+    }
+    let point = p != 0 || spec.flags & ALT != 0;
+
+    let grouping = if conv == b'f' { spec.grouping() } else { None };
+    let int_digits = if e >= 0 { e as usize + 1 } else { 1 };
+    let mut ebuf = [0_u8; 8];
+    let estr: &[u8] = if conv == b'f' {
+        b""
+    } else {
+        let marker = if upper { b'E' } else { b'e' };
+        exponent(&mut ebuf, marker, e as i32, 2)
+    };
+    let mut len = 1 + p as usize + point as usize + estr.len();
+    if conv == b'f' {
+        len += int_digits - 1;
+        if let Some(grouping) = &grouping {
+            len += grouping.separators(int_digits) * grouping.sep.len();
+        }
+    }
+    let len = sign.len() + len;
+
+    w.pad_left(spec.flags, spec.width, len);
+    w.put(sign);
+    w.pad_zeros(spec.flags, spec.width, len);
+
+    let mut buf = [0_u8; 9];
+    if conv == b'f' {
+        if a > r {
+            a = r;
+        }
+        let mut rest = int_digits;
+        for (i, limb) in big[a..=r].iter().enumerate() {
+            let s = limb_digits(&mut buf, *limb, i != 0);
+            let s: &[u8] = if s.is_empty() { b"0" } else { s };
+            w.put_grouped(s, &mut rest, int_digits, grouping.as_ref());
+        }
+        if point {
+            w.put(b".");
+        }
+        let mut d = r + 1;
+        while d < z && p > 0 {
+            let s = limb_digits(&mut buf, big[d], true);
+            w.put(&s[..(p as usize).min(9)]);
+            d += 1;
+            p -= 9;
+        }
+        if p > 0 {
+            w.pad(b'0', p as usize);
+        }
+    } else {
+        if z <= a {
+            z = a + 1;
+        }
+        let mut d = a;
+        while d < z && p >= 0 {
+            let mut s = limb_digits(&mut buf, big[d], d != a);
+            if s.is_empty() {
+                s = b"0";
+            }
+            if d == a {
+                w.put(&s[..1]);
+                s = &s[1..];
+                if point {
+                    w.put(b".");
+                }
+            }
+            w.put(&s[..s.len().min(p as usize)]);
+            p -= s.len() as i64;
+            d += 1;
+        }
+        if p > 0 {
+            w.pad(b'0', p as usize);
+        }
+        w.put(estr);
+    }
+
+    w.pad_right(spec.flags, spec.width, len);
+}
+
+impl Writer<'_> {
+    fn put_grouped(
+        &mut self,
+        digits: &[u8],
+        rest: &mut usize,
+        total: usize,
+        grouping: Option<&Grouping>,
+    ) {
+        match grouping {
+            Some(grouping) => grouping.put_digits(self, digits, rest, total),
+            None => {
+                self.put(digits);
+                *rest -= digits.len();
+            }
+        }
+    }
+}
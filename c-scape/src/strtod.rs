@@ -1,6 +1,12 @@
+//! `strtod` and friends.
+//!
+//! Decimal strings are normalized into a bounded buffer of significant
+//! digits and handed to Rust's correctly rounded parser. Hexadecimal strings
+//! are exact in binary, so they're rounded here directly.
+
+use core::ffi::CStr;
 use core::ptr::null_mut;
-use core::str::FromStr;
-use core::{slice, str};
+use core::str::{self, FromStr};
 use errno::{set_errno, Errno};
 use libc::{c_char, c_double, c_float};
 
@@ -15,110 +21,92 @@ unsafe extern "C" fn atof(nptr: *const c_char) -> c_double {
 unsafe extern "C" fn strtof(nptr: *const c_char, endptr: *mut *mut c_char) -> c_float {
     libc!(libc::strtof(nptr, endptr));
 
-    let nptr = nptr.cast::<u8>();
-    let orig = nptr;
-    let (nptr, format) = scan_float(nptr);
-    let s = make_str(orig, nptr);
-
-    match format {
-        Format::Hexadecimal(_any_nonzero) => {
-            todo!("hexadecimal float parsing")
-        }
-        Format::Decimal(any_nonzero) => {
-            if let Ok(f) = f32::from_str(s) {
-                set_endptr(endptr, nptr);
-                set_errno_f32(f, any_nonzero);
-                return f;
-            }
-        }
-        Format::Infinity => {
-            set_endptr(endptr, nptr);
-            return if s.starts_with('-') {
-                -f32::INFINITY
-            } else {
-                f32::INFINITY
-            };
-        }
-        Format::NaN(payload) => {
-            let result = if s.starts_with('-') {
-                libm::copysignf(f32::NAN, -1.0)
-            } else {
-                libm::copysignf(f32::NAN, 1.0)
-            };
-            if let Some(payload) = payload {
-                if let Ok(payload) = u32::try_from(payload) {
-                    if (libm::copysignf(result, -1.0).to_bits() & payload) == 0 {
-                        set_endptr(endptr, nptr);
-                        return f32::from_bits(result.to_bits() | payload);
-                    }
-                }
-            } else {
-                set_endptr(endptr, nptr);
-                return result;
-            }
-        }
-    }
-
-    set_endptr(endptr, orig);
-    0.0
+    strtox(nptr, endptr)
 }
 
 #[no_mangle]
 unsafe extern "C" fn strtod(nptr: *const c_char, endptr: *mut *mut c_char) -> c_double {
     libc!(libc::strtod(nptr, endptr));
 
-    let nptr = nptr.cast::<u8>();
-    let orig = nptr;
-    let (nptr, format) = scan_float(nptr);
-    let s = make_str(orig, nptr);
+    strtox(nptr, endptr)
+}
 
-    match format {
-        Format::Hexadecimal(_any_nonzero) => {
-            todo!("hexadecimal float parsing")
-        }
-        Format::Decimal(any_nonzero) => {
-            if let Ok(f) = f64::from_str(s) {
-                set_endptr(endptr, nptr);
-                set_errno_f64(f, any_nonzero);
-                return f;
-            }
-        }
-        Format::Infinity => {
-            set_endptr(endptr, nptr);
-            return if s.starts_with('-') {
-                -f64::INFINITY
-            } else {
-                f64::INFINITY
-            };
-        }
-        Format::NaN(payload) => {
-            let result = if s.starts_with('-') {
-                libm::copysign(f64::NAN, -1.0)
-            } else {
-                libm::copysign(f64::NAN, 1.0)
-            };
-            if let Some(payload) = payload {
-                if (libm::copysign(result, -1.0).to_bits() & payload) == 0 {
-                    set_endptr(endptr, nptr);
-                    return f64::from_bits(result.to_bits() | payload);
-                }
-            } else {
-                set_endptr(endptr, nptr);
-                return result;
-            }
-        }
+/// The floating-point types we parse into.
+trait Float: FromStr + Copy {
+    /// The number of bits in the significand, including the implicit bit.
+    const MANT_DIG: u32;
+    /// The exponent of the smallest normal value.
+    const MIN_EXP: i64;
+    /// The exponent bias.
+    const BIAS: i64;
+    /// The sign bit.
+    const SIGN: u64;
+    /// The bits of a positive infinity.
+    const INFINITY: u64;
+    /// The bits of a positive quiet NaN.
+    const NAN: u64;
+    /// The bits of a NaN that are available for a payload.
+    const PAYLOAD: u64;
+
+    fn from_bits(bits: u64) -> Self;
+    fn to_bits(self) -> u64;
+}
+
+impl Float for f32 {
+    const MANT_DIG: u32 = f32::MANTISSA_DIGITS;
+    const MIN_EXP: i64 = f32::MIN_EXP as i64 - 1;
+    const BIAS: i64 = 127;
+    const SIGN: u64 = 1 << 31;
+    const INFINITY: u64 = 0x7f80_0000;
+    const NAN: u64 = 0x7fc0_0000;
+    const PAYLOAD: u64 = (1 << 22) - 1;
+
+    fn from_bits(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
     }
 
-    set_endptr(endptr, orig);
-    0.0
+    fn to_bits(self) -> u64 {
+        f32::to_bits(self).into()
+    }
 }
 
-unsafe fn make_str<'a>(start: *const u8, nptr: *const u8) -> &'a str {
-    str::from_utf8_unchecked(slice::from_raw_parts(
-        start,
-        nptr.offset_from(start) as usize,
-    ))
-    .trim_start()
+impl Float for f64 {
+    const MANT_DIG: u32 = f64::MANTISSA_DIGITS;
+    const MIN_EXP: i64 = f64::MIN_EXP as i64 - 1;
+    const BIAS: i64 = 1023;
+    const SIGN: u64 = 1 << 63;
+    const INFINITY: u64 = 0x7ff0_0000_0000_0000;
+    const NAN: u64 = 0x7ff8_0000_0000_0000;
+    const PAYLOAD: u64 = (1 << 51) - 1;
+
+    fn from_bits(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+}
+
+/// The body of `strtof` and `strtod`.
+unsafe fn strtox<F: Float>(nptr: *const c_char, endptr: *mut *mut c_char) -> F {
+    let mut digits = Digits::new();
+    let (end, negative, format) = scan_float(nptr.cast(), &mut digits);
+
+    let sign = if negative { F::SIGN } else { 0 };
+    let bits = match format {
+        None => {
+            set_endptr(endptr, nptr.cast());
+            return F::from_bits(0);
+        }
+        Some(Format::Decimal) => decimal::<F>(&digits),
+        Some(Format::Hexadecimal(hex)) => hexadecimal::<F>(&hex),
+        Some(Format::Infinity) => F::INFINITY,
+        Some(Format::NaN(payload)) => F::NAN | payload.unwrap_or(0) & F::PAYLOAD,
+    };
+
+    set_endptr(endptr, end);
+    F::from_bits(bits | sign)
 }
 
 unsafe fn set_endptr(endptr: *mut *mut c_char, nptr: *const u8) {
@@ -127,175 +115,559 @@ unsafe fn set_endptr(endptr: *mut *mut c_char, nptr: *const u8) {
     }
 }
 
-// If we tried to parse a number but got infinity, or if the number was
-// subnormal, or we saw non-zero digits but got zero, set errno.
-fn set_errno_f32(f: f32, any_nonzero: bool) {
-    if f.is_infinite() || f.is_subnormal() || (f == 0.0 && any_nonzero) {
+/// The number of significant digits we keep. Any number halfway between two
+/// `double`s has fewer significant digits than this, so the digits after it
+/// only matter in whether any of them are nonzero.
+const MAX_DIGITS: usize = 800;
+
+/// The decimal exponents past which every nonzero number overflows or
+/// underflows.
+const MAX_EXP: i64 = 100_000;
+
+/// The significant digits of a decimal number, without leading zeros.
+struct Digits {
+    /// The digits, plus room for a final nonzero digit standing in for any
+    /// that were dropped.
+    buf: [u8; MAX_DIGITS + 1],
+    len: usize,
+    /// The value is `0.{buf} × 10^exp`.
+    exp: i64,
+}
+
+impl Digits {
+    fn new() -> Self {
+        Self {
+            buf: [0; MAX_DIGITS + 1],
+            len: 0,
+            exp: 0,
+        }
+    }
+}
+
+/// The significant bits of a hexadecimal number: the value is
+/// `mant × 2^exp`, plus a little more if `sticky` is set.
+struct Hex {
+    mant: u64,
+    sticky: bool,
+    exp: i64,
+}
+
+enum Format {
+    Decimal,
+    Hexadecimal(Hex),
+    Infinity,
+    NaN(Option<u64>),
+}
+
+fn decimal<F: Float>(digits: &Digits) -> u64 {
+    if digits.len == 0 {
+        return 0;
+    }
+
+    // Write the digits out as `0.{digits}e{exp}` for Rust's parser.
+    let mut buf = [0_u8; MAX_DIGITS + 32];
+    buf[..2].copy_from_slice(b"0.");
+    let mut len = 2;
+    buf[len..][..digits.len].copy_from_slice(&digits.buf[..digits.len]);
+    len += digits.len;
+    buf[len] = b'e';
+    len += 1;
+    let mut exp = digits.exp.clamp(-MAX_EXP, MAX_EXP);
+    if exp < 0 {
+        buf[len] = b'-';
+        len += 1;
+        exp = -exp;
+    }
+    let mut exp_digits = [0_u8; 8];
+    let mut n = exp_digits.len();
+    loop {
+        n -= 1;
+        exp_digits[n] = b'0' + (exp % 10) as u8;
+        exp /= 10;
+        if exp == 0 {
+            break;
+        }
+    }
+    buf[len..][..exp_digits.len() - n].copy_from_slice(&exp_digits[n..]);
+    len += exp_digits.len() - n;
+
+    let s = unsafe { str::from_utf8_unchecked(&buf[..len]) };
+    let Ok(f) = F::from_str(s) else {
+        unreachable!()
+    };
+    let bits = f.to_bits();
+
+    // Report overflow to infinity, and underflow to a subnormal or zero
+    // unless the result is exact.
+    let underflow = bits < 1 << (F::MANT_DIG - 1) && !(bits != 0 && is_exact::<F>(digits, bits));
+    if underflow || bits == F::INFINITY {
         set_errno(Errno(libc::ERANGE));
     }
+    bits
+}
+
+/// Test whether `digits` are exactly the subnormal value with bits `m`.
+fn is_exact<F: Float>(digits: &Digits, m: u64) -> bool {
+    const BILLION: u64 = 1_000_000_000;
+
+    // The value is `m × 2^-q`, which is `(m >> tz) × 5^k × 10^-k`.
+    let q = (F::BIAS - 2) as u32 + F::MANT_DIG;
+    let tz = m.trailing_zeros();
+    let k = q - tz;
+
+    // Compute `(m >> tz) × 5^k` in base-10⁹ limbs, least significant first.
+    let mut limbs = [0_u32; 90];
+    let mut n = 0;
+    let mut x = m >> tz;
+    while x != 0 {
+        limbs[n] = (x % BILLION) as u32;
+        x /= BILLION;
+        n += 1;
+    }
+    let mut left = k;
+    while left > 0 {
+        let step = left.min(12);
+        let mul = 5_u64.pow(step);
+        let mut carry = 0;
+        for limb in &mut limbs[..n] {
+            let v = u64::from(*limb) * mul + carry;
+            *limb = (v % BILLION) as u32;
+            carry = v / BILLION;
+        }
+        while carry != 0 {
+            limbs[n] = (carry % BILLION) as u32;
+            carry /= BILLION;
+            n += 1;
+        }
+        left -= step;
+    }
+
+    // That product is odd, so it has no trailing zeros. Compare its digits
+    // with ours, without their trailing zeros.
+    let mut len = digits.len;
+    while digits.buf[len - 1] == b'0' {
+        len -= 1;
+    }
+    let mut i = 0;
+    for (j, limb) in limbs[..n].iter().rev().enumerate() {
+        let mut buf = [0_u8; 9];
+        let mut limb = *limb;
+        for c in buf.iter_mut().rev() {
+            *c = b'0' + (limb % 10) as u8;
+            limb /= 10;
+        }
+        let buf = if j == 0 {
+            let zeros = buf.iter().take_while(|c| **c == b'0').count();
+            &buf[zeros..]
+        } else {
+            &buf[..]
+        };
+        if digits.buf.get(i..i + buf.len()) != Some(buf) {
+            return false;
+        }
+        i += buf.len();
+    }
+    i == len && digits.exp == i as i64 - i64::from(k)
 }
 
-fn set_errno_f64(f: f64, any_nonzero: bool) {
-    if f.is_infinite() || f.is_subnormal() || (f == 0.0 && any_nonzero) {
+fn hexadecimal<F: Float>(hex: &Hex) -> u64 {
+    if hex.mant == 0 {
+        return 0;
+    }
+
+    let p = i64::from(F::MANT_DIG);
+    let n = i64::from(u64::BITS - hex.mant.leading_zeros());
+    // The exponent of the leading bit.
+    let e = hex.exp.saturating_add(n - 1);
+
+    // Normal numbers keep `p` bits, and subnormals keep fewer.
+    let keep = if e >= F::MIN_EXP {
+        p
+    } else {
+        p - F::MIN_EXP.saturating_sub(e).min(p + 2)
+    };
+    let (m, inexact) = round(hex.mant, hex.sticky, n - keep);
+
+    // Compute the bits with the implicit bit included, so that a carry out of
+    // the significand increments the exponent.
+    let bits = if e >= F::MIN_EXP {
+        let exp = e
+            .saturating_add(F::BIAS - 1)
+            .min(F::INFINITY as i64 >> (p - 1));
+        ((exp as u64) << (p - 1)).saturating_add(m)
+    } else {
+        m
+    };
+
+    if bits >= F::INFINITY {
         set_errno(Errno(libc::ERANGE));
+        return F::INFINITY;
     }
+
+    // Like glibc, detect tininess after rounding: report underflow unless
+    // rounding to `p` bits, ignoring the exponent range, would reach the
+    // smallest normal value.
+    if inexact && e < F::MIN_EXP {
+        let carries = round(hex.mant, hex.sticky, n - p).0 >> p != 0;
+        if !(e == F::MIN_EXP - 1 && carries) {
+            set_errno(Errno(libc::ERANGE));
+        }
+    }
+
+    bits
 }
 
-unsafe fn scan_float(mut nptr: *const u8) -> (*const u8, Format) {
-    while (*nptr).is_ascii_whitespace() {
+/// Round `mant` to drop `shift` low bits, with round-half-even, and report
+/// whether the result is inexact.
+fn round(mant: u64, sticky: bool, shift: i64) -> (u64, bool) {
+    if shift <= 0 {
+        return (mant << -shift, sticky);
+    }
+
+    let mant = u128::from(mant);
+    let shift = shift as u32;
+    let m = mant >> shift;
+    let rest = mant & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let up = rest > half || (rest == half && (sticky || m & 1 != 0));
+    (m as u64 + u64::from(up), rest != 0 || sticky)
+}
+
+/// Test whether `c` is a space in the C locale.
+fn is_space(c: u8) -> bool {
+    c == b' ' || (b'\t'..=b'\r').contains(&c)
+}
+
+/// Test whether the bytes at `nptr` start with `prefix`, ignoring case.
+unsafe fn starts_with(nptr: *const u8, prefix: &[u8]) -> bool {
+    for (i, c) in prefix.iter().enumerate() {
+        if (*nptr.add(i)).to_ascii_lowercase() != *c {
+            return false;
+        }
+    }
+    true
+}
+
+/// Test whether the bytes at `nptr` start with the decimal point `point`.
+unsafe fn at_point(nptr: *const u8, point: &[u8]) -> bool {
+    point.iter().enumerate().all(|(i, c)| *nptr.add(i) == *c)
+}
+
+/// Scan a floating-point number, and return the end of it, whether it's
+/// negative, and what it is. If there's no number, return `None`.
+unsafe fn scan_float(
+    mut nptr: *const u8,
+    digits: &mut Digits,
+) -> (*const u8, bool, Option<Format>) {
+    while is_space(*nptr) {
         nptr = nptr.add(1);
     }
 
+    let negative = *nptr == b'-';
     if *nptr == b'-' || *nptr == b'+' {
         nptr = nptr.add(1);
     }
 
-    if (*nptr).to_ascii_lowercase() == b'i'
-        && (*nptr.add(1)).to_ascii_lowercase() == b'n'
-        && (*nptr.add(2)).to_ascii_lowercase() == b'f'
-    {
+    if starts_with(nptr, b"inf") {
         nptr = nptr.add(3);
-        if (*nptr).to_ascii_lowercase() == b'i'
-            && (*nptr.add(1)).to_ascii_lowercase() == b'n'
-            && (*nptr.add(2)).to_ascii_lowercase() == b'i'
-            && (*nptr.add(3)).to_ascii_lowercase() == b't'
-            && (*nptr.add(4)).to_ascii_lowercase() == b'y'
-        {
+        if starts_with(nptr, b"inity") {
             nptr = nptr.add(5);
         }
-        return (nptr, Format::Infinity);
+        return (nptr, negative, Some(Format::Infinity));
     }
 
-    if (*nptr).to_ascii_lowercase() == b'n'
-        && (*nptr.add(1)).to_ascii_lowercase() == b'a'
-        && (*nptr.add(2)).to_ascii_lowercase() == b'n'
-    {
+    if starts_with(nptr, b"nan") {
         nptr = nptr.add(3);
+        let mut payload = None;
         if *nptr == b'(' {
-            let paren = nptr;
-            nptr = nptr.add(1);
-            let payload = if *nptr == b'0' && (*nptr.add(1)).to_ascii_lowercase() == b'x' {
-                nptr = nptr.add(2);
-                let start = nptr;
-                while (*nptr).is_ascii_hexdigit() {
-                    nptr = nptr.add(1);
-                }
-                let s = make_str(start, nptr);
-                if s.is_empty() {
-                    0
-                } else {
-                    u64::from_str_radix(s, 16).unwrap()
-                }
-            } else {
-                let start = nptr;
-                while (*nptr).is_ascii_digit() {
-                    nptr = nptr.add(1);
-                }
-                let s = make_str(start, nptr);
-                if s.is_empty() {
-                    0
-                } else {
-                    s.parse().unwrap()
-                }
-            };
-            if *nptr == b')' {
-                nptr = nptr.add(1);
-                return (nptr, Format::NaN(Some(payload)));
+            let start = nptr.add(1);
+            let mut end = start;
+            while (*end).is_ascii_alphanumeric() || *end == b'_' {
+                end = end.add(1);
+            }
+            if *end == b')' {
+                payload = parse_payload(start, end);
+                nptr = end.add(1);
             }
-            nptr = paren;
         }
-        return (nptr, Format::NaN(None));
+        return (nptr, negative, Some(Format::NaN(payload)));
     }
 
-    let mut hex = false;
-    if *nptr == b'0' {
-        nptr = nptr.add(1);
-        if *nptr == b'x' || *nptr == b'X' {
-            nptr = nptr.add(1);
-            hex = true;
-        }
-    }
+    let point = CStr::from_ptr((*libc::localeconv()).decimal_point).to_bytes();
+    let point = if point.is_empty() { b"." } else { point };
 
-    let mut any_nonzero = false;
-    if hex {
-        while (*nptr).is_ascii_hexdigit() {
-            if *nptr != b'0' {
-                any_nonzero = true;
-            }
-            nptr = nptr.add(1);
+    if *nptr == b'0' && (*nptr.add(1)).eq_ignore_ascii_case(&b'x') {
+        let start = nptr.add(2);
+        let mut digits = start;
+        if at_point(digits, point) {
+            digits = digits.add(point.len());
         }
-    } else {
-        while (*nptr).is_ascii_digit() {
-            if *nptr != b'0' {
-                any_nonzero = true;
-            }
-            nptr = nptr.add(1);
+        if (*digits).is_ascii_hexdigit() {
+            let (end, hex) = scan_hex(start, point);
+            return (end, negative, Some(Format::Hexadecimal(hex)));
         }
+        // A `0x` with no digits after it is just a `0`.
+        return (nptr.add(1), negative, Some(Format::Decimal));
     }
 
-    if *nptr == b'.' {
+    let mut any_digits = false;
+    let mut point_exp: i64 = 0;
+    while (*nptr).is_ascii_digit() {
+        push_digit(digits, *nptr, true);
+        any_digits = true;
         nptr = nptr.add(1);
-
-        if hex {
-            while (*nptr).is_ascii_hexdigit() {
-                if *nptr != b'0' {
-                    any_nonzero = true;
-                }
-                nptr = nptr.add(1);
-            }
-        } else {
+    }
+    if at_point(nptr, point) {
+        let after = nptr.add(point.len());
+        if any_digits || (*after).is_ascii_digit() {
+            nptr = after;
             while (*nptr).is_ascii_digit() {
-                if *nptr != b'0' {
-                    any_nonzero = true;
+                if digits.len == 0 && *nptr == b'0' {
+                    point_exp -= 1;
+                } else {
+                    push_digit(digits, *nptr, false);
                 }
+                any_digits = true;
                 nptr = nptr.add(1);
             }
         }
     }
+    if !any_digits {
+        return (nptr, negative, None);
+    }
+
+    let (end, exp) = scan_exponent(nptr, b'e');
+    digits.exp = digits.exp.saturating_add(point_exp).saturating_add(exp);
+    (end, negative, Some(Format::Decimal))
+}
+
+/// Append a decimal digit to `digits`. Once the buffer is full, remember
+/// whether any dropped digit was nonzero in its final slot.
+fn push_digit(digits: &mut Digits, c: u8, integer: bool) {
+    if digits.len == 0 && c == b'0' {
+        return;
+    }
+    if digits.len < MAX_DIGITS {
+        digits.buf[digits.len] = c;
+        digits.len += 1;
+    } else if c != b'0' {
+        digits.buf[MAX_DIGITS] = b'1';
+        digits.len = MAX_DIGITS + 1;
+    }
+    if integer {
+        digits.exp = digits.exp.saturating_add(1);
+    }
+}
 
-    let mut before_exp = None;
-    if hex {
-        if *nptr == b'p' || *nptr == b'P' {
-            before_exp = Some(nptr);
-            nptr = nptr.add(1);
+/// Scan the digits of a hexadecimal number, starting after the `0x`.
+unsafe fn scan_hex(mut nptr: *const u8, point: &[u8]) -> (*const u8, Hex) {
+    let mut hex = Hex {
+        mant: 0,
+        sticky: false,
+        exp: 0,
+    };
+    let mut fraction = false;
+    loop {
+        let c = *nptr;
+        let d = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ if !fraction && at_point(nptr, point) => {
+                fraction = true;
+                nptr = nptr.add(point.len());
+                continue;
+            }
+            _ => break,
+        };
+        if hex.mant >> 60 == 0 {
+            hex.mant = hex.mant << 4 | u64::from(d);
+            if fraction {
+                hex.exp -= 4;
+            }
+        } else {
+            hex.sticky |= d != 0;
+            if !fraction {
+                hex.exp += 4;
+            }
         }
+        nptr = nptr.add(1);
+    }
+
+    let (end, exp) = scan_exponent(nptr, b'p');
+    hex.exp = hex.exp.saturating_add(exp);
+    (end, hex)
+}
+
+/// Scan an optional exponent introduced by `marker`, and return the end of
+/// it and its value.
+unsafe fn scan_exponent(nptr: *const u8, marker: u8) -> (*const u8, i64) {
+    if (*nptr).to_ascii_lowercase() != marker {
+        return (nptr, 0);
+    }
+    let mut p = nptr.add(1);
+    let negative = *p == b'-';
+    if *p == b'-' || *p == b'+' {
+        p = p.add(1);
+    }
+    if !(*p).is_ascii_digit() {
+        return (nptr, 0);
+    }
+    let mut exp: i64 = 0;
+    while (*p).is_ascii_digit() {
+        exp = exp.saturating_mul(10).saturating_add(i64::from(*p - b'0'));
+        p = p.add(1);
+    }
+    (p, if negative { -exp } else { exp })
+}
+
+/// Parse the contents of a `nan(...)` the way `strtoull` with base 0 would.
+/// If they aren't entirely a number, there's no payload.
+unsafe fn parse_payload(start: *const u8, end: *const u8) -> Option<u64> {
+    let s = str::from_utf8_unchecked(core::slice::from_raw_parts(
+        start,
+        end.offset_from(start) as usize,
+    ));
+    let (s, radix) = if let Some(s) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        (s, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        (&s[1..], 8)
     } else {
-        if *nptr == b'e' || *nptr == b'E' {
-            before_exp = Some(nptr);
-            nptr = nptr.add(1);
+        (s, 10)
+    };
+    if s.is_empty() {
+        return None;
+    }
+    if !s.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    // Like `strtoull`, saturate on overflow, and set `errno` to say so.
+    match u64::from_str_radix(s, radix) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            set_errno(Errno(libc::ERANGE));
+            Some(u64::MAX)
         }
     }
+}
 
-    if let Some(before_exp) = before_exp {
-        if *nptr == b'-' || *nptr == b'+' {
-            nptr = nptr.add(1);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errno::errno;
+
+    /// Parse `s` with `strtod`, returning the bits of the result, the number
+    /// of bytes consumed, and `errno`.
+    fn parse_f64(s: &CStr) -> (u64, usize, i32) {
+        unsafe {
+            let mut end = null_mut();
+            set_errno(Errno(0));
+            let value = strtod(s.as_ptr(), &mut end);
+            (
+                value.to_bits(),
+                end.offset_from(s.as_ptr()) as usize,
+                errno().0,
+            )
         }
+    }
 
-        if (*nptr).is_ascii_digit() {
-            while (*nptr).is_ascii_digit() {
-                nptr = nptr.add(1);
-            }
-        } else {
-            nptr = before_exp;
+    fn parse_f32(s: &CStr) -> (u32, usize, i32) {
+        unsafe {
+            let mut end = null_mut();
+            set_errno(Errno(0));
+            let value = strtof(s.as_ptr(), &mut end);
+            (
+                value.to_bits(),
+                end.offset_from(s.as_ptr()) as usize,
+                errno().0,
+            )
         }
     }
 
-    (
-        nptr,
-        if hex {
-            if before_exp.is_none() {
-                todo!("strtod hexadecimal format with no `p`");
-            }
-            Format::Hexadecimal(any_nonzero)
-        } else {
-            Format::Decimal(any_nonzero)
-        },
-    )
-}
+    #[test]
+    fn test_strtod_hex() {
+        assert_eq!(parse_f64(c"0x1.8p1"), (0x4008_0000_0000_0000, 7, 0));
+        assert_eq!(parse_f64(c"-0X.8P+2"), (0xc000_0000_0000_0000, 8, 0));
+        assert_eq!(parse_f64(c"0x1p-1074"), (1, 9, 0));
+        // Round to nearest, ties to even.
+        assert_eq!(
+            parse_f64(c"0x1.fffffffffffff8p0"),
+            (0x4000_0000_0000_0000, 20, 0)
+        );
+        assert_eq!(
+            parse_f64(c"0x1.00000000000008p0"),
+            (0x3ff0_0000_0000_0000, 20, 0)
+        );
+        assert_eq!(
+            parse_f64(c"0x1.00000000000018p0"),
+            (0x3ff0_0000_0000_0002, 20, 0)
+        );
+        // Without hex digits, only the `0` is parsed.
+        assert_eq!(parse_f64(c"0x"), (0, 1, 0));
+        assert_eq!(parse_f64(c"0x.p1"), (0, 1, 0));
+        assert_eq!(parse_f32(c"0x1.fffffep127"), (0x7f7f_ffff, 14, 0));
+        assert_eq!(parse_f32(c"0x1.0000018p0"), (0x3f80_0001, 13, 0));
+    }
 
-enum Format {
-    Decimal(bool),
-    Hexadecimal(bool),
-    Infinity,
-    NaN(Option<u64>),
+    #[test]
+    fn test_strtod_subnormal() {
+        let erange = libc::ERANGE;
+        assert_eq!(parse_f64(c"4.9406564584124654e-324"), (1, 23, erange));
+        assert_eq!(parse_f64(c"2.4703282292062328e-324"), (1, 23, erange));
+        assert_eq!(
+            parse_f64(c"2.2250738585072011e-308"),
+            (0x000f_ffff_ffff_ffff, 23, erange)
+        );
+        assert_eq!(
+            parse_f64(c"2.2250738585072014e-308"),
+            (0x0010_0000_0000_0000, 23, 0)
+        );
+        assert_eq!(parse_f64(c"0x1.8p-1074"), (2, 11, erange));
+        assert_eq!(parse_f64(c"0x1p-1075"), (0, 9, erange));
+        assert_eq!(parse_f64(c"1e-400"), (0, 6, erange));
+        assert_eq!(parse_f32(c"0x1p-149"), (1, 8, 0));
+        assert_eq!(parse_f32(c"1.4e-45"), (1, 7, erange));
+        assert_eq!(parse_f32(c"1e-46"), (0, 5, erange));
+    }
+
+    #[test]
+    fn test_strtod_overflow() {
+        let erange = libc::ERANGE;
+        assert_eq!(parse_f64(c"1e309"), (0x7ff0_0000_0000_0000, 5, erange));
+        assert_eq!(parse_f64(c"-1e400"), (0xfff0_0000_0000_0000, 6, erange));
+        assert_eq!(parse_f64(c"0x1p1024"), (0x7ff0_0000_0000_0000, 8, erange));
+        assert_eq!(
+            parse_f64(c"1.7976931348623157e308"),
+            (0x7fef_ffff_ffff_ffff, 22, 0)
+        );
+        assert_eq!(
+            parse_f64(c"1.7976931348623159e308"),
+            (0x7ff0_0000_0000_0000, 22, erange)
+        );
+        assert_eq!(parse_f32(c"3.5e38"), (0x7f80_0000, 6, erange));
+    }
+
+    #[test]
+    fn test_strtod_nan_inf() {
+        assert_eq!(parse_f64(c"nan"), (0x7ff8_0000_0000_0000, 3, 0));
+        assert_eq!(parse_f64(c"nan(0x1234)"), (0x7ff8_0000_0000_1234, 11, 0));
+        assert_eq!(parse_f64(c"NAN(12)"), (0x7ff8_0000_0000_000c, 7, 0));
+        assert_eq!(parse_f64(c"nan(010)"), (0x7ff8_0000_0000_0008, 8, 0));
+        // A payload that isn't a number is ignored.
+        assert_eq!(parse_f64(c"-nan(abc)"), (0xfff8_0000_0000_0000, 9, 0));
+        // Without a closing parenthesis, only the `nan` is parsed.
+        assert_eq!(parse_f64(c"nan("), (0x7ff8_0000_0000_0000, 3, 0));
+        assert_eq!(parse_f32(c"nan(0x10)"), (0x7fc0_0010, 9, 0));
+        assert_eq!(parse_f64(c"inf"), (0x7ff0_0000_0000_0000, 3, 0));
+        assert_eq!(parse_f64(c"-infinity"), (0xfff0_0000_0000_0000, 9, 0));
+        assert_eq!(parse_f64(c"infinit"), (0x7ff0_0000_0000_0000, 3, 0));
+    }
+
+    #[test]
+    fn test_strtod_partial() {
+        assert_eq!(parse_f64(c"  +1.5e"), (0x3ff8_0000_0000_0000, 6, 0));
+        assert_eq!(parse_f64(c"1e+"), (0x3ff0_0000_0000_0000, 1, 0));
+        assert_eq!(parse_f64(c"1.5x"), (0x3ff8_0000_0000_0000, 3, 0));
+        assert_eq!(parse_f64(c".e1"), (0, 0, 0));
+    }
 }
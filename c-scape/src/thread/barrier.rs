@@ -0,0 +1,207 @@
+use rustix::thread::futex;
+
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+use libc::c_int;
+
+use super::timeout::Deadline;
+
+const SIZEOF_PTHREAD_BARRIER_T: usize = libc::__SIZEOF_PTHREAD_BARRIER_T;
+
+/// The bit of `PthreadBarrierattrT::flags` and `PthreadBarrierT::flags` for
+/// `PTHREAD_PROCESS_SHARED`.
+const FLAG_PSHARED: u32 = 0x1;
+
+/// Set in `PthreadBarrierT::in_use` when `pthread_barrier_destroy` is waiting
+/// for the count in the other bits to drop to zero.
+const IN_USE_DESTROY_WAITING: u32 = 1 << 31;
+
+#[cfg_attr(target_pointer_width = "32", repr(C, align(4)))]
+#[cfg_attr(target_pointer_width = "64", repr(C, align(8)))]
+struct PthreadBarrierT {
+    /// The number of threads that must call `pthread_barrier_wait` to
+    /// complete each round.
+    count: u32,
+    flags: u32,
+    /// The number of threads that have arrived in the current round.
+    arrived: AtomicU32,
+    /// The round number, incremented each time a round completes. Threads
+    /// waiting for the round to complete wait on this.
+    seq: AtomicU32,
+    /// The number of threads inside `pthread_barrier_wait`, so that
+    /// `pthread_barrier_destroy` can wait for them to leave.
+    in_use: AtomicU32,
+    pad: [u8; SIZEOF_PTHREAD_BARRIER_T - 5 * 4],
+}
+libc_type!(PthreadBarrierT, pthread_barrier_t);
+
+#[repr(C)]
+struct PthreadBarrierattrT {
+    flags: u32,
+}
+libc_type!(PthreadBarrierattrT, pthread_barrierattr_t);
+
+impl PthreadBarrierT {
+    #[inline]
+    fn futex_flags(&self) -> futex::Flags {
+        if self.flags & FLAG_PSHARED != 0 {
+            futex::Flags::empty()
+        } else {
+            futex::Flags::PRIVATE
+        }
+    }
+
+    fn wait(&self) -> c_int {
+        let flags = self.futex_flags();
+        self.in_use.fetch_add(1, Ordering::Relaxed);
+
+        // Examine the round number before arriving, so that we can tell when
+        // the round we arrive in completes.
+        let seq = self.seq.load(Ordering::Acquire);
+
+        let result = if self.arrived.fetch_add(1, Ordering::AcqRel) + 1 == self.count {
+            // We're the last to arrive. Reset for the next round, and release
+            // everyone else.
+            self.arrived.store(0, Ordering::Relaxed);
+            self.seq.fetch_add(1, Ordering::Release);
+            futex::wake(&self.seq, flags, i32::MAX as u32).ok();
+            libc::PTHREAD_BARRIER_SERIAL_THREAD
+        } else {
+            while self.seq.load(Ordering::Acquire) == seq {
+                Deadline::Never.wait(&self.seq, flags, seq).ok();
+            }
+            0
+        };
+
+        // Leave, letting `pthread_barrier_destroy` know if it's waiting for us.
+        if self.in_use.fetch_sub(1, Ordering::Release) - 1 == IN_USE_DESTROY_WAITING {
+            futex::wake(&self.in_use, flags, 1).ok();
+        }
+        result
+    }
+
+    fn destroy(&self) -> c_int {
+        if self.arrived.load(Ordering::Relaxed) != 0 {
+            return libc::EBUSY;
+        }
+
+        // Threads released from the last round may still be on their way
+        // out, and the caller may free the barrier as soon as we return.
+        let flags = self.futex_flags();
+        loop {
+            let in_use = self.in_use.load(Ordering::Acquire);
+            if in_use & !IN_USE_DESTROY_WAITING == 0 {
+                return 0;
+            }
+            if in_use & IN_USE_DESTROY_WAITING == 0
+                && self
+                    .in_use
+                    .compare_exchange(
+                        in_use,
+                        in_use | IN_USE_DESTROY_WAITING,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            Deadline::Never
+                .wait(&self.in_use, flags, in_use | IN_USE_DESTROY_WAITING)
+                .ok();
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrierattr_init(attr: *mut PthreadBarrierattrT) -> c_int {
+    libc!(libc::pthread_barrierattr_init(checked_cast!(attr)));
+    ptr::write(attr, PthreadBarrierattrT { flags: 0 });
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrierattr_destroy(attr: *mut PthreadBarrierattrT) -> c_int {
+    libc!(libc::pthread_barrierattr_destroy(checked_cast!(attr)));
+    ptr::drop_in_place(attr);
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrierattr_setpshared(
+    attr: *mut PthreadBarrierattrT,
+    pshared: c_int,
+) -> c_int {
+    libc!(libc::pthread_barrierattr_setpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    match pshared {
+        libc::PTHREAD_PROCESS_PRIVATE => (*attr).flags &= !FLAG_PSHARED,
+        libc::PTHREAD_PROCESS_SHARED => (*attr).flags |= FLAG_PSHARED,
+        _ => return libc::EINVAL,
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrierattr_getpshared(
+    attr: *const PthreadBarrierattrT,
+    pshared: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_barrierattr_getpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    *pshared = if (*attr).flags & FLAG_PSHARED != 0 {
+        libc::PTHREAD_PROCESS_SHARED
+    } else {
+        libc::PTHREAD_PROCESS_PRIVATE
+    };
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrier_init(
+    barrier: *mut PthreadBarrierT,
+    attr: *const PthreadBarrierattrT,
+    count: libc::c_uint,
+) -> c_int {
+    libc!(libc::pthread_barrier_init(
+        checked_cast!(barrier),
+        checked_cast!(attr),
+        count
+    ));
+
+    if count == 0 || count > i32::MAX as u32 {
+        return libc::EINVAL;
+    }
+    let flags = if attr.is_null() { 0 } else { (*attr).flags };
+
+    ptr::write(
+        barrier,
+        PthreadBarrierT {
+            count,
+            flags,
+            arrived: AtomicU32::new(0),
+            seq: AtomicU32::new(0),
+            in_use: AtomicU32::new(0),
+            pad: [0_u8; SIZEOF_PTHREAD_BARRIER_T - 5 * 4],
+        },
+    );
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrier_destroy(barrier: *mut PthreadBarrierT) -> c_int {
+    libc!(libc::pthread_barrier_destroy(checked_cast!(barrier)));
+    (*barrier).destroy()
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_barrier_wait(barrier: *mut PthreadBarrierT) -> c_int {
+    libc!(libc::pthread_barrier_wait(checked_cast!(barrier)));
+    (*barrier).wait()
+}
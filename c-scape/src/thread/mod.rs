@@ -1,18 +1,25 @@
+mod barrier;
 mod key;
 mod mutex;
 mod once;
+mod robust_list;
 mod rwlock;
+pub(crate) mod setxid;
 mod spinlock;
+mod timeout;
 
 use alloc::boxed::Box;
 use alloc::format;
+use alloc::sync::Arc;
 use core::ffi::c_void;
 use core::mem::{transmute, zeroed, MaybeUninit};
 use core::ptr::{self, copy_nonoverlapping, null_mut, NonNull};
 use core::slice;
+use core::sync::atomic::{AtomicU32, Ordering};
 use origin::thread::{self, Thread};
 use rustix::fs::{Mode, OFlags};
 use rustix::runtime::KernelSigSet;
+use rustix::thread::futex;
 
 use libc::{c_char, c_int, size_t};
 
@@ -46,6 +53,9 @@ bitflags::bitflags! {
     #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
     pub struct PthreadAttrFlags: usize {
         const DETACHSTATE = 0x1;
+        /// `PTHREAD_EXPLICIT_SCHED`; otherwise, new threads inherit their
+        /// scheduling policy and parameters from the creating thread.
+        const EXPLICIT_SCHED = 0x2;
 
         /// <https://docs.rs/bitflags/*/bitflags/#externally-defined-flags>
         const _ = !0;
@@ -60,7 +70,9 @@ struct PthreadAttrT {
     stack_size: usize,
     guard_size: usize,
     flags: PthreadAttrFlags,
-    pad0: usize,
+    sched_policy: c_int,
+    sched_priority: c_int,
+    #[cfg(target_pointer_width = "64")]
     pad1: usize,
     pad2: usize,
     #[cfg(any(target_arch = "aarch64", target_arch = "x86"))]
@@ -77,7 +89,9 @@ impl Default for PthreadAttrT {
             stack_size: thread::default_stack_size(),
             guard_size: thread::default_guard_size(),
             flags: PthreadAttrFlags::empty(),
-            pad0: 0,
+            sched_policy: libc::SCHED_OTHER,
+            sched_priority: 0,
+            #[cfg(target_pointer_width = "64")]
             pad1: 0,
             pad2: 0,
             #[cfg(any(target_arch = "aarch64", target_arch = "x86"))]
@@ -100,8 +114,14 @@ unsafe extern "C" fn pthread_getattr_np(thread: PthreadT, attr: *mut PthreadAttr
         to_libc(thread),
         checked_cast!(attr)
     ));
-    let (stack_addr, stack_size, guard_size) =
-        thread::stack(Thread::from_raw_unchecked(thread.cast()));
+    let origin_thread = Thread::from_raw_unchecked(thread.cast());
+    let (stack_addr, stack_size, guard_size) = thread::stack(origin_thread);
+    // If the thread has exited, it no longer has a policy to report, so
+    // report the default one.
+    let (sched_policy, sched_priority) = match get_sched(origin_thread) {
+        Ok((policy, param)) => (policy, param.sched_priority),
+        Err(_) => (libc::SCHED_OTHER, 0),
+    };
     ptr::write(
         attr,
         PthreadAttrT {
@@ -109,7 +129,9 @@ unsafe extern "C" fn pthread_getattr_np(thread: PthreadT, attr: *mut PthreadAttr
             stack_size,
             guard_size,
             flags: PthreadAttrFlags::empty(),
-            pad0: 0,
+            sched_policy,
+            sched_priority,
+            #[cfg(target_pointer_width = "64")]
             pad1: 0,
             pad2: 0,
             #[cfg(any(target_arch = "aarch64", target_arch = "x86"))]
@@ -221,6 +243,152 @@ unsafe extern "C" fn pthread_attr_setguardsize(attr: *mut PthreadAttrT, guardsiz
     0
 }
 
+// These aren't in the libc crate.
+const PTHREAD_SCOPE_SYSTEM: c_int = 0;
+const PTHREAD_SCOPE_PROCESS: c_int = 1;
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_setinheritsched(
+    attr: *mut PthreadAttrT,
+    inherit: c_int,
+) -> c_int {
+    libc!(libc::pthread_attr_setinheritsched(
+        checked_cast!(attr),
+        inherit
+    ));
+    let value = match inherit {
+        libc::PTHREAD_EXPLICIT_SCHED => true,
+        libc::PTHREAD_INHERIT_SCHED => false,
+        _ => return libc::EINVAL,
+    };
+    (*attr).flags.set(PthreadAttrFlags::EXPLICIT_SCHED, value);
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_getinheritsched(
+    attr: *const PthreadAttrT,
+    inherit: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_attr_getinheritsched(
+        checked_cast!(attr),
+        inherit
+    ));
+    *inherit = if (*attr).flags.contains(PthreadAttrFlags::EXPLICIT_SCHED) {
+        libc::PTHREAD_EXPLICIT_SCHED
+    } else {
+        libc::PTHREAD_INHERIT_SCHED
+    };
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_setschedpolicy(attr: *mut PthreadAttrT, policy: c_int) -> c_int {
+    libc!(libc::pthread_attr_setschedpolicy(
+        checked_cast!(attr),
+        policy
+    ));
+    match policy {
+        libc::SCHED_OTHER | libc::SCHED_FIFO | libc::SCHED_RR => {}
+        _ => return libc::EINVAL,
+    }
+    (*attr).sched_policy = policy;
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_getschedpolicy(
+    attr: *const PthreadAttrT,
+    policy: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_attr_getschedpolicy(
+        checked_cast!(attr),
+        policy
+    ));
+    *policy = (*attr).sched_policy;
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_setschedparam(
+    attr: *mut PthreadAttrT,
+    param: *const libc::sched_param,
+) -> c_int {
+    libc!(libc::pthread_attr_setschedparam(checked_cast!(attr), param));
+
+    // Check the priority against the range for the policy set so far.
+    let policy = (*attr).sched_policy;
+    let priority = (*param).sched_priority;
+    match (
+        crate::process_::priority_min(policy),
+        crate::process_::priority_max(policy),
+    ) {
+        (Ok(min), Ok(max)) if (min..=max).contains(&priority) => {}
+        _ => return libc::EINVAL,
+    }
+
+    (*attr).sched_priority = priority;
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_getschedparam(
+    attr: *const PthreadAttrT,
+    param: *mut libc::sched_param,
+) -> c_int {
+    libc!(libc::pthread_attr_getschedparam(checked_cast!(attr), param));
+    param.write(libc::sched_param {
+        sched_priority: (*attr).sched_priority,
+    });
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_setscope(attr: *mut PthreadAttrT, scope: c_int) -> c_int {
+    //libc!(libc::pthread_attr_setscope(checked_cast!(attr), scope));
+    let _ = attr;
+
+    // Linux threads always contend for the CPU with all threads in the
+    // system.
+    match scope {
+        PTHREAD_SCOPE_SYSTEM => 0,
+        PTHREAD_SCOPE_PROCESS => libc::ENOTSUP,
+        _ => libc::EINVAL,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_attr_getscope(attr: *const PthreadAttrT, scope: *mut c_int) -> c_int {
+    //libc!(libc::pthread_attr_getscope(checked_cast!(attr), scope));
+    let _ = attr;
+
+    *scope = PTHREAD_SCOPE_SYSTEM;
+    0
+}
+
+/// Return the kernel's id for `thread`, for passing to the scheduling
+/// syscalls.
+unsafe fn sched_tid(thread: Thread) -> Result<libc::pid_t, c_int> {
+    match thread::id(thread) {
+        Some(id) => Ok(id.as_raw_nonzero().get()),
+        None => Err(libc::ESRCH),
+    }
+}
+
+/// Return the scheduling policy and parameters of `thread`.
+unsafe fn get_sched(thread: Thread) -> Result<(c_int, libc::sched_param), c_int> {
+    let tid = sched_tid(thread)?;
+    let policy = crate::process_::get_scheduler(tid).map_err(|err| err.raw_os_error())?;
+    let param = crate::process_::get_param(tid).map_err(|err| err.raw_os_error())?;
+    Ok((policy, param))
+}
+
+/// The states of the gate a new thread waits at until `pthread_create` has
+/// applied its scheduling attributes.
+const GATE_WAIT: u32 = 0;
+const GATE_GO: u32 = 1;
+const GATE_ABORT: u32 = 2;
+
 #[no_mangle]
 unsafe extern "C" fn pthread_create(
     pthread: *mut PthreadT,
@@ -240,8 +408,10 @@ unsafe extern "C" fn pthread_create(
         stack_size,
         guard_size,
         flags,
-        pad0: _,
-        pad1: _,
+        sched_policy,
+        sched_priority,
+        #[cfg(target_pointer_width = "64")]
+            pad1: _,
         pad2: _,
         #[cfg(any(target_arch = "aarch64", target_arch = "x86"))]
             pad3: _,
@@ -257,7 +427,23 @@ unsafe extern "C" fn pthread_create(
         "custom thread stacks not supported yet"
     );
 
-    let args = [NonNull::new(fn_ as *mut c_void), NonNull::new(arg)];
+    // With explicit scheduling attributes, the new thread waits at a gate
+    // until we've applied them, so that it never runs `fn_` with the wrong
+    // policy, and so that we can report failure to apply them.
+    let gate = if flags.contains(PthreadAttrFlags::EXPLICIT_SCHED) {
+        Some(Arc::new(AtomicU32::new(GATE_WAIT)))
+    } else {
+        None
+    };
+    let gate_arg = gate
+        .as_ref()
+        .and_then(|gate| NonNull::new(Arc::into_raw(gate.clone()).cast_mut().cast()));
+
+    let args = [
+        NonNull::new(fn_ as *mut c_void),
+        NonNull::new(arg),
+        gate_arg,
+    ];
 
     // `create_thread` takes a bare function pointer, and it's not
     // `extern "C"`, so we have to wrap the user's `fn_`.
@@ -273,16 +459,54 @@ unsafe extern "C" fn pthread_create(
             None => null_mut(),
         };
 
+        if let Some(gate) = args[2] {
+            let gate = Arc::from_raw(gate.as_ptr().cast::<AtomicU32>());
+            let state = loop {
+                let state = gate.load(Ordering::Acquire);
+                if state != GATE_WAIT {
+                    break state;
+                }
+                futex::wait(&gate, futex::Flags::PRIVATE, GATE_WAIT, None).ok();
+            };
+            if state == GATE_ABORT {
+                return None;
+            }
+        }
+
         let return_value = fn_(arg);
 
         NonNull::new(return_value)
     }
 
-    // Create the thread.
+    // Create the thread. Don't let credentials change in the middle of this,
+    // because the new thread might not get the change.
+    let creation = setxid::THREAD_CREATION.read();
     let thread = match thread::create(call, &args, stack_size, guard_size) {
         Ok(thread) => thread,
-        Err(e) => return e.raw_os_error(),
+        Err(e) => {
+            if let Some(gate_arg) = gate_arg {
+                Arc::decrement_strong_count(gate_arg.as_ptr().cast::<AtomicU32>());
+            }
+            return e.raw_os_error();
+        }
     };
+    drop(creation);
+
+    if let Some(gate) = gate {
+        let param = libc::sched_param { sched_priority };
+        let result = sched_tid(thread).and_then(|tid| {
+            crate::process_::set_scheduler(tid, sched_policy, &param)
+                .map_err(|err| err.raw_os_error())
+        });
+        let state = if result.is_ok() { GATE_GO } else { GATE_ABORT };
+        gate.store(state, Ordering::Release);
+        futex::wake(&gate, futex::Flags::PRIVATE, 1).ok();
+
+        if let Err(err) = result {
+            thread::join(thread);
+            return err;
+        }
+    }
 
     // In theory we could optimize this by adding an argument to origin's
     // `create_thread` to initialize the thread in the detached state,
@@ -347,10 +571,12 @@ unsafe extern "C" fn pthread_sigmask(
     let set = if set.is_null() {
         None
     } else {
-        Some(&*set.cast::<KernelSigSet>())
+        Some(crate::signal::without_reserved(
+            &*set.cast::<KernelSigSet>(),
+        ))
     };
 
-    match rustix::runtime::kernel_sigprocmask(how, set) {
+    match rustix::runtime::kernel_sigprocmask(how, set.as_ref()) {
         Ok(old) => {
             if !oldset.is_null() {
                 oldset.write(crate::expand_sigset(old));
@@ -361,6 +587,37 @@ unsafe extern "C" fn pthread_sigmask(
     }
 }
 
+#[no_mangle]
+unsafe extern "C" fn pthread_kill(pthread: PthreadT, sig: c_int) -> c_int {
+    libc!(libc::pthread_kill(to_libc(pthread), sig));
+
+    if (rustix::runtime::KERNEL_SIGRTMIN..libc::SIGRTMIN()).contains(&sig) {
+        return libc::EINVAL;
+    }
+
+    let tid = match sched_tid(Thread::from_raw_unchecked(pthread.cast())) {
+        Ok(tid) => tid,
+        Err(err) => return err,
+    };
+
+    // A `sig` of zero just checks that the thread exists.
+    match tgkill(tid, sig) {
+        Ok(()) => 0,
+        Err(err) => err.raw_os_error(),
+    }
+}
+
+/// Send `sig` to the thread in this process with kernel id `tid`.
+///
+/// We use `tgkill` rather than `tkill` so that if the thread has exited and
+/// its id has been reused by another process, the signal isn't delivered
+/// there.
+unsafe fn tgkill(tid: libc::pid_t, sig: c_int) -> rustix::io::Result<()> {
+    let pid = rustix::process::getpid().as_raw_nonzero().get();
+    crate::raw_syscall::syscall3(libc::SYS_tgkill, pid as usize, tid as usize, sig as usize)
+        .map(|_| ())
+}
+
 #[no_mangle]
 unsafe extern "C" fn pthread_attr_getstacksize(
     attr: *const PthreadAttrT,
@@ -457,6 +714,104 @@ unsafe extern "C" fn pthread_getname_np(
     0
 }
 
+#[no_mangle]
+unsafe extern "C" fn pthread_setschedparam(
+    pthread: PthreadT,
+    policy: c_int,
+    param: *const libc::sched_param,
+) -> c_int {
+    libc!(libc::pthread_setschedparam(to_libc(pthread), policy, param));
+
+    let result = sched_tid(Thread::from_raw_unchecked(pthread.cast())).and_then(|tid| {
+        crate::process_::set_scheduler(tid, policy, &*param).map_err(|err| err.raw_os_error())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_getschedparam(
+    pthread: PthreadT,
+    policy: *mut c_int,
+    param: *mut libc::sched_param,
+) -> c_int {
+    libc!(libc::pthread_getschedparam(to_libc(pthread), policy, param));
+
+    match get_sched(Thread::from_raw_unchecked(pthread.cast())) {
+        Ok((got_policy, got_param)) => {
+            *policy = got_policy;
+            param.write(got_param);
+            0
+        }
+        Err(err) => err,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_setschedprio(pthread: PthreadT, prio: c_int) -> c_int {
+    libc!(libc::pthread_setschedprio(to_libc(pthread), prio));
+
+    let param = libc::sched_param {
+        sched_priority: prio,
+    };
+    let result = sched_tid(Thread::from_raw_unchecked(pthread.cast()))
+        .and_then(|tid| crate::process_::set_param(tid, &param).map_err(|err| err.raw_os_error()));
+    match result {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_setaffinity_np(
+    pthread: PthreadT,
+    cpusetsize: size_t,
+    cpuset: *const libc::cpu_set_t,
+) -> c_int {
+    libc!(libc::pthread_setaffinity_np(
+        to_libc(pthread),
+        cpusetsize,
+        cpuset
+    ));
+
+    let tid = match sched_tid(Thread::from_raw_unchecked(pthread.cast())) {
+        Ok(tid) => tid,
+        Err(err) => return err,
+    };
+    let set = crate::process_::cpu_set_from_libc(cpusetsize, cpuset);
+    match rustix::thread::sched_setaffinity(rustix::process::Pid::from_raw(tid), &set) {
+        Ok(()) => 0,
+        Err(err) => err.raw_os_error(),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_getaffinity_np(
+    pthread: PthreadT,
+    cpusetsize: size_t,
+    cpuset: *mut libc::cpu_set_t,
+) -> c_int {
+    libc!(libc::pthread_getaffinity_np(
+        to_libc(pthread),
+        cpusetsize,
+        cpuset
+    ));
+
+    let tid = match sched_tid(Thread::from_raw_unchecked(pthread.cast())) {
+        Ok(tid) => tid,
+        Err(err) => return err,
+    };
+    match rustix::thread::sched_getaffinity(rustix::process::Pid::from_raw(tid)) {
+        Ok(set) => {
+            crate::process_::cpu_set_to_libc(&set, cpusetsize, cpuset);
+            0
+        }
+        Err(err) => err.raw_os_error(),
+    }
+}
+
 #[cfg(target_os = "linux")]
 #[no_mangle]
 unsafe extern "C" fn pthread_setname_np(pthread: PthreadT, name: *const libc::c_char) -> c_int {
@@ -519,12 +874,23 @@ unsafe extern "C" fn __cxa_thread_atexit_impl(
 unsafe extern "C" fn __tls_get_addr(p: &[usize; 2]) -> *mut c_void {
     //libc!(libc::__tls_get_addr(p));
     let [module, offset] = *p;
-    thread::current_tls_addr(module, offset)
+    crate::dl::tls_get_addr(module, offset)
 }
 
+/// On x86, the general-dynamic TLS model calls `___tls_get_addr` with its
+/// argument in `eax`.
 #[cfg(target_arch = "x86")]
 #[no_mangle]
+#[unsafe(naked)]
 unsafe extern "C" fn ___tls_get_addr() {
     //libc!(libc::___tls_get_addr());
-    todo!("___tls_get_addr")
+    core::arch::naked_asm!(
+        // Pass `eax` on the stack, keeping the stack 16-byte aligned.
+        "sub esp, 8",
+        "push eax",
+        "call {__tls_get_addr}",
+        "add esp, 12",
+        "ret",
+        __tls_get_addr = sym __tls_get_addr
+    )
 }
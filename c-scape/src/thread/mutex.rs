@@ -1,28 +1,58 @@
-use rustix_futex_sync::lock_api::{RawMutex as _, RawReentrantMutex};
-use rustix_futex_sync::{RawCondvar, RawMutex};
+use rustix::io::Errno;
+use rustix::thread::futex;
+use rustix::time::{ClockId, Timespec};
 
-use core::mem::{size_of, ManuallyDrop};
+use core::mem::size_of;
 use core::ptr;
 use core::sync::atomic::{AtomicU32, Ordering};
-use core::time::Duration;
 use libc::c_int;
 
-use crate::GetThreadId;
-
-#[allow(non_camel_case_types)]
-#[repr(C)]
-union pthread_mutex_u {
-    normal: ManuallyDrop<RawMutex>,
-    reentrant: ManuallyDrop<RawReentrantMutex<RawMutex, GetThreadId>>,
+use super::robust_list::{self, RobustFutex};
+use super::timeout::{read_abstime, Deadline};
+
+/// The bits of a futex word that hold the owning thread's id.
+const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+// The layout of the `kind` field of `PthreadMutexattrT` and `PthreadMutexT`.
+// The low bits hold the mutex type, `PTHREAD_MUTEX_NORMAL` and so on.
+const KIND_TYPE_MASK: u32 = 0x3;
+const KIND_ROBUST: u32 = 0x4;
+const KIND_PSHARED: u32 = 0x8;
+const KIND_PRIO_INHERIT: u32 = 0x10;
+/// Set on a robust mutex when its owner died, until `pthread_mutex_consistent`
+/// is called.
+const KIND_INCONSISTENT: u32 = 0x20;
+/// Set on a robust mutex when it's unlocked while inconsistent.
+const KIND_NOTRECOVERABLE: u32 = 0x40;
+const KIND_PRIOCEILING_SHIFT: u32 = 16;
+const KIND_PRIOCEILING_MASK: u32 = 0xff << KIND_PRIOCEILING_SHIFT;
+
+/// The range of `SCHED_FIFO` priorities on Linux, which is what priority
+/// ceilings are expressed in.
+const MIN_PRIOCEILING: c_int = 1;
+const MAX_PRIOCEILING: c_int = 99;
+
+/// Return the current thread's id, as stored in futex words.
+#[inline]
+pub(super) fn current_tid() -> u32 {
+    origin::thread::current_id().as_raw_nonzero().get() as u32
 }
 
+/// Our mutexes store the owning thread's id in the futex word, which is what
+/// the kernel expects for robust and priority-inheritance mutexes, and which
+/// works across processes for process-shared mutexes.
 #[allow(non_camel_case_types)]
 #[repr(C)]
 struct PthreadMutexT {
     kind: AtomicU32,
-    u: pthread_mutex_u,
+    /// The recursion count, for `PTHREAD_MUTEX_RECURSIVE` mutexes. This is
+    /// only accessed by the owning thread.
+    count: AtomicU32,
+    /// The futex word is zero when unlocked, and otherwise holds the owning
+    /// thread's id, with the `WAITERS` and `OWNER_DIED` flags.
+    futex: RobustFutex,
     pad0: usize,
-    #[cfg(any(target_arch = "aarch64", target_arch = "x86"))]
+    #[cfg(target_arch = "aarch64")]
     pad1: usize,
 }
 libc_type!(PthreadMutexT, pthread_mutex_t);
@@ -36,6 +66,244 @@ struct PthreadMutexattrT {
 }
 libc_type!(PthreadMutexattrT, pthread_mutexattr_t);
 
+impl PthreadMutexT {
+    #[inline]
+    fn owner(&self) -> u32 {
+        self.futex.word.load(Ordering::Relaxed) & FUTEX_TID_MASK
+    }
+
+    #[inline]
+    fn futex_flags(kind: u32) -> futex::Flags {
+        if kind & KIND_PSHARED != 0 {
+            futex::Flags::empty()
+        } else {
+            futex::Flags::PRIVATE
+        }
+    }
+
+    unsafe fn lock(&self, deadline: Deadline<'_>) -> c_int {
+        let kind = self.kind.load(Ordering::Relaxed);
+        let type_ = (kind & KIND_TYPE_MASK) as c_int;
+        let robust = kind & KIND_ROBUST != 0;
+        let pi = kind & KIND_PRIO_INHERIT != 0;
+        let tid = current_tid();
+
+        if self.owner() == tid {
+            match type_ {
+                libc::PTHREAD_MUTEX_RECURSIVE => {
+                    return match self.count.load(Ordering::Relaxed).checked_add(1) {
+                        Some(count) => {
+                            self.count.store(count, Ordering::Relaxed);
+                            0
+                        }
+                        None => libc::EAGAIN,
+                    };
+                }
+                // Relocking a normal mutex deadlocks, unless we have to
+                // track the owner anyway.
+                libc::PTHREAD_MUTEX_NORMAL if !robust && !pi => {}
+                libc::PTHREAD_MUTEX_NORMAL | libc::PTHREAD_MUTEX_ERRORCHECK => {
+                    return match deadline {
+                        Deadline::Now => libc::EBUSY,
+                        _ => libc::EDEADLK,
+                    };
+                }
+                _ => unimplemented!("unsupported pthread mutex kind {}", kind),
+            }
+        }
+
+        if kind & KIND_NOTRECOVERABLE != 0 {
+            return libc::ENOTRECOVERABLE;
+        }
+
+        if robust {
+            robust_list::set_pending(&self.futex, pi);
+        }
+        let result = if pi {
+            self.acquire_pi(kind, deadline)
+        } else {
+            self.acquire(tid, kind, deadline)
+        };
+        let owner_died = match result {
+            Ok(owner_died) => owner_died,
+            Err(err) => {
+                if robust {
+                    robust_list::clear_pending();
+                }
+                return err;
+            }
+        };
+        if robust {
+            robust_list::push(&self.futex, pi);
+            robust_list::clear_pending();
+        }
+        self.count.store(1, Ordering::Relaxed);
+
+        // Check the state again, now that we hold the lock.
+        let kind = self.kind.load(Ordering::Relaxed);
+        if kind & KIND_NOTRECOVERABLE != 0 {
+            self.count.store(0, Ordering::Relaxed);
+            self.release(kind);
+            return libc::ENOTRECOVERABLE;
+        }
+        if owner_died && robust {
+            self.kind.fetch_or(KIND_INCONSISTENT, Ordering::Relaxed);
+            return libc::EOWNERDEAD;
+        }
+        0
+    }
+
+    /// Acquire the futex, returning whether the previous owner died while
+    /// holding it.
+    fn acquire(&self, tid: u32, kind: u32, deadline: Deadline<'_>) -> Result<bool, c_int> {
+        let futex = &self.futex.word;
+        let flags = Self::futex_flags(kind);
+
+        // Once we've waited, other threads may be waiting too, so when we
+        // acquire the lock, we conservatively mark it as having waiters.
+        let mut waiters = 0;
+
+        loop {
+            let val = self.spin();
+            if val & FUTEX_TID_MASK == 0 {
+                let new = tid | waiters | (val & futex::WAITERS);
+                match futex.compare_exchange(val, new, Ordering::Acquire, Ordering::Relaxed) {
+                    Ok(_) => return Ok(val & futex::OWNER_DIED != 0),
+                    Err(_) => continue,
+                }
+            }
+
+            if let Deadline::Now = deadline {
+                return Err(libc::EBUSY);
+            }
+
+            if val & futex::WAITERS == 0
+                && futex
+                    .compare_exchange(
+                        val,
+                        val | futex::WAITERS,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            let val = val | futex::WAITERS;
+            waiters = futex::WAITERS;
+
+            deadline.wait(futex, flags, val)?;
+        }
+    }
+
+    /// Spin briefly while the futex is locked without waiters, in case it's
+    /// released soon, and return its value.
+    fn spin(&self) -> u32 {
+        let mut spin = 100;
+        loop {
+            let val = self.futex.word.load(Ordering::Relaxed);
+            if val & FUTEX_TID_MASK == 0 || val & futex::WAITERS != 0 || spin == 0 {
+                return val;
+            }
+            core::hint::spin_loop();
+            spin -= 1;
+        }
+    }
+
+    /// Acquire a priority-inheritance futex, which the kernel arbitrates,
+    /// returning whether the previous owner died while holding it.
+    fn acquire_pi(&self, kind: u32, deadline: Deadline<'_>) -> Result<bool, c_int> {
+        let futex = &self.futex.word;
+        let flags = Self::futex_flags(kind);
+
+        if futex
+            .compare_exchange(0, current_tid(), Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            let result = match deadline {
+                Deadline::Now => match futex::trylock_pi(futex, flags) {
+                    Ok(true) => Ok(()),
+                    Ok(false) | Err(Errno::AGAIN) | Err(Errno::BUSY) => return Err(libc::EBUSY),
+                    Err(err) => Err(err),
+                },
+                // `FUTEX_LOCK_PI` always measures timeouts with
+                // `CLOCK_REALTIME`, which is what `pthread_mutex_timedlock`
+                // uses.
+                Deadline::At(abstime, _) => futex::lock_pi(futex, flags, Some(abstime)),
+                Deadline::Never => futex::lock_pi(futex, flags, None),
+            };
+            result.map_err(|err| err.raw_os_error())?;
+        }
+
+        // When the kernel hands us a futex whose owner died, it leaves the
+        // `OWNER_DIED` flag set for us to see.
+        if futex.load(Ordering::Relaxed) & futex::OWNER_DIED != 0 {
+            futex.fetch_and(!futex::OWNER_DIED, Ordering::Relaxed);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Release the futex, which the current thread holds.
+    unsafe fn release(&self, kind: u32) {
+        let futex = &self.futex.word;
+        let flags = Self::futex_flags(kind);
+        let robust = kind & KIND_ROBUST != 0;
+        let pi = kind & KIND_PRIO_INHERIT != 0;
+
+        if robust {
+            robust_list::set_pending(&self.futex, pi);
+            robust_list::remove(&self.futex);
+        }
+        if pi {
+            if futex
+                .compare_exchange(current_tid(), 0, Ordering::Release, Ordering::Relaxed)
+                .is_err()
+            {
+                futex::unlock_pi(futex, flags).ok();
+            }
+        } else if futex.swap(0, Ordering::Release) & futex::WAITERS != 0 {
+            futex::wake(futex, flags, 1).ok();
+        }
+        if robust {
+            robust_list::clear_pending();
+        }
+    }
+
+    unsafe fn unlock(&self) -> c_int {
+        let kind = self.kind.load(Ordering::Relaxed);
+        let type_ = (kind & KIND_TYPE_MASK) as c_int;
+        let owner = self.owner();
+
+        // Normal mutexes may be unlocked by any thread, unless we have to
+        // track the owner anyway.
+        if type_ == libc::PTHREAD_MUTEX_NORMAL && kind & (KIND_ROBUST | KIND_PRIO_INHERIT) == 0 {
+            if owner == 0 {
+                return libc::EPERM;
+            }
+        } else if owner != current_tid() {
+            return libc::EPERM;
+        }
+
+        let count = self.count.load(Ordering::Relaxed);
+        if type_ == libc::PTHREAD_MUTEX_RECURSIVE && count > 1 {
+            self.count.store(count - 1, Ordering::Relaxed);
+            return 0;
+        }
+        self.count.store(0, Ordering::Relaxed);
+
+        // Unlocking an inconsistent robust mutex makes it permanently
+        // unusable.
+        if kind & KIND_INCONSISTENT != 0 {
+            self.kind.fetch_and(!KIND_INCONSISTENT, Ordering::Relaxed);
+            self.kind.fetch_or(KIND_NOTRECOVERABLE, Ordering::Relaxed);
+        }
+
+        self.release(kind);
+        0
+    }
+}
+
 #[no_mangle]
 unsafe extern "C" fn pthread_mutexattr_destroy(attr: *mut PthreadMutexattrT) -> c_int {
     libc!(libc::pthread_mutexattr_destroy(checked_cast!(attr)));
@@ -68,7 +336,10 @@ unsafe extern "C" fn pthread_mutexattr_settype(attr: *mut PthreadMutexattrT, kin
         _ => return libc::EINVAL,
     }
 
-    (*attr).kind.store(kind as u32, Ordering::SeqCst);
+    let old = (*attr).kind.load(Ordering::SeqCst);
+    (*attr)
+        .kind
+        .store((old & !KIND_TYPE_MASK) | kind as u32, Ordering::SeqCst);
     0
 }
 
@@ -79,17 +350,168 @@ unsafe extern "C" fn pthread_mutexattr_gettype(
 ) -> c_int {
     //libc!(libc::pthread_mutexattr_gettype(checked_cast!(attr), kind));
 
-    *kind = (*attr).kind.load(Ordering::SeqCst) as c_int;
+    *kind = ((*attr).kind.load(Ordering::SeqCst) & KIND_TYPE_MASK) as c_int;
+    0
+}
+
+/// Set or clear `flag` in `attr` according to `value`.
+unsafe fn set_attr_flag(attr: *mut PthreadMutexattrT, flag: u32, value: bool) {
+    if value {
+        (*attr).kind.fetch_or(flag, Ordering::SeqCst);
+    } else {
+        (*attr).kind.fetch_and(!flag, Ordering::SeqCst);
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_setrobust(
+    attr: *mut PthreadMutexattrT,
+    robustness: c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_setrobust(
+        checked_cast!(attr),
+        robustness
+    ));
+
+    match robustness {
+        libc::PTHREAD_MUTEX_STALLED => set_attr_flag(attr, KIND_ROBUST, false),
+        libc::PTHREAD_MUTEX_ROBUST => set_attr_flag(attr, KIND_ROBUST, true),
+        _ => return libc::EINVAL,
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_getrobust(
+    attr: *const PthreadMutexattrT,
+    robustness: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_getrobust(
+        checked_cast!(attr),
+        robustness
+    ));
+
+    *robustness = if (*attr).kind.load(Ordering::SeqCst) & KIND_ROBUST != 0 {
+        libc::PTHREAD_MUTEX_ROBUST
+    } else {
+        libc::PTHREAD_MUTEX_STALLED
+    };
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_setpshared(
+    attr: *mut PthreadMutexattrT,
+    pshared: c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_setpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    match pshared {
+        libc::PTHREAD_PROCESS_PRIVATE => set_attr_flag(attr, KIND_PSHARED, false),
+        libc::PTHREAD_PROCESS_SHARED => set_attr_flag(attr, KIND_PSHARED, true),
+        _ => return libc::EINVAL,
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_getpshared(
+    attr: *const PthreadMutexattrT,
+    pshared: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_getpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    *pshared = if (*attr).kind.load(Ordering::SeqCst) & KIND_PSHARED != 0 {
+        libc::PTHREAD_PROCESS_SHARED
+    } else {
+        libc::PTHREAD_PROCESS_PRIVATE
+    };
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_setprotocol(
+    attr: *mut PthreadMutexattrT,
+    protocol: c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_setprotocol(
+        checked_cast!(attr),
+        protocol
+    ));
+
+    match protocol {
+        libc::PTHREAD_PRIO_NONE => set_attr_flag(attr, KIND_PRIO_INHERIT, false),
+        libc::PTHREAD_PRIO_INHERIT => set_attr_flag(attr, KIND_PRIO_INHERIT, true),
+        // Priority-ceiling mutexes would need to raise the owning thread's
+        // priority on every lock, which we don't implement.
+        libc::PTHREAD_PRIO_PROTECT => return libc::ENOTSUP,
+        _ => return libc::EINVAL,
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_getprotocol(
+    attr: *const PthreadMutexattrT,
+    protocol: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_mutexattr_getprotocol(
+        checked_cast!(attr),
+        protocol
+    ));
+
+    *protocol = if (*attr).kind.load(Ordering::SeqCst) & KIND_PRIO_INHERIT != 0 {
+        libc::PTHREAD_PRIO_INHERIT
+    } else {
+        libc::PTHREAD_PRIO_NONE
+    };
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_setprioceiling(
+    attr: *mut PthreadMutexattrT,
+    prioceiling: c_int,
+) -> c_int {
+    //libc!(libc::pthread_mutexattr_setprioceiling(checked_cast!(attr), prioceiling));
+
+    if !(MIN_PRIOCEILING..=MAX_PRIOCEILING).contains(&prioceiling) {
+        return libc::EINVAL;
+    }
+
+    let old = (*attr).kind.load(Ordering::SeqCst);
+    (*attr).kind.store(
+        (old & !KIND_PRIOCEILING_MASK) | ((prioceiling as u32) << KIND_PRIOCEILING_SHIFT),
+        Ordering::SeqCst,
+    );
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutexattr_getprioceiling(
+    attr: *const PthreadMutexattrT,
+    prioceiling: *mut c_int,
+) -> c_int {
+    //libc!(libc::pthread_mutexattr_getprioceiling(checked_cast!(attr), prioceiling));
+
+    let kind = (*attr).kind.load(Ordering::SeqCst);
+    *prioceiling = ((kind & KIND_PRIOCEILING_MASK) >> KIND_PRIOCEILING_SHIFT) as c_int;
     0
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_mutex_destroy(mutex: *mut PthreadMutexT) -> c_int {
     libc!(libc::pthread_mutex_destroy(checked_cast!(mutex)));
-    match (*mutex).kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => ManuallyDrop::drop(&mut (*mutex).u.normal),
-        libc::PTHREAD_MUTEX_RECURSIVE => ManuallyDrop::drop(&mut (*mutex).u.reentrant),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
+    match ((*mutex).kind.load(Ordering::SeqCst) & KIND_TYPE_MASK) as i32 {
+        libc::PTHREAD_MUTEX_NORMAL
+        | libc::PTHREAD_MUTEX_RECURSIVE
+        | libc::PTHREAD_MUTEX_ERRORCHECK => {}
         other => unimplemented!("unsupported pthread mutex kind {}", other),
     }
     (*mutex).kind.store(!0, Ordering::SeqCst);
@@ -111,17 +533,14 @@ unsafe extern "C" fn pthread_mutex_init(
         (*mutexattr).kind.load(Ordering::SeqCst)
     };
 
-    match kind as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => {
-            ptr::write(&mut (*mutex).u.normal, ManuallyDrop::new(RawMutex::INIT))
-        }
-        libc::PTHREAD_MUTEX_RECURSIVE => ptr::write(
-            &mut (*mutex).u.reentrant,
-            ManuallyDrop::new(RawReentrantMutex::INIT),
-        ),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
+    match (kind & KIND_TYPE_MASK) as i32 {
+        libc::PTHREAD_MUTEX_NORMAL
+        | libc::PTHREAD_MUTEX_RECURSIVE
+        | libc::PTHREAD_MUTEX_ERRORCHECK => {}
         other => unimplemented!("unsupported pthread mutex kind {}", other),
     }
+    ptr::write(&mut (*mutex).count, AtomicU32::new(0));
+    ptr::write(&mut (*mutex).futex, RobustFutex::new());
     (*mutex).kind.store(kind, Ordering::SeqCst);
     0
 }
@@ -129,51 +548,44 @@ unsafe extern "C" fn pthread_mutex_init(
 #[no_mangle]
 unsafe extern "C" fn pthread_mutex_lock(mutex: *mut PthreadMutexT) -> c_int {
     libc!(libc::pthread_mutex_lock(checked_cast!(mutex)));
-    match (*mutex).kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => (*mutex).u.normal.lock(),
-        libc::PTHREAD_MUTEX_RECURSIVE => (*mutex).u.reentrant.lock(),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
-        other => unimplemented!("unsupported pthread mutex kind {}", other),
-    }
-    0
+    (*mutex).lock(Deadline::Never)
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_mutex_trylock(mutex: *mut PthreadMutexT) -> c_int {
     libc!(libc::pthread_mutex_trylock(checked_cast!(mutex)));
-    if match (*mutex).kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => (*mutex).u.normal.try_lock(),
-        libc::PTHREAD_MUTEX_RECURSIVE => (*mutex).u.reentrant.try_lock(),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
-        other => unimplemented!("unsupported pthread mutex kind {}", other),
-    } {
-        0
-    } else {
-        libc::EBUSY
+    (*mutex).lock(Deadline::Now)
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutex_timedlock(
+    mutex: *mut PthreadMutexT,
+    abstime: *const libc::timespec,
+) -> c_int {
+    libc!(libc::pthread_mutex_timedlock(checked_cast!(mutex), abstime));
+
+    match read_abstime(abstime) {
+        Ok(abstime) => (*mutex).lock(Deadline::At(&abstime, ClockId::Realtime)),
+        Err(err) => err,
     }
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_mutex_unlock(mutex: *mut PthreadMutexT) -> c_int {
     libc!(libc::pthread_mutex_unlock(checked_cast!(mutex)));
+    (*mutex).unlock()
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_mutex_consistent(mutex: *mut PthreadMutexT) -> c_int {
+    libc!(libc::pthread_mutex_consistent(checked_cast!(mutex)));
 
     let mutex = &*mutex;
-    match mutex.kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => {
-            if !mutex.u.normal.is_locked() {
-                return libc::EPERM;
-            }
-            mutex.u.normal.unlock()
-        }
-        libc::PTHREAD_MUTEX_RECURSIVE => {
-            if !mutex.u.reentrant.is_locked() {
-                return libc::EPERM;
-            }
-            mutex.u.reentrant.unlock()
-        }
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
-        other => unimplemented!("unsupported pthread mutex kind {}", other),
+    let kind = mutex.kind.load(Ordering::Relaxed);
+    if kind & KIND_ROBUST == 0 || kind & KIND_INCONSISTENT == 0 || mutex.owner() != current_tid() {
+        return libc::EINVAL;
     }
+    mutex.kind.fetch_and(!KIND_INCONSISTENT, Ordering::Relaxed);
     0
 }
 
@@ -182,39 +594,104 @@ const SIZEOF_PTHREAD_COND_T: usize = 48;
 #[cfg_attr(target_arch = "x86", repr(C, align(4)))]
 #[cfg_attr(not(target_arch = "x86"), repr(C, align(8)))]
 struct PthreadCondT {
-    inner: RawCondvar,
+    /// A sequence number, incremented on every notification, so that waiters
+    /// don't miss notifications sent between unlocking the mutex and waiting.
+    seq: AtomicU32,
     attr: PthreadCondattrT,
-    pad: [u8; SIZEOF_PTHREAD_COND_T - size_of::<RawCondvar>() - size_of::<PthreadCondattrT>()],
+    pad: [u8; SIZEOF_PTHREAD_COND_T - size_of::<AtomicU32>() - size_of::<PthreadCondattrT>()],
 }
 
 libc_type!(PthreadCondT, pthread_cond_t);
 
-#[cfg(any(
-    target_arch = "x86",
-    target_arch = "x86_64",
-    target_arch = "arm",
-    all(target_arch = "aarch64", target_pointer_width = "32"),
-    target_arch = "riscv64",
-))]
-const SIZEOF_PTHREAD_CONDATTR_T: usize = 4;
-
-#[cfg(all(target_arch = "aarch64", target_pointer_width = "64"))]
-const SIZEOF_PTHREAD_CONDATTR_T: usize = 8;
+/// The bit of `PthreadCondattrT::flags` for `PTHREAD_PROCESS_SHARED`.
+const CONDATTR_PSHARED: u32 = 0x1;
+/// The bits above this in `PthreadCondattrT::flags` hold the clock id set by
+/// `pthread_condattr_setclock`, which defaults to `CLOCK_REALTIME`, 0.
+const CONDATTR_CLOCK_SHIFT: u32 = 1;
 
 #[repr(C, align(4))]
+#[derive(Default)]
 struct PthreadCondattrT {
-    pad: [u8; SIZEOF_PTHREAD_CONDATTR_T],
+    flags: u32,
+    #[cfg(all(target_arch = "aarch64", target_pointer_width = "64"))]
+    pad0: u32,
 }
 
-impl Default for PthreadCondattrT {
-    fn default() -> Self {
-        Self {
-            pad: [0_u8; SIZEOF_PTHREAD_CONDATTR_T],
+libc_type!(PthreadCondattrT, pthread_condattr_t);
+
+impl PthreadCondattrT {
+    #[inline]
+    fn clock_id(&self) -> c_int {
+        (self.flags >> CONDATTR_CLOCK_SHIFT) as c_int
+    }
+
+    #[inline]
+    fn clock(&self) -> ClockId {
+        if self.clock_id() == libc::CLOCK_MONOTONIC {
+            ClockId::Monotonic
+        } else {
+            ClockId::Realtime
         }
     }
 }
 
-libc_type!(PthreadCondattrT, pthread_condattr_t);
+impl PthreadCondT {
+    #[inline]
+    fn futex_flags(&self) -> futex::Flags {
+        if self.attr.flags & CONDATTR_PSHARED != 0 {
+            futex::Flags::empty()
+        } else {
+            futex::Flags::PRIVATE
+        }
+    }
+
+    fn notify(&self, count: u32) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex::wake(&self.seq, self.futex_flags(), count).ok();
+    }
+
+    unsafe fn wait(&self, mutex: &PthreadMutexT, abstime: Option<&Timespec>) -> c_int {
+        let kind = mutex.kind.load(Ordering::Relaxed);
+        let type_ = (kind & KIND_TYPE_MASK) as c_int;
+        if mutex.owner() != current_tid()
+            && (type_ != libc::PTHREAD_MUTEX_NORMAL
+                || kind & (KIND_ROBUST | KIND_PRIO_INHERIT) != 0)
+        {
+            return libc::EPERM;
+        }
+
+        // Examine the sequence number before unlocking the mutex.
+        let seq = self.seq.load(Ordering::Relaxed);
+
+        // Fully release the mutex, regardless of its recursion count.
+        let count = mutex.count.load(Ordering::Relaxed);
+        mutex.count.store(0, Ordering::Relaxed);
+        mutex.release(kind);
+
+        // Wait, but only if there hasn't been a notification since we
+        // examined the sequence number.
+        let result = match abstime {
+            Some(abstime) => Deadline::At(abstime, self.attr.clock()),
+            None => Deadline::Never,
+        }
+        .wait(&self.seq, self.futex_flags(), seq);
+
+        // Reacquire the mutex and restore its recursion count.
+        match mutex.lock(Deadline::Never) {
+            0 => mutex.count.store(count, Ordering::Relaxed),
+            libc::EOWNERDEAD => {
+                mutex.count.store(count, Ordering::Relaxed);
+                return libc::EOWNERDEAD;
+            }
+            err => return err,
+        }
+
+        match result {
+            Ok(()) => 0,
+            Err(err) => err,
+        }
+    }
+}
 
 #[no_mangle]
 unsafe extern "C" fn pthread_condattr_destroy(attr: *mut PthreadCondattrT) -> c_int {
@@ -239,24 +716,72 @@ unsafe extern "C" fn pthread_condattr_setclock(
         checked_cast!(attr),
         clock_id
     ));
-    let _ = attr;
 
-    if clock_id == libc::CLOCK_PROCESS_CPUTIME_ID || clock_id == libc::CLOCK_THREAD_CPUTIME_ID {
-        return libc::EINVAL;
+    // The futex timeouts we use only support these clocks.
+    match clock_id {
+        libc::CLOCK_REALTIME | libc::CLOCK_MONOTONIC => {}
+        _ => return libc::EINVAL,
     }
 
-    rustix::io::write(
-        rustix::stdio::stderr(),
-        b"unimplemented: pthread_condattr_setclock\n",
-    )
-    .ok();
+    let flags = (*attr).flags & ((1 << CONDATTR_CLOCK_SHIFT) - 1);
+    (*attr).flags = flags | ((clock_id as u32) << CONDATTR_CLOCK_SHIFT);
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_condattr_getclock(
+    attr: *const PthreadCondattrT,
+    clock_id: *mut libc::clockid_t,
+) -> c_int {
+    libc!(libc::pthread_condattr_getclock(
+        checked_cast!(attr),
+        clock_id
+    ));
+
+    *clock_id = (*attr).clock_id();
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_condattr_setpshared(
+    attr: *mut PthreadCondattrT,
+    pshared: c_int,
+) -> c_int {
+    libc!(libc::pthread_condattr_setpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    match pshared {
+        libc::PTHREAD_PROCESS_PRIVATE => (*attr).flags &= !CONDATTR_PSHARED,
+        libc::PTHREAD_PROCESS_SHARED => (*attr).flags |= CONDATTR_PSHARED,
+        _ => return libc::EINVAL,
+    }
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_condattr_getpshared(
+    attr: *const PthreadCondattrT,
+    pshared: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_condattr_getpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    *pshared = if (*attr).flags & CONDATTR_PSHARED != 0 {
+        libc::PTHREAD_PROCESS_SHARED
+    } else {
+        libc::PTHREAD_PROCESS_PRIVATE
+    };
     0
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_cond_broadcast(cond: *mut PthreadCondT) -> c_int {
     libc!(libc::pthread_cond_broadcast(checked_cast!(cond)));
-    (*cond).inner.notify_all();
+    (*cond).notify(i32::MAX as u32);
     0
 }
 
@@ -284,10 +809,10 @@ unsafe extern "C" fn pthread_cond_init(
     ptr::write(
         cond,
         PthreadCondT {
-            inner: RawCondvar::new(),
+            seq: AtomicU32::new(0),
             attr,
             pad: [0_u8;
-                SIZEOF_PTHREAD_COND_T - size_of::<RawCondvar>() - size_of::<PthreadCondattrT>()],
+                SIZEOF_PTHREAD_COND_T - size_of::<AtomicU32>() - size_of::<PthreadCondattrT>()],
         },
     );
     0
@@ -296,7 +821,7 @@ unsafe extern "C" fn pthread_cond_init(
 #[no_mangle]
 unsafe extern "C" fn pthread_cond_signal(cond: *mut PthreadCondT) -> c_int {
     libc!(libc::pthread_cond_signal(checked_cast!(cond)));
-    (*cond).inner.notify_one();
+    (*cond).notify(1);
     0
 }
 
@@ -306,13 +831,7 @@ unsafe extern "C" fn pthread_cond_wait(cond: *mut PthreadCondT, lock: *mut Pthre
         checked_cast!(cond),
         checked_cast!(lock)
     ));
-    match (*lock).kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => (*cond).inner.wait(&(*lock).u.normal),
-        libc::PTHREAD_MUTEX_RECURSIVE => todo!("PTHREAD_MUTEX_RECURSIVE"),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
-        other => unimplemented!("unsupported pthread mutex kind {}", other),
-    }
-    0
+    (*cond).wait(&*lock, None)
 }
 
 #[no_mangle]
@@ -326,27 +845,8 @@ unsafe extern "C" fn pthread_cond_timedwait(
         checked_cast!(lock),
         abstime,
     ));
-    let abstime = ptr::read(abstime);
-    let abstime = Duration::new(
-        abstime.tv_sec.try_into().unwrap(),
-        abstime.tv_nsec.try_into().unwrap(),
-    );
-    let now = rustix::time::clock_gettime(rustix::time::ClockId::Realtime);
-    let now = Duration::new(
-        now.tv_sec.try_into().unwrap(),
-        now.tv_nsec.try_into().unwrap(),
-    );
-    let reltime = abstime.saturating_sub(now);
-    match (*lock).kind.load(Ordering::SeqCst) as i32 {
-        libc::PTHREAD_MUTEX_NORMAL => {
-            if (*cond).inner.wait_timeout(&(*lock).u.normal, reltime) {
-                0
-            } else {
-                libc::ETIMEDOUT
-            }
-        }
-        libc::PTHREAD_MUTEX_RECURSIVE => todo!("PTHREAD_MUTEX_RECURSIVE"),
-        libc::PTHREAD_MUTEX_ERRORCHECK => todo!("PTHREAD_MUTEX_ERRORCHECK"),
-        other => unimplemented!("unsupported pthread mutex kind {}", other),
+    match read_abstime(abstime) {
+        Ok(abstime) => (*cond).wait(&*lock, Some(&abstime)),
+        Err(err) => err,
     }
 }
@@ -0,0 +1,170 @@
+//! Support for the kernel's robust futex list.
+//!
+//! Each thread registers a list head with the kernel, and links the robust
+//! mutexes it holds into the list. When a thread exits, including when its
+//! whole process dies, the kernel walks its list and marks each mutex still
+//! on it with `FUTEX_OWNER_DIED`, waking a waiter.
+//!
+//! See the kernel's [robust-futex-ABI documentation] for details.
+//!
+//! [robust-futex-ABI documentation]: https://docs.kernel.org/locking/robust-futex-ABI.html
+
+use core::cell::Cell;
+use core::ffi::c_void;
+use core::mem::{offset_of, size_of};
+use core::ptr::{self, null_mut};
+use core::sync::atomic::AtomicU32;
+
+use super::mutex::current_tid;
+use crate::raw_syscall::syscall2;
+
+/// A futex word, together with the links for placing it on the owning
+/// thread's robust list. Non-robust mutexes just leave the links unused.
+#[repr(C)]
+pub(super) struct RobustFutex {
+    pub(super) word: AtomicU32,
+
+    /// The kernel's `struct robust_list`. This points to the `next` field of
+    /// the next entry, or to the list head. The low bit is set if the next
+    /// entry is a priority-inheritance futex.
+    next: Cell<*mut c_void>,
+
+    /// A pointer to the `next` field of the previous entry, or to the list
+    /// head. The kernel doesn't use this; it's here so that we can unlink
+    /// entries without walking the list.
+    prev: Cell<*mut c_void>,
+}
+
+impl RobustFutex {
+    pub(super) const fn new() -> Self {
+        Self {
+            word: AtomicU32::new(0),
+            next: Cell::new(null_mut()),
+            prev: Cell::new(null_mut()),
+        }
+    }
+
+    /// The list-entry pointer for this futex, as the kernel sees it.
+    fn entry(&self, pi: bool) -> *mut c_void {
+        self.next
+            .as_ptr()
+            .cast::<c_void>()
+            .map_addr(|addr| addr | pi as usize)
+    }
+
+    /// Recover a `RobustFutex` from a list-entry pointer.
+    unsafe fn from_entry(entry: *mut c_void) -> *const Self {
+        entry
+            .map_addr(|addr| addr & !1)
+            .byte_sub(offset_of!(RobustFutex, next))
+            .cast()
+    }
+}
+
+/// The kernel's `struct robust_list_head`.
+#[repr(C)]
+struct RobustListHead {
+    list: Cell<*mut c_void>,
+    futex_offset: isize,
+    list_op_pending: Cell<*mut c_void>,
+}
+
+/// The offset the kernel adds to a list entry to find its futex word.
+const FUTEX_OFFSET: isize =
+    offset_of!(RobustFutex, word) as isize - offset_of!(RobustFutex, next) as isize;
+
+#[thread_local]
+static HEAD: RobustListHead = RobustListHead {
+    list: Cell::new(null_mut()),
+    futex_offset: FUTEX_OFFSET,
+    list_op_pending: Cell::new(null_mut()),
+};
+
+/// The thread id `HEAD` was registered for, or 0 if it hasn't been.
+#[thread_local]
+static REGISTERED_TID: Cell<u32> = Cell::new(0);
+
+/// Return the current thread's robust list head, registering it with the
+/// kernel first if needed.
+fn head() -> *const RobustListHead {
+    let head = ptr::addr_of!(HEAD);
+    let tid = current_tid();
+
+    // A child of `fork` inherits our thread-local variables but not the
+    // kernel's registration, so compare thread ids rather than using a flag.
+    if REGISTERED_TID.get() != tid {
+        unsafe {
+            (*head).list.set((*head).list.as_ptr().cast());
+            (*head).list_op_pending.set(null_mut());
+            set_robust_list(head);
+        }
+        REGISTERED_TID.set(tid);
+    }
+
+    head
+}
+
+/// Note that the current thread is about to acquire or release `futex`, so
+/// that the kernel can clean it up if the thread dies partway through.
+pub(super) fn set_pending(futex: &RobustFutex, pi: bool) {
+    unsafe { (*head()).list_op_pending.set(futex.entry(pi)) }
+}
+
+/// Clear the pending operation set by [`set_pending`].
+pub(super) fn clear_pending() {
+    unsafe { (*head()).list_op_pending.set(null_mut()) }
+}
+
+/// Add `futex`, which the current thread has just acquired, to the current
+/// thread's robust list.
+///
+/// # Safety
+///
+/// `futex` must not already be on a robust list, and must remain valid until
+/// it's removed with [`remove`].
+pub(super) unsafe fn push(futex: &RobustFutex, pi: bool) {
+    let head = head();
+    let list = (*head).list.as_ptr().cast::<c_void>();
+    let first = (*head).list.get();
+
+    futex.next.set(first);
+    futex.prev.set(list);
+    if first.map_addr(|addr| addr & !1) != list {
+        (*RobustFutex::from_entry(first))
+            .prev
+            .set(futex.entry(false));
+    }
+    (*head).list.set(futex.entry(pi));
+}
+
+/// Remove `futex`, which the current thread is about to release, from the
+/// current thread's robust list.
+///
+/// # Safety
+///
+/// `futex` must be on the current thread's robust list.
+pub(super) unsafe fn remove(futex: &RobustFutex) {
+    let list = (*head()).list.as_ptr().cast::<c_void>();
+    let next = futex.next.get();
+    let prev = futex.prev.get();
+
+    // The previous entry's `next` keeps the low bit describing the next
+    // entry, which `futex.next` already has.
+    (*prev.cast::<Cell<*mut c_void>>()).set(next);
+    if next.map_addr(|addr| addr & !1) != list {
+        (*RobustFutex::from_entry(next)).prev.set(prev);
+    }
+}
+
+/// Register `head` as the current thread's robust list.
+///
+/// rustix doesn't wrap `set_robust_list`, so we make the syscall ourselves.
+/// It only fails if the length is wrong, so we ignore its return value.
+unsafe fn set_robust_list(head: *const RobustListHead) {
+    syscall2(
+        libc::SYS_set_robust_list,
+        head.expose_provenance(),
+        size_of::<RobustListHead>(),
+    )
+    .ok();
+}
@@ -1,19 +1,89 @@
-use rustix_futex_sync::lock_api::RawRwLock as _;
-use rustix_futex_sync::RawRwLock;
+//! The rwlock algorithm here is derived from Rust's
+//! library/std/src/sys/sync/rwlock/futex.rs at revision
+//! 22a5267c83a3e17f2b763279eb24bb632c45dc6b, by way of rustix-futex-sync,
+//! extended with timeouts and process-shared locks.
+
+use rustix::thread::futex;
+use rustix::time::ClockId;
 
 use core::ptr;
-use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::sync::atomic::{AtomicU32, Ordering};
 use libc::c_int;
 
+use super::mutex::current_tid;
+use super::timeout::{read_abstime, Deadline};
+
+// The layout of the `state` field of `PthreadRwlockT`.
+// Bits 0..30:
+//   0: Unlocked
+//   1..=0x3FFF_FFFE: Locked by N readers
+//   0x3FFF_FFFF: Write locked
+// Bit 30: Readers are waiting on this futex.
+// Bit 31: Writers are waiting on the writer_notify futex.
+const READ_LOCKED: u32 = 1;
+const MASK: u32 = (1 << 30) - 1;
+const WRITE_LOCKED: u32 = MASK;
+const MAX_READERS: u32 = MASK - 1;
+const READERS_WAITING: u32 = 1 << 30;
+const WRITERS_WAITING: u32 = 1 << 31;
+
+/// The bit of `PthreadRwlockT::flags` and `PthreadRwlockattrT::pshared` for
+/// `PTHREAD_PROCESS_SHARED`.
+const FLAG_PSHARED: u32 = 0x1;
+
+#[inline]
+fn is_unlocked(state: u32) -> bool {
+    state & MASK == 0
+}
+
+#[inline]
+fn is_write_locked(state: u32) -> bool {
+    state & MASK == WRITE_LOCKED
+}
+
+#[inline]
+fn has_readers_waiting(state: u32) -> bool {
+    state & READERS_WAITING != 0
+}
+
+#[inline]
+fn has_writers_waiting(state: u32) -> bool {
+    state & WRITERS_WAITING != 0
+}
+
+#[inline]
+fn is_read_lockable(state: u32) -> bool {
+    // This also returns false if the counter could overflow if we tried to read lock it.
+    //
+    // We don't allow read-locking if there's readers waiting, even if the lock is unlocked
+    // and there's no writers waiting. The only situation when this happens is after unlocking,
+    // at which point the unlocking thread might be waking up writers, which have priority over readers.
+    // The unlocking thread will clear the readers waiting bit and wake up readers, if necessary.
+    state & MASK < MAX_READERS && !has_readers_waiting(state) && !has_writers_waiting(state)
+}
+
+#[inline]
+fn has_reached_max_readers(state: u32) -> bool {
+    state & MASK == MAX_READERS
+}
+
 #[allow(non_camel_case_types)]
 #[repr(C)]
 struct PthreadRwlockT {
-    lock: RawRwLock,
-    exclusive: AtomicBool,
+    /// The reader count and waiting flags.
+    state: AtomicU32,
+    /// The 'condition variable' to notify writers through. Incremented on
+    /// every signal.
+    writer_notify: AtomicU32,
+    /// The thread id of the writer holding the lock, or 0, so that we can
+    /// report deadlocks instead of hanging.
+    writer: AtomicU32,
+    flags: AtomicU32,
     pad0: usize,
     pad1: usize,
     pad2: usize,
     pad3: usize,
+    #[cfg(target_pointer_width = "64")]
     pad4: usize,
 }
 libc_type!(PthreadRwlockT, pthread_rwlock_t);
@@ -32,11 +102,334 @@ libc_type!(PthreadRwlockT, pthread_rwlock_t);
     repr(align(8))
 )]
 struct PthreadRwlockattrT {
-    kind: AtomicU32,
-    _pad0: u32,
+    /// The reader/writer preference, which we don't support changing.
+    _kind: AtomicU32,
+    pshared: AtomicU32,
 }
 libc_type!(PthreadRwlockattrT, pthread_rwlockattr_t);
 
+impl PthreadRwlockT {
+    #[inline]
+    fn futex_flags(&self) -> futex::Flags {
+        if self.flags.load(Ordering::Relaxed) & FLAG_PSHARED != 0 {
+            futex::Flags::empty()
+        } else {
+            futex::Flags::PRIVATE
+        }
+    }
+
+    fn read(&self, deadline: Deadline<'_>) -> c_int {
+        // Waiting for ourselves would deadlock, but trying is just busy.
+        if self.writer.load(Ordering::Relaxed) == current_tid() {
+            return match deadline {
+                Deadline::Now => libc::EBUSY,
+                _ => libc::EDEADLK,
+            };
+        }
+
+        let mut state = self.spin_read();
+
+        loop {
+            // If we can lock it, lock it.
+            if is_read_lockable(state) {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state + READ_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return 0, // Locked!
+                    Err(s) => {
+                        state = s;
+                        continue;
+                    }
+                }
+            }
+
+            // Check for overflow.
+            if has_reached_max_readers(state) {
+                return libc::EAGAIN;
+            }
+
+            if let Deadline::Now = deadline {
+                return libc::EBUSY;
+            }
+
+            // Make sure the readers waiting bit is set before we go to sleep.
+            if !has_readers_waiting(state) {
+                if let Err(s) = self.state.compare_exchange(
+                    state,
+                    state | READERS_WAITING,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    state = s;
+                    continue;
+                }
+            }
+
+            // Wait for the state to change.
+            if let Err(err) =
+                deadline.wait(&self.state, self.futex_flags(), state | READERS_WAITING)
+            {
+                self.abandon_read_wait();
+                return err;
+            }
+
+            // Spin again after waking up.
+            state = self.spin_read();
+        }
+    }
+
+    fn write(&self, deadline: Deadline<'_>) -> c_int {
+        let tid = current_tid();
+        if self.writer.load(Ordering::Relaxed) == tid {
+            return match deadline {
+                Deadline::Now => libc::EBUSY,
+                _ => libc::EDEADLK,
+            };
+        }
+
+        let mut state = self.spin_write();
+
+        let mut other_writers_waiting = 0;
+
+        loop {
+            // If it's unlocked, we try to lock it.
+            if is_unlocked(state) {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state | WRITE_LOCKED | other_writers_waiting,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Locked!
+                        self.writer.store(tid, Ordering::Relaxed);
+                        return 0;
+                    }
+                    Err(s) => {
+                        state = s;
+                        continue;
+                    }
+                }
+            }
+
+            if let Deadline::Now = deadline {
+                return libc::EBUSY;
+            }
+
+            // Set the waiting bit indicating that we're waiting on it.
+            if !has_writers_waiting(state) {
+                if let Err(s) = self.state.compare_exchange(
+                    state,
+                    state | WRITERS_WAITING,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    state = s;
+                    continue;
+                }
+            }
+
+            // Other writers might be waiting now too, so we should make sure
+            // we keep that bit on once we manage lock it.
+            other_writers_waiting = WRITERS_WAITING;
+
+            // Examine the notification counter before we check if `state` has changed,
+            // to make sure we don't miss any notifications.
+            let seq = self.writer_notify.load(Ordering::Acquire);
+
+            // Don't go to sleep if the lock has become available,
+            // or if the writers waiting bit is no longer set.
+            state = self.state.load(Ordering::Relaxed);
+            if is_unlocked(state) || !has_writers_waiting(state) {
+                continue;
+            }
+
+            // Wait for the state to change.
+            if let Err(err) = deadline.wait(&self.writer_notify, self.futex_flags(), seq) {
+                self.abandon_write_wait();
+                return err;
+            }
+
+            // Spin again after waking up.
+            state = self.spin_write();
+        }
+    }
+
+    /// Clean up after a reader gives up on waiting.
+    ///
+    /// We may leave the readers waiting bit set with nobody waiting. That's
+    /// fine if the lock is held, because the unlocking thread will clear it,
+    /// but if the lock is unlocked, nobody else will, so we do what an
+    /// unlocking thread would.
+    fn abandon_read_wait(&self) {
+        let state = self.state.load(Ordering::Relaxed);
+        if is_unlocked(state) && (has_readers_waiting(state) || has_writers_waiting(state)) {
+            self.wake_writer_or_readers(state);
+        }
+    }
+
+    /// Clean up after a writer gives up on waiting.
+    ///
+    /// We can't tell whether any other writers are still waiting, and if
+    /// we leave the writers waiting bit set when none are, readers would
+    /// block until the lock is next unlocked. So we clear the waiting bits,
+    /// wake everyone, and let any remaining waiters set the bits again.
+    fn abandon_write_wait(&self) {
+        let flags = self.futex_flags();
+        let state = self
+            .state
+            .fetch_and(!(READERS_WAITING | WRITERS_WAITING), Ordering::Relaxed);
+        if has_readers_waiting(state) {
+            futex::wake(&self.state, flags, i32::MAX as u32).ok();
+        }
+        if has_writers_waiting(state) {
+            self.writer_notify.fetch_add(1, Ordering::Release);
+            futex::wake(&self.writer_notify, flags, i32::MAX as u32).ok();
+        }
+    }
+
+    fn unlock(&self) -> c_int {
+        let state = self.state.load(Ordering::Relaxed);
+        if is_unlocked(state) {
+            return libc::EPERM;
+        }
+
+        if is_write_locked(state) {
+            if self.writer.load(Ordering::Relaxed) != current_tid() {
+                return libc::EPERM;
+            }
+            self.writer.store(0, Ordering::Relaxed);
+
+            let state = self.state.fetch_sub(WRITE_LOCKED, Ordering::Release) - WRITE_LOCKED;
+
+            debug_assert!(is_unlocked(state));
+
+            if has_writers_waiting(state) || has_readers_waiting(state) {
+                self.wake_writer_or_readers(state);
+            }
+        } else {
+            let state = self.state.fetch_sub(READ_LOCKED, Ordering::Release) - READ_LOCKED;
+
+            // It's impossible for a reader to be waiting on a read-locked RwLock,
+            // except if there is also a writer waiting, or one just gave up.
+
+            // Wake up a writer if we were the last reader and there's a writer waiting.
+            if is_unlocked(state) && (has_writers_waiting(state) || has_readers_waiting(state)) {
+                self.wake_writer_or_readers(state);
+            }
+        }
+        0
+    }
+
+    /// Wake up waiting threads after unlocking.
+    ///
+    /// If both are waiting, this will wake up only one writer, but will fall
+    /// back to waking up readers if there was no writer to wake up.
+    #[cold]
+    fn wake_writer_or_readers(&self, mut state: u32) {
+        assert!(is_unlocked(state));
+
+        // The readers waiting bit might be turned on at any point now,
+        // since readers will block when there's anything waiting.
+        // Writers will just lock the lock though, regardless of the waiting bits,
+        // so we don't have to worry about the writer waiting bit.
+        //
+        // If the lock gets locked in the meantime, we don't have to do
+        // anything, because then the thread that locked the lock will take
+        // care of waking up waiters when it unlocks.
+
+        // If only writers are waiting, wake one of them up.
+        if state == WRITERS_WAITING {
+            match self
+                .state
+                .compare_exchange(state, 0, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    self.wake_writer();
+                    return;
+                }
+                Err(s) => {
+                    // Maybe some readers are now waiting too. So, continue to the next `if`.
+                    state = s;
+                }
+            }
+        }
+
+        // If both writers and readers are waiting, leave the readers waiting
+        // and only wake up one writer.
+        if state == READERS_WAITING + WRITERS_WAITING {
+            if self
+                .state
+                .compare_exchange(state, READERS_WAITING, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+            {
+                // The lock got locked. Not our problem anymore.
+                return;
+            }
+            if self.wake_writer() {
+                return;
+            }
+            // No writers were actually blocked on futex_wait, so we continue
+            // to wake up readers instead, since we can't be sure if we notified a writer.
+            state = READERS_WAITING;
+        }
+
+        // If readers are waiting, wake them all up.
+        if state == READERS_WAITING
+            && self
+                .state
+                .compare_exchange(state, 0, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            futex::wake(&self.state, self.futex_flags(), i32::MAX as u32).ok();
+        }
+    }
+
+    /// This wakes one writer and returns true if we woke up a writer that was
+    /// blocked on futex_wait.
+    ///
+    /// If this returns false, it might still be the case that we notified a
+    /// writer that was about to go to sleep.
+    fn wake_writer(&self) -> bool {
+        self.writer_notify.fetch_add(1, Ordering::Release);
+        matches!(
+            futex::wake(&self.writer_notify, self.futex_flags(), 1),
+            Ok(n) if n > 0
+        )
+    }
+
+    /// Spin for a while, but stop directly at the given condition.
+    #[inline]
+    fn spin_until(&self, f: impl Fn(u32) -> bool) -> u32 {
+        let mut spin = 100; // Chosen by fair dice roll.
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if f(state) || spin == 0 {
+                return state;
+            }
+            core::hint::spin_loop();
+            spin -= 1;
+        }
+    }
+
+    #[inline]
+    fn spin_write(&self) -> u32 {
+        // Stop spinning when it's unlocked or when there's waiting writers, to keep things somewhat fair.
+        self.spin_until(|state| is_unlocked(state) || has_writers_waiting(state))
+    }
+
+    #[inline]
+    fn spin_read(&self) -> u32 {
+        // Stop spinning when it's unlocked or read locked, or when there's waiting threads.
+        self.spin_until(|state| {
+            !is_write_locked(state) || has_readers_waiting(state) || has_writers_waiting(state)
+        })
+    }
+}
+
 #[no_mangle]
 unsafe extern "C" fn pthread_rwlock_init(
     rwlock: *mut PthreadRwlockT,
@@ -46,11 +439,15 @@ unsafe extern "C" fn pthread_rwlock_init(
         checked_cast!(rwlock),
         checked_cast!(rwlockattr)
     ));
-    if !rwlockattr.is_null() {
-        let _ = (*rwlockattr).kind.load(Ordering::SeqCst);
-    }
-    ptr::write(&mut (*rwlock).lock, RawRwLock::INIT);
-    (*rwlock).exclusive.store(false, Ordering::SeqCst);
+    let flags = if rwlockattr.is_null() {
+        0
+    } else {
+        (*rwlockattr).pshared.load(Ordering::SeqCst)
+    };
+    ptr::write(&mut (*rwlock).state, AtomicU32::new(0));
+    ptr::write(&mut (*rwlock).writer_notify, AtomicU32::new(0));
+    ptr::write(&mut (*rwlock).writer, AtomicU32::new(0));
+    ptr::write(&mut (*rwlock).flags, AtomicU32::new(flags));
 
     0
 }
@@ -65,9 +462,7 @@ unsafe extern "C" fn pthread_rwlock_destroy(rwlock: *mut PthreadRwlockT) -> c_in
 #[no_mangle]
 unsafe extern "C" fn pthread_rwlock_wrlock(rwlock: *mut PthreadRwlockT) -> c_int {
     libc!(libc::pthread_rwlock_wrlock(checked_cast!(rwlock)));
-    (*rwlock).lock.lock_exclusive();
-    (*rwlock).exclusive.store(true, Ordering::SeqCst);
-    0
+    (*rwlock).write(Deadline::Never)
 }
 
 #[no_mangle]
@@ -75,8 +470,8 @@ unsafe extern "C" fn pthread_rwlockattr_init(attr: *mut PthreadRwlockattrT) -> c
     libc!(libc::pthread_rwlockattr_init(checked_cast!(attr)));
 
     attr.write(PthreadRwlockattrT {
-        kind: AtomicU32::new(0),
-        _pad0: 0,
+        _kind: AtomicU32::new(0),
+        pshared: AtomicU32::new(0),
     });
     0
 }
@@ -87,53 +482,89 @@ unsafe extern "C" fn pthread_rwlockattr_destroy(_attr: *mut PthreadRwlockattrT)
     0
 }
 
+#[no_mangle]
+unsafe extern "C" fn pthread_rwlockattr_setpshared(
+    attr: *mut PthreadRwlockattrT,
+    pshared: c_int,
+) -> c_int {
+    libc!(libc::pthread_rwlockattr_setpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    let flags = match pshared {
+        libc::PTHREAD_PROCESS_PRIVATE => 0,
+        libc::PTHREAD_PROCESS_SHARED => FLAG_PSHARED,
+        _ => return libc::EINVAL,
+    };
+    (*attr).pshared.store(flags, Ordering::SeqCst);
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_rwlockattr_getpshared(
+    attr: *const PthreadRwlockattrT,
+    pshared: *mut c_int,
+) -> c_int {
+    libc!(libc::pthread_rwlockattr_getpshared(
+        checked_cast!(attr),
+        pshared
+    ));
+
+    *pshared = if (*attr).pshared.load(Ordering::SeqCst) & FLAG_PSHARED != 0 {
+        libc::PTHREAD_PROCESS_SHARED
+    } else {
+        libc::PTHREAD_PROCESS_PRIVATE
+    };
+    0
+}
+
 #[no_mangle]
 unsafe extern "C" fn pthread_rwlock_tryrdlock(rwlock: *mut PthreadRwlockT) -> c_int {
     libc!(libc::pthread_rwlock_tryrdlock(checked_cast!(rwlock)));
-    let result = (*rwlock).lock.try_lock_shared();
-    if result {
-        (*rwlock).exclusive.store(false, Ordering::SeqCst);
-        0
-    } else {
-        libc::EBUSY
-    }
+    (*rwlock).read(Deadline::Now)
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_rwlock_trywrlock(rwlock: *mut PthreadRwlockT) -> c_int {
     libc!(libc::pthread_rwlock_trywrlock(checked_cast!(rwlock)));
-    let result = (*rwlock).lock.try_lock_exclusive();
-    if result {
-        (*rwlock).exclusive.store(true, Ordering::SeqCst);
-        0
-    } else {
-        libc::EBUSY
-    }
+    (*rwlock).write(Deadline::Now)
 }
 
 #[no_mangle]
 unsafe extern "C" fn pthread_rwlock_rdlock(rwlock: *mut PthreadRwlockT) -> c_int {
     libc!(libc::pthread_rwlock_rdlock(checked_cast!(rwlock)));
-    (*rwlock).lock.lock_shared();
-    (*rwlock).exclusive.store(false, Ordering::SeqCst);
-    0
+    (*rwlock).read(Deadline::Never)
 }
 
 #[no_mangle]
-unsafe extern "C" fn pthread_rwlock_unlock(rwlock: *mut PthreadRwlockT) -> c_int {
-    libc!(libc::pthread_rwlock_unlock(checked_cast!(rwlock)));
+unsafe extern "C" fn pthread_rwlock_timedrdlock(
+    rwlock: *mut PthreadRwlockT,
+    abstime: *const libc::timespec,
+) -> c_int {
+    //libc!(libc::pthread_rwlock_timedrdlock(checked_cast!(rwlock), abstime));
 
-    let rwlock = &*rwlock;
-    if rwlock.exclusive.load(Ordering::SeqCst) {
-        if !rwlock.lock.is_locked_exclusive() {
-            return libc::EPERM;
-        }
-        rwlock.lock.unlock_exclusive();
-    } else {
-        if !rwlock.lock.is_locked() {
-            return libc::EPERM;
-        }
-        rwlock.lock.unlock_shared();
+    match read_abstime(abstime) {
+        Ok(abstime) => (*rwlock).read(Deadline::At(&abstime, ClockId::Realtime)),
+        Err(err) => err,
     }
-    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_rwlock_timedwrlock(
+    rwlock: *mut PthreadRwlockT,
+    abstime: *const libc::timespec,
+) -> c_int {
+    //libc!(libc::pthread_rwlock_timedwrlock(checked_cast!(rwlock), abstime));
+
+    match read_abstime(abstime) {
+        Ok(abstime) => (*rwlock).write(Deadline::At(&abstime, ClockId::Realtime)),
+        Err(err) => err,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn pthread_rwlock_unlock(rwlock: *mut PthreadRwlockT) -> c_int {
+    libc!(libc::pthread_rwlock_unlock(checked_cast!(rwlock)));
+    (*rwlock).unlock()
 }
@@ -0,0 +1,191 @@
+//! Applying credential changes to every thread in the process.
+//!
+//! Linux's credential syscalls only affect the calling thread, so, like
+//! glibc, we apply the change on the calling thread, and then send a reserved
+//! signal to every other thread, whose handler applies the same change, and
+//! wait for them all to finish. Thread creation is blocked while this is
+//! happening, so that no thread can be created with the old credentials
+//! after we've listed the threads.
+
+use crate::process::setxid::SetXid;
+use alloc::vec::Vec;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use libc::{c_int, pid_t};
+use origin::signal::{Sigaction, SigactionFlags};
+use rustix::fd::AsFd;
+use rustix::fs::{Dir, Mode, OFlags};
+use rustix::io::{self, Errno};
+use rustix::process::Signal;
+use rustix::runtime::{KernelSigSet, KERNEL_SIGRTMIN};
+use rustix::thread::futex;
+use rustix::time::Timespec;
+use rustix_futex_sync::RwLock;
+
+/// The signal we use to ask threads to apply a change. This is one of the
+/// signals reserved in `signal/mod.rs`, and it's the same one glibc uses.
+const SIGSETXID: Signal = unsafe { Signal::from_raw_unchecked(KERNEL_SIGRTMIN + 1) };
+
+/// `pthread_create` holds this for reading while creating a thread, and
+/// [`broadcast`] holds it for writing.
+pub(super) static THREAD_CREATION: RwLock<()> = RwLock::new(());
+
+/// The broadcast in progress, if any.
+static CURRENT: AtomicPtr<Broadcast> = AtomicPtr::new(null_mut());
+
+/// The number of signal handlers that have finished, used as a futex for
+/// waiting on them.
+static ACKS: AtomicU32 = AtomicU32::new(0);
+
+/// The number of signal handlers currently running, so that we know when
+/// it's safe to free a `Broadcast`.
+static HANDLERS_RUNNING: AtomicU32 = AtomicU32::new(0);
+
+const PENDING: u32 = 0;
+const DONE: u32 = 1;
+const FAILED: u32 = 2;
+
+struct Broadcast {
+    op: SetXid,
+    threads: Vec<Target>,
+}
+
+struct Target {
+    tid: pid_t,
+    state: AtomicU32,
+}
+
+/// Apply `op` to every thread in the process.
+///
+/// If `op` fails on the calling thread, nothing is changed and the error is
+/// returned. If it then fails on any other thread, the process's threads
+/// would be left with inconsistent credentials, so we abort.
+pub(crate) unsafe fn broadcast(op: SetXid) -> io::Result<()> {
+    let _creation = THREAD_CREATION.write();
+
+    let me = origin::thread::current_id().as_raw_nonzero().get();
+    let threads = other_threads(me)?;
+
+    op.apply()?;
+
+    if threads.is_empty() {
+        return Ok(());
+    }
+
+    let action = Sigaction {
+        sa_handler_kernel: Some(handler),
+        sa_flags: SigactionFlags::RESTART,
+        sa_mask: KernelSigSet::all(),
+        ..Default::default()
+    };
+    origin::signal::sigaction(SIGSETXID, Some(action))?;
+
+    let broadcast = Broadcast { op, threads };
+    CURRENT.store(
+        (&broadcast as *const Broadcast).cast_mut(),
+        Ordering::SeqCst,
+    );
+
+    for target in &broadcast.threads {
+        // If the thread has exited since we listed it, we'll notice below.
+        super::tgkill(target.tid, SIGSETXID.as_raw()).ok();
+    }
+
+    let mut failed = false;
+    loop {
+        let acks = ACKS.load(Ordering::Acquire);
+
+        let mut pending = false;
+        for target in &broadcast.threads {
+            match target.state.load(Ordering::Acquire) {
+                PENDING => {
+                    // A thread that exits before handling the signal won't
+                    // ever handle it.
+                    if super::tgkill(target.tid, 0) != Err(Errno::SRCH) {
+                        pending = true;
+                    }
+                }
+                FAILED => failed = true,
+                _ => {}
+            }
+        }
+        if !pending {
+            break;
+        }
+
+        // Wait for a handler to finish, or periodically recheck for exited
+        // threads.
+        let timeout = Timespec {
+            tv_sec: 0,
+            tv_nsec: 10_000_000,
+        };
+        futex::wait(&ACKS, futex::Flags::PRIVATE, acks, Some(&timeout)).ok();
+    }
+
+    CURRENT.store(null_mut(), Ordering::SeqCst);
+    while HANDLERS_RUNNING.load(Ordering::SeqCst) != 0 {
+        origin::thread::yield_current();
+    }
+
+    if failed {
+        rustix::io::write(
+            rustix::stdio::stderr(),
+            b"Failed to set IDs consistently across all threads.\n",
+        )
+        .ok();
+        libc::abort();
+    }
+
+    Ok(())
+}
+
+/// List the kernel ids of all the threads in the process other than `me`.
+fn other_threads(me: pid_t) -> io::Result<Vec<Target>> {
+    let dir = rustix::fs::open(
+        "/proc/self/task",
+        OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )?;
+
+    let mut threads = Vec::new();
+    for entry in Dir::read_from(dir.as_fd())? {
+        let entry = entry?;
+        let tid = match core::str::from_utf8(entry.file_name().to_bytes()) {
+            Ok(name) => match name.parse::<pid_t>() {
+                Ok(tid) => tid,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        if tid != me {
+            threads.push(Target {
+                tid,
+                state: AtomicU32::new(PENDING),
+            });
+        }
+    }
+    Ok(threads)
+}
+
+unsafe extern "C" fn handler(_sig: c_int) {
+    HANDLERS_RUNNING.fetch_add(1, Ordering::SeqCst);
+
+    let broadcast = CURRENT.load(Ordering::SeqCst);
+    if !broadcast.is_null() {
+        let broadcast = &*broadcast;
+        let me = origin::thread::current_id().as_raw_nonzero().get();
+        if let Some(target) = broadcast.threads.iter().find(|target| target.tid == me) {
+            if target.state.load(Ordering::Acquire) == PENDING {
+                let state = match broadcast.op.apply() {
+                    Ok(()) => DONE,
+                    Err(_) => FAILED,
+                };
+                target.state.store(state, Ordering::Release);
+            }
+        }
+    }
+
+    ACKS.fetch_add(1, Ordering::Release);
+    HANDLERS_RUNNING.fetch_sub(1, Ordering::SeqCst);
+    futex::wake(&ACKS, futex::Flags::PRIVATE, 1).ok();
+}
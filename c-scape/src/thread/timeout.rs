@@ -0,0 +1,65 @@
+//! Timeouts for the blocking synchronization functions.
+
+use rustix::io::Errno;
+use rustix::thread::futex;
+use rustix::time::{ClockId, Timespec};
+
+use core::num::NonZeroU32;
+use core::ptr;
+use core::sync::atomic::AtomicU32;
+use libc::c_int;
+
+/// How long to wait for a lock or a notification.
+#[derive(Clone, Copy)]
+pub(super) enum Deadline<'a> {
+    /// Don't wait at all.
+    Now,
+    /// Wait until an absolute time, measured by the given clock, which is
+    /// either `Realtime` or `Monotonic`.
+    At(&'a Timespec, ClockId),
+    /// Wait as long as it takes.
+    Never,
+}
+
+impl Deadline<'_> {
+    /// Wait on `futex` if it still holds `val`, until it's woken or the
+    /// deadline passes. Spurious wakeups are possible, so callers should
+    /// recheck their condition either way.
+    pub(super) fn wait(
+        self,
+        futex: &AtomicU32,
+        flags: futex::Flags,
+        val: u32,
+    ) -> Result<(), c_int> {
+        let result = match self {
+            Self::Now => return Err(libc::ETIMEDOUT),
+            Self::At(abstime, clock) => {
+                // `FUTEX_WAIT_BITSET` takes an absolute time, measured with
+                // `CLOCK_MONOTONIC` unless we ask for `CLOCK_REALTIME`.
+                let flags = match clock {
+                    ClockId::Realtime => flags | futex::Flags::CLOCK_REALTIME,
+                    _ => flags,
+                };
+                futex::wait_bitset(futex, flags, val, Some(abstime), NonZeroU32::MAX)
+            }
+            Self::Never => futex::wait(futex, flags, val, None),
+        };
+        match result {
+            Err(Errno::TIMEDOUT) => Err(libc::ETIMEDOUT),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Convert a user-supplied `timespec` into a `Timespec`, checking that it's
+/// valid.
+pub(super) unsafe fn read_abstime(abstime: *const libc::timespec) -> Result<Timespec, c_int> {
+    let abstime = ptr::read(abstime);
+    if !(0..1_000_000_000).contains(&abstime.tv_nsec) {
+        return Err(libc::EINVAL);
+    }
+    Ok(Timespec {
+        tv_sec: abstime.tv_sec as _,
+        tv_nsec: abstime.tv_nsec as _,
+    })
+}
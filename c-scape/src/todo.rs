@@ -1,16 +1,13 @@
 //! Unimplemented stub functions. These may help porting programs which
 //! need these functions to link but don't (always) call them at runtime.
 
-mod aio;
 mod cat;
-mod dl;
 mod fenv;
 mod jmp;
 mod locale;
 mod long_double;
 mod long_double_complex;
 mod pthread_cancel;
-mod set_id;
 mod sysv;
 mod wchar;
 
@@ -22,10 +19,6 @@ unsafe extern "C" fn sysinfo() {
     todo!("sysinfo")
 }
 #[no_mangle]
-unsafe extern "C" fn signalfd() {
-    todo!("signalfd")
-}
-#[no_mangle]
 unsafe extern "C" fn mount() {
     todo!("mount")
 }
@@ -170,22 +163,6 @@ unsafe extern "C" fn endpwent() {
     todo!("endpwent")
 }
 #[no_mangle]
-unsafe extern "C" fn setmntent() {
-    todo!("setmntent")
-}
-#[no_mangle]
-unsafe extern "C" fn getmntent() {
-    todo!("getmntent")
-}
-#[no_mangle]
-unsafe extern "C" fn endmntent() {
-    todo!("endmntent")
-}
-#[no_mangle]
-unsafe extern "C" fn hasmntopt() {
-    todo!("hasmntopt")
-}
-#[no_mangle]
 unsafe extern "C" fn setgrent() {
     todo!("setgrent")
 }
@@ -214,14 +191,6 @@ unsafe extern "C" fn getprotobynumber() {
     todo!("getprotobynumber")
 }
 #[no_mangle]
-unsafe extern "C" fn getifaddrs() {
-    todo!("getifaddrs")
-}
-#[no_mangle]
-unsafe extern "C" fn freeifaddrs() {
-    todo!("freeifaddrs")
-}
-#[no_mangle]
 unsafe extern "C" fn getnameinfo() {
     todo!("getnameinfo")
 }
@@ -245,26 +214,6 @@ unsafe extern "C" fn getspent_r() {
 unsafe extern "C" fn gethostbyname_r() {
     todo!("gethostbyname_r")
 }
-#[no_mangle]
-unsafe extern "C" fn initgroups() {
-    todo!("initgroups")
-}
-#[no_mangle]
-unsafe extern "C" fn if_indextoname() {
-    todo!("if_indextoname")
-}
-#[no_mangle]
-unsafe extern "C" fn if_nametoindex() {
-    todo!("if_nametoindex")
-}
-#[no_mangle]
-unsafe extern "C" fn if_nameindex() {
-    todo!("if_nameindex")
-}
-#[no_mangle]
-unsafe extern "C" fn if_freenameindex() {
-    todo!("if_freenameindex")
-}
 
 // Additional functions.
 
@@ -281,14 +230,6 @@ unsafe extern "C" fn process_vm_readv() {
     todo!("process_vm_readv")
 }
 #[no_mangle]
-unsafe extern "C" fn setfsuid() {
-    todo!("setfsuid")
-}
-#[no_mangle]
-unsafe extern "C" fn setfsgid() {
-    todo!("setfsgid")
-}
-#[no_mangle]
 unsafe extern "C" fn strftime() {
     todo!("strftime")
 }
@@ -305,10 +246,6 @@ unsafe extern "C" fn __isoc99_sscanf() {
     todo!("__isoc99_sscanf")
 }
 #[no_mangle]
-unsafe extern "C" fn pthread_kill() {
-    todo!("pthread_kill")
-}
-#[no_mangle]
 unsafe extern "C" fn __fdelt_chk() {
     todo!("__fdelt_chk")
 }
@@ -321,26 +258,6 @@ unsafe extern "C" fn clock_getcpuclockid() {
     todo!("clock_getcpuclockid")
 }
 #[no_mangle]
-unsafe extern "C" fn getresgid() {
-    todo!("getresgid")
-}
-#[no_mangle]
-unsafe extern "C" fn getresuid() {
-    todo!("getresuid")
-}
-#[no_mangle]
-unsafe extern "C" fn ppoll() {
-    todo!("ppoll")
-}
-#[no_mangle]
-unsafe extern "C" fn __ppoll_chk() {
-    todo!("__ppoll_chk")
-}
-#[no_mangle]
-unsafe extern "C" fn pselect() {
-    todo!("pselect")
-}
-#[no_mangle]
 unsafe extern "C" fn epoll_pwait() {
     todo!("epoll_pwait")
 }
@@ -469,14 +386,6 @@ unsafe extern "C" fn getline() {
     todo!("getline")
 }
 #[no_mangle]
-unsafe extern "C" fn getopt_long() {
-    todo!("getopt_long")
-}
-#[no_mangle]
-unsafe extern "C" fn getopt_long_only() {
-    todo!("getopt_long_only")
-}
-#[no_mangle]
 unsafe extern "C" fn ns_get16() {
     todo!("ns_get16")
 }
@@ -497,147 +406,15 @@ unsafe extern "C" fn open_memstream() {
     todo!("open_memstream")
 }
 #[no_mangle]
-unsafe extern "C" fn optarg() {
-    todo!("optarg")
-}
-#[no_mangle]
-unsafe extern "C" fn optind() {
-    todo!("optind")
-}
-#[no_mangle]
-unsafe extern "C" fn optopt() {
-    todo!("optopt")
-}
-#[no_mangle]
-unsafe extern "C" fn opterr() {
-    todo!("opterr")
-}
-#[no_mangle]
 unsafe extern "C" fn posix_spawn() {
     todo!("posix_spawn")
 }
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_getinheritsched() {
-    todo!("pthread_attr_getinheritsched")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_getschedparam() {
-    todo!("pthread_attr_getschedparam")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_getschedpolicy() {
-    todo!("pthread_attr_getschedpolicy")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_getscope() {
-    todo!("pthread_attr_getscope")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_setinheritsched() {
-    todo!("pthread_attr_setinheritsched")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_setschedparam() {
-    todo!("pthread_attr_setschedparam")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_setschedpolicy() {
-    todo!("pthread_attr_setschedpolicy")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_attr_setscope() {
-    todo!("pthread_attr_setscope")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrierattr_destroy() {
-    todo!("pthread_barrierattr_destroy")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrierattr_getpshared() {
-    todo!("pthread_barrierattr_getpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrierattr_init() {
-    todo!("pthread_barrierattr_init")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrierattr_setpshared() {
-    todo!("pthread_barrierattr_setpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrier_destroy() {
-    todo!("pthread_barrier_destroy")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrier_init() {
-    todo!("pthread_barrier_init")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_barrier_wait() {
-    todo!("pthread_barrier_wait")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_condattr_getclock() {
-    todo!("pthread_condattr_getclock")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_condattr_getpshared() {
-    todo!("pthread_condattr_getpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_condattr_setpshared() {
-    todo!("pthread_condattr_setpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_getschedparam() {
-    todo!("pthread_getschedparam")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_getprotocol() {
-    todo!("pthread_mutexattr_getprotocol")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_getpshared() {
-    todo!("pthread_mutexattr_getpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_getrobust() {
-    todo!("pthread_mutexattr_getrobust")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_setprotocol() {
-    todo!("pthread_mutexattr_setprotocol")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_setpshared() {
-    todo!("pthread_mutexattr_setpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_setrobust() {
-    todo!("pthread_mutexattr_setrobust")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_rwlockattr_getpshared() {
-    todo!("pthread_rwlockattr_getpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_rwlockattr_setpshared() {
-    todo!("pthread_rwlockattr_setpshared")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_setschedparam() {
-    todo!("pthread_setschedparam")
-}
 #[cfg(not(feature = "std"))]
 #[no_mangle]
 unsafe extern "C" fn system() {
     todo!("system")
 }
 #[no_mangle]
-unsafe extern "C" fn crypt() {
-    todo!("crypt")
-}
-#[no_mangle]
 unsafe extern "C" fn dn_expand() {
     todo!("dn_expand")
 }
@@ -646,38 +423,6 @@ unsafe extern "C" fn fmemopen() {
     todo!("fmemopen")
 }
 #[no_mangle]
-unsafe extern "C" fn fnmatch() {
-    todo!("fnmatch")
-}
-#[no_mangle]
-unsafe extern "C" fn getopt() {
-    todo!("getopt")
-}
-#[no_mangle]
-unsafe extern "C" fn hcreate() {
-    todo!("hcreate")
-}
-#[no_mangle]
-unsafe extern "C" fn hdestroy() {
-    todo!("hdestroy")
-}
-#[no_mangle]
-unsafe extern "C" fn hsearch() {
-    todo!("hsearch")
-}
-#[no_mangle]
-unsafe extern "C" fn iconv() {
-    todo!("iconv")
-}
-#[no_mangle]
-unsafe extern "C" fn iconv_close() {
-    todo!("iconv_close")
-}
-#[no_mangle]
-unsafe extern "C" fn iconv_open() {
-    todo!("iconv_open")
-}
-#[no_mangle]
 unsafe extern "C" fn __isoc99_fscanf() {
     todo!("__isoc99_fscanf")
 }
@@ -686,50 +431,10 @@ unsafe extern "C" fn __isoc99_fwscanf() {
     todo!("__isoc99_fwscanf")
 }
 #[no_mangle]
-unsafe extern "C" fn lfind() {
-    todo!("lfind")
-}
-#[no_mangle]
-unsafe extern "C" fn lsearch() {
-    todo!("lsearch")
-}
-#[no_mangle]
-unsafe extern "C" fn tsearch() {
-    todo!("tsearch")
-}
-#[no_mangle]
-unsafe extern "C" fn tfind() {
-    todo!("tfind")
-}
-#[no_mangle]
-unsafe extern "C" fn tdelete() {
-    todo!("tdelete")
-}
-#[no_mangle]
-unsafe extern "C" fn twalk() {
-    todo!("twalk")
-}
-#[no_mangle]
 unsafe extern "C" fn posix_spawn_file_actions_addclose() {
     todo!("posix_spawn_file_actions_addclose")
 }
 #[no_mangle]
-unsafe extern "C" fn pthread_mutex_consistent() {
-    todo!("pthread_mutex_consistent")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutex_timedlock() {
-    todo!("pthread_mutex_timedlock")
-}
-#[no_mangle]
-unsafe extern "C" fn insque() {
-    todo!("insque")
-}
-#[no_mangle]
-unsafe extern "C" fn remque() {
-    todo!("remque")
-}
-#[no_mangle]
 unsafe extern "C" fn sem_close() {
     todo!("sem_close")
 }
@@ -818,66 +523,10 @@ unsafe extern "C" fn pthread_getcpuclockid() {
     todo!("pthread_getcpuclockid")
 }
 #[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_getprioceiling() {
-    todo!("pthread_mutexattr_getprioceiling")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_mutexattr_setprioceiling() {
-    todo!("pthread_mutexattr_setprioceiling")
-}
-#[no_mangle]
 unsafe extern "C" fn pthread_mutex_getprioceiling() {
     todo!("pthread_mutex_getprioceiling")
 }
 #[no_mangle]
-unsafe extern "C" fn pthread_rwlock_timedrdlock() {
-    todo!("pthread_rwlock_timedrdlock")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_rwlock_timedwrlock() {
-    todo!("pthread_rwlock_timedwrlock")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_setaffinity_np() {
-    todo!("pthread_setaffinity_np")
-}
-#[no_mangle]
-unsafe extern "C" fn pthread_setschedprio() {
-    todo!("pthread_setschedprio")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_getparam() {
-    todo!("sched_getparam")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_get_priority_max() {
-    todo!("sched_get_priority_max")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_get_priority_min() {
-    todo!("sched_get_priority_min")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_getscheduler() {
-    todo!("sched_getscheduler")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_rr_get_interval() {
-    todo!("sched_rr_get_interval")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_setparam() {
-    todo!("sched_setparam")
-}
-#[no_mangle]
-unsafe extern "C" fn sched_setscheduler() {
-    todo!("sched_setscheduler")
-}
-#[no_mangle]
-unsafe extern "C" fn sigqueue() {
-    todo!("sigqueue")
-}
-#[no_mangle]
 unsafe extern "C" fn __isoc99_vfscanf() {
     todo!("__isoc99_vfscanf")
 }
@@ -1010,34 +659,10 @@ unsafe extern "C" fn get_current_dir_name() {
     todo!("get_current_dir_name")
 }
 #[no_mangle]
-unsafe extern "C" fn mallopt() {
-    todo!("mallopt")
-}
-#[no_mangle]
-unsafe extern "C" fn malloc_stats() {
-    todo!("malloc_stats")
-}
-#[no_mangle]
-unsafe extern "C" fn mallinfo() {
-    todo!("mallinfo")
-}
-#[no_mangle]
-unsafe extern "C" fn mallinfo2() {
-    todo!("mallinfo2")
-}
-#[no_mangle]
 unsafe extern "C" fn open_by_handle_at() {
     todo!("open_by_handle_at")
 }
 #[no_mangle]
-unsafe extern "C" fn nftw() {
-    todo!("nftw")
-}
-#[no_mangle]
-unsafe extern "C" fn nftw64() {
-    todo!("nftw64")
-}
-#[no_mangle]
 unsafe extern "C" fn pkey_mprotect() {
     todo!("pkey_mprotect")
 }
@@ -1082,34 +707,10 @@ unsafe extern "C" fn ulimit() {
     todo!("ulimit")
 }
 #[no_mangle]
-unsafe extern "C" fn fts_read() {
-    todo!("fts_read")
-}
-#[no_mangle]
-unsafe extern "C" fn fts_open() {
-    todo!("fts_open")
-}
-#[no_mangle]
-unsafe extern "C" fn fts_close() {
-    todo!("fts_close")
-}
-#[no_mangle]
 unsafe extern "C" fn __open_2() {
     todo!("__open_2")
 }
 #[no_mangle]
-unsafe extern "C" fn wordexp() {
-    todo!("wordexp")
-}
-#[no_mangle]
-unsafe extern "C" fn wordfree() {
-    todo!("wordfree")
-}
-#[no_mangle]
-unsafe extern "C" fn ftw() {
-    todo!("ftw")
-}
-#[no_mangle]
 unsafe extern "C" fn getpass() {
     todo!("getpass")
 }
@@ -1118,10 +719,6 @@ unsafe extern "C" fn getusershell() {
     todo!("getusershell")
 }
 #[no_mangle]
-unsafe extern "C" fn glob() {
-    todo!("glob")
-}
-#[no_mangle]
 unsafe extern "C" fn on_exit() {
     todo!("on_exit")
 }
@@ -1150,10 +747,6 @@ unsafe extern "C" fn gammaf() {
     todo!("gammaf")
 }
 #[no_mangle]
-unsafe extern "C" fn strfromf() {
-    todo!("strfromf")
-}
-#[no_mangle]
 unsafe extern "C" fn hstrerror() {
     todo!("hstrerror")
 }
@@ -1,34 +0,0 @@
-//! POSIX async I/O API. Not widely used.
-
-#[no_mangle]
-unsafe extern "C" fn aio_cancel() {
-    todo!("aio_cancel")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_error() {
-    todo!("aio_error")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_fsync() {
-    todo!("aio_fsync")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_read() {
-    todo!("aio_read")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_write() {
-    todo!("aio_write")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_return() {
-    todo!("aio_return")
-}
-#[no_mangle]
-unsafe extern "C" fn aio_suspend() {
-    todo!("aio_suspend")
-}
-#[no_mangle]
-unsafe extern "C" fn lio_listio() {
-    todo!("lio_listio")
-}
@@ -1,16 +0,0 @@
-#[no_mangle]
-unsafe extern "C" fn dladdr() {
-    todo!("dladdr")
-}
-#[no_mangle]
-unsafe extern "C" fn dlclose() {
-    todo!("dlclose")
-}
-#[no_mangle]
-unsafe extern "C" fn dlerror() {
-    todo!("dlerror")
-}
-#[no_mangle]
-unsafe extern "C" fn dlopen() {
-    todo!("dlopen")
-}
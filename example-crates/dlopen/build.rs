@@ -0,0 +1,24 @@
+use std::env;
+use std::process::Command;
+
+fn main() {
+    // Pass -nostartfiles to the linker.
+    println!("cargo:rustc-link-arg=-nostartfiles");
+
+    // Export all of c-scape's functions, so that the linker keeps them even
+    // though this program doesn't call them, and so that the plugin can
+    // find them.
+    println!("cargo:rustc-link-arg=-Wl,--export-dynamic");
+
+    // Build the plugin.
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let plugin = format!("{}/libplugin.so", out_dir);
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let status = Command::new(cc)
+        .args(["-shared", "-fPIC", "-O2", "-o", &plugin, "src/plugin.c"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    println!("cargo:rustc-env=PLUGIN={}", plugin);
+    println!("cargo:rerun-if-changed=src/plugin.c");
+}
@@ -0,0 +1,22 @@
+//! Load a plugin with `dlopen`, and call a function in it. The plugin uses
+//! libc functions which c-scape provides, including some this program never
+//! calls itself.
+
+use std::ffi::{CStr, CString};
+
+fn main() {
+    let path = CString::new(env!("PLUGIN")).unwrap();
+    unsafe {
+        let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            panic!("{:?}", CStr::from_ptr(libc::dlerror()));
+        }
+
+        let sym = libc::dlsym(handle, c"plugin_main".as_ptr());
+        assert!(!sym.is_null());
+        let plugin_main: unsafe extern "C" fn() -> libc::c_int = std::mem::transmute(sym);
+        println!("plugin_main returned {}", plugin_main());
+
+        assert_eq!(libc::dlclose(handle), 0);
+    }
+}
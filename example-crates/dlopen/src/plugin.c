@@ -0,0 +1,46 @@
+// A plugin which uses libc functions that the program loading it doesn't.
+
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int saved_argc;
+static const char *saved_arg;
+
+__attribute__((constructor)) static void init(int argc, char **argv, char **envp) {
+    (void)envp;
+    saved_argc = argc;
+    saved_arg = argc > 1 ? argv[1] : "(none)";
+}
+
+static int compare(const void *a, const void *b) {
+    return strcmp(*(const char *const *)a, *(const char *const *)b);
+}
+
+int plugin_helper(void) {
+    return 42;
+}
+
+int plugin_main(void) {
+    char text[] = "pear,apple,fig";
+    const char *words[3];
+    char *save;
+    int n = 0;
+    for (char *word = strtok_r(text, ",", &save); word != NULL; word = strtok_r(NULL, ",", &save)) {
+        words[n++] = word;
+    }
+    qsort(words, n, sizeof(words[0]), compare);
+
+    char line[64];
+    snprintf(line, sizeof(line), "sorted: %s %s %s", words[0], words[1], words[2]);
+    puts(line);
+    printf("argc=%d arg=%s\n", saved_argc, saved_arg);
+
+    // This plugin was loaded with `RTLD_LOCAL`, but `RTLD_DEFAULT` lookups
+    // from it still search its own symbols.
+    int (*helper)(void) = (int (*)(void))dlsym(RTLD_DEFAULT, "plugin_helper");
+    printf("plugin_helper returned %d\n", helper != NULL ? helper() : -1);
+    fflush(stdout);
+    return n;
+}
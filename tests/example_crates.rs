@@ -191,6 +191,21 @@ fn example_crate_c_gull_unwinding() {
     assert!(output.status.success());
 }
 
+#[test]
+fn example_crate_dlopen() {
+    test_crate(
+        "dlopen",
+        &["hello"],
+        &[],
+        "sorted: apple fig pear\n\
+         argc=2 arg=hello\n\
+         plugin_helper returned 42\n\
+         plugin_main returned 3\n",
+        "",
+        None,
+    );
+}
+
 #[test]
 fn example_crate_dns() {
     test_crate(